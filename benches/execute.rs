@@ -0,0 +1,43 @@
+#![allow(clippy::needless_return)]
+
+// execute() on the opcodes that cost the most or run the most: DXYN with the tallest
+// sprite, FX33's three BCD digits and 8XY4's carry. Run with `cargo bench`.
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use rustchip8::Chip8;
+use std::hint::black_box;
+
+fn machine() -> Chip8 {
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.load_rom(&[0x12, 0x00]).unwrap();
+    return chip8;
+}
+
+fn execute(c: &mut Criterion) {
+    // D01F: 15 rows of the font at the right edge, every other call erases the last one's
+    // and collides
+    let mut chip8 = machine();
+    chip8.set_register(0, 60);
+    chip8.set_register(1, 20);
+    chip8.set_reg_i(rustchip8::FONT_ADDR as u16);
+    c.bench_function("DXYN 8x15, clipped at the edges", |b| {
+        b.iter(|| chip8.execute(black_box(0xD01F)).unwrap())
+    });
+
+    let mut chip8 = machine();
+    chip8.set_register(3, 219);
+    chip8.set_reg_i(0x300);
+    c.bench_function("FX33", |b| {
+        b.iter(|| chip8.execute(black_box(0xF333)).unwrap())
+    });
+
+    let mut chip8 = machine();
+    chip8.set_register(1, 0xF0);
+    chip8.set_register(2, 0x11);
+    c.bench_function("8XY4", |b| {
+        b.iter(|| chip8.execute(black_box(0x8124)).unwrap())
+    });
+}
+
+criterion_group!(benches, execute);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+/* Runs a ROM for a second through the C interface and prints the display.
+ *
+ *   cargo build --lib --no-default-features
+ *   cc examples/ffi/main.c -Iinclude -Ltarget/debug -lrustchip8 -o chip8-ffi
+ *   LD_LIBRARY_PATH=target/debug ./chip8-ffi test_roms/ibm.ch8
+ */
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "rustchip8.h"
+
+int main(int argc, char **argv) {
+    const char *path = argc > 1 ? argv[1] : "test_roms/ibm.ch8";
+    FILE *file = fopen(path, "rb");
+    if (!file) {
+        perror(path);
+        return 1;
+    }
+    uint8_t rom[4096];
+    size_t len = fread(rom, 1, sizeof rom, file);
+    fclose(file);
+
+    Chip8 *chip8 = chip8_new_seeded(0);
+    if (chip8_load_rom(chip8, rom, len) != 0) {
+        fprintf(stderr, "ERROR: %s\n", chip8_last_error());
+        chip8_free(chip8);
+        return 1;
+    }
+
+    /* 60 frames of 12 instructions */
+    for (int frame = 0; frame < 60; frame++) {
+        if (chip8_step(chip8, 12) < 0) {
+            fprintf(stderr, "ERROR: %s\n", chip8_last_error());
+            break;
+        }
+        chip8_tick_timers(chip8);
+    }
+
+    int width = chip8_display_width(chip8);
+    int height = chip8_display_height(chip8);
+    uint8_t *pixels = malloc((size_t)width * height);
+    chip8_get_display(chip8, pixels, (size_t)width * height);
+    for (int y = 0; y < height; y++) {
+        for (int x = 0; x < width; x++) {
+            putchar(pixels[y * width + x] ? '#' : '.');
+        }
+        putchar('\n');
+    }
+    free(pixels);
+
+    printf("PC %#05x, V0 %d\n", chip8_get_pc(chip8), chip8_get_register(chip8, 0));
+    chip8_free(chip8);
+
+    /* every call tolerates a null machine */
+    chip8_step(NULL, 1);
+    printf("null machine: %s\n", chip8_last_error());
+    return 0;
+}
@@ -0,0 +1,36 @@
+-- Plays the left paddle of David Winter's PONG (--features scripting):
+--
+--   RustChip8 --script examples/scripts/pong.lua pong.ch8
+--
+-- PONG keeps the ball at V6/V7 (x, y) and the left paddle's top at VB, the paddle is 6
+-- pixels tall and keys 1 and 4 move it up and down.
+
+UP = 0x1
+DOWN = 0x4
+misses = 0
+last_x = 0
+
+function on_frame()
+    local ball_y = reg(7)
+    -- PONG lets VB go below 0, the paddle is drawn wrapped around then
+    local top = reg(0xB)
+    if top > 127 then
+        top = top - 256
+    end
+    local paddle = top + 3
+    release(UP)
+    release(DOWN)
+    if ball_y < paddle - 1 then
+        press(UP)
+    elseif ball_y > paddle + 1 then
+        press(DOWN)
+    end
+
+    -- the ball jumps back to the right when it got past the paddle
+    local ball_x = reg(6)
+    if last_x < 4 and ball_x > 32 then
+        misses = misses + 1
+        print("missed", misses, "at frame", frame())
+    end
+    last_x = ball_x
+end
@@ -0,0 +1,64 @@
+// cargo +nightly fuzz run rom
+// cargo +nightly fuzz run rom fuzz/regressions/*   (replays the inputs that found bugs)
+// cargo test --test fuzz_invariants   (the same checks, without cargo-fuzz)
+//
+// Arbitrary ROMs under every quirk combination. The first two bytes pick the quirks and
+// machine options, the rest is loaded as the program. Besides not panicking the machine
+// has to stay consistent after every step: pc and I inside memory, the stack within its
+// limit.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rustchip8::{Chip8, MAX_ROM_SIZE, Quirks};
+
+const STEPS: u32 = 100_000;
+
+fuzz_target!(|data: &[u8]| {
+    let [config, keys, rom @ ..] = data else {
+        return;
+    };
+    if rom.is_empty() {
+        return;
+    }
+    let rom = &rom[..rom.len().min(MAX_ROM_SIZE)];
+    let flag = |bit: u8| config & (1 << bit) != 0;
+
+    let mut chip8 = Chip8::with_seed(*keys as u64);
+    chip8.set_quirks(Quirks {
+        shift_uses_vy: flag(0),
+        memory_increment: flag(1),
+        jump_uses_vx: flag(2),
+        sprite_wrap: flag(3),
+        vf_reset: flag(4),
+        display_wait: flag(5),
+        index_overflow: flag(6),
+    });
+    chip8.set_strict_memory(flag(7));
+    chip8.set_extended_memory(keys & 1 != 0);
+    chip8.set_stack_limit(1 + (*keys as usize >> 4));
+    chip8.set_ignore_sys(keys & 2 != 0);
+    chip8.load_rom(rom).unwrap();
+
+    let mut key_state = *keys as u32;
+    for step in 0..STEPS {
+        if step % 16 == 0 {
+            chip8.tick_60hz();
+        }
+        // a changing keypad so FX0A and EX9E/EXA1 take both paths
+        if step % 64 == 0 {
+            key_state = key_state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            chip8.set_key((key_state >> 16) as u8 & 0xF, key_state & 0x100 != 0);
+        }
+        if chip8.step().is_err() || chip8.has_exited() {
+            break;
+        }
+
+        let mem_len = chip8.mem().len();
+        // with strict memory pc may sit just past the end, the next fetch reports it
+        let pc_limit = if chip8.is_strict_memory() { mem_len + 2 } else { mem_len };
+        assert!((chip8.pc() as usize) < pc_limit, "pc {:#X} outside memory", chip8.pc());
+        assert!((chip8.reg_i() as usize) < mem_len, "I {:#X} outside memory", chip8.reg_i());
+        assert!(chip8.stack().len() <= chip8.stack_limit());
+        assert!(chip8.stack().iter().all(|&addr| (addr as usize) < pc_limit));
+    }
+});
@@ -0,0 +1,93 @@
+#ifndef RUSTCHIP8_H
+#define RUSTCHIP8_H
+
+/* C interface to the RustChip8 core, see src/ffi.rs. Generated by cbindgen, regenerate it with
+ * `cbindgen --config cbindgen.toml --output include/rustchip8.h`.
+ * Link against librustchip8 (cargo build --lib --no-default-features). */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+typedef struct Chip8 Chip8;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * A new machine with a time-based CXNN seed, free it with chip8_free()
+ */
+Chip8 *chip8_new(void);
+
+/**
+ * A new machine whose CXNN sequence is reproducible from `seed`
+ */
+Chip8 *chip8_new_seeded(uint64_t seed);
+
+/**
+ * Null is fine.
+ *
+ * # Safety
+ * `chip8` has to come from chip8_new() and must not be used afterwards.
+ */
+void chip8_free(Chip8 *chip8);
+
+/**
+ * Resets the machine and loads `len` bytes from `rom`. 0 on success.
+ *
+ * # Safety
+ * `rom` has to point to `len` readable bytes.
+ */
+int32_t chip8_load_rom(Chip8 *chip8, const uint8_t *rom, size_t len);
+
+/**
+ * Runs up to `n` instructions and returns how many ran. It stops early when the program
+ * waits for a key or the next frame, or has exited. A CPU error returns -1.
+ */
+int64_t chip8_step(Chip8 *chip8, uint32_t n);
+
+/**
+ * The 60Hz tick: timers count down and a display wait ends
+ */
+void chip8_tick_timers(Chip8 *chip8);
+
+/**
+ * Width and height of the current resolution, 64x32 or 128x64, -1 for a null machine
+ */
+int32_t chip8_display_width(Chip8 *chip8);
+
+int32_t chip8_display_height(Chip8 *chip8);
+
+/**
+ * Copies the display row by row into `out`, one byte per pixel holding its bitplanes
+ * (0 off, 1 and 2 XO-CHIP planes, 3 both). Returns the bytes written, width * height, or
+ * -1 when `len` is smaller than that.
+ *
+ * # Safety
+ * `out` has to point to `len` writable bytes.
+ */
+int64_t chip8_get_display(Chip8 *chip8, uint8_t *out, size_t len);
+
+void chip8_set_key(Chip8 *chip8, uint8_t key, bool pressed);
+
+/**
+ * VX, 0-255, or -1 when x isn't 0-15
+ */
+int32_t chip8_get_register(Chip8 *chip8, uint8_t x);
+
+int32_t chip8_get_pc(Chip8 *chip8);
+
+bool chip8_sound_active(Chip8 *chip8);
+
+/**
+ * The message for the last failure on this thread, or null. It stays valid until the
+ * next failing call on the same thread.
+ */
+const char *chip8_last_error(void);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* RUSTCHIP8_H */
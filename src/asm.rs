@@ -0,0 +1,458 @@
+// Assembler for the mnemonics the disassembler prints (Cowgod's syntax), so small test
+// programs can be written as text:
+//
+//   start:  LD V0, 0          ; comments run to the end of the line
+//           LD I, sprite
+//   loop:   DRW V0, V1, 5
+//           ADD V0, 8
+//           SE V0, 64
+//           JP loop
+//   end:    JP end
+//   sprite: db 0xF0, 0x90, 0b11110000, 0x90, 0xF0
+//
+// Numbers are decimal, 0x hex or 0b binary, and labels can be used wherever a number goes.
+// `db` emits bytes and `dw` big-endian words, `LD I, long ADDR` is the XO-CHIP F000 NNNN.
+// Labels are resolved in a second pass, so forward references work.
+
+use crate::Instruction;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    // 1-based line in the source
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+// One line with its label and comment stripped
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+enum Operand {
+    Register(u8),
+    // I, DT, ST, K, F, HF, B, R, [I] and LONG, upper-cased
+    Special(String),
+    Value(i64),
+}
+
+struct Assembler<'a> {
+    labels: HashMap<&'a str, u16>,
+}
+
+impl Assembler<'_> {
+    fn operand(&self, text: &str) -> Result<Operand, String> {
+        match keyword(text) {
+            Some(operand) => return Ok(operand),
+            None => return Ok(Operand::Value(self.value(text)?)),
+        }
+    }
+
+    fn value(&self, text: &str) -> Result<i64, String> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let lower = digits.to_ascii_lowercase();
+        let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()
+        } else if let Some(binary) = lower.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).ok()
+        } else if lower.starts_with(|c: char| c.is_ascii_digit()) {
+            lower.parse().ok()
+        } else if !negative {
+            let Some(&addr) = self.labels.get(text) else {
+                return Err(format!("unknown label '{text}'"));
+            };
+            Some(addr as i64)
+        } else {
+            None
+        };
+        let value = parsed.ok_or_else(|| format!("invalid number '{text}'"))?;
+
+        return Ok(if negative { -value } else { value });
+    }
+
+    fn encode(&self, statement: &Statement) -> Result<Vec<u8>, String> {
+        use Instruction::*;
+        use Operand::*;
+
+        let operands = statement
+            .operands
+            .iter()
+            .map(|text| self.operand(text))
+            .collect::<Result<Vec<_>, _>>()?;
+        let special = |operand: &Operand, name: &str| matches!(operand, Special(s) if s == name);
+
+        let mnemonic = statement.mnemonic.as_str();
+        if mnemonic == "DB" || mnemonic == "DW" {
+            let mut bytes = Vec::new();
+            for operand in &operands {
+                let Value(value) = operand else {
+                    return Err(format!("{mnemonic} expects numbers"));
+                };
+                if mnemonic == "DB" {
+                    bytes.push(byte(*value)?);
+                } else {
+                    bytes.extend(word(*value)?.to_be_bytes());
+                }
+            }
+            return Ok(bytes);
+        }
+
+        let instruction = match (mnemonic, operands.as_slice()) {
+            ("CLS", []) => Cls,
+            ("RET", []) => Ret,
+            ("SCD", [Value(n)]) => ScrollDown(nibble(*n)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => LowRes,
+            ("HIGH", []) => HighRes,
+            ("SYS", [Value(addr)]) => Sys(address(*addr)?),
+            ("JP", [Value(addr)]) => Jump(address(*addr)?),
+            ("JP", [Register(0), Value(addr)]) => {
+                let nnn = address(*addr)?;
+                JumpOffset {
+                    x: (nnn >> 8) as u8,
+                    nnn,
+                }
+            }
+            ("CALL", [Value(addr)]) => Call(address(*addr)?),
+            ("SE", [Register(x), Value(nn)]) => SkipEqImm {
+                x: *x,
+                nn: byte(*nn)?,
+            },
+            ("SE", [Register(x), Register(y)]) => SkipEqReg { x: *x, y: *y },
+            ("SNE", [Register(x), Value(nn)]) => SkipNeImm {
+                x: *x,
+                nn: byte(*nn)?,
+            },
+            ("SNE", [Register(x), Register(y)]) => SkipNeReg { x: *x, y: *y },
+            ("LD", [Register(x), Value(nn)]) => LoadImm {
+                x: *x,
+                nn: byte(*nn)?,
+            },
+            ("LD", [Register(x), Register(y)]) => Move { x: *x, y: *y },
+            ("LD", [Register(x), dt]) if special(dt, "DT") => LoadDelay { x: *x },
+            ("LD", [Register(x), k]) if special(k, "K") => WaitKey { x: *x },
+            ("LD", [Register(x), i]) if special(i, "[I]") => Load { x: *x },
+            ("LD", [Register(x), r]) if special(r, "R") => LoadFlags { x: *x },
+            ("LD", [i, Value(addr)]) if special(i, "I") => LoadI(address(*addr)?),
+            ("LD", [i, long, Value(addr)]) if special(i, "I") && special(long, "LONG") => {
+                let mut bytes = LoadILong.encode().to_be_bytes().to_vec();
+                bytes.extend(word(*addr)?.to_be_bytes());
+                return Ok(bytes);
+            }
+            ("LD", [target, Register(x)]) => {
+                let x = *x;
+                match target {
+                    Special(name) if name == "DT" => SetDelay { x },
+                    Special(name) if name == "ST" => SetSound { x },
+                    Special(name) if name == "F" => Font { x },
+                    Special(name) if name == "HF" => BigFont { x },
+                    Special(name) if name == "B" => Bcd { x },
+                    Special(name) if name == "[I]" => Store { x },
+                    Special(name) if name == "R" => SaveFlags { x },
+                    _ => return Err(String::from("invalid operands for LD")),
+                }
+            }
+            ("ADD", [Register(x), Value(nn)]) => AddImm {
+                x: *x,
+                nn: byte(*nn)?,
+            },
+            ("ADD", [Register(x), Register(y)]) => Add { x: *x, y: *y },
+            ("ADD", [i, Register(x)]) if special(i, "I") => AddI { x: *x },
+            ("OR", [Register(x), Register(y)]) => Or { x: *x, y: *y },
+            ("AND", [Register(x), Register(y)]) => And { x: *x, y: *y },
+            ("XOR", [Register(x), Register(y)]) => Xor { x: *x, y: *y },
+            ("SUB", [Register(x), Register(y)]) => Sub { x: *x, y: *y },
+            ("SUBN", [Register(x), Register(y)]) => SubN { x: *x, y: *y },
+            // with a single register it is shifted in place, whatever the shift quirk
+            ("SHR", [Register(x)]) => ShiftRight { x: *x, y: *x },
+            ("SHR", [Register(x), Register(y)]) => ShiftRight { x: *x, y: *y },
+            ("SHL", [Register(x)]) => ShiftLeft { x: *x, y: *x },
+            ("SHL", [Register(x), Register(y)]) => ShiftLeft { x: *x, y: *y },
+            ("RND", [Register(x), Value(nn)]) => Random {
+                x: *x,
+                nn: byte(*nn)?,
+            },
+            ("DRW", [Register(x), Register(y), Value(n)]) => Draw {
+                x: *x,
+                y: *y,
+                n: nibble(*n)?,
+            },
+            ("SKP", [Register(x)]) => SkipKey { x: *x },
+            ("SKNP", [Register(x)]) => SkipNotKey { x: *x },
+            ("PLANE", [Value(n)]) => Plane(nibble(*n)?),
+            ("AUDIO", []) => Audio,
+            ("PITCH", [Register(x)]) => Pitch { x: *x },
+            _ if MNEMONICS.contains(&mnemonic) => {
+                return Err(format!("invalid operands for {mnemonic}"));
+            }
+            _ => return Err(format!("unknown instruction '{mnemonic}'")),
+        };
+
+        return Ok(instruction.encode().to_be_bytes().to_vec());
+    }
+}
+
+const MNEMONICS: [&str; 31] = [
+    "CLS", "RET", "SCD", "SCR", "SCL", "EXIT", "LOW", "HIGH", "SYS", "JP", "CALL", "SE", "SNE",
+    "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND", "DRW", "SKP", "SKNP",
+    "PLANE", "AUDIO", "PITCH", "DB", "DW",
+];
+
+fn address(value: i64) -> Result<u16, String> {
+    if !(0..=0xFFF).contains(&value) {
+        return Err(format!("address {value:#X} is out of range 0x000-0xFFF"));
+    }
+    return Ok(value as u16);
+}
+
+// Negative bytes are two's complement, so `ADD V0, -1` counts down
+fn byte(value: i64) -> Result<u8, String> {
+    if !(-128..=0xFF).contains(&value) {
+        return Err(format!("{value} doesn't fit in a byte"));
+    }
+    return Ok(value as u8);
+}
+
+fn word(value: i64) -> Result<u16, String> {
+    if !(-0x8000..=0xFFFF).contains(&value) {
+        return Err(format!("{value} doesn't fit in a word"));
+    }
+    return Ok(value as u16);
+}
+
+fn nibble(value: i64) -> Result<u8, String> {
+    if !(0..=0xF).contains(&value) {
+        return Err(format!("{value} is out of range 0-15"));
+    }
+    return Ok(value as u8);
+}
+
+// Registers and the other operand names, which can't be labels
+fn keyword(text: &str) -> Option<Operand> {
+    let upper = text.to_ascii_uppercase();
+    if let Some(register) = upper.strip_prefix('V')
+        && register.len() == 1
+        && let Ok(x) = u8::from_str_radix(register, 16)
+    {
+        return Some(Operand::Register(x));
+    }
+    if matches!(
+        upper.as_str(),
+        "I" | "DT" | "ST" | "K" | "F" | "HF" | "B" | "R" | "[I]" | "LONG"
+    ) {
+        return Some(Operand::Special(upper));
+    }
+
+    return None;
+}
+
+fn is_label(name: &str) -> bool {
+    return name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && keyword(name).is_none();
+}
+
+// Machine code for `source` when loaded at `base`, normally PROGRAM_START
+pub fn assemble(source: &str, base: u16) -> Result<Vec<u8>, AsmError> {
+    let mut assembler = Assembler {
+        labels: HashMap::new(),
+    };
+    let mut statements = Vec::new();
+
+    // First pass: every statement's size is known without the labels, which gives each
+    // label its address
+    let mut addr = base as usize;
+    for (i, text) in source.lines().enumerate() {
+        let line = i + 1;
+        let error = |message: String| AsmError { line, message };
+        let mut text = text.split(';').next().unwrap_or("").trim();
+        if let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(error(format!("invalid label name '{label}'")));
+            }
+            if assembler.labels.insert(label, addr as u16).is_some() {
+                return Err(error(format!("label '{label}' is defined twice")));
+            }
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands: Vec<&str> = match operands.trim() {
+            "" => Vec::new(),
+            // `long ADDR` is two operands
+            operands => operands
+                .split(',')
+                .flat_map(
+                    |operand| match operand.trim().split_once(char::is_whitespace) {
+                        Some((long, addr)) if long.eq_ignore_ascii_case("long") => {
+                            vec![long, addr.trim()]
+                        }
+                        _ => vec![operand.trim()],
+                    },
+                )
+                .collect(),
+        };
+        let mnemonic = mnemonic.to_ascii_uppercase();
+        let size = match mnemonic.as_str() {
+            "DB" => operands.len(),
+            "DW" => operands.len() * 2,
+            "LD" if operands.len() == 3 => 4,
+            _ => 2,
+        };
+        addr += size;
+        if addr > crate::EXTENDED_MEM_SIZE {
+            return Err(error(String::from("program runs past the end of memory")));
+        }
+        statements.push(Statement {
+            line,
+            mnemonic,
+            operands,
+        });
+    }
+
+    // Second pass: encode with every label known
+    let mut rom = Vec::new();
+    for statement in &statements {
+        let bytes = assembler.encode(statement).map_err(|message| AsmError {
+            line: statement.line,
+            message,
+        })?;
+        rom.extend(bytes);
+    }
+
+    return Ok(rom);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8, PROGRAM_START, disassemble};
+
+    fn run(source: &str, steps: usize) -> Chip8 {
+        let rom = assemble(source, PROGRAM_START as u16).unwrap();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&rom).unwrap();
+        for _ in 0..steps {
+            chip8.step().unwrap();
+        }
+        return chip8;
+    }
+
+    fn error(source: &str) -> String {
+        return assemble(source, PROGRAM_START as u16)
+            .unwrap_err()
+            .to_string();
+    }
+
+    #[test]
+    fn disassembly_assembles_back() {
+        for opcode in 0..=u16::MAX {
+            // F000 is printed without the address word that follows it
+            if opcode == 0xF000 || Instruction::decode(opcode).is_none() {
+                continue;
+            }
+            let text = disassemble(opcode);
+            let bytes = assemble(&text, 0x200).unwrap_or_else(|e| panic!("{text}: {e}"));
+            assert_eq!(bytes, opcode.to_be_bytes(), "{text}");
+        }
+        assert_eq!(
+            assemble("LD I, long 0xABCD", 0x200),
+            Ok(vec![0xF0, 0x00, 0xAB, 0xCD])
+        );
+    }
+
+    #[test]
+    fn example_program_draws_a_row() {
+        // the program at the top of this file
+        let source = "
+            start:  LD V0, 0          ; comments run to the end of the line
+                    LD I, sprite
+            loop:   DRW V0, V1, 5
+                    ADD V0, 8
+                    SE V0, 64
+                    JP loop
+            end:    JP end
+            sprite: db 0xF0, 0x90, 0b11110000, 0x90, 0xF0
+        ";
+        let chip8 = run(source, 2 + 8 * 4);
+        assert_eq!(chip8.registers()[0], 64);
+        assert_eq!(chip8.reg_i(), 0x20E);
+        assert!(chip8.is_self_jump());
+        // eight 4x5 boxes across the top, 0 and 3 set in the hollow rows
+        for x in 0..64 {
+            assert_eq!(chip8.pixel(x, 0), x % 8 < 4, "x {x}");
+            assert_eq!(chip8.pixel(x, 1), x % 8 == 0 || x % 8 == 3, "x {x}");
+            assert!(!chip8.pixel(x, 5));
+        }
+    }
+
+    #[test]
+    fn forward_references_and_data() {
+        let source = "
+                    CALL sub
+                    JP done
+            sub:    LD VA, 10
+                    ADD VA, -1
+                    RET
+            done:   LD I, data
+                    LD V3, [I]
+            stop:   JP stop
+            data:   dw 0x1234, 0xFFFF
+        ";
+        let rom = assemble(source, 0x200).unwrap();
+        assert_eq!(rom[..4], [0x22, 0x04, 0x12, 0x0A]);
+        assert_eq!(rom[rom.len() - 4..], [0x12, 0x34, 0xFF, 0xFF]);
+        let chip8 = run(source, 7);
+        assert_eq!(chip8.registers()[0xA], 9);
+        assert!(chip8.stack().is_empty());
+        assert_eq!(chip8.registers()[..4], [0x12, 0x34, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(error("CLS\nJP nowhere"), "line 2: unknown label 'nowhere'");
+        assert_eq!(
+            error("a: CLS\na: RET"),
+            "line 2: label 'a' is defined twice"
+        );
+        assert_eq!(error("v1: CLS"), "line 1: invalid label name 'v1'");
+        assert_eq!(
+            error("\n\nJP 0x1000"),
+            "line 3: address 0x1000 is out of range 0x000-0xFFF"
+        );
+        assert_eq!(error("LD V0, 256"), "line 1: 256 doesn't fit in a byte");
+        assert_eq!(error("DRW V0, V1, 16"), "line 1: 16 is out of range 0-15");
+        assert_eq!(error("MOV V0, V1"), "line 1: unknown instruction 'MOV'");
+        assert_eq!(error("ADD V0"), "line 1: invalid operands for ADD");
+        assert_eq!(error("LD V0, 0x"), "line 1: invalid number '0x'");
+        assert_eq!(error("db V0"), "line 1: DB expects numbers");
+    }
+
+    #[test]
+    fn committed_test_rom_matches_its_source() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms");
+        let source = std::fs::read_to_string(dir.join("hires-vip.asm")).unwrap();
+        let rom = std::fs::read(dir.join("hires-vip.ch8")).unwrap();
+        assert_eq!(assemble(&source, 0x200), Ok(rom));
+    }
+}
@@ -0,0 +1,299 @@
+// The beeper's sound without the audio device: a tone (or the XO-CHIP pattern) shaped by a
+// short attack/release envelope, rendered into whatever buffer the backend hands over.
+// Unsigned 8-bit mono, 128 is silence.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+// XO-CHIP plays its 128-bit pattern at 4000 bits per second for pitch 64
+const PATTERN_BASE_RATE: f32 = 4000.0;
+// long enough that starting and stopping doesn't click, short enough to not blur notes
+pub const ATTACK: Duration = Duration::from_millis(2);
+pub const RELEASE: Duration = Duration::from_millis(5);
+const SILENCE: u8 = 128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wave {
+    Square,
+    Sine,
+    Triangle,
+    // a new random level every half period, pitched like the others
+    Noise,
+}
+
+impl FromStr for Wave {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => return Ok(Wave::Square),
+            "sine" => return Ok(Wave::Sine),
+            "triangle" => return Ok(Wave::Triangle),
+            "noise" => return Ok(Wave::Noise),
+            _ => {
+                return Err(format!(
+                    "unknown wave '{s}', expected square, sine, triangle or noise"
+                ));
+            }
+        }
+    }
+}
+
+pub struct Synth {
+    sample_rate: u32,
+    wave: Wave,
+    freq: f32,
+    // over one period of the wave, or once over all 128 pattern bits
+    phase: f32,
+    // XO-CHIP pattern and pitch, None plays `wave`
+    pattern: Option<([u8; 16], u8)>,
+    gate: bool,
+    // envelope level, 0 to 1
+    level: f32,
+    attack_step: f32,
+    release_step: f32,
+    noise: u16,
+    noise_level: f32,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32, wave: Wave, freq: f32) -> Self {
+        let per_sample = |time: Duration| {
+            return 1.0 / (time.as_secs_f32() * sample_rate as f32).max(1.0);
+        };
+        return Synth {
+            sample_rate,
+            wave,
+            freq,
+            phase: 0.0,
+            pattern: None,
+            gate: false,
+            level: 0.0,
+            attack_step: per_sample(ATTACK),
+            release_step: per_sample(RELEASE),
+            noise: 0xACE1,
+            noise_level: 1.0,
+        };
+    }
+
+    // The sound timer: on starts the attack, off the release
+    pub fn set_gate(&mut self, on: bool) {
+        self.gate = on;
+    }
+
+    pub fn set_pattern(&mut self, pattern: Option<([u8; 16], u8)>) {
+        if pattern != self.pattern {
+            self.pattern = pattern;
+            self.phase = 0.0;
+        }
+    }
+
+    // Cuts the sound off without a release
+    pub fn silence(&mut self) {
+        self.gate = false;
+        self.level = 0.0;
+    }
+
+    // Nothing left to play once the release has ended
+    pub fn is_silent(&self) -> bool {
+        return !self.gate && self.level == 0.0;
+    }
+
+    pub fn render(&mut self, samples: &mut [u8]) {
+        let step = match self.pattern {
+            None => self.freq / self.sample_rate as f32,
+            Some((_, pitch)) => {
+                let rate = PATTERN_BASE_RATE * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+                rate / 128.0 / self.sample_rate as f32
+            }
+        };
+        for sample in samples.iter_mut() {
+            self.level = if self.gate {
+                (self.level + self.attack_step).min(1.0)
+            } else {
+                (self.level - self.release_step).max(0.0)
+            };
+            if self.level == 0.0 {
+                *sample = SILENCE;
+                continue;
+            }
+            let value = self.wave_value();
+            *sample = (SILENCE as f32 + value * self.level * 127.0).round() as u8;
+            let next = self.phase + step;
+            if self.pattern.is_none()
+                && self.wave == Wave::Noise
+                && (next * 2.0).floor() != (self.phase * 2.0).floor()
+            {
+                self.next_noise();
+            }
+            self.phase = next % 1.0;
+        }
+    }
+
+    // -1 to 1
+    fn wave_value(&self) -> f32 {
+        if let Some((bits, _)) = &self.pattern {
+            let bit = (self.phase * 128.0) as usize % 128;
+            return if bits[bit / 8] >> (7 - bit % 8) & 1 != 0 {
+                1.0
+            } else {
+                -1.0
+            };
+        }
+        match self.wave {
+            Wave::Square => return if self.phase < 0.5 { 1.0 } else { -1.0 },
+            Wave::Sine => return (self.phase * std::f32::consts::TAU).sin(),
+            Wave::Triangle => return 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Wave::Noise => return self.noise_level,
+        }
+    }
+
+    // 16-bit Galois LFSR, only its top bit is used
+    fn next_noise(&mut self) {
+        let lsb = self.noise & 1;
+        self.noise >>= 1;
+        if lsb != 0 {
+            self.noise ^= 0xB400;
+        }
+        self.noise_level = if self.noise & 0x8000 != 0 { 1.0 } else { -1.0 };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 8192 Hz and a 512 Hz tone: 16 samples a period, with exact phases
+    const RATE: u32 = 8192;
+    const PERIOD: usize = 16;
+    // 2ms is 16.4 samples, the 17th is the first at full level
+    const ATTACK_SAMPLES: usize = 16;
+
+    fn render(synth: &mut Synth, count: usize) -> Vec<u8> {
+        let mut samples = vec![0; count];
+        synth.render(&mut samples);
+        return samples;
+    }
+
+    // Samples after the attack, gate on from the start
+    fn steady(wave: Wave) -> Vec<u8> {
+        let mut synth = Synth::new(RATE, wave, 512.0);
+        synth.set_gate(true);
+        return render(&mut synth, ATTACK_SAMPLES + 64 * PERIOD)[ATTACK_SAMPLES..].to_vec();
+    }
+
+    #[test]
+    fn wave_names() {
+        assert_eq!("Sine".parse(), Ok(Wave::Sine));
+        assert_eq!("noise".parse(), Ok(Wave::Noise));
+        assert_eq!(
+            "saw".parse::<Wave>(),
+            Err(String::from(
+                "unknown wave 'saw', expected square, sine, triangle or noise"
+            ))
+        );
+    }
+
+    #[test]
+    fn square_period_and_amplitude() {
+        let samples = steady(Wave::Square);
+        for (i, &sample) in samples.iter().enumerate() {
+            let high = i % PERIOD < PERIOD / 2;
+            assert_eq!(sample, if high { 255 } else { 1 }, "sample {i}");
+        }
+    }
+
+    #[test]
+    fn sine_and_triangle_shapes() {
+        for wave in [Wave::Sine, Wave::Triangle] {
+            let samples = steady(wave);
+            assert_eq!(samples[..PERIOD], samples[PERIOD..2 * PERIOD], "{wave:?}");
+            assert_eq!(*samples.iter().max().unwrap(), 255, "{wave:?}");
+            assert_eq!(*samples.iter().min().unwrap(), 1, "{wave:?}");
+        }
+        // a quarter period in, at the peak; the triangle starts at its trough
+        let sine = steady(Wave::Sine);
+        assert_eq!([sine[0], sine[4], sine[8], sine[12]], [128, 255, 128, 1]);
+        let triangle = steady(Wave::Triangle);
+        assert_eq!(triangle[..9], [1, 33, 65, 96, 128, 160, 192, 223, 255]);
+    }
+
+    #[test]
+    fn noise_changes_only_every_half_period() {
+        let samples = steady(Wave::Noise);
+        assert!(samples.iter().all(|&sample| sample == 1 || sample == 255));
+        let mut changes = 0;
+        for i in 1..samples.len() {
+            if samples[i] != samples[i - 1] {
+                // the phase was at 0 or 0.5
+                assert_eq!((ATTACK_SAMPLES + i) % (PERIOD / 2), 0, "sample {i}");
+                changes += 1;
+            }
+        }
+        assert!(changes > 16, "{changes} changes");
+    }
+
+    #[test]
+    fn envelope_ramps_without_clicks() {
+        let mut synth = Synth::new(RATE, Wave::Square, 512.0);
+        assert!(synth.is_silent());
+        assert_eq!(render(&mut synth, 4), [SILENCE; 4]);
+
+        synth.set_gate(true);
+        let attack = render(&mut synth, ATTACK_SAMPLES + 1);
+        // high half of the square, climbing by about 1/16 of full scale
+        assert_eq!(attack[0], 136);
+        assert!(attack[..8].windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(attack[ATTACK_SAMPLES - 1], 4);
+        assert_eq!(attack[ATTACK_SAMPLES], 255);
+
+        synth.set_gate(false);
+        assert!(!synth.is_silent());
+        // 5ms at 8192 Hz, the 41st sample is silent
+        let release = render(&mut synth, 48);
+        let distance: Vec<i32> = release.iter().map(|&s| (s as i32 - 128).abs()).collect();
+        assert!(distance.windows(2).all(|pair| pair[1] <= pair[0]));
+        assert!(distance[0] < 127 && distance[0] > 120);
+        assert!(release[40..].iter().all(|&sample| sample == SILENCE));
+        assert!(synth.is_silent());
+    }
+
+    #[test]
+    fn silence_cuts_off_at_once() {
+        let mut synth = Synth::new(RATE, Wave::Sine, 512.0);
+        synth.set_gate(true);
+        render(&mut synth, 100);
+        synth.silence();
+        assert!(synth.is_silent());
+        assert_eq!(render(&mut synth, 8), [SILENCE; 8]);
+    }
+
+    #[test]
+    fn pattern_plays_at_its_pitch() {
+        // 64 set bits then 64 clear ones, pitch 64 is 4000 bits a second: at 8000 Hz
+        // 256 samples a period
+        let mut bits = [0; 16];
+        bits[..8].fill(0xFF);
+        let mut synth = Synth::new(8000, Wave::Sine, 512.0);
+        synth.set_pattern(Some((bits, 64)));
+        synth.set_gate(true);
+        let samples = render(&mut synth, 512);
+        assert!(
+            samples[ATTACK_SAMPLES..128]
+                .iter()
+                .all(|&sample| sample == 255)
+        );
+        assert!(samples[128..256].iter().all(|&sample| sample == 1));
+        assert_eq!(
+            samples[256 + ATTACK_SAMPLES..],
+            samples[ATTACK_SAMPLES..256]
+        );
+
+        // 48 steps of pitch is an octave up, 128 samples
+        synth.set_pattern(Some((bits, 112)));
+        let samples = render(&mut synth, 256);
+        assert!(samples[..64].iter().all(|&sample| sample == 255));
+        assert!(samples[64..128].iter().all(|&sample| sample == 1));
+        assert_eq!(samples[..128], samples[128..]);
+    }
+}
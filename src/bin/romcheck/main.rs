@@ -0,0 +1,342 @@
+#![allow(clippy::needless_return)]
+
+// Triage for a ROM collection: every ROM runs headless for the same number of cycles with
+// the same seed, and a table says how each one ended, how many opcode patterns it used and
+// whether it ever drew anything. ROMs that stop on an error are the ones still hitting a
+// path the core doesn't implement.
+//
+//   cargo run --bin romcheck -- roms/
+//   cargo run --bin romcheck -- --csv roms/ > roms.csv
+//
+// The budget is spent in frames of IPF cycles, a frame waiting on FX0A or the display
+// included, so a ROM sitting at a menu still ends. Keys are pressed on a fixed schedule to
+// get past title screens. ROMs are independent, they run on one thread per core.
+
+use rustchip8::{Chip8, Chip8Error, Profile};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::{env, fs, process, thread};
+
+const USAGE: &str = "\
+usage: romcheck [OPTIONS] ROM_OR_DIR...
+
+  --cycles N       cycles to run each ROM for (default 50000)
+  --ipf N          cycles per 60Hz frame (default 10)
+  --seed N         seed for CXNN (default 1)
+  --profile NAME   quirk preset: cosmac, chip48 or schip (default cosmac)
+  --jobs N         threads (default: one per core)
+  --csv            print CSV instead of a table";
+
+// every KEY_PERIOD frames the next key is held for KEY_HOLD of them
+const KEY_PERIOD: u64 = 60;
+const KEY_HOLD: u64 = 6;
+
+struct Options {
+    cycles: u64,
+    ipf: u32,
+    seed: u64,
+    profile: Profile,
+    jobs: usize,
+    csv: bool,
+}
+
+enum Ending {
+    // the whole budget ran
+    Limit,
+    SelfJump {
+        pc: u16,
+    },
+    Exited,
+    Error {
+        pc: u16,
+        opcode: u16,
+        error: Chip8Error,
+    },
+    // no machine ran: unreadable, empty or too big
+    NotLoaded(String),
+}
+
+struct Report {
+    name: String,
+    ending: Ending,
+    instructions: u64,
+    opcodes: usize,
+    display_changed: bool,
+}
+
+impl Ending {
+    fn kind(&self) -> &'static str {
+        match self {
+            Ending::Limit => return "limit",
+            Ending::SelfJump { .. } => return "self-jump",
+            Ending::Exited => return "exited",
+            Ending::Error { .. } => return "error",
+            Ending::NotLoaded(_) => return "not-loaded",
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Ending::Limit => return String::from("ran to cycle limit"),
+            Ending::SelfJump { pc } => return format!("halted on self-jump at {pc:#05X}"),
+            Ending::Exited => return String::from("exited with 00FD"),
+            Ending::Error { pc, opcode, error } => {
+                return format!("error at {pc:#05X} on {opcode:04X}: {error}");
+            }
+            Ending::NotLoaded(why) => return format!("not loaded: {why}"),
+        }
+    }
+}
+
+fn main() {
+    let mut options = Options {
+        cycles: 50_000,
+        ipf: 10,
+        seed: 1,
+        profile: Profile::Cosmac,
+        jobs: thread::available_parallelism().map_or(1, |n| n.get()),
+        csv: false,
+    };
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--cycles" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .map(|n| options.cycles = n)
+                .ok_or_else(|| String::from("--cycles expects a number")),
+            "--ipf" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .filter(|&n| n > 0)
+                .map(|n| options.ipf = n)
+                .ok_or_else(|| String::from("--ipf expects a number above 0")),
+            "--seed" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .map(|n| options.seed = n)
+                .ok_or_else(|| String::from("--seed expects a number")),
+            "--profile" => args
+                .next()
+                .ok_or_else(|| String::from("--profile expects a name"))
+                .and_then(|name| name.parse().map(|p| options.profile = p)),
+            "--jobs" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .filter(|&n| n > 0)
+                .map(|n| options.jobs = n)
+                .ok_or_else(|| String::from("--jobs expects a number above 0")),
+            "--csv" => {
+                options.csv = true;
+                Ok(())
+            }
+            _ if arg.starts_with("--") => Err(format!("unknown option {arg}")),
+            _ => {
+                paths.push(PathBuf::from(arg));
+                Ok(())
+            }
+        };
+        if let Err(e) = parsed {
+            eprintln!("ERROR: {e}\n{USAGE}");
+            process::exit(1);
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        process::exit(1);
+    }
+
+    let roms = collect_roms(&paths).unwrap_or_else(|e| {
+        eprintln!("ERROR: {e}");
+        process::exit(1);
+    });
+    let reports = check_all(&roms, &options);
+    if options.csv {
+        print_csv(&reports);
+    } else {
+        print_table(&reports);
+    }
+}
+
+// Workers take the next ROM off a shared counter, the reports come back in ROM order
+fn check_all(roms: &[PathBuf], options: &Options) -> Vec<Report> {
+    let next = AtomicUsize::new(0);
+    let mut reports: Vec<(usize, Report)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..options.jobs.min(roms.len()))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(path) = roms.get(i) else {
+                            return done;
+                        };
+                        done.push((i, check(path, options)));
+                    }
+                })
+            })
+            .collect();
+        return workers
+            .into_iter()
+            .flat_map(|worker| worker.join().unwrap_or_default())
+            .collect();
+    });
+    reports.sort_by_key(|(i, _)| *i);
+
+    return reports.into_iter().map(|(_, report)| report).collect();
+}
+
+fn check(path: &Path, options: &Options) -> Report {
+    let mut report = Report {
+        name: path.display().to_string(),
+        ending: Ending::Limit,
+        instructions: 0,
+        opcodes: 0,
+        display_changed: false,
+    };
+    let rom = match fs::read(path) {
+        Ok(rom) => rom,
+        Err(e) => {
+            report.ending = Ending::NotLoaded(e.to_string());
+            return report;
+        }
+    };
+    let mut chip8 = Chip8::with_seed(options.seed);
+    chip8.set_quirks(options.profile.quirks());
+    chip8.set_stats(true);
+    if let Err(e) = chip8.load_rom(&rom) {
+        report.ending = Ending::NotLoaded(e.to_string());
+        return report;
+    }
+
+    let blank = chip8.display_packed();
+    let frames = options.cycles.div_ceil(options.ipf as u64);
+    for frame in 0..frames {
+        let mut keys = [false; 16];
+        if frame % KEY_PERIOD < KEY_HOLD {
+            keys[(frame / KEY_PERIOD % 16) as usize] = true;
+        }
+        let result = chip8.run_frame(options.ipf, &keys);
+        report.instructions += result.instructions as u64;
+        if result.display_changed && !report.display_changed {
+            report.display_changed = chip8.display_packed() != blank;
+        }
+        if let Some((pc, error)) = result.halted {
+            let mem = chip8.mem();
+            let opcode = u16::from_be_bytes([
+                mem[pc as usize % mem.len()],
+                mem[(pc as usize + 1) % mem.len()],
+            ]);
+            report.ending = Ending::Error { pc, opcode, error };
+            break;
+        }
+        if result.exited {
+            report.ending = Ending::Exited;
+            break;
+        }
+        if chip8.is_self_jump() {
+            report.ending = Ending::SelfJump { pc: chip8.pc() };
+            break;
+        }
+    }
+    report.opcodes = chip8.stats().map_or(0, |stats| stats.families().len());
+
+    return report;
+}
+
+fn print_table(reports: &[Report]) {
+    let width = reports
+        .iter()
+        .map(|report| report.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    println!(
+        "{:<width$}  {:>12}  {:>7}  {:<7}  RESULT",
+        "ROM", "INSTRUCTIONS", "OPCODES", "DISPLAY"
+    );
+    for report in reports {
+        println!(
+            "{:<width$}  {:>12}  {:>7}  {:<7}  {}",
+            report.name,
+            report.instructions,
+            report.opcodes,
+            if report.display_changed { "yes" } else { "no" },
+            report.ending.describe()
+        );
+    }
+
+    let count = |kind: &str| {
+        return reports
+            .iter()
+            .filter(|report| report.ending.kind() == kind)
+            .count();
+    };
+    println!(
+        "{} ROMs: {} ran to the limit, {} self-jump, {} exited, {} error, {} not loaded",
+        reports.len(),
+        count("limit"),
+        count("self-jump"),
+        count("exited"),
+        count("error"),
+        count("not-loaded")
+    );
+}
+
+fn print_csv(reports: &[Report]) {
+    println!("rom,result,pc,opcode,instructions,unique_opcodes,display_changed,detail");
+    for report in reports {
+        let (pc, opcode) = match &report.ending {
+            Ending::Error { pc, opcode, .. } => (format!("{pc:#05X}"), format!("{opcode:04X}")),
+            Ending::SelfJump { pc } => (format!("{pc:#05X}"), String::new()),
+            _ => (String::new(), String::new()),
+        };
+        println!(
+            "{},{},{pc},{opcode},{},{},{},{}",
+            csv_field(&report.name),
+            report.ending.kind(),
+            report.instructions,
+            report.opcodes,
+            report.display_changed,
+            csv_field(&report.ending.describe())
+        );
+    }
+}
+
+// Quoted when it has to be, quotes doubled
+fn csv_field(text: &str) -> String {
+    if !text.contains([',', '"', '\n']) {
+        return text.to_string();
+    }
+
+    return format!("\"{}\"", text.replace('"', "\"\""));
+}
+
+// Directories are searched one level deep for .ch8 and .c8 files, in name order
+fn collect_roms(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut roms = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            roms.push(path.clone());
+            continue;
+        }
+        let entries =
+            fs::read_dir(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| has_rom_extension(path))
+            .collect();
+        found.sort();
+        roms.extend(found);
+    }
+
+    return Ok(roms);
+}
+
+fn has_rom_extension(path: &Path) -> bool {
+    return path.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy().to_ascii_lowercase();
+        ext == "ch8" || ext == "c8"
+    });
+}
@@ -0,0 +1,318 @@
+#![allow(clippy::needless_return)]
+
+// Differential testing: every ROM runs in the core and in the independent interpreter in
+// reference.rs, in lockstep, and after each instruction the registers, pc, I, stack,
+// timers, memory and display have to agree. Golden displays only check how a run ends,
+// this catches the flag write that is in the wrong order on the way there.
+//
+//   cargo run --features diff-test --bin rustchip8-diff -- roms/
+//
+// Keys are pressed on a fixed schedule and timers tick every IPF instructions, the same
+// for both. CXNN is checked against its mask and the core's byte copied over. A ROM stops
+// at its first SUPER-CHIP or XO-CHIP instruction, the reference only knows CHIP-8.
+
+mod reference;
+
+use reference::{Fault, Machine};
+use rustchip8::{Chip8, MAX_ROM_SIZE, PROGRAM_START, Profile};
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+const USAGE: &str = "\
+usage: rustchip8-diff [OPTIONS] ROM_OR_DIR...
+
+  --profile NAME   quirk preset for both: cosmac, chip48 or schip (default cosmac)
+  --steps N        instructions to compare per ROM (default 200000)";
+
+const IPF: u64 = 10;
+// every KEY_PERIOD instructions the next key is held for KEY_HOLD of them
+const KEY_PERIOD: u64 = 3000;
+const KEY_HOLD: u64 = 300;
+
+enum Outcome {
+    // ran the whole budget, or both stopped the same way
+    Agreed(String),
+    Diverged(String),
+}
+
+fn main() {
+    let mut profile = Profile::Cosmac;
+    let mut steps = 200_000;
+    let mut paths = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let parsed = match arg.as_str() {
+            "--profile" => args
+                .next()
+                .ok_or_else(|| String::from("--profile expects a name"))
+                .and_then(|name| name.parse().map(|p| profile = p)),
+            "--steps" => args
+                .next()
+                .and_then(|n| n.parse().ok())
+                .map(|n| steps = n)
+                .ok_or_else(|| String::from("--steps expects a number")),
+            _ if arg.starts_with("--") => Err(format!("unknown option {arg}")),
+            _ => {
+                paths.push(PathBuf::from(arg));
+                Ok(())
+            }
+        };
+        if let Err(e) = parsed {
+            eprintln!("ERROR: {e}\n{USAGE}");
+            process::exit(1);
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("{USAGE}");
+        process::exit(1);
+    }
+
+    let roms = collect_roms(&paths).unwrap_or_else(|e| {
+        eprintln!("ERROR: {e}");
+        process::exit(1);
+    });
+    let mut diverged = 0;
+    for path in &roms {
+        let outcome = fs::read(path)
+            .map_err(|e| e.to_string())
+            .map(|rom| compare(&rom, profile, steps));
+        match outcome {
+            Ok(Outcome::Agreed(how)) => println!("ok    {}: {how}", path.display()),
+            Ok(Outcome::Diverged(report)) => {
+                diverged += 1;
+                println!("FAIL  {}:\n{report}", path.display());
+            }
+            Err(e) => println!("skip  {}: {e}", path.display()),
+        }
+    }
+    println!("{} ROMs, {diverged} diverged", roms.len());
+    if diverged > 0 {
+        process::exit(1);
+    }
+}
+
+// Directories are searched one level deep for .ch8 and .c8 files, in name order
+fn collect_roms(paths: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut roms = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            roms.push(path.clone());
+            continue;
+        }
+        let entries =
+            fs::read_dir(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        let mut found: Vec<PathBuf> = entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| has_rom_extension(path))
+            .collect();
+        found.sort();
+        roms.extend(found);
+    }
+
+    return Ok(roms);
+}
+
+fn has_rom_extension(path: &Path) -> bool {
+    return path.extension().is_some_and(|ext| {
+        let ext = ext.to_string_lossy().to_ascii_lowercase();
+        ext == "ch8" || ext == "c8"
+    });
+}
+
+fn compare(rom: &[u8], profile: Profile, steps: u64) -> Outcome {
+    if rom.is_empty() || rom.len() > MAX_ROM_SIZE {
+        return Outcome::Agreed(format!("skipped, {} bytes isn't a CHIP-8 ROM", rom.len()));
+    }
+    let mut core = Chip8::with_seed(1);
+    let quirks = profile.quirks();
+    core.set_quirks(quirks);
+    if let Err(e) = core.load_rom(rom) {
+        return Outcome::Agreed(format!("skipped, {e}"));
+    }
+    let mut mem = [0; reference::MEM_SIZE];
+    mem.copy_from_slice(core.mem());
+    let mut reference = Machine::new(
+        mem,
+        PROGRAM_START as u16,
+        reference::Quirks {
+            shift_uses_vy: quirks.shift_uses_vy,
+            memory_increment: quirks.memory_increment,
+            jump_uses_vx: quirks.jump_uses_vx,
+            sprite_wrap: quirks.sprite_wrap,
+            vf_reset: quirks.vf_reset,
+            index_overflow: quirks.index_overflow,
+        },
+    );
+
+    for step in 0..steps {
+        let key = (step / KEY_PERIOD % 16) as usize;
+        let down = step % KEY_PERIOD < KEY_HOLD;
+        if reference.keys[key] != down {
+            core.set_key(key as u8, down);
+            reference.set_key(key, down);
+        }
+        // a display_wait DXYN holds the core until the next vertical blank
+        if step % IPF == 0 || core.is_waiting_for_vblank() {
+            core.tick_60hz();
+            reference.tick();
+        }
+        if core.is_waiting_for_key() || reference.waiting.is_some() {
+            if core.is_waiting_for_key() != reference.waiting.is_some() {
+                return Outcome::Diverged(report(&core, &reference, step, None, "FX0A waiting"));
+            }
+            continue;
+        }
+
+        let pc = core.pc();
+        let opcode = core.peek_opcode();
+        let expected = reference.step();
+        let actual = core.step();
+        match (&expected, &actual) {
+            (Err(Fault::Unsupported(word)), _) => {
+                return Outcome::Agreed(format!(
+                    "{step} instructions, stopped at {word:04X} at {pc:#05X}, not CHIP-8"
+                ));
+            }
+            (Err(fault), Err(e)) => {
+                return Outcome::Agreed(format!(
+                    "{step} instructions, both stopped at {pc:#05X}: {e} / {fault:?}"
+                ));
+            }
+            (Err(fault), Ok(_)) => {
+                let why = format!("only the reference stopped: {fault:?}");
+                return Outcome::Diverged(report(&core, &reference, step, opcode, &why));
+            }
+            (Ok(_), Err(e)) => {
+                let why = format!("only the core stopped: {e}");
+                return Outcome::Diverged(report(&core, &reference, step, opcode, &why));
+            }
+            (Ok(_), Ok(_)) => {}
+        }
+        if let Some(word) = opcode
+            && word & 0xF000 == 0xC000
+        {
+            let x = (word >> 8 & 0xF) as usize;
+            let random = core.registers()[x];
+            if random & !(word as u8) != 0 {
+                let why = format!("CXNN result {random:#04X} has bits outside the mask");
+                return Outcome::Diverged(report(&core, &reference, step, opcode, &why));
+            }
+            reference.v[x] = random;
+        }
+        let differences = differences(&core, &reference);
+        if !differences.is_empty() {
+            let why = format!("{} differ", differences.join(", "));
+            return Outcome::Diverged(report(&core, &reference, step, opcode, &why));
+        }
+    }
+
+    return Outcome::Agreed(format!("{steps} instructions"));
+}
+
+fn differences(core: &Chip8, reference: &Machine) -> Vec<&'static str> {
+    let fields = [
+        ("pc", core.pc() == reference.pc),
+        ("I", core.reg_i() == reference.i),
+        ("V", *core.registers() == reference.v),
+        ("stack", core.stack() == reference.stack.as_slice()),
+        ("delay timer", core.delay_timer() == reference.delay),
+        ("sound timer", core.sound_timer() == reference.sound),
+        ("memory", core.mem() == reference.mem.as_slice()),
+        (
+            "display",
+            core.display_packed() == reference.packed_screen(),
+        ),
+    ];
+    return fields
+        .iter()
+        .filter(|(_, same)| !same)
+        .map(|(name, _)| *name)
+        .collect();
+}
+
+fn report(core: &Chip8, reference: &Machine, step: u64, opcode: Option<u16>, why: &str) -> String {
+    let opcode = opcode.map_or(String::from("????"), |word| format!("{word:04X}"));
+    let mut out = format!("  after instruction {step} ({opcode}): {why}\n");
+    let registers = |v: &[u8; 16]| {
+        return v
+            .iter()
+            .map(|value| format!("{value:02X}"))
+            .collect::<Vec<_>>()
+            .join(" ");
+    };
+    out += &format!(
+        "  core       pc={:#05X} I={:#05X} V=[{}] stack={:X?} DT={} ST={} display={:016X}\n",
+        core.pc(),
+        core.reg_i(),
+        registers(core.registers()),
+        core.stack(),
+        core.delay_timer(),
+        core.sound_timer(),
+        fnv1a(&core.display_packed())
+    );
+    out += &format!(
+        "  reference  pc={:#05X} I={:#05X} V=[{}] stack={:X?} DT={} ST={} display={:016X}",
+        reference.pc,
+        reference.i,
+        registers(&reference.v),
+        reference.stack,
+        reference.delay,
+        reference.sound,
+        fnv1a(&reference.packed_screen())
+    );
+    if let Some(addr) = (0..reference::MEM_SIZE).find(|&a| core.mem()[a] != reference.mem[a]) {
+        out += &format!(
+            "\n  memory first differs at {addr:#05X}: core {:02X}, reference {:02X}",
+            core.mem()[addr],
+            reference.mem[addr]
+        );
+    }
+
+    return out;
+}
+
+// Short enough to eyeball whether two displays are the same
+fn fnv1a(bytes: &[u8]) -> u64 {
+    return bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every profile over a directory of ROMs, test_roms unless DIFF_CORPUS names another:
+    //
+    //   DIFF_CORPUS=roms/ cargo test --features diff-test -- --ignored diff_corpus
+    fn diff(dir: PathBuf, steps: u64) {
+        let roms = collect_roms(std::slice::from_ref(&dir)).unwrap();
+        assert!(!roms.is_empty(), "no ROMs in {}", dir.display());
+        let mut failures = Vec::new();
+        for profile in [Profile::Cosmac, Profile::Chip48, Profile::Schip] {
+            for path in &roms {
+                let rom = fs::read(path).unwrap();
+                if let Outcome::Diverged(report) = compare(&rom, profile, steps) {
+                    failures.push(format!("{} with {profile:?}:\n{report}", path.display()));
+                }
+            }
+        }
+        assert!(failures.is_empty(), "{}", failures.join("\n"));
+    }
+
+    fn test_roms() -> PathBuf {
+        return Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms");
+    }
+
+    #[test]
+    #[ignore = "slow, runs a whole corpus"]
+    fn diff_corpus() {
+        let dir = env::var_os("DIFF_CORPUS").map_or_else(test_roms, PathBuf::from);
+        diff(dir, 200_000);
+    }
+
+    #[test]
+    fn test_roms_agree() {
+        diff(test_roms(), 5_000);
+    }
+}
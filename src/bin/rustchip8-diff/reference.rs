@@ -0,0 +1,451 @@
+// A second CHIP-8 interpreter written from the spec (Cowgod's reference and the VIP
+// manual for the quirks), on purpose without any code from the core: plain arrays, one
+// table entry per opcode pattern and the flag writes spelled out in the order the spec
+// gives them. Slow and CHIP-8 only, which is all it needs to be. The harness in main.rs
+// gives it the same memory image to start from.
+
+pub const MEM_SIZE: usize = 4096;
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub shift_uses_vy: bool,
+    pub memory_increment: bool,
+    pub jump_uses_vx: bool,
+    pub sprite_wrap: bool,
+    pub vf_reset: bool,
+    pub index_overflow: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    // not a CHIP-8 instruction, the first SUPER-CHIP/XO-CHIP one ends the comparison
+    Unsupported(u16),
+    EmptyStack,
+    StackOverflow,
+    MachineCode(u16),
+}
+
+pub struct Machine {
+    pub mem: [u8; MEM_SIZE],
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay: u8,
+    pub sound: u8,
+    pub screen: [[bool; WIDTH]; HEIGHT],
+    pub keys: [bool; 16],
+    // FX0A: the register to fill, and the key pressed since it started waiting
+    pub waiting: Option<(usize, Option<u8>)>,
+    pub quirks: Quirks,
+}
+
+type Handler = fn(&mut Machine, Op) -> Result<(), Fault>;
+
+// The fields of one opcode, decoded up front for every handler
+#[derive(Clone, Copy)]
+struct Op {
+    word: u16,
+    x: usize,
+    y: usize,
+    n: u8,
+    nn: u8,
+    nnn: u16,
+}
+
+// (mask, value, handler), the first entry where word & mask == value wins
+const TABLE: [(u16, u16, Handler); 36] = [
+    (0xFFFF, 0x00E0, cls),
+    (0xFFFF, 0x00EE, ret),
+    (0xF000, 0x0000, sys),
+    (0xF000, 0x1000, jp),
+    (0xF000, 0x2000, call),
+    (0xF000, 0x3000, se_imm),
+    (0xF000, 0x4000, sne_imm),
+    (0xF00F, 0x5000, se_reg),
+    (0xF000, 0x6000, ld_imm),
+    (0xF000, 0x7000, add_imm),
+    (0xF00F, 0x8000, ld_reg),
+    (0xF00F, 0x8001, or),
+    (0xF00F, 0x8002, and),
+    (0xF00F, 0x8003, xor),
+    (0xF00F, 0x8004, add_reg),
+    (0xF00F, 0x8005, sub),
+    (0xF00F, 0x8006, shr),
+    (0xF00F, 0x8007, subn),
+    (0xF00F, 0x800E, shl),
+    (0xF00F, 0x9000, sne_reg),
+    (0xF000, 0xA000, ld_i),
+    (0xF000, 0xB000, jp_offset),
+    (0xF000, 0xC000, rnd),
+    (0xF00F, 0xD000, big_sprite),
+    (0xF000, 0xD000, drw),
+    (0xF0FF, 0xE09E, skp),
+    (0xF0FF, 0xE0A1, sknp),
+    (0xF0FF, 0xF007, ld_from_delay),
+    (0xF0FF, 0xF00A, wait_key),
+    (0xF0FF, 0xF015, ld_delay),
+    (0xF0FF, 0xF018, ld_sound),
+    (0xF0FF, 0xF01E, add_i),
+    (0xF0FF, 0xF029, font),
+    (0xF0FF, 0xF033, bcd),
+    (0xF0FF, 0xF055, store),
+    (0xF0FF, 0xF065, load),
+];
+
+impl Machine {
+    pub fn new(mem: [u8; MEM_SIZE], start: u16, quirks: Quirks) -> Self {
+        return Machine {
+            mem,
+            v: [0; 16],
+            i: 0,
+            pc: start,
+            stack: Vec::new(),
+            delay: 0,
+            sound: 0,
+            screen: [[false; WIDTH]; HEIGHT],
+            keys: [false; 16],
+            waiting: None,
+            quirks,
+        };
+    }
+
+    // One instruction, the opcode it ran
+    pub fn step(&mut self) -> Result<u16, Fault> {
+        let hi = self.mem[self.pc as usize % MEM_SIZE];
+        let lo = self.mem[(self.pc as usize + 1) % MEM_SIZE];
+        let word = (hi as u16) << 8 | lo as u16;
+        self.pc = (self.pc + 2) % MEM_SIZE as u16;
+
+        let op = Op {
+            word,
+            x: (word >> 8 & 0xF) as usize,
+            y: (word >> 4 & 0xF) as usize,
+            n: (word & 0xF) as u8,
+            nn: (word & 0xFF) as u8,
+            nnn: word & 0xFFF,
+        };
+        let handler = TABLE
+            .iter()
+            .find(|(mask, value, _)| word & mask == *value)
+            .map(|(_, _, handler)| *handler)
+            .ok_or(Fault::Unsupported(word))?;
+        handler(self, op)?;
+
+        return Ok(word);
+    }
+
+    pub fn tick(&mut self) {
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+    }
+
+    // FX0A takes the first key pressed while it waits, once that key is released
+    pub fn set_key(&mut self, key: usize, down: bool) {
+        let was_down = self.keys[key];
+        self.keys[key] = down;
+        match self.waiting {
+            Some((x, None)) if down && !was_down => self.waiting = Some((x, Some(key as u8))),
+            Some((x, Some(pressed))) if !down && was_down && pressed as usize == key => {
+                self.v[x] = pressed;
+                self.waiting = None;
+                self.pc = (self.pc + 2) % MEM_SIZE as u16;
+            }
+            _ => {}
+        }
+    }
+
+    // 1bpp, rows top to bottom, leftmost pixel in the high bit
+    pub fn packed_screen(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(WIDTH * HEIGHT / 8);
+        for row in &self.screen {
+            for chunk in row.chunks(8) {
+                let mut byte = 0;
+                for (bit, &lit) in chunk.iter().enumerate() {
+                    if lit {
+                        byte |= 0x80 >> bit;
+                    }
+                }
+                out.push(byte);
+            }
+        }
+
+        return out;
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.pc = (self.pc + 2) % MEM_SIZE as u16;
+        }
+    }
+
+    // 8XY1/2/3: the VIP's logic routines leave VF cleared
+    fn logic(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+    }
+}
+
+fn cls(m: &mut Machine, _: Op) -> Result<(), Fault> {
+    m.screen = [[false; WIDTH]; HEIGHT];
+    return Ok(());
+}
+
+fn ret(m: &mut Machine, _: Op) -> Result<(), Fault> {
+    m.pc = m.stack.pop().ok_or(Fault::EmptyStack)?;
+    return Ok(());
+}
+
+fn sys(_: &mut Machine, op: Op) -> Result<(), Fault> {
+    return Err(Fault::MachineCode(op.nnn));
+}
+
+fn jp(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.pc = op.nnn;
+    return Ok(());
+}
+
+fn call(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    // 16 levels like the VIP's stack area
+    if m.stack.len() == 16 {
+        return Err(Fault::StackOverflow);
+    }
+    m.stack.push(m.pc);
+    m.pc = op.nnn;
+    return Ok(());
+}
+
+fn se_imm(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.skip_if(m.v[op.x] == op.nn);
+    return Ok(());
+}
+
+fn sne_imm(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.skip_if(m.v[op.x] != op.nn);
+    return Ok(());
+}
+
+fn se_reg(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.skip_if(m.v[op.x] == m.v[op.y]);
+    return Ok(());
+}
+
+fn sne_reg(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.skip_if(m.v[op.x] != m.v[op.y]);
+    return Ok(());
+}
+
+fn ld_imm(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.v[op.x] = op.nn;
+    return Ok(());
+}
+
+fn add_imm(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.v[op.x] = m.v[op.x].wrapping_add(op.nn);
+    return Ok(());
+}
+
+fn ld_reg(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.v[op.x] = m.v[op.y];
+    return Ok(());
+}
+
+fn or(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.logic(op.x, m.v[op.x] | m.v[op.y]);
+    return Ok(());
+}
+
+fn and(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.logic(op.x, m.v[op.x] & m.v[op.y]);
+    return Ok(());
+}
+
+fn xor(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.logic(op.x, m.v[op.x] ^ m.v[op.y]);
+    return Ok(());
+}
+
+// The arithmetic ones compute the flag from the inputs, write the result, then VF: with
+// X = F the flag is what's left in VF
+
+fn add_reg(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let sum = m.v[op.x] as u16 + m.v[op.y] as u16;
+    m.v[op.x] = sum as u8;
+    m.v[0xF] = (sum > 0xFF) as u8;
+    return Ok(());
+}
+
+fn sub(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let (a, b) = (m.v[op.x], m.v[op.y]);
+    m.v[op.x] = a.wrapping_sub(b);
+    m.v[0xF] = (a >= b) as u8;
+    return Ok(());
+}
+
+fn subn(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let (a, b) = (m.v[op.x], m.v[op.y]);
+    m.v[op.x] = b.wrapping_sub(a);
+    m.v[0xF] = (b >= a) as u8;
+    return Ok(());
+}
+
+fn shr(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let source = if m.quirks.shift_uses_vy {
+        m.v[op.y]
+    } else {
+        m.v[op.x]
+    };
+    m.v[op.x] = source >> 1;
+    m.v[0xF] = source & 1;
+    return Ok(());
+}
+
+fn shl(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let source = if m.quirks.shift_uses_vy {
+        m.v[op.y]
+    } else {
+        m.v[op.x]
+    };
+    m.v[op.x] = source << 1;
+    m.v[0xF] = source >> 7;
+    return Ok(());
+}
+
+fn ld_i(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.i = op.nnn;
+    return Ok(());
+}
+
+fn jp_offset(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let offset = if m.quirks.jump_uses_vx {
+        m.v[op.x]
+    } else {
+        m.v[0]
+    };
+    m.pc = (op.nnn + offset as u16) % MEM_SIZE as u16;
+    return Ok(());
+}
+
+// The harness replaces VX with the core's random byte afterwards, after checking it
+// against the mask
+fn rnd(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.v[op.x] = 0;
+    return Ok(());
+}
+
+// DXY0 draws nothing on the VIP and a 16x16 sprite in SUPER-CHIP, neither is compared
+fn big_sprite(_: &mut Machine, op: Op) -> Result<(), Fault> {
+    return Err(Fault::Unsupported(op.word));
+}
+
+fn drw(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let x0 = m.v[op.x] as usize % WIDTH;
+    let y0 = m.v[op.y] as usize % HEIGHT;
+    let mut collision = false;
+    for row in 0..op.n as usize {
+        let mut y = y0 + row;
+        if y >= HEIGHT {
+            if !m.quirks.sprite_wrap {
+                break;
+            }
+            y %= HEIGHT;
+        }
+        let bits = m.mem[(m.i as usize + row) % MEM_SIZE];
+        for col in 0..8 {
+            let mut x = x0 + col;
+            if x >= WIDTH {
+                if !m.quirks.sprite_wrap {
+                    break;
+                }
+                x %= WIDTH;
+            }
+            if bits & (0x80 >> col) != 0 {
+                collision |= m.screen[y][x];
+                m.screen[y][x] = !m.screen[y][x];
+            }
+        }
+    }
+    m.v[0xF] = collision as u8;
+    return Ok(());
+}
+
+fn skp(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.skip_if(m.keys[m.v[op.x] as usize & 0xF]);
+    return Ok(());
+}
+
+fn sknp(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.skip_if(!m.keys[m.v[op.x] as usize & 0xF]);
+    return Ok(());
+}
+
+fn ld_from_delay(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.v[op.x] = m.delay;
+    return Ok(());
+}
+
+// Parks on itself, set_key() moves on
+fn wait_key(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.waiting = Some((op.x, None));
+    m.pc = (m.pc + MEM_SIZE as u16 - 2) % MEM_SIZE as u16;
+    return Ok(());
+}
+
+fn ld_delay(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.delay = m.v[op.x];
+    return Ok(());
+}
+
+fn ld_sound(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.sound = m.v[op.x];
+    return Ok(());
+}
+
+fn add_i(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let sum = m.i + m.v[op.x] as u16;
+    if m.quirks.index_overflow {
+        m.v[0xF] = (sum > 0xFFF) as u8;
+    }
+    // I holds a 12-bit address, there is nothing past 0xFFF to point at
+    m.i = sum & 0xFFF;
+    return Ok(());
+}
+
+// The small font starts at 0x050 in both, five bytes a digit
+fn font(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    m.i = 0x050 + 5 * (m.v[op.x] & 0xF) as u16;
+    return Ok(());
+}
+
+fn bcd(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    let value = m.v[op.x];
+    let digits = [value / 100, value / 10 % 10, value % 10];
+    for (offset, digit) in digits.into_iter().enumerate() {
+        m.mem[(m.i as usize + offset) % MEM_SIZE] = digit;
+    }
+    return Ok(());
+}
+
+fn store(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    for r in 0..=op.x {
+        m.mem[(m.i as usize + r) % MEM_SIZE] = m.v[r];
+    }
+    if m.quirks.memory_increment {
+        m.i = (m.i + op.x as u16 + 1) & 0xFFF;
+    }
+    return Ok(());
+}
+
+fn load(m: &mut Machine, op: Op) -> Result<(), Fault> {
+    for r in 0..=op.x {
+        m.v[r] = m.mem[(m.i as usize + r) % MEM_SIZE];
+    }
+    if m.quirks.memory_increment {
+        m.i = (m.i + op.x as u16 + 1) & 0xFFF;
+    }
+    return Ok(());
+}
@@ -0,0 +1,145 @@
+// The host keys of rustchip8::keymap as SDL scancodes, so --layout, --key and --keymap files
+// read as they do in the raylib window. Scancodes are physical keys, the letters name where
+// a key sits on a US keyboard.
+
+use rustchip8::keymap::{self, KeyNames};
+use sdl2::keyboard::Scancode;
+
+pub type Keymap = [Scancode; 16];
+
+// raylib's names, the same list as the window's
+const KEY_NAMES: &[(&str, Scancode)] = &[
+    ("APOSTROPHE", Scancode::Apostrophe),
+    ("COMMA", Scancode::Comma),
+    ("MINUS", Scancode::Minus),
+    ("PERIOD", Scancode::Period),
+    ("SLASH", Scancode::Slash),
+    ("ZERO", Scancode::Num0),
+    ("ONE", Scancode::Num1),
+    ("TWO", Scancode::Num2),
+    ("THREE", Scancode::Num3),
+    ("FOUR", Scancode::Num4),
+    ("FIVE", Scancode::Num5),
+    ("SIX", Scancode::Num6),
+    ("SEVEN", Scancode::Num7),
+    ("EIGHT", Scancode::Num8),
+    ("NINE", Scancode::Num9),
+    ("SEMICOLON", Scancode::Semicolon),
+    ("EQUAL", Scancode::Equals),
+    ("A", Scancode::A),
+    ("B", Scancode::B),
+    ("C", Scancode::C),
+    ("D", Scancode::D),
+    ("E", Scancode::E),
+    ("F", Scancode::F),
+    ("G", Scancode::G),
+    ("H", Scancode::H),
+    ("I", Scancode::I),
+    ("J", Scancode::J),
+    ("K", Scancode::K),
+    ("L", Scancode::L),
+    ("M", Scancode::M),
+    ("N", Scancode::N),
+    ("O", Scancode::O),
+    ("P", Scancode::P),
+    ("Q", Scancode::Q),
+    ("R", Scancode::R),
+    ("S", Scancode::S),
+    ("T", Scancode::T),
+    ("U", Scancode::U),
+    ("V", Scancode::V),
+    ("W", Scancode::W),
+    ("X", Scancode::X),
+    ("Y", Scancode::Y),
+    ("Z", Scancode::Z),
+    ("SPACE", Scancode::Space),
+    ("ESCAPE", Scancode::Escape),
+    ("ENTER", Scancode::Return),
+    ("TAB", Scancode::Tab),
+    ("BACKSPACE", Scancode::Backspace),
+    ("INSERT", Scancode::Insert),
+    ("DELETE", Scancode::Delete),
+    ("RIGHT", Scancode::Right),
+    ("LEFT", Scancode::Left),
+    ("DOWN", Scancode::Down),
+    ("UP", Scancode::Up),
+    ("PAGE_UP", Scancode::PageUp),
+    ("PAGE_DOWN", Scancode::PageDown),
+    ("HOME", Scancode::Home),
+    ("END", Scancode::End),
+    ("CAPS_LOCK", Scancode::CapsLock),
+    ("SCROLL_LOCK", Scancode::ScrollLock),
+    ("NUM_LOCK", Scancode::NumLockClear),
+    ("PRINT_SCREEN", Scancode::PrintScreen),
+    ("PAUSE", Scancode::Pause),
+    ("F1", Scancode::F1),
+    ("F2", Scancode::F2),
+    ("F3", Scancode::F3),
+    ("F4", Scancode::F4),
+    ("F5", Scancode::F5),
+    ("F6", Scancode::F6),
+    ("F7", Scancode::F7),
+    ("F8", Scancode::F8),
+    ("F9", Scancode::F9),
+    ("F10", Scancode::F10),
+    ("F11", Scancode::F11),
+    ("F12", Scancode::F12),
+    ("LEFT_SHIFT", Scancode::LShift),
+    ("LEFT_CONTROL", Scancode::LCtrl),
+    ("LEFT_ALT", Scancode::LAlt),
+    ("LEFT_SUPER", Scancode::LGui),
+    ("RIGHT_SHIFT", Scancode::RShift),
+    ("RIGHT_CONTROL", Scancode::RCtrl),
+    ("RIGHT_ALT", Scancode::RAlt),
+    ("RIGHT_SUPER", Scancode::RGui),
+    ("KB_MENU", Scancode::Application),
+    ("LEFT_BRACKET", Scancode::LeftBracket),
+    ("BACKSLASH", Scancode::Backslash),
+    ("RIGHT_BRACKET", Scancode::RightBracket),
+    ("GRAVE", Scancode::Grave),
+    ("KP_0", Scancode::Kp0),
+    ("KP_1", Scancode::Kp1),
+    ("KP_2", Scancode::Kp2),
+    ("KP_3", Scancode::Kp3),
+    ("KP_4", Scancode::Kp4),
+    ("KP_5", Scancode::Kp5),
+    ("KP_6", Scancode::Kp6),
+    ("KP_7", Scancode::Kp7),
+    ("KP_8", Scancode::Kp8),
+    ("KP_9", Scancode::Kp9),
+    ("KP_DECIMAL", Scancode::KpPeriod),
+    ("KP_DIVIDE", Scancode::KpDivide),
+    ("KP_MULTIPLY", Scancode::KpMultiply),
+    ("KP_SUBTRACT", Scancode::KpMinus),
+    ("KP_ADD", Scancode::KpPlus),
+    ("KP_ENTER", Scancode::KpEnter),
+    ("KP_EQUAL", Scancode::KpEquals),
+];
+
+pub fn from_names(names: &KeyNames) -> Keymap {
+    return names.map(|name| parse_key(name).expect("the layouts use raylib's names"));
+}
+
+pub fn parse_key(name: &str) -> Result<Scancode, String> {
+    let bare = keymap::normalize(name);
+    return KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| *key_name == bare)
+        .map(|(_, key)| *key)
+        .ok_or_else(|| format!("unknown key '{name}'"));
+}
+
+pub fn key_name(key: Scancode) -> &'static str {
+    return KEY_NAMES
+        .iter()
+        .find(|(_, k)| *k == key)
+        .map_or("?", |(name, _)| name);
+}
+
+pub fn parse_layout(name: &str) -> Result<Keymap, String> {
+    return keymap::parse_layout(name).map(|names| from_names(&names));
+}
+
+pub fn parse(text: &str) -> Result<Keymap, String> {
+    return keymap::parse(text, parse_key, |key| key_name(key).to_string());
+}
@@ -0,0 +1,417 @@
+#![allow(clippy::needless_return)]
+
+// The emulator in an SDL2 window, for machines where raylib's cmake build fails. Like
+// rustchip8-tui it plays games through host::FrameLoop: the display scaled up from an SDL
+// texture, the keypad layouts, keymap files and palettes of the raylib window and its beep,
+// queued with SDL_QueueAudio. The debugging tools stay with the raylib window. Build it on its own so
+// raylib isn't built too:
+//
+//   cargo run --no-default-features --features sdl --bin rustchip8-sdl -- ROM
+
+mod keys;
+
+use keys::Keymap;
+use rustchip8::audio::{Synth, Wave};
+use rustchip8::host::{FrameInput, FrameLoop, Frontend, Status};
+use rustchip8::palette::{PALETTES, parse_color, parse_palette};
+use rustchip8::screenshot::Rgb;
+use rustchip8::{Chip8, HIRES_HEIGHT, HIRES_WIDTH, Profile, SCREEN_HEIGHT, SCREEN_WIDTH};
+use rustchip8::{disassemble, is_vip_hires_rom};
+use sdl2::EventPump;
+use sdl2::audio::{AudioQueue, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::Scancode;
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::Window;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use std::{env, fs, process, thread};
+
+const USAGE: &str = "\
+usage: rustchip8-sdl [OPTIONS] ROM
+
+  --ipf N                 instructions per 60Hz frame (default 12)
+  --profile NAME          quirk preset: cosmac, chip48 or schip
+  --scale N               window pixels per CHIP-8 pixel at 64x32 (default 16)
+  --keymap F              keypad mapping file, lines of '<0-F> = KEY_NAME'
+  --layout NAME           built-in keypad mapping: qwerty, azerty or natural
+  --key K=NAME            map CHIP-8 key K to host key NAME, on top of the layout
+  --palette NAME|C,C,..   classic, amber, paperwhite, gameboy or up to 4 RRGGBB colors
+  --bg RRGGBB             background color
+  --fg RRGGBB             foreground color
+
+keys: 1234/QWER/ASDF/ZXCV keypad (see --layout), Space pause, N step while paused, Esc quit";
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const DEFAULT_IPF: u32 = 12;
+const DEFAULT_SCALE: u32 = 16;
+const SAMPLE_RATE: u32 = 44100;
+const BEEP_FREQ: f32 = 440.0;
+// about two frames of sound queued ahead of the device
+const QUEUED_SAMPLES: u32 = SAMPLE_RATE / 30;
+
+#[derive(Debug)]
+struct Options {
+    rom_path: String,
+    ipf: u32,
+    profile: Profile,
+    scale: u32,
+    // --layout and --key, replaced by the --keymap file when one is given
+    keymap: Keymap,
+    keymap_file: Option<String>,
+    // background, plane 1, plane 2, both planes
+    palette: [Rgb; 4],
+}
+
+fn number(args: &mut std::slice::Iter<String>, flag: &str) -> Result<u32, String> {
+    let value = args.next().ok_or(format!("{flag} expects a value"))?;
+    return value
+        .parse()
+        .map_err(|_| format!("{flag} expects a number, got '{value}'"));
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut ipf = DEFAULT_IPF;
+    let mut profile = Profile::Cosmac;
+    let mut scale = DEFAULT_SCALE;
+    let mut keymap = keys::from_names(&rustchip8::keymap::QWERTY);
+    let mut keymap_file = None;
+    let mut palette = PALETTES[0].1;
+    // --bg/--fg win over --palette, wherever they are
+    let mut background = None;
+    let mut foreground = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ipf" => ipf = number(&mut args, "--ipf")?,
+            "--scale" => scale = number(&mut args, "--scale")?,
+            "--profile" => profile = args.next().ok_or("--profile expects a value")?.parse()?,
+            "--keymap" => {
+                let path = args.next().ok_or("--keymap expects a path")?;
+                keymap_file = Some(path.to_string());
+            }
+            "--layout" => {
+                keymap = keys::parse_layout(args.next().ok_or("--layout expects a name")?)?;
+            }
+            "--key" => {
+                let value = args.next().ok_or("--key expects K=NAME")?;
+                let (key, name) = value
+                    .split_once('=')
+                    .ok_or_else(|| format!("--key expects K=NAME, got '{value}'"))?;
+                let index = match u8::from_str_radix(key, 16) {
+                    Ok(index) if key.len() == 1 => index as usize,
+                    _ => return Err(format!("--key expects a CHIP-8 key 0-F, got '{key}'")),
+                };
+                keymap[index] = keys::parse_key(name)?;
+            }
+            "--palette" => {
+                palette = parse_palette(args.next().ok_or("--palette expects a value")?)?;
+            }
+            "--bg" => background = Some(parse_color(args.next().ok_or("--bg expects a color")?)?),
+            "--fg" => foreground = Some(parse_color(args.next().ok_or("--fg expects a color")?)?),
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{flag}'")),
+            path if rom_path.is_none() => rom_path = Some(path.to_string()),
+            path => return Err(format!("unexpected argument '{path}'")),
+        }
+    }
+
+    if let Some(color) = background {
+        palette[0] = color;
+    }
+    if let Some(color) = foreground {
+        palette[1] = color;
+    }
+
+    return Ok(Options {
+        rom_path: rom_path.ok_or("no ROM given")?,
+        ipf: ipf.max(1),
+        profile,
+        scale: scale.max(1),
+        keymap,
+        keymap_file,
+        palette,
+    });
+}
+
+// --keymap wins, then a per-game <rom>.keymap next to the ROM, then --layout
+fn read_keymap(options: &Options) -> Result<Keymap, String> {
+    let sidecar = format!("{}.keymap", options.rom_path);
+    let path = match &options.keymap_file {
+        Some(path) => path,
+        None if Path::new(&sidecar).exists() => &sidecar,
+        None => return Ok(options.keymap),
+    };
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("failed to read keymap '{path}': {e}"))?;
+    return keys::parse(&text).map_err(|e| format!("keymap '{path}': {e}"));
+}
+
+// The largest rectangle of the display's shape that fits the window, centered
+fn fit((width, height): (usize, usize), (win_width, win_height): (u32, u32)) -> Rect {
+    let scale = (win_width as f32 / width as f32).min(win_height as f32 / height as f32);
+    let (w, h) = (
+        (width as f32 * scale) as u32,
+        (height as f32 * scale) as u32,
+    );
+    let x = (win_width.saturating_sub(w) / 2) as i32;
+    let y = (win_height.saturating_sub(h) / 2) as i32;
+
+    return Rect::new(x, y, w.max(1), h.max(1));
+}
+
+struct SdlFrontend<'a> {
+    canvas: Canvas<Window>,
+    // HIRES_WIDTH x HIRES_HEIGHT, the current resolution uses its top left corner
+    texture: Texture<'a>,
+    events: EventPump,
+    // None without a sound device
+    audio: Option<AudioQueue<u8>>,
+    synth: Synth,
+    // one frame of samples
+    samples: Vec<u8>,
+    // the XO-CHIP pattern, taken from the machine in present() for play_beep()
+    pattern: Option<([u8; 16], u8)>,
+    keymap: Keymap,
+    palette: [Rgb; 4],
+    title: String,
+    quit: bool,
+}
+
+impl Frontend for SdlFrontend<'_> {
+    fn poll_input(&mut self) -> FrameInput {
+        let mut input = FrameInput::default();
+        for event in self.events.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    scancode: Some(Scancode::Escape),
+                    ..
+                } => self.quit = true,
+                Event::KeyDown {
+                    scancode: Some(Scancode::Space),
+                    repeat: false,
+                    ..
+                } => input.toggle_pause = !input.toggle_pause,
+                Event::KeyDown {
+                    scancode: Some(Scancode::N),
+                    repeat: false,
+                    ..
+                } => input.step = true,
+                _ => {}
+            }
+        }
+        let keyboard = self.events.keyboard_state();
+        for (key, &scancode) in self.keymap.iter().enumerate() {
+            input.keys[key] = keyboard.is_scancode_pressed(scancode);
+        }
+
+        return input;
+    }
+
+    fn present(&mut self, chip8: &Chip8, changed: bool, status: &Status) {
+        self.pattern = chip8
+            .uses_audio_pattern()
+            .then(|| (chip8.audio_pattern(), chip8.pitch()));
+        let title = match status {
+            Status::Halted { pc, error } => {
+                format!("RustChip8 - CPU halted at {pc:#05X}: {error}")
+            }
+            Status::Paused => format!(
+                "RustChip8 - PAUSED at {:#05X}: {}",
+                chip8.pc(),
+                chip8.peek_opcode().map_or(String::from("?"), disassemble)
+            ),
+            Status::Exited => String::from("RustChip8 - EXITED"),
+            Status::Running => String::from("RustChip8"),
+        };
+        if title != self.title {
+            // only fails for a title with a NUL in it
+            let _ = self.canvas.window_mut().set_title(&title);
+            self.title = title;
+        }
+
+        let (width, height) = chip8.display_size();
+        if changed {
+            let mut rgb = Vec::with_capacity(width * height * 3);
+            for row in chip8.display_rows() {
+                for &pixel in row {
+                    rgb.extend_from_slice(&self.palette[pixel as usize & 3]);
+                }
+            }
+            let area = Rect::new(0, 0, width as u32, height as u32);
+            if let Err(e) = self.texture.update(area, &rgb, width * 3) {
+                eprintln!("ERROR: failed to update the display texture: {e}");
+            }
+        }
+        // every frame, the window may have been resized or uncovered
+        let [r, g, b] = self.palette[0];
+        self.canvas.set_draw_color(Color::RGB(r, g, b));
+        self.canvas.clear();
+        let source = Rect::new(0, 0, width as u32, height as u32);
+        let size = self.canvas.output_size().unwrap_or((1, 1));
+        if let Err(e) = self
+            .canvas
+            .copy(&self.texture, source, fit((width, height), size))
+        {
+            eprintln!("ERROR: failed to draw the display: {e}");
+        }
+        self.canvas.present();
+    }
+
+    // Keeps about two frames queued while there is something to hear, the release of the
+    // envelope included, and lets the queue run dry after that
+    fn play_beep(&mut self, on: bool) {
+        let Some(audio) = &self.audio else {
+            return;
+        };
+        self.synth.set_pattern(self.pattern);
+        self.synth.set_gate(on);
+        while (on || !self.synth.is_silent()) && audio.size() < QUEUED_SAMPLES {
+            self.synth.render(&mut self.samples);
+            if audio.queue_audio(&self.samples).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn should_close(&self) -> bool {
+        return self.quit;
+    }
+}
+
+fn exit_with(message: String) -> ! {
+    eprintln!("ERROR: {message}");
+    process::exit(1);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_args(&args).unwrap_or_else(|e| exit_with(format!("{e}\n{USAGE}")));
+    let keymap = read_keymap(&options).unwrap_or_else(|e| exit_with(e));
+    let rom = fs::read(&options.rom_path)
+        .unwrap_or_else(|e| exit_with(format!("failed to read ROM '{}': {e}", options.rom_path)));
+    let mut chip8 = Chip8::new();
+    chip8.set_quirks(options.profile.quirks());
+    chip8.set_vip_hires(is_vip_hires_rom(&rom));
+    if let Err(e) = chip8.load_rom(&rom) {
+        exit_with(e.to_string());
+    }
+
+    let sdl = sdl2::init().unwrap_or_else(|e| exit_with(format!("SDL: {e}")));
+    let video = sdl
+        .video()
+        .unwrap_or_else(|e| exit_with(format!("SDL: {e}")));
+    let window = video
+        .window(
+            "RustChip8",
+            SCREEN_WIDTH as u32 * options.scale,
+            SCREEN_HEIGHT as u32 * options.scale,
+        )
+        .position_centered()
+        .resizable()
+        .build()
+        .unwrap_or_else(|e| exit_with(format!("failed to open a window: {e}")));
+    let canvas = window
+        .into_canvas()
+        .build()
+        .unwrap_or_else(|e| exit_with(format!("failed to create a renderer: {e}")));
+    let textures = canvas.texture_creator();
+    let texture = textures
+        .create_texture_streaming(
+            PixelFormatEnum::RGB24,
+            HIRES_WIDTH as u32,
+            HIRES_HEIGHT as u32,
+        )
+        .unwrap_or_else(|e| exit_with(format!("failed to create the display texture: {e}")));
+    let events = sdl
+        .event_pump()
+        .unwrap_or_else(|e| exit_with(format!("SDL: {e}")));
+
+    // the game still runs without sound
+    let desired = AudioSpecDesired {
+        freq: Some(SAMPLE_RATE as i32),
+        channels: Some(1),
+        samples: None,
+    };
+    let audio = match sdl
+        .audio()
+        .and_then(|audio| audio.open_queue::<u8, _>(None, &desired))
+    {
+        Ok(queue) => {
+            queue.resume();
+            Some(queue)
+        }
+        Err(e) => {
+            eprintln!("WARNING: no sound: {e}");
+            None
+        }
+    };
+
+    let mut frontend = SdlFrontend {
+        canvas,
+        texture,
+        events,
+        audio,
+        synth: Synth::new(SAMPLE_RATE, Wave::Square, BEEP_FREQ),
+        samples: vec![0; (SAMPLE_RATE / 60) as usize],
+        pattern: None,
+        keymap,
+        palette: options.palette,
+        title: String::from("RustChip8"),
+        quit: false,
+    };
+    let mut frame_loop = FrameLoop::new(options.ipf * 60);
+    let mut last_frame = Instant::now();
+    while !frontend.should_close() {
+        let now = Instant::now();
+        frame_loop.frame(&mut chip8, &mut frontend, now - last_frame);
+        last_frame = now;
+
+        if let Some(wait) = (now + FRAME).checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        return parse_args(&args);
+    }
+
+    #[test]
+    fn keypad_and_palette_options() {
+        let options = parse(&["--layout", "azerty", "--key", "5=space", "game.ch8"]).unwrap();
+        let mut expected = keys::from_names(&rustchip8::keymap::AZERTY);
+        expected[5] = Scancode::Space;
+        assert_eq!(options.keymap, expected);
+        assert_eq!(options.keymap[4], Scancode::A);
+        assert_eq!(options.palette, PALETTES[0].1);
+
+        let options = parse(&["--fg", "ffcc00", "--palette", "amber", "game.ch8"]).unwrap();
+        let amber = PALETTES[1].1;
+        assert_eq!(
+            options.palette,
+            [amber[0], [0xFF, 0xCC, 0x00], amber[2], amber[3]]
+        );
+
+        // every layout has a scancode for each of its keys
+        for (name, _) in rustchip8::keymap::LAYOUTS {
+            assert!(keys::parse_layout(name).is_ok(), "{name}");
+        }
+        let error = |args: &[&str]| parse(args).unwrap_err();
+        assert_eq!(
+            error(&["--key", "G=A"]),
+            "--key expects a CHIP-8 key 0-F, got 'G'"
+        );
+        assert_eq!(error(&["--key", "1=NUMPAD"]), "unknown key 'NUMPAD'");
+        assert!(error(&["--layout", "dvorak"]).contains("unknown layout"));
+        assert!(error(&["--bg", "black"]).contains("invalid color 'black'"));
+    }
+}
@@ -0,0 +1,23 @@
+#![allow(clippy::needless_return)]
+
+// The emulator in a terminal, for SSH sessions and machines without a GPU. Two pixels
+// share a character cell: '▀' in the top one's color over the bottom one's, so 64x32 takes
+// 64x16 cells and hi-res 128x32.
+//
+// Terminals only report key presses, so a CHIP-8 key counts as held for KEY_HOLD after
+// its last press or auto-repeat.
+
+mod term;
+mod tui;
+
+const USAGE: &str = "\
+usage: rustchip8-tui [OPTIONS] ROM
+
+  --ipf N          instructions per 60Hz frame (default 12)
+  --profile NAME   quirk preset: cosmac, chip48 or schip
+
+keys: 1234/QWER/ASDF/ZXCV keypad, Space pause, N step while paused, Esc or Ctrl-C quit";
+
+fn main() {
+    tui::run();
+}
@@ -0,0 +1,89 @@
+// The terminal through crossterm: raw mode, the alternate screen with the cursor hidden
+// and the input as key and resize events. The picture itself is plain ANSI escapes, see
+// tui.rs.
+
+use crossterm::event::{self, Event, KeyEvent};
+use crossterm::{cursor, execute, terminal};
+use std::io::{self, stdout};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+// Whether the terminal still has to be restored, by Drop or the panic hook
+static ENTERED: AtomicBool = AtomicBool::new(false);
+
+// Raw mode on the alternate screen with the cursor hidden, until dropped. A panic restores
+// the terminal too, before the message is printed.
+pub struct Terminal {
+    // columns and rows, kept up to date by the resize events
+    size: (usize, usize),
+}
+
+impl Terminal {
+    pub fn enter() -> io::Result<Terminal> {
+        let size = terminal::size()?;
+        terminal::enable_raw_mode()?;
+        ENTERED.store(true, Ordering::SeqCst);
+
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            restore();
+            default_hook(info);
+        }));
+
+        execute!(
+            stdout(),
+            terminal::EnterAlternateScreen,
+            cursor::Hide,
+            terminal::Clear(terminal::ClearType::All)
+        )?;
+
+        return Ok(Terminal { size: fit(size) });
+    }
+
+    // Columns and rows
+    pub fn size(&self) -> (usize, usize) {
+        return self.size;
+    }
+
+    // The keys typed since the last call, without waiting. Resizes update size().
+    pub fn read_input(&mut self) -> Vec<KeyEvent> {
+        let mut keys = Vec::new();
+        while event::poll(Duration::ZERO).unwrap_or(false) {
+            match event::read() {
+                Ok(Event::Key(key)) => keys.push(key),
+                Ok(Event::Resize(columns, rows)) => self.size = fit((columns, rows)),
+                Ok(_) => {}
+                Err(_) => break,
+            }
+        }
+
+        return keys;
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+// Some terminals report 0x0, e.g. over a serial line
+fn fit((columns, rows): (u16, u16)) -> (usize, usize) {
+    if columns == 0 || rows == 0 {
+        return (80, 24);
+    }
+
+    return (columns as usize, rows as usize);
+}
+
+fn restore() {
+    if ENTERED.swap(false, Ordering::SeqCst) {
+        let _ = execute!(
+            stdout(),
+            crossterm::style::ResetColor,
+            cursor::Show,
+            terminal::LeaveAlternateScreen
+        );
+        let _ = terminal::disable_raw_mode();
+    }
+}
@@ -0,0 +1,224 @@
+// The terminal frontend: the keypad, 60Hz frames through host::FrameLoop and a redraw
+// whenever the display, the status line or the terminal size changes.
+
+use super::USAGE;
+use super::term::Terminal;
+use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers};
+use rustchip8::host::{FrameInput, FrameLoop, Frontend, Status};
+use rustchip8::screenshot::Rgb;
+use rustchip8::{Chip8, Profile, disassemble};
+use std::fmt::Write as _;
+use std::io::{Write, stdout};
+use std::time::{Duration, Instant};
+use std::{env, fs, process, thread};
+
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+const KEY_HOLD: Duration = Duration::from_millis(150);
+const DEFAULT_IPF: u32 = 12;
+// background, plane 1, plane 2, both planes, the classic palette of the window
+const PALETTE: [Rgb; 4] = [[0, 0, 0], [0, 228, 48], [0, 120, 255], [255, 255, 255]];
+// The COSMAC VIP keypad on the left of a QWERTY keyboard, indexed by CHIP-8 key
+const KEYPAD: [u8; 16] = *b"x123qweasdzc4rfv";
+
+struct Options {
+    rom_path: String,
+    ipf: u32,
+    profile: Profile,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut rom_path = None;
+    let mut ipf = DEFAULT_IPF;
+    let mut profile = Profile::Cosmac;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ipf" => {
+                let value = args.next().ok_or("--ipf expects a value")?;
+                ipf = value
+                    .parse()
+                    .map_err(|_| format!("--ipf expects a number, got '{value}'"))?;
+            }
+            "--profile" => profile = args.next().ok_or("--profile expects a value")?.parse()?,
+            flag if flag.starts_with("--") => return Err(format!("unknown option '{flag}'")),
+            path if rom_path.is_none() => rom_path = Some(path.to_string()),
+            path => return Err(format!("unexpected argument '{path}'")),
+        }
+    }
+
+    return Ok(Options {
+        rom_path: rom_path.ok_or("no ROM given")?,
+        ipf: ipf.max(1),
+        profile,
+    });
+}
+
+// The display at the terminal's center as half-block cells, one escape per color change
+fn render(chip8: &Chip8, (columns, rows): (usize, usize), status: &str) -> String {
+    let width = chip8.resolution().width();
+    let height = chip8.resolution().height();
+    let mut out = String::from("\x1b[0m\x1b[2J");
+    // the status line goes under the picture
+    if columns < width || rows < height / 2 + 1 {
+        write!(
+            out,
+            "\x1b[1;1Hterminal too small, {width}x{} needed",
+            height / 2 + 1
+        )
+        .unwrap();
+        return out;
+    }
+    let left = (columns - width) / 2 + 1;
+    let top = (rows - height / 2 - 1) / 2 + 1;
+
+    let display = chip8.display();
+    for y in (0..height).step_by(2) {
+        write!(out, "\x1b[{};{left}H", top + y / 2).unwrap();
+        let mut colors = None;
+        for x in 0..width {
+            let upper = PALETTE[display[y][x] as usize & 3];
+            let lower = PALETTE[display[y + 1][x] as usize & 3];
+            if colors != Some((upper, lower)) {
+                let ([r, g, b], [r2, g2, b2]) = (upper, lower);
+                write!(out, "\x1b[38;2;{r};{g};{b}m\x1b[48;2;{r2};{g2};{b2}m").unwrap();
+                colors = Some((upper, lower));
+            }
+            out.push('▀');
+        }
+    }
+    write!(out, "\x1b[0m\x1b[{};{left}H{status}", top + height / 2).unwrap();
+
+    return out;
+}
+
+// The terminal as a Frontend. Keys stay down for KEY_HOLD, see main.rs, or until released
+// where the terminal reports that.
+struct TerminalFrontend {
+    terminal: Terminal,
+    // when each CHIP-8 key was last pressed
+    pressed: [Option<Instant>; 16],
+    quit: bool,
+    size: (usize, usize),
+    status: String,
+    beeping: bool,
+}
+
+impl Frontend for TerminalFrontend {
+    fn poll_input(&mut self) -> FrameInput {
+        let mut input = FrameInput::default();
+        let now = Instant::now();
+        for event in self.terminal.read_input() {
+            let c = match event.code {
+                KeyCode::Char(c) => c.to_ascii_lowercase(),
+                KeyCode::Esc => {
+                    self.quit = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            let keypad = KEYPAD.iter().position(|&k| k as char == c);
+            match event.kind {
+                KeyEventKind::Release => {
+                    if let Some(key) = keypad {
+                        self.pressed[key] = None;
+                    }
+                }
+                // raw mode turns Ctrl-C into a key
+                _ if c == 'c' && event.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.quit = true;
+                }
+                KeyEventKind::Press if c == ' ' => input.toggle_pause = !input.toggle_pause,
+                KeyEventKind::Press if c == 'n' => input.step = true,
+                _ => {
+                    if let Some(key) = keypad {
+                        self.pressed[key] = Some(now);
+                    }
+                }
+            }
+        }
+        for (key, since) in self.pressed.iter_mut().enumerate() {
+            if since.is_some_and(|since| now - since > KEY_HOLD) {
+                *since = None;
+            }
+            input.keys[key] = since.is_some();
+        }
+
+        return input;
+    }
+
+    fn present(&mut self, chip8: &Chip8, changed: bool, status: &Status) {
+        let status = match status {
+            Status::Halted { pc, error } => format!("CPU halted at {pc:#05X}: {error}"),
+            Status::Paused => format!(
+                "PAUSED at {:#05X}: {}",
+                chip8.pc(),
+                chip8.peek_opcode().map_or(String::from("?"), disassemble)
+            ),
+            Status::Exited => String::from("EXITED"),
+            Status::Running => String::from("Space pause, N step, Esc quit"),
+        };
+        let size = self.terminal.size();
+        if changed || size != self.size || status != self.status {
+            self.size = size;
+            print!("{}", render(chip8, size, &status));
+            self.status = status;
+        }
+    }
+
+    // the terminal bell for every new sound
+    fn play_beep(&mut self, on: bool) {
+        if on && !self.beeping {
+            print!("\x07");
+        }
+        self.beeping = on;
+        let _ = stdout().flush();
+    }
+
+    fn should_close(&self) -> bool {
+        return self.quit;
+    }
+}
+
+pub fn run() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = parse_args(&args).unwrap_or_else(|e| {
+        eprintln!("ERROR: {e}");
+        eprintln!("{USAGE}");
+        process::exit(1);
+    });
+    let rom = fs::read(&options.rom_path).unwrap_or_else(|e| {
+        eprintln!("ERROR: failed to read ROM '{}': {e}", options.rom_path);
+        process::exit(1);
+    });
+    let mut chip8 = Chip8::new();
+    chip8.set_quirks(options.profile.quirks());
+    chip8.set_vip_hires(rustchip8::is_vip_hires_rom(&rom));
+    if let Err(e) = chip8.load_rom(&rom) {
+        eprintln!("ERROR: {e}");
+        process::exit(1);
+    }
+
+    let terminal = Terminal::enter().unwrap_or_else(|e| {
+        eprintln!("ERROR: stdin has to be a terminal: {e}");
+        process::exit(1);
+    });
+    let mut frontend = TerminalFrontend {
+        terminal,
+        pressed: [None; 16],
+        quit: false,
+        size: (0, 0),
+        status: String::new(),
+        beeping: false,
+    };
+    let mut frame_loop = FrameLoop::new(options.ipf * 60);
+    let mut last_frame = Instant::now();
+    while !frontend.should_close() {
+        let now = Instant::now();
+        frame_loop.frame(&mut chip8, &mut frontend, now - last_frame);
+        last_frame = now;
+
+        if let Some(wait) = (now + FRAME).checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+}
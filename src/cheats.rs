@@ -0,0 +1,170 @@
+// Cheats from a `<rom>.cheats` file, one per line:
+//
+//   freeze 0x3A0 0x09 lives     write the byte again after every frame
+//   poke 0x250 0xFF             write it once when the ROM is loaded
+//
+// Numbers are decimal or 0x hex, anything after the value names the cheat and `#` starts
+// a comment. Every cheat starts out enabled, the frontend toggles them.
+
+use crate::Chip8;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheatKind {
+    // the numbers are what save states store
+    Freeze = 0,
+    Poke = 1,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub kind: CheatKind,
+    pub addr: u16,
+    pub value: u8,
+    pub enabled: bool,
+    // empty if the line didn't give one
+    pub name: String,
+}
+
+impl fmt::Display for Cheat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self.kind {
+            CheatKind::Freeze => "freeze",
+            CheatKind::Poke => "poke",
+        };
+        write!(f, "{kind} {:#05X} {:#04X}", self.addr, self.value)?;
+        if !self.name.is_empty() {
+            write!(f, " {}", self.name)?;
+        }
+        return Ok(());
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheatError {
+    // 1-based line in the file
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CheatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for CheatError {}
+
+fn number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => return u32::from_str_radix(hex, 16).ok(),
+        None => return text.parse().ok(),
+    }
+}
+
+// `mem_len` is the size of the machine's memory, 4K or the XO-CHIP 64K
+pub fn parse_cheats(text: &str, mem_len: usize) -> Result<Vec<Cheat>, CheatError> {
+    let mut cheats = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| CheatError {
+            line: i + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let Some(kind) = words.next() else { continue };
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "freeze" => CheatKind::Freeze,
+            "poke" => CheatKind::Poke,
+            _ => {
+                return Err(error(format!(
+                    "unknown cheat '{kind}', expected freeze or poke"
+                )));
+            }
+        };
+        let (Some(addr), Some(value)) = (words.next(), words.next()) else {
+            return Err(error(String::from("expected an address and a value")));
+        };
+        let addr = match number(addr) {
+            Some(addr) if (addr as usize) < mem_len => addr as u16,
+            Some(_) => {
+                return Err(error(format!(
+                    "address {addr} is past the end of memory ({mem_len:#X} bytes)"
+                )));
+            }
+            None => return Err(error(format!("invalid address '{addr}'"))),
+        };
+        let value = match number(value) {
+            Some(value) if value <= 0xFF => value as u8,
+            _ => return Err(error(format!("invalid byte value '{value}'"))),
+        };
+        cheats.push(Cheat {
+            kind,
+            addr,
+            value,
+            enabled: true,
+            name: words.collect::<Vec<_>>().join(" "),
+        });
+    }
+
+    return Ok(cheats);
+}
+
+impl Chip8 {
+    // Replaces the cheats, nothing is written until apply_pokes() or the next frame
+    pub fn set_cheats(&mut self, cheats: Vec<Cheat>) {
+        self.cheats = cheats;
+    }
+
+    pub fn cheats(&self) -> &[Cheat] {
+        return &self.cheats;
+    }
+
+    // Flips cheat `index` and returns whether it is now on. Turning a poke on writes it
+    // right away.
+    pub fn toggle_cheat(&mut self, index: usize) -> Option<bool> {
+        let cheat = self.cheats.get_mut(index)?;
+        cheat.enabled = !cheat.enabled;
+        let (enabled, kind, addr, value) = (cheat.enabled, cheat.kind, cheat.addr, cheat.value);
+        if enabled && kind == CheatKind::Poke {
+            self.write_cheat(addr, value);
+        }
+
+        return Some(enabled);
+    }
+
+    // Once after the ROM is loaded
+    pub fn apply_pokes(&mut self) {
+        self.apply(CheatKind::Poke);
+    }
+
+    // Once per frame, after the frame's instructions and before it is drawn
+    pub fn apply_freezes(&mut self) {
+        self.apply(CheatKind::Freeze);
+    }
+
+    fn apply(&mut self, kind: CheatKind) {
+        for i in 0..self.cheats.len() {
+            let Cheat {
+                enabled,
+                addr,
+                value,
+                ..
+            } = self.cheats[i];
+            if enabled && self.cheats[i].kind == kind {
+                self.write_cheat(addr, value);
+            }
+        }
+    }
+
+    // The addresses were checked against the memory size when parsing, but a state load or
+    // set_extended_memory() may have shrunk it since
+    fn write_cheat(&mut self, addr: u16, value: u8) {
+        if let Some(byte) = self.mem.get_mut(addr as usize)
+            && *byte != value
+        {
+            *byte = value;
+            self.idle.progress();
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{Instruction, Symbols};
+
+// Addresses that have a name in `symbols` are written as the name: CALL draw_sprite
+fn decode(opcode: u16, symbols: &Symbols) -> Option<String> {
+    let instruction = Instruction::decode(opcode)?;
+    let label = |addr: u16| symbols.label(addr);
+    let text = match instruction {
+        Instruction::Sys(addr) => label(addr).map(|name| format!("SYS {name}")),
+        Instruction::Jump(addr) => label(addr).map(|name| format!("JP {name}")),
+        Instruction::Call(addr) => label(addr).map(|name| format!("CALL {name}")),
+        Instruction::LoadI(addr) => label(addr).map(|name| format!("LD I, {name}")),
+        Instruction::JumpOffset { nnn, .. } => label(nnn).map(|name| format!("JP V0, {name}")),
+        _ => None,
+    };
+
+    return Some(text.unwrap_or_else(|| instruction.to_string()));
+}
+
+pub fn disassemble(opcode: u16) -> String {
+    return disassemble_with(opcode, &Symbols::new());
+}
+
+pub fn disassemble_with(opcode: u16, symbols: &Symbols) -> String {
+    return decode(opcode, symbols).unwrap_or_else(|| format!(".word {opcode:#06X}"));
+}
+
+// Linear listing of a ROM loaded at `base`, one line per instruction. Pairs that don't
+// decode (usually sprite data) are listed byte by byte as `.byte 0xNN`.
+pub fn disassemble_rom(rom: &[u8], base: u16) -> String {
+    return disassemble_rom_with(rom, base, &Symbols::new());
+}
+
+// Same, with a `name:` line above every address that has one
+pub fn disassemble_rom_with(rom: &[u8], base: u16, symbols: &Symbols) -> String {
+    let mut listing = String::new();
+    let label = |listing: &mut String, addr: usize| {
+        if let Some(name) = symbols.label(addr as u16) {
+            listing.push_str(&format!("{name}:\n"));
+        }
+    };
+    let mut offset = 0;
+    while offset < rom.len() {
+        let addr = base as usize + offset;
+        label(&mut listing, addr);
+        let pair = &rom[offset..rom.len().min(offset + 2)];
+        let opcode = match pair {
+            [hi, lo] => Some((*hi as u16) << 8 | *lo as u16),
+            _ => None,
+        };
+
+        // XO-CHIP F000 NNNN is the only instruction that spans two words
+        if let (Some(0xF000), Some(&[hi, lo])) = (opcode, rom.get(offset + 2..offset + 4)) {
+            let long_addr = (hi as u16) << 8 | lo as u16;
+            let operand = match symbols.label(long_addr) {
+                Some(name) => name.to_string(),
+                None => format!("{long_addr:#06X}"),
+            };
+            listing += &format!("{addr:04X}: F000{long_addr:04X}  LD I, {operand}\n");
+            offset += 4;
+            continue;
+        }
+
+        match opcode.and_then(|opcode| decode(opcode, symbols)) {
+            Some(mnemonic) => {
+                listing += &format!("{addr:04X}: {:02X}{:02X}  {mnemonic}\n", pair[0], pair[1]);
+            }
+            None => {
+                for (j, byte) in pair.iter().enumerate() {
+                    if j > 0 {
+                        label(&mut listing, addr + j);
+                    }
+                    listing += &format!("{:04X}: {byte:02X}    .byte {byte:#04X}\n", addr + j);
+                }
+            }
+        }
+        offset += 2;
+    }
+
+    return listing;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_replace_addresses() {
+        let mut symbols = Symbols::new();
+        symbols.insert("draw", 0x300);
+        assert_eq!(disassemble_with(0x2300, &symbols), "CALL draw");
+        assert_eq!(disassemble_with(0xA300, &symbols), "LD I, draw");
+        assert_eq!(disassemble_with(0x1302, &symbols), "JP 0x302");
+        let listing = disassemble_rom_with(&[0x13, 0x00], 0x300, &symbols);
+        assert_eq!(listing, "draw:\n0300: 1300  JP draw\n");
+    }
+
+    #[test]
+    fn every_family_has_its_mnemonic() {
+        let cases = [
+            (0x00E0, "CLS"),
+            (0x00EE, "RET"),
+            (0x00C4, "SCD 4"),
+            (0x00FB, "SCR"),
+            (0x00FC, "SCL"),
+            (0x00FD, "EXIT"),
+            (0x00FE, "LOW"),
+            (0x00FF, "HIGH"),
+            (0x0123, "SYS 0x123"),
+            (0x1234, "JP 0x234"),
+            (0x2ABC, "CALL 0xABC"),
+            (0x3A12, "SE VA, 0x12"),
+            (0x4B34, "SNE VB, 0x34"),
+            (0x5120, "SE V1, V2"),
+            (0x6C56, "LD VC, 0x56"),
+            (0x7D78, "ADD VD, 0x78"),
+            (0x8120, "LD V1, V2"),
+            (0x8121, "OR V1, V2"),
+            (0x8122, "AND V1, V2"),
+            (0x8123, "XOR V1, V2"),
+            (0x8124, "ADD V1, V2"),
+            (0x8125, "SUB V1, V2"),
+            (0x8126, "SHR V1, V2"),
+            (0x8127, "SUBN V1, V2"),
+            (0x812E, "SHL V1, V2"),
+            (0x93F0, "SNE V3, VF"),
+            (0xA22A, "LD I, 0x22A"),
+            (0xB300, "JP V0, 0x300"),
+            (0xC40F, "RND V4, 0x0F"),
+            (0xD015, "DRW V0, V1, 5"),
+            (0xD010, "DRW V0, V1, 0"),
+            (0xE59E, "SKP V5"),
+            (0xE6A1, "SKNP V6"),
+            (0xF707, "LD V7, DT"),
+            (0xF80A, "LD V8, K"),
+            (0xF915, "LD DT, V9"),
+            (0xFA18, "LD ST, VA"),
+            (0xFB1E, "ADD I, VB"),
+            (0xFC29, "LD F, VC"),
+            (0xF000, "LD I, long"),
+            (0xF201, "PLANE 2"),
+            (0xF002, "AUDIO"),
+            (0xF33A, "PITCH V3"),
+            (0xFD30, "LD HF, VD"),
+            (0xFE33, "LD B, VE"),
+            (0xF175, "LD R, V1"),
+            (0xF285, "LD V2, R"),
+            (0xFF55, "LD [I], VF"),
+            (0xF065, "LD V0, [I]"),
+        ];
+        for (opcode, mnemonic) in cases {
+            assert_eq!(disassemble(opcode), mnemonic, "{opcode:04X}");
+        }
+    }
+
+    #[test]
+    fn words_that_dont_decode() {
+        for opcode in [0x5121, 0x812F, 0x9121, 0xE19F, 0xF0FF] {
+            assert_eq!(disassemble(opcode), format!(".word {opcode:#06X}"));
+        }
+    }
+
+    #[test]
+    fn rom_listing() {
+        // CLS, LD I long 0x1234, a pair that isn't an instruction, an odd byte at the end
+        let rom = [0x00, 0xE0, 0xF0, 0x00, 0x12, 0x34, 0xFF, 0xFF, 0xA2];
+        let listing = disassemble_rom(&rom, 0x200);
+        assert_eq!(
+            listing,
+            "0200: 00E0  CLS\n\
+             0202: F0001234  LD I, 0x1234\n\
+             0206: FF    .byte 0xFF\n\
+             0207: FF    .byte 0xFF\n\
+             0208: A2    .byte 0xA2\n"
+        );
+    }
+}
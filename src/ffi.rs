@@ -0,0 +1,198 @@
+// C interface for launchers and ctypes, see include/rustchip8.h (regenerate it with
+// `cbindgen --config cbindgen.toml --output include/rustchip8.h`).
+//
+// Every function tolerates a null machine and catches panics at the boundary. Failures
+// return -1 (or null) and leave a message for chip8_last_error().
+
+use crate::Chip8;
+use std::cell::RefCell;
+use std::ffi::{CString, c_char};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_error(message: String) {
+    let message = CString::new(message).unwrap_or_else(|_| c"error message contains NUL".into());
+    LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+}
+
+// Runs `f` with the machine behind `chip8`, `failed` is returned when it is null, returns
+// an error or panics
+fn with_chip8<T>(
+    chip8: *mut Chip8,
+    failed: T,
+    f: impl FnOnce(&mut Chip8) -> Result<T, String>,
+) -> T {
+    // SAFETY: non-null pointers come from chip8_new() and haven't been freed, the caller
+    // promises that much
+    let Some(chip8) = (unsafe { chip8.as_mut() }) else {
+        set_error(String::from("null Chip8 pointer"));
+        return failed;
+    };
+    match panic::catch_unwind(AssertUnwindSafe(|| f(chip8))) {
+        Ok(Ok(value)) => return value,
+        Ok(Err(message)) => set_error(message),
+        Err(_) => set_error(String::from("panic inside the emulator")),
+    }
+
+    return failed;
+}
+
+/// A new machine with a time-based CXNN seed, free it with chip8_free()
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_new() -> *mut Chip8 {
+    return chip8_new_seeded(crate::time_seed());
+}
+
+/// A new machine whose CXNN sequence is reproducible from `seed`
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_new_seeded(seed: u64) -> *mut Chip8 {
+    match panic::catch_unwind(|| Box::new(Chip8::with_seed(seed))) {
+        Ok(chip8) => return Box::into_raw(chip8),
+        Err(_) => {
+            set_error(String::from("panic inside the emulator"));
+            return ptr::null_mut();
+        }
+    }
+}
+
+/// Null is fine.
+///
+/// # Safety
+/// `chip8` has to come from chip8_new() and must not be used afterwards.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_free(chip8: *mut Chip8) {
+    if !chip8.is_null() {
+        // SAFETY: see above
+        drop(unsafe { Box::from_raw(chip8) });
+    }
+}
+
+/// Resets the machine and loads `len` bytes from `rom`. 0 on success.
+///
+/// # Safety
+/// `rom` has to point to `len` readable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_load_rom(chip8: *mut Chip8, rom: *const u8, len: usize) -> i32 {
+    if rom.is_null() {
+        set_error(String::from("null ROM pointer"));
+        return -1;
+    }
+    // SAFETY: see above
+    let rom = unsafe { std::slice::from_raw_parts(rom, len) };
+    return with_chip8(chip8, -1, |chip8| {
+        chip8.reset();
+        chip8.load_rom(rom).map_err(|e| e.to_string())?;
+        return Ok(0);
+    });
+}
+
+/// Runs up to `n` instructions and returns how many ran. It stops early when the program
+/// waits for a key or the next frame, or has exited. A CPU error returns -1.
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_step(chip8: *mut Chip8, n: u32) -> i64 {
+    return with_chip8(chip8, -1, |chip8| {
+        let mut executed = 0;
+        while executed < n
+            && !chip8.is_waiting_for_key()
+            && !chip8.is_waiting_for_vblank()
+            && !chip8.has_exited()
+        {
+            let pc = chip8.pc();
+            chip8
+                .step()
+                .map_err(|e| format!("CPU halted at {pc:#05X}: {e}"))?;
+            executed += 1;
+        }
+        return Ok(executed as i64);
+    });
+}
+
+/// The 60Hz tick: timers count down and a display wait ends
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_tick_timers(chip8: *mut Chip8) {
+    with_chip8(chip8, (), |chip8| {
+        chip8.tick_60hz();
+        return Ok(());
+    });
+}
+
+/// Width and height of the current resolution, 64x32 or 128x64, -1 for a null machine
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_display_width(chip8: *mut Chip8) -> i32 {
+    return with_chip8(chip8, -1, |chip8| Ok(chip8.resolution().width() as i32));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_display_height(chip8: *mut Chip8) -> i32 {
+    return with_chip8(chip8, -1, |chip8| Ok(chip8.resolution().height() as i32));
+}
+
+/// Copies the display row by row into `out`, one byte per pixel holding its bitplanes
+/// (0 off, 1 and 2 XO-CHIP planes, 3 both). Returns the bytes written, width * height, or
+/// -1 when `len` is smaller than that.
+///
+/// # Safety
+/// `out` has to point to `len` writable bytes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn chip8_get_display(chip8: *mut Chip8, out: *mut u8, len: usize) -> i64 {
+    if out.is_null() {
+        set_error(String::from("null display buffer"));
+        return -1;
+    }
+    // SAFETY: see above
+    let out = unsafe { std::slice::from_raw_parts_mut(out, len) };
+    return with_chip8(chip8, -1, |chip8| {
+        let width = chip8.resolution().width();
+        let height = chip8.resolution().height();
+        if len < width * height {
+            return Err(format!(
+                "display buffer holds {len} bytes, {} needed",
+                width * height
+            ));
+        }
+        for (y, row) in chip8.display().iter().take(height).enumerate() {
+            out[y * width..(y + 1) * width].copy_from_slice(&row[..width]);
+        }
+        return Ok((width * height) as i64);
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_set_key(chip8: *mut Chip8, key: u8, pressed: bool) {
+    with_chip8(chip8, (), |chip8| {
+        chip8.set_key(key, pressed);
+        return Ok(());
+    });
+}
+
+/// VX, 0-255, or -1 when x isn't 0-15
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_get_register(chip8: *mut Chip8, x: u8) -> i32 {
+    return with_chip8(chip8, -1, |chip8| match chip8.registers().get(x as usize) {
+        Some(&val) => Ok(val as i32),
+        None => Err(format!("no register V{x}")),
+    });
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_get_pc(chip8: *mut Chip8) -> i32 {
+    return with_chip8(chip8, -1, |chip8| Ok(chip8.pc() as i32));
+}
+
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_sound_active(chip8: *mut Chip8) -> bool {
+    return with_chip8(chip8, false, |chip8| Ok(chip8.sound_active()));
+}
+
+/// The message for the last failure on this thread, or null. It stays valid until the
+/// next failing call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn chip8_last_error() -> *const c_char {
+    return LAST_ERROR.with(|error| {
+        return error.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr());
+    });
+}
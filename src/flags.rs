@@ -0,0 +1,203 @@
+// SUPER-CHIP "RPL user flags" that outlive the program, the way the HP-48 kept them.
+// Games use FX75/FX85 for high scores, so every FX75 is handed to a FlagStorage right away.
+
+use crate::Chip8;
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+pub trait FlagStorage {
+    fn load(&mut self) -> io::Result<[u8; 8]>;
+    fn store(&mut self, flags: &[u8; 8]) -> io::Result<()>;
+}
+
+// Flags that only live as long as the storage, e.g. for headless runs that have to be
+// reproducible
+#[derive(Debug, Clone, Default)]
+pub struct MemoryFlags {
+    pub flags: [u8; 8],
+}
+
+impl FlagStorage for MemoryFlags {
+    fn load(&mut self) -> io::Result<[u8; 8]> {
+        return Ok(self.flags);
+    }
+
+    fn store(&mut self, flags: &[u8; 8]) -> io::Result<()> {
+        self.flags = *flags;
+        return Ok(());
+    }
+}
+
+// The 8 bytes as they are in a file, a missing file means all flags are 0
+#[derive(Debug, Clone)]
+pub struct FileFlags {
+    path: PathBuf,
+}
+
+impl FileFlags {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        return FileFlags { path: path.into() };
+    }
+}
+
+impl FlagStorage for FileFlags {
+    fn load(&mut self) -> io::Result<[u8; 8]> {
+        let data = match fs::read(&self.path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok([0; 8]),
+            Err(e) => return Err(e),
+        };
+        if data.len() != 8 {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("expected 8 bytes of flags, found {}", data.len()),
+            ));
+        }
+        let mut flags = [0; 8];
+        flags.copy_from_slice(&data);
+
+        return Ok(flags);
+    }
+
+    fn store(&mut self, flags: &[u8; 8]) -> io::Result<()> {
+        return fs::write(&self.path, flags);
+    }
+}
+
+impl Chip8 {
+    // Replaces the current flags with the stored ones and sends every FX75 to `storage`
+    // from now on. None, or a storage that fails to load, keeps the flags in memory only.
+    pub fn set_flag_storage(&mut self, storage: Option<Box<dyn FlagStorage>>) -> io::Result<()> {
+        self.flag_storage = None;
+        let Some(mut storage) = storage else {
+            return Ok(());
+        };
+        self.rpl_flags = storage.load()?;
+        self.flag_storage = Some(storage);
+
+        return Ok(());
+    }
+
+    pub fn rpl_flags(&self) -> [u8; 8] {
+        return self.rpl_flags;
+    }
+
+    // Writes the flags out once more, for a clean exit
+    pub fn save_flags(&mut self) -> io::Result<()> {
+        match &mut self.flag_storage {
+            Some(storage) => return storage.store(&self.rpl_flags),
+            None => return Ok(()),
+        }
+    }
+
+    // The first failed FX75 write since the last call. The program keeps running with the
+    // flags in memory.
+    pub fn take_flag_error(&mut self) -> Option<io::Error> {
+        return self.flag_error.take();
+    }
+
+    pub(crate) fn store_flags(&mut self) {
+        if let Some(storage) = &mut self.flag_storage
+            && let Err(e) = storage.store(&self.rpl_flags)
+            && self.flag_error.is_none()
+        {
+            self.flag_error = Some(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn temp_path(name: &str) -> PathBuf {
+        return env::temp_dir().join(format!("rustchip8-{}-{name}.flags", process::id()));
+    }
+
+    // LD V0..V7 with 1..8 then into the flags with FX75 (X = F, clamped to 7)
+    fn store_rom() -> Vec<u8> {
+        let mut rom: Vec<u8> = (0..8).flat_map(|x| [0x60 | x, x + 1]).collect();
+        rom.extend([0xFF, 0x75]);
+        return rom;
+    }
+
+    fn run(chip8: &mut Chip8, steps: usize) {
+        for _ in 0..steps {
+            chip8.step().unwrap();
+        }
+    }
+
+    #[test]
+    fn flags_persist_through_a_file() {
+        let path = temp_path("round-trip");
+        let _ = fs::remove_file(&path);
+
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&store_rom()).unwrap();
+        chip8
+            .set_flag_storage(Some(Box::new(FileFlags::new(&path))))
+            .unwrap();
+        // no file yet, all flags 0
+        assert_eq!(chip8.rpl_flags(), [0; 8]);
+        run(&mut chip8, 9);
+        assert_eq!(chip8.rpl_flags(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(fs::read(&path).unwrap(), [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        // the next run reads them back with FX85 (X = 9, clamped to 7 too)
+        let mut next = Chip8::with_seed(0);
+        next.load_rom(&[0xF9, 0x85]).unwrap();
+        next.set_flag_storage(Some(Box::new(FileFlags::new(&path))))
+            .unwrap();
+        run(&mut next, 1);
+        assert_eq!(next.registers()[..8], [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(next.registers()[8..], [0; 8]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn file_of_the_wrong_size() {
+        let path = temp_path("short");
+        fs::write(&path, [1, 2, 3]).unwrap();
+        let mut chip8 = Chip8::with_seed(0);
+        let e = chip8
+            .set_flag_storage(Some(Box::new(FileFlags::new(&path))))
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_writes_are_reported_once() {
+        let path = temp_path("missing-dir").join("flags");
+        let mut chip8 = Chip8::with_seed(0);
+        let mut rom = store_rom();
+        rom.extend([0xF0, 0x75]);
+        chip8.load_rom(&rom).unwrap();
+        chip8
+            .set_flag_storage(Some(Box::new(FileFlags::new(&path))))
+            .unwrap();
+        run(&mut chip8, 10);
+        // the program keeps the flags in memory
+        assert_eq!(chip8.rpl_flags(), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert!(chip8.take_flag_error().is_some());
+        assert!(chip8.take_flag_error().is_none());
+        assert!(chip8.save_flags().is_err());
+    }
+
+    #[test]
+    fn memory_storage() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&store_rom()).unwrap();
+        let storage = MemoryFlags {
+            flags: [9, 9, 9, 9, 9, 9, 9, 9],
+        };
+        chip8.set_flag_storage(Some(Box::new(storage))).unwrap();
+        assert_eq!(chip8.rpl_flags(), [9; 8]);
+        run(&mut chip8, 9);
+        assert!(chip8.save_flags().is_ok());
+        assert!(chip8.take_flag_error().is_none());
+    }
+}
@@ -0,0 +1,45 @@
+use rustchip8::{Framebuffer, HIRES_HEIGHT, HIRES_WIDTH};
+use std::collections::VecDeque;
+
+pub const DEFAULT_FRAMES: usize = 2;
+
+// Shows a pixel as lit when it was lit in any of the last few frames, so sprites that are
+// erased and redrawn every frame (XOR drawing) stop blinking. Purely cosmetic: it only
+// reads display(), emulation and collisions never see it.
+pub struct AntiFlicker {
+    history: VecDeque<Framebuffer>,
+    frames: usize,
+    shown: Framebuffer,
+}
+
+impl AntiFlicker {
+    pub fn new(frames: usize) -> Self {
+        return AntiFlicker {
+            history: VecDeque::with_capacity(frames),
+            frames: frames.max(1),
+            shown: [[0; HIRES_WIDTH]; HIRES_HEIGHT],
+        };
+    }
+
+    // Call once per frame with the current display. Returns the blended picture and
+    // whether it differs from the previous one.
+    pub fn blend(&mut self, display: &Framebuffer) -> (&Framebuffer, bool) {
+        if self.history.len() == self.frames {
+            self.history.pop_front();
+        }
+        self.history.push_back(*display);
+
+        let mut blended = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+        for frame in &self.history {
+            for (out, row) in blended.iter_mut().zip(frame) {
+                for (pixel, &value) in out.iter_mut().zip(row) {
+                    *pixel |= value;
+                }
+            }
+        }
+        let changed = blended != self.shown;
+        self.shown = blended;
+
+        return (&self.shown, changed);
+    }
+}
@@ -0,0 +1,91 @@
+use raylib::prelude::*;
+use rustchip8::audio::{Synth, Wave};
+use std::time::Duration;
+
+const SAMPLE_RATE: u32 = 44100;
+// raylib's default is bigger, this is about 23ms
+pub const DEFAULT_BUFFER_SAMPLES: usize = 1024;
+
+// rustchip8::audio::Synth played through a raylib audio stream. 8-bit samples keep the byte count
+// raylib-rs passes to UpdateAudioStream equal to the sample count raylib expects.
+pub struct Beeper {
+    // dropped before the device it was opened on
+    stream: AudioStream,
+    audio: RaylibAudio,
+    synth: Synth,
+    samples: Vec<u8>,
+    playing: bool,
+    // buffers queued since the release ended, the stream stops once both of its halves are
+    // silence so the fade isn't cut off
+    silent_buffers: u32,
+}
+
+impl Beeper {
+    // `buffer` is how much audio is queued at a time, None for DEFAULT_BUFFER_SAMPLES
+    pub fn new(
+        thread: &RaylibThread,
+        wave: Wave,
+        freq: f32,
+        volume: f32,
+        buffer: Option<Duration>,
+    ) -> Self {
+        let buffer_samples = buffer.map_or(DEFAULT_BUFFER_SAMPLES, |buffer| {
+            (buffer.as_secs_f64() * SAMPLE_RATE as f64) as usize
+        });
+        let mut audio = RaylibAudio::init_audio_device();
+        unsafe {
+            raylib::ffi::SetAudioStreamBufferSizeDefault(buffer_samples as i32);
+        }
+        let mut stream = AudioStream::init_audio_stream(thread, SAMPLE_RATE, 8, 1);
+        audio.set_audio_stream_volume(&mut stream, volume);
+
+        Beeper {
+            stream,
+            audio,
+            synth: Synth::new(SAMPLE_RATE, wave, freq),
+            samples: vec![0; buffer_samples],
+            playing: false,
+            silent_buffers: 0,
+        }
+    }
+
+    // The stream keeps going after the timer stops until the release has faded out
+    pub fn update(&mut self, active: bool, pattern: Option<([u8; 16], u8)>) {
+        self.synth.set_pattern(pattern);
+        self.synth.set_gate(active);
+
+        if active && !self.playing {
+            self.fill_buffer();
+            self.audio.play_audio_stream(&mut self.stream);
+            self.playing = true;
+        } else if self.playing && self.silent_buffers >= 2 {
+            self.audio.stop_audio_stream(&mut self.stream);
+            self.playing = false;
+        }
+
+        // with buffers shorter than a frame both halves of the stream may have run dry
+        for _ in 0..2 {
+            if self.playing && self.audio.is_audio_stream_processed(&self.stream) {
+                self.fill_buffer();
+            }
+        }
+    }
+
+    // For when update() won't be called for a while, raylib would loop the last buffers
+    pub fn stop(&mut self) {
+        self.synth.silence();
+        if self.playing {
+            self.audio.stop_audio_stream(&mut self.stream);
+            self.playing = false;
+        }
+    }
+
+    fn fill_buffer(&mut self) {
+        self.silent_buffers = match self.synth.is_silent() {
+            true => self.silent_buffers + 1,
+            false => 0,
+        };
+        self.synth.render(&mut self.samples);
+        self.stream.update_audio_stream(&self.samples);
+    }
+}
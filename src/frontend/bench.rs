@@ -0,0 +1,190 @@
+use super::shutdown;
+use rustchip8::{Chip8, TIMER_HZ};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_SECONDS: f64 = 5.0;
+// instructions between looks at the clock, Instant::now() costs about as much as one
+const CLOCK_CHECK: u64 = 4096;
+
+// Synthetic mix for --bench without a ROM: a 15 row sprite, BCD and 8XY4 with carry in a
+// loop, plus the loads and skips around them. `base` is where it gets loaded.
+pub fn synthetic_rom(base: u16) -> Vec<u8> {
+    let program: [u16; 10] = [
+        0xA000 | (base + 0x100), // LD I, sprite
+        0xD01F,                  // DRW V0, V1, 15
+        0xA000 | (base + 0x110), // LD I, digits
+        0xF233,                  // LD B, V2
+        0x8014,                  // ADD V0, V1
+        0x7201,                  // ADD V2, 0x01
+        0x8124,                  // ADD V1, V2
+        0x3F00,                  // SE VF, 0x00
+        0x6300,                  // LD V3, 0x00
+        0x1000 | base,           // JP start
+    ];
+    let mut rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+    // the sprite 0x100 bytes in, BCD digits go 0x10 after it
+    rom.resize(0x100, 0);
+    rom.extend((0..15).map(|row| if row % 2 == 0 { 0xAA } else { 0x55 }));
+
+    return rom;
+}
+
+// Opcode with its operands masked off, 8XY4 and 8XY5 stay apart
+fn family(opcode: u16) -> u16 {
+    let mask = match opcode >> 12 {
+        0x0 if matches!(opcode & 0xFFF0, 0x00C0 | 0x00D0) => 0xFFF0,
+        0x0 => 0xFFFF,
+        0x5 | 0x8 | 0x9 => 0xF00F,
+        0xE | 0xF => 0xF0FF,
+        _ => 0xF000,
+    };
+    return opcode & mask;
+}
+
+// 0xD000 -> "DXYN", 0x8004 -> "8XY4"
+fn family_name(family: u16) -> String {
+    let digits = format!("{family:04X}");
+    let pattern = match family >> 12 {
+        0x0 if family & 0xFFF0 == 0x00C0 || family & 0xFFF0 == 0x00D0 => "...N",
+        0x0 => return digits,
+        0x1 | 0x2 | 0xA | 0xB => ".NNN",
+        0x3 | 0x4 | 0x6 | 0x7 | 0xC => ".XNN",
+        0xD => ".XYN",
+        0x5 | 0x8 | 0x9 => ".XY.",
+        _ => ".X..",
+    };
+    return digits
+        .chars()
+        .zip(pattern.chars())
+        .map(|(digit, p)| if p == '.' { digit } else { p })
+        .collect();
+}
+
+#[derive(Default)]
+struct FamilyStats {
+    count: u64,
+    time: Duration,
+}
+
+// Runs the CPU flat out without rendering or sleeping. Timers still tick 60 times per
+// `hz` instructions so delay loops end, and a program that stops (self-jump, exit, waiting
+// for a key nobody will press) is restarted from the beginning.
+struct Bench<'a> {
+    chip8: &'a mut Chip8,
+    rom: &'a [u8],
+    hz: u32,
+    tick_budget: u32,
+    restarts: u64,
+}
+
+impl Bench<'_> {
+    // Timers and restarts, kept out of the profiled step()
+    fn prepare(&mut self) -> Result<(), String> {
+        if self.tick_budget >= self.hz || self.chip8.is_waiting_for_vblank() {
+            self.chip8.tick_60hz();
+            self.tick_budget = self.tick_budget.saturating_sub(self.hz);
+        }
+        if self.chip8.is_self_jump() || self.chip8.has_exited() || self.chip8.is_waiting_for_key() {
+            self.chip8.reset();
+            self.chip8.load_rom(self.rom).map_err(|e| e.to_string())?;
+            self.restarts += 1;
+        }
+        self.tick_budget += TIMER_HZ;
+
+        return Ok(());
+    }
+
+    fn step(&mut self) -> Result<u16, String> {
+        let pc = self.chip8.pc();
+        let info = self
+            .chip8
+            .step()
+            .map_err(|e| format!("CPU halted at {pc:#05X}: {e}"))?;
+        return Ok(info.opcode);
+    }
+}
+
+// Average cost of the two Instant::now() calls around every profiled instruction
+fn clock_overhead() -> Duration {
+    const SAMPLES: u32 = 10_000;
+    let start = Instant::now();
+    for _ in 0..SAMPLES {
+        std::hint::black_box(Instant::now());
+        std::hint::black_box(Instant::now());
+    }
+    return start.elapsed() / SAMPLES;
+}
+
+// First a plain run for the instruction rate, then one as long timing every instruction by
+// family. The per-family bookkeeping only exists in the second pass. Ctrl+C ends the pass
+// it comes in and reports what was measured so far.
+pub fn run(chip8: &mut Chip8, rom: &[u8], hz: u32, duration: Duration) -> Result<String, String> {
+    let mut bench = Bench {
+        chip8,
+        rom,
+        hz: hz.max(1),
+        tick_budget: 0,
+        restarts: 0,
+    };
+
+    let mut executed = 0u64;
+    let start = Instant::now();
+    let mut elapsed = Duration::ZERO;
+    while elapsed < duration && !shutdown::interrupted() {
+        for _ in 0..CLOCK_CHECK {
+            bench.prepare()?;
+            bench.step()?;
+        }
+        executed += CLOCK_CHECK;
+        elapsed = start.elapsed();
+    }
+    let restarts = bench.restarts;
+
+    let overhead = clock_overhead();
+    let mut families: HashMap<u16, FamilyStats> = HashMap::new();
+    let start = Instant::now();
+    let mut profiled = 0u64;
+    while start.elapsed() < duration && !shutdown::interrupted() {
+        for _ in 0..CLOCK_CHECK {
+            bench.prepare()?;
+            let before = Instant::now();
+            let opcode = bench.step()?;
+            let time = before.elapsed().saturating_sub(overhead);
+            let stats = families.entry(family(opcode)).or_default();
+            stats.count += 1;
+            stats.time += time;
+        }
+        profiled += CLOCK_CHECK;
+    }
+
+    let mut out = format!(
+        "{executed} instructions in {:.2}s, {:.2} MIPS\n",
+        elapsed.as_secs_f64(),
+        executed as f64 / elapsed.as_secs_f64() / 1e6
+    );
+    if shutdown::interrupted() {
+        out.push_str("interrupted, the numbers cover the part that ran\n");
+    }
+    if restarts > 0 {
+        out.push_str(&format!(
+            "the program stopped and was restarted {restarts} times\n"
+        ));
+    }
+    let total: Duration = families.values().map(|stats| stats.time).sum();
+    let mut families: Vec<_> = families.into_iter().collect();
+    families.sort_by(|a, b| b.1.time.cmp(&a.1.time).then(a.0.cmp(&b.0)));
+    out.push_str("family      count  instrs   time  ns/instr\n");
+    for (family, stats) in families {
+        out.push_str(&format!(
+            "{:<6} {:>10} {:>6.1}% {:>5.1}% {:>9.1}\n",
+            family_name(family),
+            stats.count,
+            stats.count as f64 * 100.0 / profiled as f64,
+            stats.time.as_secs_f64() * 100.0 / total.as_secs_f64().max(f64::MIN_POSITIVE),
+            stats.time.as_nanos() as f64 / stats.count as f64
+        ));
+    }
+
+    return Ok(out);
+}
@@ -0,0 +1,880 @@
+use super::antiflicker;
+use super::bench;
+use super::debugger::{parse_addr, parse_watch};
+use super::gauges::{self, Gauge};
+use super::input::{self, GamepadConfig};
+use super::keymap::{self, Keymap};
+use super::logging;
+use super::picker::DEFAULT_ROM_DIR;
+use rustchip8::audio::Wave;
+use rustchip8::palette::{PALETTES, parse_color, parse_palette};
+use rustchip8::screenshot::Rgb;
+use rustchip8::{
+    KeyMode, MEM_SIZE, PROGRAM_START, Profile, Quirks, Timing, TraceFilter, VIP_CYCLE_HZ,
+    WatchTarget,
+};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const FRAME_RATE: u32 = 60;
+// ~700 instructions per second, a decent middle ground for most ROMs
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 12;
+const DEFAULT_TURBO: f64 = 8.0;
+const MAX_TURBO: f64 = 100.0;
+const DEFAULT_BEEP_FREQ: f32 = 440.0;
+const DEFAULT_VOLUME: f32 = 0.5;
+// a buffer shorter than a frame underruns between two refills
+const MIN_AUDIO_BUFFER_MS: u64 = 5;
+const MAX_AUDIO_BUFFER_MS: u64 = 500;
+// window pixels per CHIP-8 pixel at startup
+const DEFAULT_SCALE: i32 = 16;
+// how much of the previous frame the CRT filter keeps
+const DEFAULT_GHOSTING: f32 = 0.3;
+// per script callback, see rustchip8::script
+const DEFAULT_SCRIPT_BUDGET: Duration = Duration::from_millis(10);
+
+pub struct Options {
+    pub rom_path: Option<String>,
+    // --rom-b: a second machine Ctrl+B switches to, with its own quirk preset when given
+    pub rom_b: Option<String>,
+    pub quirks_b: Option<Quirks>,
+    // both machines side by side at half size
+    pub split: bool,
+    // --wall DIR: every ROM in DIR at once, see frontend::wall
+    pub wall: Option<String>,
+    // listed when started without a ROM
+    pub rom_dir: String,
+    // instructions per second
+    pub cpu_hz: u32,
+    // speed multiplier while Tab is held
+    pub turbo: f64,
+    pub beep_freq: f32,
+    pub wave: Wave,
+    // --audio-buffer, queued audio and so the latency, None keeps the beeper's default
+    pub audio_buffer: Option<Duration>,
+    pub volume: f32,
+    pub mute: bool,
+    pub disassemble: bool,
+    // --validate: static checks of the ROM instead of running it, --json for tools
+    pub validate: bool,
+    pub json: bool,
+    // --assemble IN OUT: write the machine code for source IN to OUT and exit
+    pub assemble: Option<(String, String)>,
+    pub quirks: Quirks,
+    // the --profile preset the quirks started from, kept in the settings file
+    pub profile: Profile,
+    pub extended_memory: bool,
+    // --hires-vip, also turned on for ROMs that look like one
+    pub vip_hires: bool,
+    // where the ROM is loaded and execution starts
+    pub load_addr: u16,
+    // memory accesses past the end halt the CPU instead of wrapping
+    pub strict_memory: bool,
+    // --strict, also turns on strict_memory
+    pub strict_control_flow: bool,
+    pub stack_size: usize,
+    // 0NNN machine code calls are skipped instead of halting
+    pub ignore_sys: bool,
+    // run this many cycles without a window and print the final machine
+    pub headless: Option<u64>,
+    // golden display to compare with after the headless run, UPDATE_GOLDEN=1 rewrites it
+    pub expect_screen: Option<String>,
+    // --headless with a debugger prompt on stdin
+    pub debug: bool,
+    // count instructions from the start, --stats-out writes the counts on exit
+    pub stats: bool,
+    pub stats_out: Option<String>,
+    // --bench: run flat out for bench_time seconds and print the speed
+    pub bench: bool,
+    pub bench_time: f64,
+    // write a PNG of the display once this many cycles have run
+    pub screenshot_after: Option<(u64, String)>,
+    // animated GIF of the display from the start, --frames long in headless mode
+    pub record_gif: Option<String>,
+    pub gif_frames: Option<u64>,
+    pub palette: [Rgb; 4],
+    pub scale: i32,
+    // where the window opens and how big, --scale sets the size without it
+    pub window_pos: Option<(i32, i32)>,
+    pub window_size: Option<(i32, i32)>,
+    // --config F instead of the settings file in the config directory, see frontend::settings
+    pub config: Option<String>,
+    pub no_config: bool,
+    pub integer_scaling: bool,
+    // bilinear instead of nearest-neighbour scaling
+    pub linear_filter: bool,
+    pub fullscreen: bool,
+    // keep running while the window isn't focused instead of pausing
+    pub run_in_background: bool,
+    // window title without the fps/ips figures
+    pub quiet_title: bool,
+    // shown instead of the ROM's file name
+    pub title: Option<String>,
+    // save the effective settings to <rom>.json and exit
+    pub write_metadata: bool,
+    // scanlines and phosphor persistence, see frontend::crt
+    pub crt: bool,
+    pub ghosting: f32,
+    // number of frames OR-ed together for display
+    pub anti_flicker: Option<usize>,
+    pub trace: bool,
+    // stderr when None
+    pub trace_file: Option<String>,
+    pub trace_filter: Option<TraceFilter>,
+    pub trace_after: u64,
+    // addresses or names from the --symbols file, looked up once it is loaded
+    pub breakpoints: Vec<String>,
+    // --symbols, labels for the disassembly, the debugger and the trace
+    pub symbols: Option<String>,
+    pub watches: Vec<WatchTarget>,
+    // --gauge, memory values shown in a corner
+    pub gauges: Vec<Gauge>,
+    // input recording to write on exit / to play back instead of the keyboard
+    pub record: Option<String>,
+    pub replay: Option<String>,
+    // --layout, replaced by the --keymap file when one is given
+    pub keymap: Keymap,
+    pub keymap_file: Option<String>,
+    // --key-mode, how EX9E/EXA1 see each key
+    pub key_modes: [KeyMode; 16],
+    // --key-help, what each key does in the game, shown beside the keypad cheatsheet
+    pub key_help: [Option<String>; 16],
+    // the cheatsheet for the first seconds of a ROM, off with --no-keypad-help
+    pub keypad_help: bool,
+    // --log-level and --log-file, see frontend::logging
+    pub log_level: Option<String>,
+    pub log_file: Option<String>,
+    // --cheats F instead of <rom>.cheats
+    pub cheats_file: Option<String>,
+    // which file of a zip archive to load when it holds several ROMs
+    pub zip_member: Option<String>,
+    // --script F: Lua bot or test oracle run between frames, needs the scripting feature
+    pub script: Option<String>,
+    // how long one script callback may run
+    pub script_budget: Duration,
+    // --listen ADDR: accept remote control commands over TCP, see frontend::remote
+    pub listen: Option<String>,
+    pub gamepad: GamepadConfig,
+    // with Timing::Vip cpu_hz is VIP machine cycles per second instead of instructions
+    pub timing: Timing,
+    // end a frame early in a delay timer busy-wait, off with --no-idle-skip
+    pub idle_skip: bool,
+}
+
+impl Options {
+    // For stepping a whole frame at a time, the window itself runs at exactly cpu_hz.
+    // Instructions, or machine cycles with --timing vip.
+    pub fn cycles_per_frame(&self) -> u32 {
+        return ((self.cpu_hz + FRAME_RATE / 2) / FRAME_RATE).max(1);
+    }
+
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut options = Options {
+            rom_path: None,
+            rom_b: None,
+            quirks_b: None,
+            split: false,
+            wall: None,
+            rom_dir: String::from(DEFAULT_ROM_DIR),
+            cpu_hz: DEFAULT_INSTRUCTIONS_PER_FRAME * FRAME_RATE,
+            turbo: DEFAULT_TURBO,
+            beep_freq: DEFAULT_BEEP_FREQ,
+            wave: Wave::Square,
+            audio_buffer: None,
+            volume: DEFAULT_VOLUME,
+            mute: false,
+            disassemble: false,
+            validate: false,
+            json: false,
+            assemble: None,
+            quirks: Quirks::new(),
+            profile: Profile::Cosmac,
+            extended_memory: false,
+            vip_hires: false,
+            load_addr: PROGRAM_START as u16,
+            strict_memory: false,
+            strict_control_flow: false,
+            stack_size: rustchip8::STACK_SIZE,
+            ignore_sys: false,
+            headless: None,
+            expect_screen: None,
+            debug: false,
+            stats: false,
+            stats_out: None,
+            bench: false,
+            bench_time: bench::DEFAULT_SECONDS,
+            screenshot_after: None,
+            record_gif: None,
+            gif_frames: None,
+            palette: PALETTES[0].1,
+            scale: DEFAULT_SCALE,
+            window_pos: None,
+            window_size: None,
+            config: None,
+            no_config: false,
+            integer_scaling: true,
+            linear_filter: false,
+            fullscreen: false,
+            run_in_background: false,
+            quiet_title: false,
+            title: None,
+            write_metadata: false,
+            crt: false,
+            ghosting: DEFAULT_GHOSTING,
+            anti_flicker: None,
+            trace: false,
+            trace_file: None,
+            trace_filter: None,
+            trace_after: 0,
+            breakpoints: Vec::new(),
+            symbols: None,
+            watches: Vec::new(),
+            gauges: Vec::new(),
+            record: None,
+            replay: None,
+            keymap: keymap::from_names(&rustchip8::keymap::QWERTY),
+            keymap_file: None,
+            key_modes: [KeyMode::Level; 16],
+            key_help: Default::default(),
+            keypad_help: true,
+            cheats_file: None,
+            zip_member: None,
+            log_level: None,
+            log_file: None,
+            listen: None,
+            script: None,
+            script_budget: DEFAULT_SCRIPT_BUDGET,
+            gamepad: GamepadConfig {
+                index: 0,
+                a: input::DEFAULT_A,
+                b: input::DEFAULT_B,
+                stick_deadzone: None,
+            },
+            timing: Timing::Fixed,
+            idle_skip: true,
+        };
+
+        let mut profile = Profile::Cosmac;
+        // applied on top of the profile no matter where they appear on the command line
+        let mut overrides: Vec<(&str, bool)> = Vec::new();
+        // same for --bg/--fg over --palette
+        let mut background = None;
+        let mut foreground = None;
+
+        // --flag=value is the same as --flag value
+        let args: Vec<&str> = args
+            .iter()
+            .flat_map(|arg| match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => vec![flag, value],
+                _ => vec![arg.as_str()],
+            })
+            .collect();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg {
+                "--ipf" => {
+                    let ipf: u32 = parse_number(arg, args.next())?;
+                    options.cpu_hz = ipf.saturating_mul(FRAME_RATE);
+                }
+                "--hz" => {
+                    options.cpu_hz = parse_number(arg, args.next())?;
+                }
+                "--turbo" => {
+                    let turbo: f64 = parse_number(arg, args.next())?;
+                    if !(1.0..=MAX_TURBO).contains(&turbo) {
+                        return Err(format!("--turbo expects a factor from 1 to {MAX_TURBO}"));
+                    }
+                    options.turbo = turbo;
+                }
+                "--beep-freq" => {
+                    options.beep_freq = parse_number(arg, args.next())?;
+                }
+                "--wave" => {
+                    options.wave = args.next().ok_or("--wave expects a waveform")?.parse()?;
+                }
+                "--audio-buffer" => {
+                    let ms: u64 = parse_number(arg, args.next())?;
+                    if !(MIN_AUDIO_BUFFER_MS..=MAX_AUDIO_BUFFER_MS).contains(&ms) {
+                        return Err(format!(
+                            "--audio-buffer expects {MIN_AUDIO_BUFFER_MS} to {MAX_AUDIO_BUFFER_MS} milliseconds"
+                        ));
+                    }
+                    options.audio_buffer = Some(Duration::from_millis(ms));
+                }
+                "--volume" => {
+                    let volume: f32 = parse_number(arg, args.next())?;
+                    options.volume = volume.clamp(0.0, 1.0);
+                }
+                "--mute" => options.mute = true,
+                "--disassemble" => options.disassemble = true,
+                "--validate" => options.validate = true,
+                "--json" => options.json = true,
+                "--assemble" => {
+                    let (Some(input), Some(output)) = (args.next(), args.next()) else {
+                        return Err(String::from(
+                            "--assemble expects a source and an output path",
+                        ));
+                    };
+                    options.assemble = Some((input.to_string(), output.to_string()));
+                }
+                "--bench" => options.bench = true,
+                "--bench-time" => {
+                    let seconds: f64 = parse_number(arg, args.next())?;
+                    if !(seconds > 0.0 && seconds.is_finite()) {
+                        return Err(format!("--bench-time must be positive, got {seconds}"));
+                    }
+                    options.bench_time = seconds;
+                    options.bench = true;
+                }
+                "--screenshot-after" => {
+                    let cycles = parse_number(arg, args.next())?;
+                    let path = args
+                        .next()
+                        .ok_or("--screenshot-after expects N and a path")?;
+                    options.screenshot_after = Some((cycles, path.to_string()));
+                }
+                "--strict-memory" => options.strict_memory = true,
+                "--strict" => {
+                    options.strict_memory = true;
+                    options.strict_control_flow = true;
+                }
+                "--ignore-sys" => options.ignore_sys = true,
+                "--stack-size" => options.stack_size = parse_number(arg, args.next())?,
+                "--xo-memory" => options.extended_memory = true,
+                "--hires-vip" => options.vip_hires = true,
+                "--load-addr" => {
+                    let value = args.next().ok_or("--load-addr expects an address")?;
+                    let addr = parse_addr(value)?;
+                    if !addr.is_multiple_of(2) || addr as usize >= MEM_SIZE {
+                        return Err(format!(
+                            "--load-addr must be an even address below {MEM_SIZE:#05X}, got {value}"
+                        ));
+                    }
+                    options.load_addr = addr;
+                }
+                "--headless" => {
+                    options.headless = Some(parse_number(arg, args.next())?);
+                }
+                "--debug" => options.debug = true,
+                "--stats" => options.stats = true,
+                "--stats-out" => {
+                    let path = args.next().ok_or("--stats-out expects a path")?;
+                    options.stats_out = Some(path.to_string());
+                    options.stats = true;
+                }
+                "--expect-screen" => {
+                    let path = args.next().ok_or("--expect-screen expects a path")?;
+                    options.expect_screen = Some(path.to_string());
+                }
+                "--timing" => match args.next() {
+                    Some("fixed") => options.timing = Timing::Fixed,
+                    Some("vip") => options.timing = Timing::Vip,
+                    _ => return Err(String::from("--timing expects fixed or vip")),
+                },
+                "--profile" => {
+                    let value = args.next().ok_or("--profile expects a value")?;
+                    profile = value.parse()?;
+                }
+                "--scale" => {
+                    let scale: i32 = parse_number(arg, args.next())?;
+                    options.scale = scale.max(1);
+                    options.window_size = None;
+                }
+                "--window-pos" => {
+                    let value = args.next().ok_or("--window-pos expects X,Y")?;
+                    let position = value.split_once(',').and_then(|(x, y)| {
+                        return Some((x.trim().parse().ok()?, y.trim().parse().ok()?));
+                    });
+                    options.window_pos = Some(
+                        position
+                            .ok_or_else(|| format!("--window-pos expects X,Y, got '{value}'"))?,
+                    );
+                }
+                "--window-size" => {
+                    let value = args.next().ok_or("--window-size expects WIDTHxHEIGHT")?;
+                    let size = value.split_once('x').and_then(|(width, height)| {
+                        let size: (i32, i32) = (width.parse().ok()?, height.parse().ok()?);
+                        return (size.0 > 0 && size.1 > 0).then_some(size);
+                    });
+                    options.window_size = Some(size.ok_or_else(|| {
+                        format!("--window-size expects WIDTHxHEIGHT, got '{value}'")
+                    })?);
+                }
+                "--config" => {
+                    let path = args.next().ok_or("--config expects a path")?;
+                    options.config = Some(path.to_string());
+                }
+                "--no-config" => options.no_config = true,
+                "--no-keypad-help" => options.keypad_help = false,
+                "--no-idle-skip" => options.idle_skip = false,
+                "--trace" => options.trace = true,
+                "--trace-file" => {
+                    let path = args.next().ok_or("--trace-file expects a path")?;
+                    options.trace = true;
+                    options.trace_file = Some(path.to_string());
+                }
+                "--trace-filter" => {
+                    let value = args
+                        .next()
+                        .ok_or("--trace-filter expects opcode families")?;
+                    options.trace_filter = Some(value.parse()?);
+                }
+                "--trace-after" => options.trace_after = parse_number(arg, args.next())?,
+                "--break" => {
+                    let value = args.next().ok_or("--break expects an address or a name")?;
+                    options.breakpoints.push(value.to_string());
+                }
+                "--symbols" => {
+                    let path = args.next().ok_or("--symbols expects a path")?;
+                    options.symbols = Some(path.to_string());
+                }
+                "--watch" => {
+                    let value = args
+                        .next()
+                        .ok_or("--watch expects an address or register")?;
+                    options.watches.push(parse_watch(value)?);
+                }
+                "--gauge" => {
+                    let value = args.next().ok_or("--gauge expects NAME=ADDR:FORMAT")?;
+                    options.gauges.extend(gauges::parse_gauges(value));
+                }
+                "--record-gif" => {
+                    let path = args.next().ok_or("--record-gif expects a path")?;
+                    options.record_gif = Some(path.to_string());
+                }
+                "--frames" => options.gif_frames = Some(parse_number(arg, args.next())?),
+                "--record" => {
+                    let path = args.next().ok_or("--record expects a path")?;
+                    options.record = Some(path.to_string());
+                }
+                "--replay" => {
+                    let path = args.next().ok_or("--replay expects a path")?;
+                    options.replay = Some(path.to_string());
+                }
+                "--rom-a" => {
+                    let path = args.next().ok_or("--rom-a expects a path")?;
+                    if options.rom_path.is_some() {
+                        return Err(format!("unexpected argument '{path}'"));
+                    }
+                    options.rom_path = Some(path.to_string());
+                }
+                "--rom-b" => {
+                    let path = args.next().ok_or("--rom-b expects a path")?;
+                    options.rom_b = Some(path.to_string());
+                }
+                "--profile-b" => {
+                    let value: Profile =
+                        args.next().ok_or("--profile-b expects a value")?.parse()?;
+                    options.quirks_b = Some(value.quirks());
+                }
+                "--split" => options.split = true,
+                "--wall" => {
+                    let dir = args.next().ok_or("--wall expects a directory")?;
+                    options.wall = Some(dir.to_string());
+                }
+                "--rom-dir" => {
+                    let dir = args.next().ok_or("--rom-dir expects a directory")?;
+                    options.rom_dir = dir.to_string();
+                }
+                "--keymap" => {
+                    let path = args.next().ok_or("--keymap expects a path")?;
+                    options.keymap_file = Some(path.to_string());
+                }
+                "--layout" => {
+                    options.keymap =
+                        keymap::parse_layout(args.next().ok_or("--layout expects a name")?)?;
+                }
+                "--key" => {
+                    let value = args.next().ok_or("--key expects K=NAME")?;
+                    let (key, name) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("--key expects K=NAME, got '{value}'"))?;
+                    let key = parse_key(arg, Some(key))?;
+                    options.keymap[key as usize] = keymap::parse_key(name)?;
+                }
+                "--key-mode" => {
+                    let value = args.next().ok_or("--key-mode expects [K=]MODE")?;
+                    for item in value.split(',').map(str::trim) {
+                        match item.split_once('=') {
+                            Some((key, mode)) => {
+                                let key = parse_key(arg, Some(key))?;
+                                options.key_modes[key as usize] = mode.parse()?;
+                            }
+                            None => options.key_modes = [item.parse()?; 16],
+                        }
+                    }
+                }
+                "--key-help" => {
+                    let value = args.next().ok_or("--key-help expects K=TEXT")?;
+                    let (key, text) = value
+                        .split_once('=')
+                        .ok_or_else(|| format!("--key-help expects K=TEXT, got '{value}'"))?;
+                    let key = parse_key(arg, Some(key.trim()))?;
+                    options.key_help[key as usize] = Some(text.trim().to_string());
+                }
+                "--script" => {
+                    let path = args.next().ok_or("--script expects a path")?;
+                    if !cfg!(feature = "scripting") {
+                        return Err(String::from(
+                            "--script needs a build with --features scripting",
+                        ));
+                    }
+                    options.script = Some(path.to_string());
+                }
+                "--script-budget" => {
+                    let ms: u64 = parse_number(arg, args.next())?;
+                    options.script_budget = Duration::from_millis(ms.max(1));
+                }
+                "--log-level" => {
+                    let spec = args.next().ok_or("--log-level expects a level")?;
+                    logging::check_spec(spec)?;
+                    options.log_level = Some(spec.to_string());
+                }
+                "--log-file" => {
+                    let path = args.next().ok_or("--log-file expects a path")?;
+                    options.log_file = Some(path.to_string());
+                }
+                "--cheats" => {
+                    let path = args.next().ok_or("--cheats expects a path")?;
+                    options.cheats_file = Some(path.to_string());
+                }
+                "--zip-member" => {
+                    let name = args.next().ok_or("--zip-member expects a file name")?;
+                    options.zip_member = Some(name.to_string());
+                }
+                "--listen" => {
+                    let addr = args.next().ok_or("--listen expects an address")?;
+                    options.listen = Some(addr.to_string());
+                }
+                "--gamepad" => options.gamepad.index = parse_number(arg, args.next())?,
+                "--gamepad-a" => options.gamepad.a = parse_key(arg, args.next())?,
+                "--gamepad-b" => options.gamepad.b = parse_key(arg, args.next())?,
+                "--stick-deadzone" => {
+                    let deadzone: f32 = parse_number(arg, args.next())?;
+                    if !(0.0..1.0).contains(&deadzone) {
+                        return Err(String::from("--stick-deadzone expects a value from 0 to 1"));
+                    }
+                    options.gamepad.stick_deadzone = Some(deadzone);
+                }
+                "--filter" => match args.next() {
+                    Some("nearest") => options.linear_filter = false,
+                    Some("linear") => options.linear_filter = true,
+                    _ => return Err(String::from("--filter expects nearest or linear")),
+                },
+                "--fullscreen" => options.fullscreen = true,
+                "--run-in-background" => options.run_in_background = true,
+                "--quiet-title" => options.quiet_title = true,
+                "--title" => {
+                    options.title = Some(args.next().ok_or("--title expects a name")?.to_string());
+                }
+                "--write-metadata" => options.write_metadata = true,
+                "--crt" => options.crt = true,
+                "--anti-flicker" => {
+                    options
+                        .anti_flicker
+                        .get_or_insert(antiflicker::DEFAULT_FRAMES);
+                }
+                "--anti-flicker-frames" => {
+                    let frames: usize = parse_number(arg, args.next())?;
+                    if !(2..=3).contains(&frames) {
+                        return Err(String::from("--anti-flicker-frames expects 2 or 3"));
+                    }
+                    options.anti_flicker = Some(frames);
+                }
+                "--ghosting" => {
+                    let ghosting: f32 = parse_number(arg, args.next())?;
+                    if !(0.0..=1.0).contains(&ghosting) {
+                        return Err(String::from("--ghosting expects a value from 0 to 1"));
+                    }
+                    options.ghosting = ghosting;
+                }
+                "--integer-scaling" => options.integer_scaling = parse_switch(arg, args.next())?,
+                "--palette" => {
+                    let value = args.next().ok_or("--palette expects a value")?;
+                    options.palette = parse_palette(value)?;
+                }
+                "--bg" => {
+                    background = Some(parse_color(args.next().ok_or("--bg expects a color")?)?);
+                }
+                "--fg" => {
+                    foreground = Some(parse_color(args.next().ok_or("--fg expects a color")?)?);
+                }
+                flag if flag.starts_with("--quirk-") => {
+                    overrides.push((&flag["--quirk-".len()..], parse_switch(flag, args.next())?));
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown option '{flag}'")),
+                path => {
+                    if options.rom_path.is_some() {
+                        return Err(format!("unexpected argument '{path}'"));
+                    }
+                    options.rom_path = Some(path.to_string());
+                }
+            }
+        }
+        options.cpu_hz = options.cpu_hz.max(1);
+        if options.timing == Timing::Vip {
+            options.cpu_hz = VIP_CYCLE_HZ;
+        }
+        if options.gif_frames.is_some() {
+            if options.record_gif.is_none() {
+                return Err(String::from("--frames needs --record-gif"));
+            }
+            // recording a fixed number of frames runs without the window, the runner stops
+            // at the last frame on its own
+            options.headless.get_or_insert(u64::MAX);
+        }
+        if options.debug && matches!(options.rom_path.as_deref(), None | Some("-")) {
+            return Err(String::from(
+                "--debug reads commands from stdin, the ROM has to be a file",
+            ));
+        }
+        if options.debug {
+            // without a limit from --headless N the prompt is the only way to stop
+            options.headless.get_or_insert(u64::MAX);
+        }
+        if options.expect_screen.is_some() && options.headless.is_none() {
+            return Err(String::from("--expect-screen needs --headless"));
+        }
+        if options.record.is_some() && (options.replay.is_some() || options.headless.is_some()) {
+            return Err(String::from(
+                "--record needs the window and can't be combined with --replay",
+            ));
+        }
+        if options.cheats_file.is_some()
+            && (options.headless.is_some() || options.record.is_some() || options.replay.is_some())
+        {
+            return Err(String::from(
+                "--cheats needs the window and can't be combined with --record or --replay",
+            ));
+        }
+        if options.listen.is_some() && options.headless.is_some() {
+            return Err(String::from("--listen needs the window"));
+        }
+        if let Some(path) = &options.rom_b {
+            if path == "-" || matches!(options.rom_path.as_deref(), None | Some("-")) {
+                return Err(String::from("--rom-b needs both ROMs to be files"));
+            }
+            if options.headless.is_some() || options.record.is_some() || options.replay.is_some() {
+                return Err(String::from(
+                    "--rom-b needs the window and can't be combined with --record or --replay",
+                ));
+            }
+        }
+        if options.wall.is_some()
+            && (options.rom_path.is_some()
+                || options.rom_b.is_some()
+                || options.headless.is_some()
+                || options.bench
+                || options.disassemble
+                || options.validate
+                || options.record.is_some()
+                || options.replay.is_some()
+                || options.listen.is_some()
+                || options.script.is_some())
+        {
+            return Err(String::from(
+                "--wall runs its own ROMs in the window, it can't be combined with a ROM, --rom-b, --headless, --bench, --disassemble, --validate, --record, --replay, --listen or --script",
+            ));
+        }
+        if options.json && !options.validate {
+            return Err(String::from("--json is the output format of --validate"));
+        }
+        if (options.split || options.quirks_b.is_some()) && options.rom_b.is_none() {
+            return Err(String::from("--split and --profile-b need --rom-b"));
+        }
+
+        if let Some(color) = background {
+            options.palette[0] = color;
+        }
+        if let Some(color) = foreground {
+            options.palette[1] = color;
+        }
+
+        options.profile = profile;
+        options.quirks = profile.quirks();
+        for (quirk, enabled) in overrides {
+            let field = match quirk {
+                "shift" => &mut options.quirks.shift_uses_vy,
+                "memory" => &mut options.quirks.memory_increment,
+                "jump" => &mut options.quirks.jump_uses_vx,
+                "wrap" => &mut options.quirks.sprite_wrap,
+                "vfreset" => &mut options.quirks.vf_reset,
+                "dispwait" => &mut options.quirks.display_wait,
+                "overflow" => &mut options.quirks.index_overflow,
+                _ => return Err(format!("unknown quirk '{quirk}'")),
+            };
+            *field = enabled;
+        }
+
+        return Ok(options);
+    }
+}
+
+fn parse_switch(flag: &str, value: Option<&str>) -> Result<bool, String> {
+    match value {
+        Some("on" | "true" | "1") => return Ok(true),
+        Some("off" | "false" | "0") => return Ok(false),
+        _ => return Err(format!("{flag} expects on or off")),
+    }
+}
+
+// A single hex digit naming a CHIP-8 key
+fn parse_key(flag: &str, value: Option<&str>) -> Result<u8, String> {
+    let value = value.ok_or_else(|| format!("{flag} expects a key"))?;
+    match u8::from_str_radix(value, 16) {
+        Ok(key) if value.len() == 1 => return Ok(key),
+        _ => return Err(format!("{flag} expects a CHIP-8 key 0-F, got '{value}'")),
+    }
+}
+
+fn parse_number<T: FromStr>(flag: &str, value: Option<&str>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{flag} expects a value"))?;
+    return value
+        .parse()
+        .map_err(|_| format!("{flag} expects a number, got '{value}'"));
+}
+
+pub const USAGE: &str = "\
+usage: RustChip8 [OPTIONS] [ROM | -]
+       RustChip8 [OPTIONS] --rom-a ROM --rom-b ROM [--profile-b NAME] [--split]
+
+  --ipf N                 instructions per 60Hz frame
+  --hz N                  instructions per second
+  --timing fixed|vip      fixed instruction rate (default) or per-instruction COSMAC VIP cycle costs
+  --no-idle-skip          run delay timer wait loops instruction by instruction instead of
+                          ending the frame once they can't finish before the next tick
+  --turbo N               fast-forward factor while Tab is held (default 8)
+  --beep-freq HZ          beep pitch
+  --wave NAME             beep waveform: square (default), sine, triangle or noise
+  --audio-buffer MS       audio queued at a time, lower for less latency (default about 23)
+  --volume 0..1           beep volume
+  --mute                  disable audio
+  --disassemble           print a listing of the ROM and exit
+  --validate              check the ROM without running it: code that doesn't decode, bad
+                          jumps, sprites read past its end, quirk-sensitive instructions
+  --json                  print the --validate report as JSON
+  --assemble IN OUT       assemble source file IN into ROM OUT (loaded at --load-addr) and exit
+  --write-metadata        save the effective settings to <rom>.json and exit
+  --bench                 measure how fast the CPU runs the ROM (or a built-in mix without one)
+  --bench-time S          seconds per benchmark pass (default 5, implies --bench)
+  --headless N            run N cycles without a window, then print the screen and registers
+  --expect-screen F       with --headless: fail unless the display matches text file F
+                          (UPDATE_GOLDEN=1 writes F instead)
+  --stats                 count executed instructions from the start (F3 shows them)
+  --stats-out F           write the instruction counts to JSON file F on exit
+  --debug                 headless with a debugger prompt on stdin (step, break, mem, disas, set..)
+  --screenshot-after N F  save the display to PNG file F after N cycles
+  --record-gif F          record the display to animated GIF F (F8 toggles recording)
+  --frames N              with --record-gif: record N frames without a window
+  --log-level SPEC        error, warn, info (default), debug or trace, also per target like
+                          warn,rustchip8::cpu=trace (RUST_LOG without it)
+  --log-file F            write the log to file F instead of stderr
+  --trace                 log every executed instruction to stderr
+  --trace-file F          log instructions to file F instead
+  --trace-filter LIST     only log these opcode families, e.g. DXYN,FX0A
+  --trace-after N         start logging after N instructions
+  --break ADDR|NAME       pause before executing ADDR or the --symbols label NAME, can be
+                          repeated
+  --symbols F             labels for addresses, lines of 'name = 0x2A4', used by the
+                          disassembly, the debugger and the trace
+  --watch ADDR|vX         pause when a memory byte or register changes, can be repeated
+  --gauge NAME=ADDR:FMT   show a memory value in a corner (Ctrl+G), FMT is u8, u16, bcd3
+                          or binary, can be repeated or comma-separated
+  --record F              record keypad input to F for --replay
+  --replay F              play back input recorded with --record (also with --headless)
+  --rom-a F / --rom-b F   run two ROMs in separate machines, Ctrl+B switches between them
+  --profile-b NAME        quirk preset for the --rom-b machine (default the same as A)
+  --split                 show both machines side by side
+  --wall DIR              run every ROM in DIR at once in a grid, click one to zoom in
+  --rom-dir DIR           ROMs to list when started without one (default roms)
+  --keymap F              keypad mapping file, lines of '<0-F> = KEY_NAME'
+  --layout NAME           built-in keypad mapping: qwerty, azerty or natural
+  --key K=NAME            map CHIP-8 key K to host key NAME, on top of the layout
+  --key-mode [K=]MODE     what EX9E/EXA1 see of key K (all keys without K): level (held),
+                          press or release (only in the frame it went down or up),
+                          comma-separated or repeated
+  --key-help K=TEXT       what key K does, shown next to the keypad cheatsheet (F4)
+  --no-keypad-help        don't show the keypad cheatsheet when a ROM starts
+  --cheats F              freeze/poke cheats to use instead of <rom>.cheats
+  --zip-member NAME       file to load from a zip archive that holds several ROMs
+  --script F              run Lua script F between frames: on_frame(), on_breakpoint(addr)
+                          (needs --features scripting)
+  --script-budget MS      longest a script callback may run (default 10)
+  --listen ADDR           accept KEYDOWN/KEYUP/RESET/LOADSTATE/SCREENSHOT/PAUSE/STEP commands
+                          over TCP on ADDR, e.g. 127.0.0.1:5555
+  --gamepad N             use gamepad N (default 0), the d-pad is 2/4/6/8
+  --gamepad-a K           CHIP-8 key for the A button (default 5)
+  --gamepad-b K           CHIP-8 key for the B button (default 6)
+  --stick-deadzone 0..1   also use the left stick as the d-pad past this deadzone
+  --xo-memory             64K XO-CHIP address space (automatic for ROMs that don't fit in 4K)
+  --hires-vip             COSMAC VIP 64x64 hi-res mode (automatic for ROMs with its stub)
+  --load-addr ADDR        load the ROM and start at ADDR instead of 0x200 (0x600 for ETI-660)
+  --strict-memory         accesses past the end of memory are an error instead of wrapping
+  --strict                --strict-memory, and pc leaving memory or odd jumps are errors
+  --stack-size N          deepest subroutine nesting before a stack overflow (default 16)
+  --ignore-sys            skip 0NNN machine code calls instead of halting
+  --profile NAME          quirk preset: cosmac, chip48 or schip
+  --scale N               window pixels per CHIP-8 pixel at startup
+  --window-pos X,Y        open the window at X,Y on the screen
+  --window-size WxH       open the window W by H pixels big instead of --scale
+  --config F              read and save settings in file F
+  --no-config             don't read or save the settings file
+  --fullscreen            start in fullscreen
+  --run-in-background     keep running when the window loses focus
+  --quiet-title           leave fps and ips out of the window title
+  --title NAME            show NAME in the title instead of the ROM's file name
+  --integer-scaling on|off
+                          whole-number pixel sizes, off stretches to the window
+  --filter nearest|linear scaling filter for the display
+  --crt                   start with the CRT scanline filter (Ctrl+T toggles it)
+  --ghosting 0..1         CRT phosphor persistence, 0 turns it off
+  --anti-flicker          show pixels lit in either of the last two frames
+  --anti-flicker-frames N blend 2 or 3 frames (implies --anti-flicker)
+  --palette NAME|C,C,..   classic, amber, paperwhite, gameboy or up to 4 RRGGBB colors
+  --bg RRGGBB             background color
+  --fg RRGGBB             foreground color
+  --quirk-shift on|off    8XY6/8XYE shift VY into VX
+  --quirk-memory on|off   FX55/FX65 increment I
+  --quirk-jump on|off     BNNN jumps to XNN + VX
+  --quirk-wrap on|off     DXYN wraps sprites instead of clipping
+  --quirk-vfreset on|off  8XY1/8XY2/8XY3 clear VF
+  --quirk-dispwait on|off DXYN waits for the next 60Hz frame
+  --quirk-overflow on|off FX1E sets VF when I passes 0xFFF";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Options {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        return Options::parse(&args).unwrap_or_else(|e| panic!("{args:?}: {e}"));
+    }
+
+    fn error(args: &[&str]) -> String {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        match Options::parse(&args) {
+            Ok(_) => panic!("{args:?} parsed"),
+            Err(e) => return e,
+        }
+    }
+
+    #[test]
+    fn fg_and_bg_override_the_palette() {
+        let options = parse(&["--bg", "101020", "--palette", "amber", "--fg=ffcc00"]);
+        let amber = PALETTES[1].1;
+        assert_eq!(
+            options.palette,
+            [[0x10, 0x10, 0x20], [0xFF, 0xCC, 0x00], amber[2], amber[3]]
+        );
+        assert!(error(&["--fg", "yellow"]).contains("invalid color 'yellow'"));
+        assert_eq!(error(&["--bg"]), "--bg expects a color");
+    }
+
+    #[test]
+    fn strict_turns_on_both_checks() {
+        let options = parse(&["--strict", "game.ch8"]);
+        assert!(options.strict_memory && options.strict_control_flow);
+        let options = parse(&["--strict-memory", "game.ch8"]);
+        assert!(options.strict_memory && !options.strict_control_flow);
+    }
+}
@@ -0,0 +1,66 @@
+use rustchip8::FrameResult;
+
+// How many drawn frames a collision stays lit, fading out
+pub const COLLISION_FRAMES: u32 = 8;
+
+// The pixels the last colliding DXYN erased, kept for a few frames so a collision that
+// happens once is still visible, and VF after the most recent DXYN.
+pub struct CollisionFlash {
+    pixels: Vec<(usize, usize)>,
+    frames_left: u32,
+    // VF right after the last DXYN, None until one ran
+    vf: Option<u8>,
+}
+
+impl CollisionFlash {
+    pub fn new() -> Self {
+        return CollisionFlash {
+            pixels: Vec::new(),
+            frames_left: 0,
+            vf: None,
+        };
+    }
+
+    // The DXYNs of a frame. Draws without a collision leave the last flash to fade, so
+    // sprites redrawn every frame don't hide it.
+    pub fn record(&mut self, frame: &FrameResult) {
+        if frame.draw_vf.is_some() {
+            self.vf = frame.draw_vf;
+        }
+        if !frame.collisions.is_empty() {
+            self.pixels.clear();
+            self.pixels.extend_from_slice(&frame.collisions);
+            self.frames_left = COLLISION_FRAMES;
+        }
+    }
+
+    pub fn end_frame(&mut self) {
+        self.frames_left = self.frames_left.saturating_sub(1);
+        if self.frames_left == 0 {
+            self.pixels.clear();
+        }
+    }
+
+    // After a reset or a new ROM
+    pub fn clear(&mut self) {
+        *self = CollisionFlash::new();
+    }
+
+    // The pixels still lit and how far they have faded, 1.0 just after the collision
+    pub fn pixels(&self) -> (&[(usize, usize)], f32) {
+        return (
+            &self.pixels,
+            self.frames_left as f32 / COLLISION_FRAMES as f32,
+        );
+    }
+
+    pub fn vf(&self) -> Option<u8> {
+        return self.vf;
+    }
+}
+
+impl Default for CollisionFlash {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
@@ -0,0 +1,84 @@
+use raylib::prelude::Color;
+use rustchip8::Framebuffer;
+
+// Texels per CHIP-8 pixel in low-res mode, the output is always 256x128
+const LOWRES_SCALE: usize = 4;
+// every other texel row is a dark scanline
+const SCANLINE: f32 = 0.55;
+// the right column and bottom row of every pixel are the gap between phosphor dots
+const GRID: f32 = 0.8;
+
+// CPU-side CRT look: scanlines, a faint pixel grid and phosphor persistence, where lit
+// pixels fade out over a few frames instead of vanishing (which also hides the flicker
+// of XOR drawing).
+pub struct Crt {
+    // how much of the previous frame stays visible, 0 disables persistence
+    ghosting: f32,
+    // last shown color of every CHIP-8 pixel
+    glow: Vec<[f32; 3]>,
+}
+
+impl Crt {
+    pub fn new(ghosting: f32) -> Self {
+        return Crt {
+            ghosting: ghosting.clamp(0.0, 1.0),
+            glow: Vec::new(),
+        };
+    }
+
+    // Texels per CHIP-8 pixel for a display `cols` wide
+    pub fn scale(cols: usize) -> usize {
+        return LOWRES_SCALE * rustchip8::SCREEN_WIDTH / cols;
+    }
+
+    // With persistence the picture keeps changing after the display stops
+    pub fn animates(&self) -> bool {
+        return self.ghosting > 0.0;
+    }
+
+    // Fills `out` with RGBA texels, Crt::scale() per CHIP-8 pixel in each direction
+    pub fn render(
+        &mut self,
+        display: &Framebuffer,
+        (cols, rows): (usize, usize),
+        palette: &[Color; 4],
+        out: &mut [u8],
+    ) {
+        let scale = Crt::scale(cols);
+        if self.glow.len() != cols * rows {
+            self.glow = vec![[0.0; 3]; cols * rows];
+        }
+
+        let background = palette[0];
+        for (y, row) in display.iter().take(rows).enumerate() {
+            for (x, &pixel) in row.iter().take(cols).enumerate() {
+                let lit = palette[pixel as usize & 0b11];
+                let glow = &mut self.glow[y * cols + x];
+                for (channel, value) in glow.iter_mut().zip([lit.r, lit.g, lit.b]) {
+                    *channel = (value as f32).max(*channel * self.ghosting);
+                }
+                let background = [background.r, background.g, background.b];
+
+                for ty in 0..scale {
+                    for tx in 0..scale {
+                        let mut shade = 1.0;
+                        if (y * scale + ty) % 2 == 1 {
+                            shade *= SCANLINE;
+                        }
+                        if tx == scale - 1 || ty == scale - 1 {
+                            shade *= GRID;
+                        }
+
+                        let i = ((y * scale + ty) * cols * scale + x * scale + tx) * 4;
+                        for c in 0..3 {
+                            // keep at least the background so ghosts fade into it
+                            let value = glow[c].max(background[c] as f32) * shade;
+                            out[i + c] = value as u8;
+                        }
+                        out[i + 3] = 255;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,526 @@
+use super::headless::{self, Runner};
+use super::shutdown;
+use rustchip8::{Chip8, CpuState, Symbols, WatchTarget, disassemble_with};
+use std::io::{self, BufRead, Write, stdin};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+pub enum Command {
+    Continue,
+    // run this many instructions
+    Step(u32),
+    // over a 2NNN call, a plain step for anything else
+    StepOver,
+    // until the current subroutine returns
+    StepOut,
+    Registers,
+    // an address or a name from the symbol file, looked up when the command runs
+    Break(String),
+    Delete(String),
+    Watch(WatchTarget),
+    Unwatch(WatchTarget),
+    // hex dump of len bytes
+    Memory { addr: u16, len: u16 },
+    // listing of count instructions, from pc when addr is None
+    Disassemble { addr: Option<u16>, count: u16 },
+    Display,
+    Set(Target, u16),
+    Quit,
+    Help,
+}
+
+// What `set` can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    V(u8),
+    I,
+    Pc,
+    Dt,
+    St,
+}
+
+// Instructions a step-over or step-out runs at most, for subroutines that never return
+pub const STEP_LIMIT: u64 = 100_000;
+
+pub const HELP: &str = "commands: c (continue), s [n] (step), n (step over), finish (step out), \
+                        regs, b <addr|name>, del <addr|name>, \
+                        watch <addr|vX>, unwatch <addr|vX>, mem <addr> [len], \
+                        disas [addr] [n], display, set <vX|i|pc|dt|st> <value>, q (quit); \
+                        numbers are hex";
+
+// Lines typed on stdin, read on a separate thread so the window keeps drawing. When the
+// ROM itself came through stdin this simply hits EOF and never sends anything.
+pub fn spawn_console() -> Receiver<String> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in stdin().lock().lines() {
+            let Ok(line) = line else { break };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    return receiver;
+}
+
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().unwrap_or("");
+    let mut arg = || {
+        return words
+            .next()
+            .ok_or_else(|| format!("{command} expects an argument"));
+    };
+
+    let command = match command {
+        "c" | "continue" => Command::Continue,
+        "s" | "step" => match words.next() {
+            Some(n) => Command::Step(parse_addr(n)?.max(1) as u32),
+            None => Command::Step(1),
+        },
+        "n" | "next" => Command::StepOver,
+        "finish" | "out" => Command::StepOut,
+        "regs" => Command::Registers,
+        "b" | "break" => Command::Break(arg()?.to_string()),
+        "del" | "delete" => Command::Delete(arg()?.to_string()),
+        "watch" => Command::Watch(parse_watch(arg()?)?),
+        "unwatch" => Command::Unwatch(parse_watch(arg()?)?),
+        "mem" | "x" => {
+            let addr = parse_addr(arg()?)?;
+            let len = words.next().map_or(Ok(0x10), parse_addr)?;
+            Command::Memory { addr, len }
+        }
+        "disas" => {
+            let addr = words.next().map(parse_addr).transpose()?;
+            let count = words.next().map_or(Ok(0x10), parse_addr)?;
+            Command::Disassemble { addr, count }
+        }
+        "display" => Command::Display,
+        "set" => {
+            let target = arg()?;
+            let value = parse_addr(arg()?)?;
+            Command::Set(parse_target(target)?, value)
+        }
+        "q" | "quit" => Command::Quit,
+        "h" | "help" => Command::Help,
+        _ => return Err(format!("unknown command '{line}', {HELP}")),
+    };
+    if let Some(extra) = words.next() {
+        return Err(format!("unexpected argument '{extra}'"));
+    }
+
+    return Ok(command);
+}
+
+fn parse_target(value: &str) -> Result<Target, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "i" => return Ok(Target::I),
+        "pc" => return Ok(Target::Pc),
+        "dt" => return Ok(Target::Dt),
+        "st" => return Ok(Target::St),
+        _ => {}
+    }
+    match parse_watch(value) {
+        Ok(WatchTarget::Reg(x)) => return Ok(Target::V(x)),
+        _ => return Err(format!("can't set '{value}', expected vX, i, pc, dt or st")),
+    }
+}
+
+// The commands that don't run the CPU, shared by the window's console and the headless
+// debugger. Returns what to print, None for the ones the caller has to handle.
+pub fn apply(chip8: &mut Chip8, command: &Command) -> Option<Result<String, String>> {
+    let output = match *command {
+        Command::Registers => Ok(headless::registers(chip8)),
+        Command::Break(ref location) => match parse_location(location, chip8.symbols()) {
+            Ok(addr) => {
+                chip8.set_breakpoint(addr);
+                Ok(format!("Breakpoint set at {}\n", describe(chip8, addr)))
+            }
+            Err(e) => Err(e),
+        },
+        Command::Delete(ref location) => match parse_location(location, chip8.symbols()) {
+            Ok(addr) if chip8.clear_breakpoint(addr) => {
+                Ok(format!("Breakpoint at {} deleted\n", describe(chip8, addr)))
+            }
+            Ok(addr) => Err(format!("No breakpoint at {}", describe(chip8, addr))),
+            Err(e) => Err(e),
+        },
+        Command::Watch(target) => {
+            watch(chip8, target, true);
+            Ok(String::new())
+        }
+        Command::Unwatch(target) => match watch(chip8, target, false) {
+            true => Ok(String::new()),
+            false => Err(String::from("Nothing was watched there")),
+        },
+        Command::Memory { addr, len } => Ok(memory(chip8, addr, len)),
+        Command::Disassemble { addr, count } => {
+            Ok(listing(chip8, addr.unwrap_or(chip8.pc()), count))
+        }
+        Command::Display => Ok(headless::screen(chip8)),
+        Command::Set(target, value) => set(chip8, target, value).map(|()| String::new()),
+        Command::Help => Ok(format!("{HELP}\n")),
+        Command::Continue
+        | Command::Step(_)
+        | Command::StepOver
+        | Command::StepOut
+        | Command::Quit => return None,
+    };
+
+    return Some(output);
+}
+
+// 0x2A4, or 0x2A4 (draw_sprite) when it has a name
+fn describe(chip8: &Chip8, addr: u16) -> String {
+    match chip8.symbols().label(addr) {
+        Some(name) => return format!("{addr:#05X} ({name})"),
+        None => return format!("{addr:#05X}"),
+    }
+}
+
+// Where a step-over or step-out stops: the stack back at `depth` (or shallower) and, for a
+// step-over, pc at the instruction after the call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepTarget {
+    depth: usize,
+    pc: Option<u16>,
+}
+
+impl StepTarget {
+    // None when the next instruction isn't a 2NNN
+    pub fn over(chip8: &Chip8) -> Option<Self> {
+        let opcode = chip8.peek_opcode()?;
+        if opcode & 0xF000 != 0x2000 {
+            return None;
+        }
+        return Some(StepTarget {
+            depth: chip8.stack().len(),
+            pc: Some(chip8.pc().wrapping_add(2)),
+        });
+    }
+
+    // None outside of a subroutine
+    pub fn out(chip8: &Chip8) -> Option<Self> {
+        let depth = chip8.stack().len().checked_sub(1)?;
+        return Some(StepTarget { depth, pc: None });
+    }
+
+    pub fn reached(&self, chip8: &Chip8) -> bool {
+        return chip8.stack().len() <= self.depth && self.pc.is_none_or(|pc| chip8.pc() == pc);
+    }
+}
+
+pub fn watch(chip8: &mut Chip8, target: WatchTarget, enable: bool) -> bool {
+    match (target, enable) {
+        (WatchTarget::Mem(addr), true) => chip8.watch_mem(addr),
+        (WatchTarget::Reg(reg), true) => chip8.watch_reg(reg),
+        (WatchTarget::Mem(addr), false) => return chip8.unwatch_mem(addr),
+        (WatchTarget::Reg(reg), false) => return chip8.unwatch_reg(reg),
+    }
+
+    return true;
+}
+
+fn set(chip8: &mut Chip8, target: Target, value: u16) -> Result<(), String> {
+    let byte = || u8::try_from(value).map_err(|_| format!("{value:#X} doesn't fit in a byte"));
+    match target {
+        Target::V(x) => chip8.set_register(x, byte()?),
+        Target::I => chip8.set_reg_i(value),
+        Target::Pc if value as usize >= chip8.mem().len() => {
+            return Err(format!("pc {value:#05X} is outside memory"));
+        }
+        Target::Pc => chip8.set_pc(value),
+        Target::Dt => chip8.set_delay_timer(byte()?),
+        Target::St => chip8.set_sound_timer(byte()?),
+    }
+
+    return Ok(());
+}
+
+// 16 bytes per line with their ASCII, reads wrap around the end of memory
+fn memory(chip8: &Chip8, addr: u16, len: u16) -> String {
+    let mem = chip8.mem();
+    let mut out = String::new();
+    for start in (0..len as usize).step_by(16) {
+        let line_addr = (addr as usize + start) % mem.len();
+        let bytes: Vec<u8> = (start..(start + 16).min(len as usize))
+            .map(|i| mem[(addr as usize + i) % mem.len()])
+            .collect();
+        let hex: Vec<String> = bytes.iter().map(|byte| format!("{byte:02X}")).collect();
+        let ascii: String = bytes
+            .iter()
+            .map(|&byte| {
+                if byte.is_ascii_graphic() {
+                    byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        out.push_str(&format!(
+            "{line_addr:04X}: {:<47}  {ascii}\n",
+            hex.join(" ")
+        ));
+    }
+
+    return out;
+}
+
+// `count` instructions from `addr`, the one at pc marked with '>' and the named ones under
+// a `name:` line
+fn listing(chip8: &Chip8, addr: u16, count: u16) -> String {
+    let mem = chip8.mem();
+    let mut out = String::new();
+    let mut addr = addr as usize % mem.len();
+    for _ in 0..count {
+        if let Some(name) = chip8.symbols().label(addr as u16) {
+            out.push_str(&format!("{name}:\n"));
+        }
+        let opcode = u16::from_be_bytes([mem[addr], mem[(addr + 1) % mem.len()]]);
+        let marker = if addr == chip8.pc() as usize {
+            '>'
+        } else {
+            ' '
+        };
+        out.push_str(&format!(
+            "{marker} {addr:04X}: {opcode:04X}  {}\n",
+            disassemble_with(opcode, chip8.symbols())
+        ));
+        addr = (addr + 2) % mem.len();
+    }
+
+    return out;
+}
+
+// --headless --debug: a prompt on `input` that runs the machine with `runner`, never past
+// `limit` instructions. Bad commands only print an error, Ctrl+C stops a run and goes back
+// to the prompt.
+pub fn repl(
+    chip8: &mut Chip8,
+    runner: &mut Runner,
+    limit: u64,
+    input: impl BufRead,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    runner.set_debug(true);
+    writeln!(out, "{HELP}")?;
+    write!(out, "{}", listing(chip8, chip8.pc(), 1))?;
+    let mut lines = input.lines();
+    loop {
+        write!(out, "(chip8) ")?;
+        out.flush()?;
+        let Some(line) = lines.next().transpose()? else {
+            writeln!(out)?;
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match parse_command(&line) {
+            Ok(command) => command,
+            Err(e) => {
+                writeln!(out, "ERROR: {e}")?;
+                continue;
+            }
+        };
+        let until = match (apply(chip8, &command), command) {
+            (Some(Ok(output)), _) => {
+                write!(out, "{output}")?;
+                continue;
+            }
+            (Some(Err(e)), _) => {
+                writeln!(out, "{e}")?;
+                continue;
+            }
+            (None, Command::Quit) => break,
+            (None, Command::Step(n)) => runner.cycles().saturating_add(n as u64).min(limit),
+            (None, Command::StepOver) => {
+                let target = StepTarget::over(chip8);
+                runner.set_target(target);
+                let n = if target.is_some() { STEP_LIMIT } else { 1 };
+                runner.cycles().saturating_add(n).min(limit)
+            }
+            (None, Command::StepOut) => {
+                let Some(target) = StepTarget::out(chip8) else {
+                    writeln!(out, "Not in a subroutine")?;
+                    continue;
+                };
+                runner.set_target(Some(target));
+                runner.cycles().saturating_add(STEP_LIMIT).min(limit)
+            }
+            (None, _) => limit,
+        };
+
+        // one typed at the prompt isn't for this run
+        shutdown::take_interrupt();
+        let result = runner.run_until(chip8, until);
+        // still set when the run stopped for another reason
+        let unfinished = runner.take_target().is_some();
+        if let Err(e) = result {
+            writeln!(out, "ERROR: {e}")?;
+        } else if shutdown::take_interrupt() {
+            writeln!(out, "Interrupted at {}", describe(chip8, chip8.pc()))?;
+        } else if let Some(hit) = runner.take_watch_hit() {
+            writeln!(out, "Watchpoint: {hit}")?;
+        } else if chip8.at_breakpoint() {
+            writeln!(out, "Breakpoint at {}", describe(chip8, chip8.pc()))?;
+        } else if runner.cycles() >= limit {
+            writeln!(out, "Stopped after {limit} instructions")?;
+        } else if unfinished && runner.cycles() >= until {
+            writeln!(
+                out,
+                "Gave up after {STEP_LIMIT} instructions without returning"
+            )?;
+        } else {
+            match chip8.cpu_state() {
+                CpuState::Running => {}
+                CpuState::WaitingForKey => writeln!(out, "Waiting for a key")?,
+                CpuState::SelfJump => writeln!(out, "Program stopped in a jump to itself")?,
+                CpuState::Exited => writeln!(out, "Program exited")?,
+            }
+        }
+        write!(out, "{}", listing(chip8, chip8.pc(), 1))?;
+    }
+
+    return Ok(());
+}
+
+// 0x220 or plain hex 220
+pub fn parse_addr(value: &str) -> Result<u16, String> {
+    let hex = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    return u16::from_str_radix(hex, 16).map_err(|_| format!("invalid address '{value}'"));
+}
+
+// A name from the symbol file, or an address like parse_addr(). Names win over hex that
+// looks the same, so a label called `add` is the label.
+pub fn parse_location(value: &str, symbols: &Symbols) -> Result<u16, String> {
+    if let Some(addr) = symbols.addr(value) {
+        return Ok(addr);
+    }
+    match parse_addr(value) {
+        Ok(addr) => return Ok(addr),
+        Err(_) if symbols.is_empty() => {
+            return Err(format!(
+                "invalid address '{value}', names need a symbol file (--symbols)"
+            ));
+        }
+        Err(_) => return Err(format!("unknown symbol or address '{value}'")),
+    }
+}
+
+// vX for a register, anything else is a memory address
+pub fn parse_watch(value: &str) -> Result<WatchTarget, String> {
+    if let Some(reg) = value.strip_prefix(['v', 'V'])
+        && reg.len() == 1
+    {
+        let reg = u8::from_str_radix(reg, 16).map_err(|_| format!("invalid register '{value}'"))?;
+        return Ok(WatchTarget::Reg(reg));
+    }
+
+    return Ok(WatchTarget::Mem(parse_addr(value)?));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustchip8::assemble;
+
+    // The output of a session typing `input` at the prompt
+    fn session(source: &str, input: &str) -> String {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&assemble(source, 0x200).unwrap()).unwrap();
+        let mut runner = Runner::new(600, None);
+        let mut out = Vec::new();
+        repl(&mut chip8, &mut runner, 1000, input.as_bytes(), &mut out).unwrap();
+        return String::from_utf8(out).unwrap();
+    }
+
+    const COUNTER: &str = "
+                LD V0, 5
+                LD V1, 7
+        loop:   ADD V0, 1
+                JP loop
+    ";
+
+    #[test]
+    fn scripted_session() {
+        let out = session(COUNTER, "b 204\nc\nregs\nc\nregs\nset V3 4F\nregs\nq\n");
+        assert!(out.contains("Breakpoint set at 0x204\n"), "{out}");
+        assert!(out.contains("Breakpoint at 0x204\n> 0204: 7001  ADD V0, 0x01\n"));
+        assert!(out.contains("V0=05 V1=07 V2=00"));
+        // a continue from the breakpoint goes once around the loop
+        assert!(out.contains("V0=06 V1=07 V2=00 V3=00"));
+        assert!(out.contains("V0=06 V1=07 V2=00 V3=4F"));
+        assert!(out.contains("PC=0204 I=0000 DT=00 ST=00 SP=0\n"));
+        assert!(out.ends_with("(chip8) "), "{out}");
+    }
+
+    #[test]
+    fn steps_and_listings() {
+        let out = session(COUNTER, "s 3\nregs\nmem 200 4\ndisas 200 2\n");
+        assert!(out.contains("V0=06 V1=07"), "{out}");
+        assert!(out.contains("> 0206: 1204  JP 0x204\n"));
+        assert!(out.contains("0200: 60 05 61 07"));
+        assert!(out.contains("  0200: 6005  LD V0, 0x05\n  0202: 6107  LD V1, 0x07\n"));
+        // EOF leaves like quit
+        assert!(out.ends_with("(chip8) \n"));
+    }
+
+    #[test]
+    fn runs_end_where_the_program_stops() {
+        let out = session("LD V0, K", "c\n");
+        assert!(out.contains("Waiting for a key\n"), "{out}");
+        let out = session("stop: JP stop", "c\n");
+        assert!(
+            out.contains("Program stopped in a jump to itself\n"),
+            "{out}"
+        );
+        let out = session(COUNTER, "c\n");
+        assert!(out.contains("Stopped after 1000 instructions\n"), "{out}");
+    }
+
+    #[test]
+    fn display_dump() {
+        let out = session("LD F, V0\nDRW V0, V0, 5\nstop: JP stop", "c\ndisplay\n");
+        // the font's 0
+        assert!(out.contains("(chip8) ████"), "{out}");
+        assert!(out.contains("\n█  █"));
+    }
+
+    #[test]
+    fn break_locations() {
+        let symbols = rustchip8::parse_symbols("add = 0x300\n_draw = 0x2A4").unwrap();
+        // a label wins over hex that reads the same
+        assert_eq!(parse_location("add", &symbols), Ok(0x300));
+        assert_eq!(parse_location("_draw", &symbols), Ok(0x2A4));
+        assert_eq!(parse_location("0x2A4", &symbols), Ok(0x2A4));
+        assert_eq!(parse_location("2a6", &symbols), Ok(0x2A6));
+        assert_eq!(
+            parse_location("draw", &symbols),
+            Err(String::from("unknown symbol or address 'draw'"))
+        );
+        assert_eq!(
+            parse_location("draw", &Symbols::new()),
+            Err(String::from(
+                "invalid address 'draw', names need a symbol file (--symbols)"
+            ))
+        );
+    }
+
+    #[test]
+    fn mistakes_keep_the_prompt() {
+        let out = session(COUNTER, "frobnicate\nset V3\nset Q 1\nb nowhere\nregs\n");
+        assert!(
+            out.contains("ERROR: unknown command 'frobnicate', commands:"),
+            "{out}"
+        );
+        assert!(out.contains("ERROR: set expects an argument\n"));
+        assert!(out.contains("ERROR: can't set 'Q', expected vX, i, pc, dt or st\n"));
+        assert!(out.contains("invalid address 'nowhere', names need a symbol file (--symbols)\n"));
+        assert!(out.contains("V0=00 V1=00"));
+    }
+}
@@ -0,0 +1,111 @@
+// Gauges: memory values pinned in a corner of the window while finding out where a game
+// keeps its variables. `--gauge score=0x3E0:bcd3,lives=0x3E4:u8` or the "gauges" object of
+// a sidecar. A gauge that doesn't parse or points past the end of memory shows its error
+// in place of the value.
+
+use super::debugger::parse_addr;
+use rustchip8::Chip8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GaugeFormat {
+    U8,
+    // big-endian like the CHIP-8's own addresses
+    U16,
+    // hundreds, tens and ones in three bytes, as FX33 writes them
+    Bcd3,
+    Binary,
+}
+
+impl GaugeFormat {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "u8" => return Some(GaugeFormat::U8),
+            "u16" => return Some(GaugeFormat::U16),
+            "bcd3" => return Some(GaugeFormat::Bcd3),
+            "binary" | "bin" => return Some(GaugeFormat::Binary),
+            _ => return None,
+        }
+    }
+
+    fn len(self) -> usize {
+        match self {
+            GaugeFormat::U8 | GaugeFormat::Binary => return 1,
+            GaugeFormat::U16 => return 2,
+            GaugeFormat::Bcd3 => return 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gauge {
+    pub name: String,
+    // ADDR:FORMAT as given, for --write-metadata
+    pub spec: String,
+    target: Result<(usize, GaugeFormat), String>,
+}
+
+impl Gauge {
+    // NAME=ADDR:FORMAT
+    pub fn parse(text: &str) -> Self {
+        let text = text.trim();
+        let Some((name, spec)) = text.split_once('=') else {
+            return Gauge {
+                name: text.to_string(),
+                spec: String::new(),
+                target: Err(String::from("expected NAME=ADDR:FORMAT")),
+            };
+        };
+        let spec = spec.trim();
+        return Gauge {
+            name: name.trim().to_string(),
+            spec: spec.to_string(),
+            target: parse_target(spec),
+        };
+    }
+
+    // The value as text, or why there is none
+    pub fn read(&self, chip8: &Chip8) -> Result<String, String> {
+        let (addr, format) = self.target.clone()?;
+        let Some(bytes) = chip8.mem_range(addr, format.len()) else {
+            return Err(format!(
+                "{addr:#05X} is past the end of memory ({:#X} bytes)",
+                chip8.mem().len()
+            ));
+        };
+        match format {
+            GaugeFormat::U8 => return Ok(bytes[0].to_string()),
+            GaugeFormat::U16 => return Ok(u16::from_be_bytes([bytes[0], bytes[1]]).to_string()),
+            GaugeFormat::Bcd3 => {
+                if let Some(digit) = bytes.iter().find(|&&digit| digit > 9) {
+                    return Err(format!("{digit:#04X} is not a BCD digit"));
+                }
+                return Ok(bytes.iter().map(|digit| digit.to_string()).collect());
+            }
+            GaugeFormat::Binary => return Ok(format!("{:08b}", bytes[0])),
+        }
+    }
+}
+
+fn parse_target(spec: &str) -> Result<(usize, GaugeFormat), String> {
+    let Some((addr, format)) = spec.split_once(':') else {
+        return Err(String::from("expected ADDR:FORMAT"));
+    };
+    let addr = parse_addr(addr.trim())? as usize;
+    let format = GaugeFormat::parse(format.trim()).ok_or_else(|| {
+        format!(
+            "unknown format '{}', expected u8, u16, bcd3 or binary",
+            format.trim()
+        )
+    })?;
+
+    return Ok((addr, format));
+}
+
+// A comma-separated list like the one --gauge takes
+pub fn parse_gauges(text: &str) -> Vec<Gauge> {
+    return text
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(Gauge::parse)
+        .collect();
+}
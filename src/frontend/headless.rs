@@ -0,0 +1,459 @@
+use super::debugger::StepTarget;
+use super::shutdown;
+use rustchip8::gif::GifRecorder;
+#[cfg(feature = "scripting")]
+use rustchip8::script::Script;
+use rustchip8::{Chip8, Player, TIMER_HZ, WatchHit, disassemble_with};
+use std::env;
+use std::fs;
+use std::io::Write;
+
+// Windowless run for test suites. Stops at a 1NNN self-jump, on 00FD or on an FX0A
+// nothing will ever answer. Timers tick 60 times per `hz` cycles (spread evenly when hz
+// isn't a multiple of 60, a cycle is one instruction unless VIP timing prices them) so delay
+// loops finish, and a display_wait DXYN ends its frame early just like in the window. With a replay the recorded ticks and keys drive the
+// machine instead. A GIF recording gets one frame per 60Hz tick either way. Whole trips
+// around an idle wait (see Chip8::idle_trips()) count towards N without being run.
+pub struct Runner {
+    cycles: u64,
+    // TIMER_HZ per cycle, a tick is due once it reaches hz
+    tick_budget: u32,
+    frames: u64,
+    // stop after this many frames, for --frames
+    frame_limit: Option<u64>,
+    hz: u32,
+    player: Option<Player>,
+    gif: Option<GifRecorder<Box<dyn Write>>>,
+    // stop at breakpoints and watchpoints, for the --debug prompt
+    debug: bool,
+    watch_hit: Option<WatchHit>,
+    // a step-over or step-out of the --debug prompt, cleared once reached
+    target: Option<StepTarget>,
+    // --script, its pause() ends the run
+    #[cfg(feature = "scripting")]
+    script: Option<Script>,
+}
+
+impl Runner {
+    pub fn new(hz: u32, player: Option<Player>) -> Self {
+        return Runner {
+            cycles: 0,
+            tick_budget: 0,
+            frames: 0,
+            frame_limit: None,
+            hz: hz.max(1),
+            player,
+            gif: None,
+            debug: false,
+            watch_hit: None,
+            target: None,
+            #[cfg(feature = "scripting")]
+            script: None,
+        };
+    }
+
+    pub fn set_gif(&mut self, gif: GifRecorder<Box<dyn Write>>) {
+        self.gif = Some(gif);
+    }
+
+    pub fn set_frame_limit(&mut self, frames: Option<u64>) {
+        self.frame_limit = frames;
+    }
+
+    pub fn set_debug(&mut self, debug: bool) {
+        self.debug = debug;
+    }
+
+    pub fn cycles(&self) -> u64 {
+        return self.cycles;
+    }
+
+    // The watchpoint that stopped the last run, with set_debug()
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        return self.watch_hit.take();
+    }
+
+    // Ends the next runs once `target` is reached
+    pub fn set_target(&mut self, target: Option<StepTarget>) {
+        self.target = target;
+    }
+
+    pub fn take_target(&mut self) -> Option<StepTarget> {
+        return self.target.take();
+    }
+
+    #[cfg(feature = "scripting")]
+    pub fn set_script(&mut self, script: Script) {
+        self.script = Some(script);
+    }
+
+    pub fn take_gif(&mut self) -> Option<GifRecorder<Box<dyn Write>>> {
+        return self.gif.take();
+    }
+
+    // Runs until `until` instructions have executed in total, or the program stops. With
+    // set_debug() also at a breakpoint, unless it's where the run started.
+    pub fn run_until(&mut self, chip8: &mut Chip8, until: u64) -> Result<(), String> {
+        let start = self.cycles;
+        while self.cycles < until {
+            if let Some(player) = &mut self.player {
+                player.apply_due(chip8, self.cycles);
+            }
+            let vblank = self.player.is_none() && chip8.is_waiting_for_vblank();
+            if self.tick_budget >= self.hz || vblank {
+                if self.player.is_none() {
+                    chip8.tick_60hz();
+                }
+                // a display wait starts the next frame early
+                self.tick_budget = if vblank {
+                    0
+                } else {
+                    self.tick_budget - self.hz
+                };
+                if let Some(gif) = &mut self.gif {
+                    gif.push_frame(chip8)
+                        .map_err(|e| format!("failed to write GIF: {e}"))?;
+                }
+                self.frames += 1;
+                if shutdown::interrupted() {
+                    break;
+                }
+                #[cfg(feature = "scripting")]
+                if self.run_script(chip8, None)? {
+                    break;
+                }
+            }
+            if self.frame_limit.is_some_and(|limit| self.frames >= limit) {
+                break;
+            }
+            if chip8.is_self_jump()
+                || chip8.has_exited()
+                || chip8.is_waiting_for_key()
+                || chip8.is_waiting_for_vblank()
+            {
+                break;
+            }
+            // up to the next tick, not in a replay whose events could fall inside a trip
+            if self.player.is_none() && chip8.is_idle() {
+                let to_tick = (self.hz - self.tick_budget).div_ceil(TIMER_HZ) as u64;
+                let budget = to_tick.min(until - self.cycles) as u32;
+                let (skipped, skipped_cycles) = chip8.idle_trips(budget);
+                if skipped > 0 {
+                    self.cycles += skipped as u64;
+                    self.tick_budget += TIMER_HZ * skipped_cycles;
+                    continue;
+                }
+            }
+            if self.debug && self.cycles != start && chip8.at_breakpoint() {
+                break;
+            }
+            #[cfg(feature = "scripting")]
+            if chip8.at_breakpoint() && self.run_script(chip8, Some(chip8.pc()))? {
+                break;
+            }
+            self.cycles += 1;
+
+            let pc = chip8.pc();
+            let info = chip8.step().map_err(|e| {
+                let op = match chip8.mem().get(pc as usize..pc as usize + 2) {
+                    Some(&[hi, lo]) => {
+                        disassemble_with(u16::from_be_bytes([hi, lo]), chip8.symbols())
+                    }
+                    _ => String::from("?"),
+                };
+                format!("CPU halted at {pc:#05X} ({op}): {e}")
+            })?;
+            self.tick_budget += TIMER_HZ * info.cycles;
+            if self.debug
+                && let Some(hit) = chip8.take_watch_hit()
+            {
+                self.watch_hit = Some(hit);
+                break;
+            }
+            if self.target.is_some_and(|target| target.reached(chip8)) {
+                self.target = None;
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // on_frame(), or on_breakpoint() with the breakpoint's address. True when the script
+    // paused, which ends the run.
+    #[cfg(feature = "scripting")]
+    fn run_script(&mut self, chip8: &mut Chip8, breakpoint: Option<u16>) -> Result<bool, String> {
+        let Some(script) = &mut self.script else {
+            return Ok(false);
+        };
+        match breakpoint {
+            Some(addr) => script.on_breakpoint(chip8, addr),
+            None => script.on_frame(chip8),
+        }
+        .map_err(|e| format!("script: {e}"))?;
+        if self.player.is_none() {
+            for (key, pressed) in script.keys().into_iter().enumerate() {
+                chip8.set_key(key as u8, pressed);
+            }
+        }
+        let requests = script.take_requests();
+        eprint!("{}", requests.output);
+        if requests.screenshot {
+            log::warn!("screenshot() only works in the window, use --screenshot-after");
+        }
+
+        return Ok(requests.pause == Some(true));
+    }
+}
+
+// Display as rows of '█' and ' ', followed by the register file
+pub fn dump(chip8: &Chip8) -> String {
+    return screen(chip8) + &registers(chip8);
+}
+
+// Display as rows of '█' and ' ' without trailing blanks, also the --expect-screen format
+pub fn screen(chip8: &Chip8) -> String {
+    let mut out = String::new();
+    for row in chip8.display_rows() {
+        let line: String = row
+            .iter()
+            .map(|&pixel| if pixel != 0 { '█' } else { ' ' })
+            .collect();
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+
+    return out;
+}
+
+// Err with the rows that differ, expected above actual and '^' under every pixel that
+// changed, so the broken part of a test ROM's output is easy to spot
+pub fn compare_screen(expected: &str, actual: &str) -> Result<(), String> {
+    let expected: Vec<Vec<char>> = expected.lines().map(|l| l.chars().collect()).collect();
+    let actual: Vec<Vec<char>> = actual.lines().map(|l| l.chars().collect()).collect();
+    let width = expected
+        .iter()
+        .chain(&actual)
+        .map(Vec::len)
+        .max()
+        .unwrap_or(0);
+    let padded = |rows: &[Vec<char>], y: usize| -> Vec<char> {
+        let mut row = rows.get(y).cloned().unwrap_or_default();
+        row.resize(width, ' ');
+        return row;
+    };
+
+    let mut diff = String::new();
+    let mut rows = 0;
+    for y in 0..expected.len().max(actual.len()) {
+        let want = padded(&expected, y);
+        let got = padded(&actual, y);
+        if want == got {
+            continue;
+        }
+        let marks: String = want
+            .iter()
+            .zip(&got)
+            .map(|(a, b)| if a == b { ' ' } else { '^' })
+            .collect();
+        diff.push_str(&format!(
+            "row {y:2} expected |{}|\n",
+            String::from_iter(&want)
+        ));
+        diff.push_str(&format!("         actual |{}|\n", String::from_iter(&got)));
+        diff.push_str(&format!("                 {}\n", marks.trim_end()));
+        rows += 1;
+    }
+    if expected.len() != actual.len() {
+        diff.push_str(&format!(
+            "expected {} rows, the display has {}\n",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    if diff.is_empty() {
+        return Ok(());
+    }
+
+    return Err(format!("{rows} rows differ\n{diff}"));
+}
+
+// --expect-screen: the display against the golden at `path`, Err with the rows that
+// differ. With UPDATE_GOLDEN=1 the golden is rewritten from the display instead, Ok(true).
+pub fn check_golden(chip8: &Chip8, path: &str) -> Result<bool, String> {
+    let screen = screen(chip8);
+    if env::var_os("UPDATE_GOLDEN").is_some_and(|value| value == "1") {
+        fs::write(path, &screen).map_err(|e| format!("failed to write {path}: {e}"))?;
+        return Ok(true);
+    }
+    let expected = fs::read_to_string(path).map_err(|e| format!("failed to read {path}: {e}"))?;
+    compare_screen(&expected, &screen)
+        .map_err(|diff| format!("the display doesn't match {path}, {diff}"))?;
+
+    return Ok(false);
+}
+
+// V0-VF on two lines, then pc, I, timers and stack depth
+pub fn registers(chip8: &Chip8) -> String {
+    let mut out = String::new();
+    let registers = chip8.registers();
+    for (half, regs) in registers.chunks(8).enumerate() {
+        let line = regs
+            .iter()
+            .enumerate()
+            .map(|(i, val)| format!("V{:X}={val:02X}", half * 8 + i))
+            .collect::<Vec<_>>()
+            .join(" ");
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "PC={:04X} I={:04X} DT={:02X} ST={:02X} SP={}\n",
+        chip8.pc(),
+        chip8.reg_i(),
+        chip8.delay_timer(),
+        chip8.sound_timer(),
+        chip8.stack().len()
+    ));
+
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustchip8::Profile;
+    use std::path::Path;
+
+    // The suite's menu picks its test from 0x1FF, and the platform of the quirks test from
+    // 0x1FE (1 CHIP-8, 2 SUPER-CHIP)
+    const IBM_LOGO: u8 = 1;
+    const CORAX: u8 = 2;
+    const FLAGS: u8 = 3;
+    const QUIRKS: u8 = 4;
+
+    fn path(name: &str) -> String {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_roms");
+        return dir.join(name).to_string_lossy().into_owned();
+    }
+
+    // Runs `rom` headless at 600 instructions a second for at most `cycles` instructions,
+    // like --headless
+    fn run(rom: &str, profile: Profile, pokes: &[(usize, u8)], cycles: u64) -> Chip8 {
+        let data = fs::read(path(rom)).unwrap();
+        let mut chip8 = Chip8::with_seed(0);
+        *chip8.quirks_mut() = profile.quirks();
+        chip8.set_vip_hires(rustchip8::is_vip_hires_rom(&data));
+        chip8.load_rom(&data).unwrap();
+        for &(addr, val) in pokes {
+            chip8.poke(addr, val).unwrap();
+        }
+        Runner::new(600, None)
+            .run_until(&mut chip8, cycles)
+            .unwrap();
+        return chip8;
+    }
+
+    // run() and check the display against `golden`. UPDATE_GOLDEN=1 cargo test writes the
+    // goldens instead.
+    fn check(
+        rom: &str,
+        profile: Profile,
+        pokes: &[(usize, u8)],
+        cycles: u64,
+        golden: &str,
+    ) -> Chip8 {
+        let chip8 = run(rom, profile, pokes, cycles);
+        if let Err(e) = check_golden(&chip8, &path(&format!("golden/{golden}"))) {
+            panic!("{rom} with {profile:?}: {e}");
+        }
+        return chip8;
+    }
+
+    // The test suite's `test` for `platform` in its menu (1 CHIP-8, 2 SUPER-CHIP, 3 XO-CHIP)
+    fn suite(test: u8, platform: u8, profile: Profile, golden: &str) -> Chip8 {
+        let pokes = [(0x1FF, test), (0x1FE, platform)];
+        return check("chip8-test-suite.ch8", profile, &pokes, 100_000, golden);
+    }
+
+    const QUIRK_ROWS: [&str; 6] = [
+        "vf reset",
+        "memory",
+        "display wait",
+        "clipping",
+        "shifting",
+        "jumping",
+    ];
+
+    // The quirks the quirks test failed for its platform. Each of its rows ends in a tick or
+    // a cross at x 59, the middle line of a tick starts at the left and a cross's doesn't.
+    fn failed_quirks(chip8: &Chip8) -> Vec<&'static str> {
+        let rows: Vec<&[u8]> = chip8.display_rows().collect();
+        let mut failed = Vec::new();
+        for (i, quirk) in QUIRK_ROWS.into_iter().enumerate() {
+            let middle = rows[3 + 5 * i];
+            assert!(middle[60] != 0, "no result for {quirk}");
+            if middle[59] == 0 {
+                failed.push(quirk);
+            }
+        }
+        return failed;
+    }
+
+    #[test]
+    fn ibm_logo() {
+        check("ibm.ch8", Profile::Cosmac, &[], 1000, "ibm.txt");
+    }
+
+    #[test]
+    fn vip_hires() {
+        check("hires-vip.ch8", Profile::Cosmac, &[], 1000, "hires-vip.txt");
+    }
+
+    #[test]
+    fn suite_ibm_logo() {
+        // the same picture as ibm.ch8 draws
+        suite(IBM_LOGO, 0, Profile::Cosmac, "ibm.txt");
+    }
+
+    #[test]
+    fn corax_plus() {
+        suite(CORAX, 0, Profile::Cosmac, "corax+.txt");
+    }
+
+    #[test]
+    fn flags() {
+        suite(FLAGS, 0, Profile::Cosmac, "flags.txt");
+    }
+
+    #[test]
+    fn quirks_per_profile() {
+        let cosmac = suite(QUIRKS, 1, Profile::Cosmac, "quirks-cosmac.txt");
+        assert_eq!(failed_quirks(&cosmac), Vec::<&str>::new());
+        let schip = suite(QUIRKS, 2, Profile::Schip, "quirks-schip.txt");
+        assert_eq!(failed_quirks(&schip), Vec::<&str>::new());
+        // The suite has no CHIP-48 entry. CHIP-48 is SUPER-CHIP's predecessor on the HP48
+        // and shares its quirks but one: FX55/FX65 still move I, as on the VIP.
+        let chip48 = suite(QUIRKS, 2, Profile::Chip48, "quirks-chip48.txt");
+        assert_eq!(failed_quirks(&chip48), ["memory"]);
+        let pokes = [(0x1FF, QUIRKS), (0x1FE, 1)];
+        let chip48_on_vip = run("chip8-test-suite.ch8", Profile::Chip48, &pokes, 100_000);
+        assert!(!failed_quirks(&chip48_on_vip).contains(&"memory"));
+    }
+
+    #[test]
+    fn mismatches_show_the_rows() {
+        let diff = compare_screen("█ █\n\n", "███\n\n").unwrap_err();
+        assert_eq!(
+            diff,
+            "1 rows differ\n\
+             row  0 expected |█ █|\n         actual |███|\n                  ^\n"
+        );
+        assert!(compare_screen("█\n", "█\n").is_ok());
+        let diff = compare_screen("█\n", "█\n\n").unwrap_err();
+        assert!(
+            diff.ends_with("expected 1 rows, the display has 2\n"),
+            "{diff}"
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use super::keymap::{self, Keymap};
+use log::info;
+use raylib::consts::{GamepadAxis, GamepadButton};
+use raylib::prelude::*;
+
+// Standard mapping: the d-pad is 2/4/6/8 (the direction keys of most games), A and B are
+// --gamepad-a/--gamepad-b, every other button gets one of the remaining keys
+const DPAD: [(GamepadButton, u8); 4] = [
+    (GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_UP, 0x2),
+    (GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_LEFT, 0x4),
+    (GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_RIGHT, 0x6),
+    (GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_DOWN, 0x8),
+];
+const OTHER_BUTTONS: [(GamepadButton, u8); 9] = [
+    (GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_LEFT, 0xA),
+    (GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_UP, 0xB),
+    (GamepadButton::GAMEPAD_BUTTON_LEFT_TRIGGER_1, 0x1),
+    (GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_1, 0x3),
+    (GamepadButton::GAMEPAD_BUTTON_LEFT_TRIGGER_2, 0x7),
+    (GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_2, 0x9),
+    (GamepadButton::GAMEPAD_BUTTON_MIDDLE_LEFT, 0x0),
+    (GamepadButton::GAMEPAD_BUTTON_MIDDLE_RIGHT, 0xF),
+    (GamepadButton::GAMEPAD_BUTTON_LEFT_THUMB, 0xC),
+];
+pub const DEFAULT_A: u8 = 0x5;
+pub const DEFAULT_B: u8 = 0x6;
+
+#[derive(Debug, Clone, Copy)]
+pub struct GamepadConfig {
+    pub index: i32,
+    pub a: u8,
+    pub b: u8,
+    // the left stick acts as the d-pad once it's pushed further than this
+    pub stick_deadzone: Option<f32>,
+}
+
+// One thing on the host that presses a CHIP-8 key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    Key(KeyboardKey),
+    Button(GamepadButton),
+    // the left stick pushed this way past the deadzone
+    Stick(&'static str),
+}
+
+impl Binding {
+    pub fn is_gamepad(self) -> bool {
+        return !matches!(self, Binding::Key(_));
+    }
+
+    // Short enough for a keypad cell, buttons by their Xbox names
+    pub fn label(self) -> String {
+        let button = match self {
+            Binding::Key(key) => return keymap::key_label(key),
+            Binding::Stick(direction) => return format!("stick {direction}"),
+            Binding::Button(button) => button,
+        };
+        let name = match button {
+            GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_UP => "d-pad up",
+            GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_LEFT => "d-pad left",
+            GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_RIGHT => "d-pad right",
+            GamepadButton::GAMEPAD_BUTTON_LEFT_FACE_DOWN => "d-pad down",
+            GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_DOWN => "A",
+            GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_RIGHT => "B",
+            GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_LEFT => "X",
+            GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_UP => "Y",
+            GamepadButton::GAMEPAD_BUTTON_LEFT_TRIGGER_1 => "LB",
+            GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_1 => "RB",
+            GamepadButton::GAMEPAD_BUTTON_LEFT_TRIGGER_2 => "LT",
+            GamepadButton::GAMEPAD_BUTTON_RIGHT_TRIGGER_2 => "RT",
+            GamepadButton::GAMEPAD_BUTTON_MIDDLE_LEFT => "Back",
+            GamepadButton::GAMEPAD_BUTTON_MIDDLE_RIGHT => "Start",
+            GamepadButton::GAMEPAD_BUTTON_LEFT_THUMB => "LS",
+            _ => "?",
+        };
+        return String::from(name);
+    }
+}
+
+// Every host input with the CHIP-8 key it presses, the keymap first and then the gamepad.
+// A CHIP-8 key can have several, a host input has one.
+pub struct Bindings {
+    entries: Vec<(Binding, u8)>,
+}
+
+impl Bindings {
+    pub fn new(keymap: &Keymap, gamepad: &GamepadConfig) -> Self {
+        let mut entries: Vec<(Binding, u8)> = keymap
+            .iter()
+            .enumerate()
+            .map(|(key, host_key)| (Binding::Key(*host_key), key as u8))
+            .collect();
+        let face = [
+            (GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_DOWN, gamepad.a),
+            (GamepadButton::GAMEPAD_BUTTON_RIGHT_FACE_RIGHT, gamepad.b),
+        ];
+        for (button, key) in DPAD.iter().chain(&face).chain(&OTHER_BUTTONS) {
+            entries.push((Binding::Button(*button), *key));
+        }
+        if gamepad.stick_deadzone.is_some() {
+            for (direction, key) in [("up", 0x2), ("left", 0x4), ("right", 0x6), ("down", 0x8)] {
+                entries.push((Binding::Stick(direction), key));
+            }
+        }
+
+        return Bindings { entries };
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Binding, u8)> + '_ {
+        return self.entries.iter().copied();
+    }
+
+    // What presses CHIP-8 key `key`, in the order above
+    pub fn for_key(&self, key: u8) -> impl Iterator<Item = Binding> + '_ {
+        return self
+            .iter()
+            .filter(move |(_, k)| *k == key)
+            .map(|(binding, _)| binding);
+    }
+}
+
+// Everything the host can press, merged into the 16 CHIP-8 keys. A key is down when it is
+// held on the keyboard or on the gamepad.
+pub struct Input {
+    bindings: Bindings,
+    gamepad: GamepadConfig,
+    // last seen gamepad availability, to report plugging and unplugging
+    connected: bool,
+}
+
+impl Input {
+    pub fn new(keymap: Keymap, gamepad: GamepadConfig) -> Self {
+        return Input {
+            bindings: Bindings::new(&keymap, &gamepad),
+            gamepad,
+            connected: false,
+        };
+    }
+
+    pub fn poll(&mut self, rl: &RaylibHandle) -> [bool; 16] {
+        let mut keys = [false; 16];
+        for (binding, key) in self.bindings.iter() {
+            if let Binding::Key(host_key) = binding {
+                keys[key as usize] |= rl.is_key_down(host_key);
+            }
+        }
+
+        // checked every frame so a controller plugged in after launch just starts working
+        let index = self.gamepad.index;
+        let connected = rl.is_gamepad_available(index);
+        if connected != self.connected {
+            let name = rl.get_gamepad_name(index).unwrap_or_default();
+            if connected {
+                info!("Gamepad {index} connected: {name}");
+            } else {
+                info!("Gamepad {index} disconnected");
+            }
+            self.connected = connected;
+        }
+        if !connected {
+            return keys;
+        }
+
+        for (binding, key) in self.bindings.iter() {
+            if let Binding::Button(button) = binding {
+                keys[key as usize] |= rl.is_gamepad_button_down(index, button);
+            }
+        }
+
+        if let Some(deadzone) = self.gamepad.stick_deadzone {
+            let x = rl.get_gamepad_axis_movement(index, GamepadAxis::GAMEPAD_AXIS_LEFT_X);
+            let y = rl.get_gamepad_axis_movement(index, GamepadAxis::GAMEPAD_AXIS_LEFT_Y);
+            keys[0x2] |= y < -deadzone;
+            keys[0x8] |= y > deadzone;
+            keys[0x4] |= x < -deadzone;
+            keys[0x6] |= x > deadzone;
+        }
+
+        return keys;
+    }
+
+    pub fn bindings(&self) -> &Bindings {
+        return &self.bindings;
+    }
+
+    // as of the last poll()
+    pub fn gamepad_connected(&self) -> bool {
+        return self.connected;
+    }
+}
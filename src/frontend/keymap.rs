@@ -0,0 +1,245 @@
+use raylib::prelude::KeyboardKey;
+use rustchip8::keymap::{self, KeyNames};
+
+pub type Keymap = [KeyboardKey; 16];
+
+// A layout of rustchip8::keymap as raylib keys
+pub fn from_names(names: &KeyNames) -> Keymap {
+    return names.map(|name| parse_key(name).expect("the layouts use raylib's names"));
+}
+
+// raylib's names without the KEY_ prefix
+const KEY_NAMES: &[(&str, KeyboardKey)] = &[
+    ("APOSTROPHE", KeyboardKey::KEY_APOSTROPHE),
+    ("COMMA", KeyboardKey::KEY_COMMA),
+    ("MINUS", KeyboardKey::KEY_MINUS),
+    ("PERIOD", KeyboardKey::KEY_PERIOD),
+    ("SLASH", KeyboardKey::KEY_SLASH),
+    ("ZERO", KeyboardKey::KEY_ZERO),
+    ("ONE", KeyboardKey::KEY_ONE),
+    ("TWO", KeyboardKey::KEY_TWO),
+    ("THREE", KeyboardKey::KEY_THREE),
+    ("FOUR", KeyboardKey::KEY_FOUR),
+    ("FIVE", KeyboardKey::KEY_FIVE),
+    ("SIX", KeyboardKey::KEY_SIX),
+    ("SEVEN", KeyboardKey::KEY_SEVEN),
+    ("EIGHT", KeyboardKey::KEY_EIGHT),
+    ("NINE", KeyboardKey::KEY_NINE),
+    ("SEMICOLON", KeyboardKey::KEY_SEMICOLON),
+    ("EQUAL", KeyboardKey::KEY_EQUAL),
+    ("A", KeyboardKey::KEY_A),
+    ("B", KeyboardKey::KEY_B),
+    ("C", KeyboardKey::KEY_C),
+    ("D", KeyboardKey::KEY_D),
+    ("E", KeyboardKey::KEY_E),
+    ("F", KeyboardKey::KEY_F),
+    ("G", KeyboardKey::KEY_G),
+    ("H", KeyboardKey::KEY_H),
+    ("I", KeyboardKey::KEY_I),
+    ("J", KeyboardKey::KEY_J),
+    ("K", KeyboardKey::KEY_K),
+    ("L", KeyboardKey::KEY_L),
+    ("M", KeyboardKey::KEY_M),
+    ("N", KeyboardKey::KEY_N),
+    ("O", KeyboardKey::KEY_O),
+    ("P", KeyboardKey::KEY_P),
+    ("Q", KeyboardKey::KEY_Q),
+    ("R", KeyboardKey::KEY_R),
+    ("S", KeyboardKey::KEY_S),
+    ("T", KeyboardKey::KEY_T),
+    ("U", KeyboardKey::KEY_U),
+    ("V", KeyboardKey::KEY_V),
+    ("W", KeyboardKey::KEY_W),
+    ("X", KeyboardKey::KEY_X),
+    ("Y", KeyboardKey::KEY_Y),
+    ("Z", KeyboardKey::KEY_Z),
+    ("SPACE", KeyboardKey::KEY_SPACE),
+    ("ESCAPE", KeyboardKey::KEY_ESCAPE),
+    ("ENTER", KeyboardKey::KEY_ENTER),
+    ("TAB", KeyboardKey::KEY_TAB),
+    ("BACKSPACE", KeyboardKey::KEY_BACKSPACE),
+    ("INSERT", KeyboardKey::KEY_INSERT),
+    ("DELETE", KeyboardKey::KEY_DELETE),
+    ("RIGHT", KeyboardKey::KEY_RIGHT),
+    ("LEFT", KeyboardKey::KEY_LEFT),
+    ("DOWN", KeyboardKey::KEY_DOWN),
+    ("UP", KeyboardKey::KEY_UP),
+    ("PAGE_UP", KeyboardKey::KEY_PAGE_UP),
+    ("PAGE_DOWN", KeyboardKey::KEY_PAGE_DOWN),
+    ("HOME", KeyboardKey::KEY_HOME),
+    ("END", KeyboardKey::KEY_END),
+    ("CAPS_LOCK", KeyboardKey::KEY_CAPS_LOCK),
+    ("SCROLL_LOCK", KeyboardKey::KEY_SCROLL_LOCK),
+    ("NUM_LOCK", KeyboardKey::KEY_NUM_LOCK),
+    ("PRINT_SCREEN", KeyboardKey::KEY_PRINT_SCREEN),
+    ("PAUSE", KeyboardKey::KEY_PAUSE),
+    ("F1", KeyboardKey::KEY_F1),
+    ("F2", KeyboardKey::KEY_F2),
+    ("F3", KeyboardKey::KEY_F3),
+    ("F4", KeyboardKey::KEY_F4),
+    ("F5", KeyboardKey::KEY_F5),
+    ("F6", KeyboardKey::KEY_F6),
+    ("F7", KeyboardKey::KEY_F7),
+    ("F8", KeyboardKey::KEY_F8),
+    ("F9", KeyboardKey::KEY_F9),
+    ("F10", KeyboardKey::KEY_F10),
+    ("F11", KeyboardKey::KEY_F11),
+    ("F12", KeyboardKey::KEY_F12),
+    ("LEFT_SHIFT", KeyboardKey::KEY_LEFT_SHIFT),
+    ("LEFT_CONTROL", KeyboardKey::KEY_LEFT_CONTROL),
+    ("LEFT_ALT", KeyboardKey::KEY_LEFT_ALT),
+    ("LEFT_SUPER", KeyboardKey::KEY_LEFT_SUPER),
+    ("RIGHT_SHIFT", KeyboardKey::KEY_RIGHT_SHIFT),
+    ("RIGHT_CONTROL", KeyboardKey::KEY_RIGHT_CONTROL),
+    ("RIGHT_ALT", KeyboardKey::KEY_RIGHT_ALT),
+    ("RIGHT_SUPER", KeyboardKey::KEY_RIGHT_SUPER),
+    ("KB_MENU", KeyboardKey::KEY_KB_MENU),
+    ("LEFT_BRACKET", KeyboardKey::KEY_LEFT_BRACKET),
+    ("BACKSLASH", KeyboardKey::KEY_BACKSLASH),
+    ("RIGHT_BRACKET", KeyboardKey::KEY_RIGHT_BRACKET),
+    ("GRAVE", KeyboardKey::KEY_GRAVE),
+    ("KP_0", KeyboardKey::KEY_KP_0),
+    ("KP_1", KeyboardKey::KEY_KP_1),
+    ("KP_2", KeyboardKey::KEY_KP_2),
+    ("KP_3", KeyboardKey::KEY_KP_3),
+    ("KP_4", KeyboardKey::KEY_KP_4),
+    ("KP_5", KeyboardKey::KEY_KP_5),
+    ("KP_6", KeyboardKey::KEY_KP_6),
+    ("KP_7", KeyboardKey::KEY_KP_7),
+    ("KP_8", KeyboardKey::KEY_KP_8),
+    ("KP_9", KeyboardKey::KEY_KP_9),
+    ("KP_DECIMAL", KeyboardKey::KEY_KP_DECIMAL),
+    ("KP_DIVIDE", KeyboardKey::KEY_KP_DIVIDE),
+    ("KP_MULTIPLY", KeyboardKey::KEY_KP_MULTIPLY),
+    ("KP_SUBTRACT", KeyboardKey::KEY_KP_SUBTRACT),
+    ("KP_ADD", KeyboardKey::KEY_KP_ADD),
+    ("KP_ENTER", KeyboardKey::KEY_KP_ENTER),
+    ("KP_EQUAL", KeyboardKey::KEY_KP_EQUAL),
+];
+
+// Raylib names like KEY_UP, the prefix is optional and case doesn't matter. Plain digits
+// work too, 1 is KEY_ONE.
+pub fn parse_key(name: &str) -> Result<KeyboardKey, String> {
+    let bare = keymap::normalize(name);
+    return KEY_NAMES
+        .iter()
+        .find(|(key_name, _)| *key_name == bare)
+        .map(|(_, key)| *key)
+        .ok_or_else(|| format!("unknown key '{name}'"));
+}
+
+pub fn key_name(key: KeyboardKey) -> &'static str {
+    return KEY_NAMES
+        .iter()
+        .find(|(_, k)| *k == key)
+        .map_or("?", |(name, _)| name);
+}
+
+// For showing the key to players: digits as digits, KEY_RIGHT_CONTROL as RIGHT CTRL
+pub fn key_label(key: KeyboardKey) -> String {
+    let name = key_name(key);
+    if let Some(digit) = keymap::digit(name) {
+        return digit.to_string();
+    }
+
+    return name.replace("CONTROL", "CTRL").replace('_', " ");
+}
+
+pub fn parse_layout(name: &str) -> Result<Keymap, String> {
+    return keymap::parse_layout(name).map(|names| from_names(&names));
+}
+
+// A keymap file, see rustchip8::keymap::parse()
+pub fn parse(text: &str) -> Result<Keymap, String> {
+    return keymap::parse(text, parse_key, |key| key_name(key).to_string());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A keymap file mapping every key like `keymap`
+    fn text(keymap: &Keymap) -> String {
+        return (0..16)
+            .map(|i| format!("{i:X} = \"{}\"\n", key_name(keymap[i])))
+            .collect();
+    }
+
+    #[test]
+    fn key_names() {
+        assert_eq!(parse_key("KEY_UP"), Ok(KeyboardKey::KEY_UP));
+        assert_eq!(parse_key("up"), Ok(KeyboardKey::KEY_UP));
+        assert_eq!(parse_key("1"), Ok(KeyboardKey::KEY_ONE));
+        assert_eq!(parse_key("key_one"), Ok(KeyboardKey::KEY_ONE));
+        assert_eq!(
+            parse_key("Right_Control"),
+            Ok(KeyboardKey::KEY_RIGHT_CONTROL)
+        );
+        assert_eq!(parse_key("12"), Err(String::from("unknown key '12'")));
+        assert_eq!(parse_key("KEY_"), Err(String::from("unknown key 'KEY_'")));
+        assert_eq!(key_label(KeyboardKey::KEY_SEVEN), "7");
+        assert_eq!(key_label(KeyboardKey::KEY_RIGHT_CONTROL), "RIGHT CTRL");
+    }
+
+    #[test]
+    fn layouts_map_every_key_once() {
+        for (name, names) in keymap::LAYOUTS {
+            let keymap = from_names(&names);
+            assert_eq!(parse_layout(name), Ok(keymap));
+            for (i, key) in keymap.iter().enumerate() {
+                assert_ne!(key_name(*key), "?", "{name} {i:X}");
+                assert!(!keymap[i + 1..].contains(key), "{name} uses {key:?} twice");
+            }
+            // and survives a trip through the file format
+            assert_eq!(parse(&text(&keymap)), Ok(keymap));
+        }
+        assert!(
+            parse_layout("dvorak")
+                .unwrap_err()
+                .contains("unknown layout")
+        );
+    }
+
+    #[test]
+    fn files() {
+        let mut file = String::from("# d-pad on the arrows\n[keys]\n\n");
+        let qwerty = from_names(&keymap::QWERTY);
+        file += &text(&qwerty)
+            .replace("2 = \"TWO\"", "2 = \"UP\"   # up")
+            .replace("8 = \"S\"", "\"8\" = KEY_DOWN");
+        let mut expected = qwerty;
+        expected[2] = KeyboardKey::KEY_UP;
+        expected[8] = KeyboardKey::KEY_DOWN;
+        assert_eq!(parse(&file), Ok(expected));
+    }
+
+    #[test]
+    fn errors() {
+        let full = text(&from_names(&keymap::QWERTY));
+        let missing = full.replace("5 = \"W\"\n", "").replace("F = \"V\"\n", "");
+        assert_eq!(parse(&missing), Err(String::from("keys not mapped: 5, F")));
+
+        let twice = format!("{full}5 = \"P\"\n");
+        assert_eq!(
+            parse(&twice),
+            Err(String::from("line 17: key 5 is already mapped on line 6"))
+        );
+        let shared = full.replace("5 = \"W\"", "5 = \"Q\"");
+        assert_eq!(
+            parse(&shared),
+            Err(String::from("line 6: Q is already used for key 4"))
+        );
+        assert_eq!(
+            parse("G = \"UP\""),
+            Err(String::from("line 1: 'G' is not a CHIP-8 key 0-F"))
+        );
+        assert_eq!(
+            parse("\n2 = \"NOPE\""),
+            Err(String::from("line 2: unknown key 'NOPE'"))
+        );
+        assert_eq!(
+            parse("2 UP"),
+            Err(String::from("line 1: expected '<key> = <host key>'"))
+        );
+    }
+}
@@ -0,0 +1,52 @@
+// The env_logger the window and --headless install for the `log` records of the library and
+// the frontend. `--log-level` takes a level and/or per-target ones, e.g.
+// `warn,rustchip8::cpu=trace`, the RUST_LOG syntax, and wins over RUST_LOG. A target also
+// covers everything below it and the longest match wins. Errors and warnings keep their old
+// `ERROR:`/`WARNING:` prefixes and info is printed as is, so by default the output reads the
+// same as before.
+
+use env_logger::{Builder, Env, Target};
+use log::{Level, LevelFilter};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::str::FromStr;
+
+// Checks `spec` the way env_logger reads it, which only warns about what it can't parse
+pub fn check_spec(spec: &str) -> Result<(), String> {
+    for part in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let name = part.split_once('=').map_or(part, |(_, level)| level);
+        if LevelFilter::from_str(name).is_err() {
+            return Err(format!(
+                "invalid log level '{name}', expected error, warn, info, debug or trace"
+            ));
+        }
+    }
+
+    return Ok(());
+}
+
+// Logs to stderr, or to `path` (--log-file) instead. `spec` is --log-level, RUST_LOG or info
+// without one.
+pub fn init(spec: Option<&str>, path: Option<&str>) -> Result<(), String> {
+    let mut builder = Builder::from_env(Env::default().default_filter_or("info"));
+    if let Some(spec) = spec {
+        builder.parse_filters(spec);
+    }
+    if let Some(path) = path {
+        let file =
+            File::create(path).map_err(|e| format!("failed to create log file {path}: {e}"))?;
+        builder.target(Target::Pipe(Box::new(LineWriter::new(file))));
+    }
+    builder.format(|out, record| match record.level() {
+        Level::Error => writeln!(out, "ERROR: {}", record.args()),
+        Level::Warn => writeln!(out, "WARNING: {}", record.args()),
+        Level::Info => writeln!(out, "{}", record.args()),
+        level => writeln!(out, "{level} {}: {}", record.target(), record.args()),
+    });
+
+    return builder.try_init().map_err(|e| e.to_string());
+}
@@ -0,0 +1,485 @@
+// Per-game settings in a `<rom>.json` sidecar:
+//
+//   {
+//     "title": "Blinky",
+//     "profile": "schip",
+//     "ips": 1200,
+//     "palette": "amber",
+//     "quirks": { "wrap": true },
+//     "keymap": { "5": "SPACE" },
+//     "keys": { "4": "left", "6": "right" },
+//     "gauges": { "score": "0x3E0:bcd3" }
+//   }
+//
+// They are turned into the equivalent command line flags and parsed in front of the real
+// ones, so anything given on the command line still wins.
+
+use super::cli::Options;
+use super::keymap::{self, Keymap};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const QUIRKS: [&str; 7] = [
+    "shift", "memory", "jump", "wrap", "vfreset", "dispwait", "overflow",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+struct Parser<'a> {
+    text: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, what: &str) -> String {
+        let line = self.text[..self.pos]
+            .iter()
+            .filter(|&&c| c == b'\n')
+            .count()
+            + 1;
+        return format!("line {line}: {what}");
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.text.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), String> {
+        self.skip_whitespace();
+        if self.text.get(self.pos) != Some(&c) {
+            return Err(self.error(&format!("expected '{}'", c as char)));
+        }
+        self.pos += 1;
+
+        return Ok(());
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        let rest = &self.text[self.pos..];
+        for (word, value) in [
+            ("null", Json::Null),
+            ("true", Json::Bool(true)),
+            ("false", Json::Bool(false)),
+        ] {
+            if rest.starts_with(word.as_bytes()) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        match rest.first() {
+            Some(b'"') => return Ok(Json::String(self.string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                self.skip_whitespace();
+                if self.text.get(self.pos) == Some(&b']') {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value()?);
+                    self.skip_whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(Json::Array(items));
+                        }
+                        _ => return Err(self.error("expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                self.skip_whitespace();
+                if self.text.get(self.pos) == Some(&b'}') {
+                    self.pos += 1;
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespace();
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    fields.push((key, self.value()?));
+                    self.skip_whitespace();
+                    match self.text.get(self.pos) {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(Json::Object(fields));
+                        }
+                        _ => return Err(self.error("expected ',' or '}'")),
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() || *c == b'-' => {
+                let len = rest
+                    .iter()
+                    .position(|c| !(c.is_ascii_digit() || b"+-.eE".contains(c)))
+                    .unwrap_or(rest.len());
+                let number = std::str::from_utf8(&rest[..len]).unwrap_or("");
+                let number = number.parse().map_err(|_| self.error("invalid number"))?;
+                self.pos += len;
+                return Ok(Json::Number(number));
+            }
+            _ => return Err(self.error("expected a value")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.text.get(self.pos) != Some(&b'"') {
+            return Err(self.error("expected a string"));
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match self.text.get(self.pos) {
+                None => return Err(self.error("unterminated string")),
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let escaped = match self.text.get(self.pos) {
+                        Some(b'n') => b'\n',
+                        Some(b't') => b'\t',
+                        Some(&c @ (b'"' | b'\\' | b'/')) => c,
+                        _ => return Err(self.error("unsupported escape in string")),
+                    };
+                    out.push(escaped);
+                }
+                Some(&c) => out.push(c),
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+
+        return String::from_utf8(out).map_err(|_| self.error("string is not UTF-8"));
+    }
+}
+
+pub(super) fn parse_json(text: &str) -> Result<Json, String> {
+    let mut parser = Parser {
+        text: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.pos != parser.text.len() {
+        return Err(parser.error("unexpected text after the end"));
+    }
+
+    return Ok(value);
+}
+
+// The flags a sidecar stands for, and the names of the settings that set them
+pub fn to_args(text: &str) -> Result<(Vec<String>, Vec<String>), String> {
+    let Json::Object(fields) = parse_json(text)? else {
+        return Err(String::from("expected an object"));
+    };
+
+    let mut args = Vec::new();
+    let mut names = Vec::new();
+    for (name, value) in fields {
+        let mut flag = |flag: &str, value: String| {
+            args.push(flag.to_string());
+            args.push(value);
+        };
+        match (name.as_str(), value) {
+            ("title", Json::String(title)) => flag("--title", title),
+            ("profile", Json::String(profile)) => flag("--profile", profile),
+            ("ips", Json::Number(ips)) if ips >= 1.0 && ips.fract() == 0.0 => {
+                flag("--hz", ips.to_string())
+            }
+            ("timing", Json::String(timing)) => flag("--timing", timing),
+            ("layout", Json::String(layout)) => flag("--layout", layout),
+            ("palette", Json::String(palette)) => flag("--palette", palette),
+            ("palette", Json::Array(colors)) => {
+                let colors = colors
+                    .into_iter()
+                    .map(|color| match color {
+                        Json::String(color) => Ok(color),
+                        _ => Err(String::from("\"palette\" colors must be strings")),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                flag("--palette", colors.join(","));
+            }
+            ("quirks", Json::Object(quirks)) => {
+                for (quirk, value) in quirks {
+                    let Json::Bool(enabled) = value else {
+                        return Err(format!("quirk \"{quirk}\" must be true or false"));
+                    };
+                    let value = String::from(if enabled { "on" } else { "off" });
+                    flag(&format!("--quirk-{quirk}"), value);
+                }
+            }
+            ("keymap", Json::Object(keys)) => {
+                for (key, value) in keys {
+                    let Json::String(host_key) = value else {
+                        return Err(format!("keymap entry \"{key}\" must be a key name"));
+                    };
+                    flag("--key", format!("{key}={host_key}"));
+                }
+            }
+            ("keys", Json::Object(keys)) => {
+                for (key, value) in keys {
+                    let Json::String(text) = value else {
+                        return Err(format!("keys entry \"{key}\" must be a description"));
+                    };
+                    flag("--key-help", format!("{key}={text}"));
+                }
+            }
+            ("gauges", Json::Object(gauges)) => {
+                for (gauge, value) in gauges {
+                    let Json::String(spec) = value else {
+                        return Err(format!(
+                            "gauge \"{gauge}\" must be a string like \"0x3E0:u8\""
+                        ));
+                    };
+                    flag("--gauge", format!("{gauge}={spec}"));
+                }
+            }
+            (
+                "title" | "profile" | "ips" | "timing" | "layout" | "palette" | "quirks" | "keymap"
+                | "keys" | "gauges",
+                _,
+            ) => return Err(format!("\"{name}\" has the wrong type")),
+            _ => return Err(format!("unknown setting \"{name}\"")),
+        }
+        names.push(name);
+    }
+
+    return Ok((args, names));
+}
+
+// Only the key descriptions of the sidecar next to `rom_path`, for a ROM loaded after
+// startup whose other settings aren't applied. None for every key without a sidecar.
+pub fn read_key_help(rom_path: &str) -> Result<[Option<String>; 16], String> {
+    let path = format!("{rom_path}.json");
+    if !Path::new(&path).exists() {
+        return Ok(Default::default());
+    }
+    let text = fs::read_to_string(&path).map_err(|e| format!("{path}: {e}"))?;
+    let (args, _) = to_args(&text).map_err(|e| format!("{path}: {e}"))?;
+    let options = Options::parse(&args).map_err(|e| format!("{path}: {e}"))?;
+
+    return Ok(options.key_help);
+}
+
+pub(super) fn quote(text: &str) -> String {
+    return format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""));
+}
+
+// The effective settings of `options` as a sidecar, for --write-metadata. `keymap` is the
+// one in use, which may have come from a keymap file.
+pub fn from_options(options: &Options, keymap: &Keymap) -> String {
+    let mut out = String::from("{\n");
+    if let Some(title) = &options.title {
+        writeln!(out, "  \"title\": {},", quote(title)).unwrap();
+    }
+    if options.timing == rustchip8::Timing::Vip {
+        out.push_str("  \"timing\": \"vip\",\n");
+    } else {
+        writeln!(out, "  \"ips\": {},", options.cpu_hz).unwrap();
+    }
+    let colors: Vec<String> = options
+        .palette
+        .iter()
+        .map(|[r, g, b]| quote(&format!("{r:02X}{g:02X}{b:02X}")))
+        .collect();
+    writeln!(out, "  \"palette\": [{}],", colors.join(", ")).unwrap();
+
+    let quirks = options.quirks;
+    let values = [
+        quirks.shift_uses_vy,
+        quirks.memory_increment,
+        quirks.jump_uses_vx,
+        quirks.sprite_wrap,
+        quirks.vf_reset,
+        quirks.display_wait,
+        quirks.index_overflow,
+    ];
+    let quirks: Vec<String> = QUIRKS
+        .iter()
+        .zip(values)
+        .map(|(name, enabled)| format!("\"{name}\": {enabled}"))
+        .collect();
+    writeln!(out, "  \"quirks\": {{ {} }},", quirks.join(", ")).unwrap();
+    if !options.gauges.is_empty() {
+        let gauges: Vec<String> = options
+            .gauges
+            .iter()
+            .map(|gauge| format!("{}: {}", quote(&gauge.name), quote(&gauge.spec)))
+            .collect();
+        writeln!(out, "  \"gauges\": {{ {} }},", gauges.join(", ")).unwrap();
+    }
+    let keys: Vec<String> = (0..16)
+        .filter_map(|key| {
+            let text = options.key_help[key].as_deref()?;
+            return Some(format!("\"{key:X}\": {}", quote(text)));
+        })
+        .collect();
+    if !keys.is_empty() {
+        writeln!(out, "  \"keys\": {{ {} }},", keys.join(", ")).unwrap();
+    }
+
+    out.push_str("  \"keymap\": {\n");
+    for (key, host_key) in keymap.iter().enumerate() {
+        let comma = if key < 15 { "," } else { "" };
+        let name = quote(keymap::key_name(*host_key));
+        writeln!(out, "    \"{key:X}\": {name}{comma}").unwrap();
+    }
+    out.push_str("  }\n}\n");
+
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustchip8::Profile;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        return args.iter().map(|arg| arg.to_string()).collect();
+    }
+
+    // What main() does: the sidecar's flags first, then the command line
+    fn apply(sidecar: &str, cli: &[&str]) -> Options {
+        let (mut args, _) = to_args(sidecar).unwrap();
+        args.extend(strings(cli));
+        return Options::parse(&args).unwrap_or_else(|e| panic!("{args:?}: {e}"));
+    }
+
+    #[test]
+    fn json_values() {
+        let value = parse_json(
+            r#" { "a": [1, -2.5, 3e2], "b": { "c": null }, "d": "x\"\\\né", "e": true } "#,
+        );
+        assert_eq!(
+            value,
+            Ok(Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Number(1.0),
+                        Json::Number(-2.5),
+                        Json::Number(300.0)
+                    ])
+                ),
+                (
+                    "b".to_string(),
+                    Json::Object(vec![("c".to_string(), Json::Null)])
+                ),
+                ("d".to_string(), Json::String("x\"\\\né".to_string())),
+                ("e".to_string(), Json::Bool(true)),
+            ]))
+        );
+        for bad in ["", "{", "[1,]", "{\"a\" 1}", "tru", "\"open", "{} x"] {
+            assert!(parse_json(bad).is_err(), "{bad}");
+        }
+        assert!(
+            parse_json("{\n\"a\": ?}")
+                .unwrap_err()
+                .starts_with("line 2:")
+        );
+    }
+
+    #[test]
+    fn settings_become_flags() {
+        let sidecar = r#"{
+            "title": "Blinky", "profile": "schip", "ips": 1200, "palette": ["ffcc00", "101020"],
+            "quirks": { "wrap": true, "shift": false }, "keymap": { "5": "SPACE" },
+            "keys": { "4": "left" }
+        }"#;
+        let (args, names) = to_args(sidecar).unwrap();
+        assert_eq!(
+            args,
+            strings(&[
+                "--title",
+                "Blinky",
+                "--profile",
+                "schip",
+                "--hz",
+                "1200",
+                "--palette",
+                "ffcc00,101020",
+                "--quirk-wrap",
+                "on",
+                "--quirk-shift",
+                "off",
+                "--key",
+                "5=SPACE",
+                "--key-help",
+                "4=left"
+            ])
+        );
+        assert_eq!(
+            names,
+            [
+                "title", "profile", "ips", "palette", "quirks", "keymap", "keys"
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_settings() {
+        let error = |sidecar: &str| to_args(sidecar).unwrap_err();
+        assert_eq!(error("[]"), "expected an object");
+        assert_eq!(error(r#"{"speed": 3}"#), "unknown setting \"speed\"");
+        assert_eq!(error(r#"{"ips": "fast"}"#), "\"ips\" has the wrong type");
+        assert_eq!(error(r#"{"ips": 1.5}"#), "\"ips\" has the wrong type");
+        assert_eq!(
+            error(r#"{"quirks": {"wrap": 1}}"#),
+            "quirk \"wrap\" must be true or false"
+        );
+        assert_eq!(
+            error(r#"{"palette": [1]}"#),
+            "\"palette\" colors must be strings"
+        );
+    }
+
+    #[test]
+    fn command_line_wins() {
+        let sidecar = r#"{ "profile": "schip", "ips": 1200, "quirks": { "wrap": false } }"#;
+        let options = apply(sidecar, &[]);
+        assert_eq!(options.profile, Profile::Schip);
+        assert_eq!(options.cpu_hz, 1200);
+        assert!(!options.quirks.sprite_wrap);
+
+        let options = apply(sidecar, &["--hz", "900", "--profile", "cosmac"]);
+        assert_eq!(options.profile, Profile::Cosmac);
+        assert_eq!(options.cpu_hz, 900);
+        // the sidecar's quirk still goes on top of the command line's profile
+        assert!(!options.quirks.sprite_wrap);
+        let options = apply(sidecar, &["--quirk-wrap", "on"]);
+        assert!(options.quirks.sprite_wrap);
+    }
+
+    #[test]
+    fn written_metadata_reads_back() {
+        let options = apply(
+            r#"{ "title": "Say \"hi\"", "ips": 800, "palette": "amber",
+                 "quirks": { "jump": true }, "keys": { "A": "fire" } }"#,
+            &["--layout", "azerty"],
+        );
+        let written = from_options(&options, &options.keymap);
+        let again = apply(&written, &[]);
+        assert_eq!(again.title.as_deref(), Some("Say \"hi\""));
+        assert_eq!(again.cpu_hz, 800);
+        assert_eq!(again.palette, options.palette);
+        assert_eq!(again.quirks, options.quirks);
+        assert_eq!(again.keymap, keymap::from_names(&rustchip8::keymap::AZERTY));
+        assert_eq!(again.key_help[0xA].as_deref(), Some("fire"));
+    }
+}
@@ -0,0 +1,24 @@
+pub mod antiflicker;
+pub mod audio;
+pub mod bench;
+pub mod cli;
+pub mod collisions;
+pub mod crt;
+pub mod debugger;
+pub mod gauges;
+pub mod headless;
+pub mod input;
+pub mod keymap;
+pub mod logging;
+pub mod metadata;
+pub mod overlay;
+pub mod picker;
+pub mod remote;
+pub mod rewind;
+pub mod screen;
+pub mod settings;
+pub mod shutdown;
+pub mod slots;
+pub mod viewport;
+pub mod wall;
+pub mod window;
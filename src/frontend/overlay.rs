@@ -0,0 +1,464 @@
+use super::collisions::CollisionFlash;
+use super::gauges::Gauge;
+use super::input::Bindings;
+use super::viewport::Viewport;
+use raylib::prelude::*;
+use rustchip8::{Chip8, disassemble_with};
+
+// rows in each column of the stats panel
+const STATS_ROWS: usize = 10;
+// calls listed in the debug strip, a deeper --stack-size only shows how many more there are
+const CALL_ROWS: usize = 16;
+
+// Ctrl plus the key toggles the cheat with the same number, 0 is the tenth
+pub const CHEAT_KEYS: [KeyboardKey; 10] = [
+    KeyboardKey::KEY_ONE,
+    KeyboardKey::KEY_TWO,
+    KeyboardKey::KEY_THREE,
+    KeyboardKey::KEY_FOUR,
+    KeyboardKey::KEY_FIVE,
+    KeyboardKey::KEY_SIX,
+    KeyboardKey::KEY_SEVEN,
+    KeyboardKey::KEY_EIGHT,
+    KeyboardKey::KEY_NINE,
+    KeyboardKey::KEY_ZERO,
+];
+
+const FONT_SIZE: i32 = 20;
+const LINE_HEIGHT: i32 = FONT_SIZE + 4;
+const PADDING: i32 = 8;
+// the memory view shrinks its font to fit small windows, but not below this
+const MIN_FONT_SIZE: i32 = 8;
+pub const MEMORY_ROW_BYTES: usize = 16;
+
+// The COSMAC VIP keypad, as the keys are laid out on it
+const KEYPAD_ROWS: [[u8; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// Register/timer readout drawn over a translucent strip along the top of the window,
+// plus a red line saying why execution stopped (breakpoint, watchpoint) if it did, and the
+// call stack on the right. `ips` is the measured instructions per second. Returns the
+// height of the strip.
+pub fn draw_debug(
+    chip8: &Chip8,
+    d: &mut impl RaylibDraw,
+    width: i32,
+    ips: u64,
+    stop_reason: Option<&str>,
+) -> i32 {
+    let opcode = match chip8.peek_opcode() {
+        Some(opcode) => format!(
+            "{opcode:04X}  {}",
+            disassemble_with(opcode, chip8.symbols())
+        ),
+        None => String::from("----"),
+    };
+    let registers = chip8.registers();
+    let reg_line = |range: std::ops::Range<usize>| {
+        range
+            .map(|i| format!("V{i:X} {:02X}", registers[i]))
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    let mut lines = vec![
+        format!(
+            "PC {:#06X}  I {:#06X}  OP {opcode}",
+            chip8.pc(),
+            chip8.reg_i()
+        ),
+        format!(
+            "DT {:02X}  ST {:02X}  SP {}/{}  IPS {ips}",
+            chip8.delay_timer(),
+            chip8.sound_timer(),
+            chip8.stack().len(),
+            chip8.stack_limit()
+        ),
+        reg_line(0..8),
+        reg_line(8..16),
+    ];
+    let first_stop_line = lines.len();
+    lines.extend(stop_reason.map(String::from));
+    let calls = call_stack(chip8);
+
+    let rows = lines.len().max(calls.len()) as i32;
+    let height = rows * LINE_HEIGHT + 2 * PADDING;
+    d.draw_rectangle(0, 0, width, height, Color::new(0, 0, 0, 180));
+    let calls_width = calls
+        .iter()
+        .map(|(line, _)| measure_text(line, FONT_SIZE))
+        .max()
+        .unwrap_or(0);
+    for (i, (line, color)) in calls.iter().enumerate() {
+        let x = width - calls_width - PADDING;
+        d.draw_text(line, x, PADDING + i as i32 * LINE_HEIGHT, FONT_SIZE, *color);
+    }
+    for (i, line) in lines.iter().enumerate() {
+        d.draw_text(
+            line,
+            PADDING,
+            PADDING + i as i32 * LINE_HEIGHT,
+            FONT_SIZE,
+            if i >= first_stop_line {
+                Color::RED
+            } else {
+                Color::WHITE
+            },
+        );
+    }
+
+    return height;
+}
+
+// The stack as calls, newest first: where each one returns to and the 2NNN just before
+// that made it. The frame the next 00EE returns from is yellow, a 2NNN that is about to
+// overflow the stack is red.
+fn call_stack(chip8: &Chip8) -> Vec<(String, Color)> {
+    let stack = chip8.stack();
+    let next = chip8.peek_opcode();
+    let mut lines = vec![(
+        format!("CALLS {}/{}", stack.len(), chip8.stack_limit()),
+        Color::GRAY,
+    )];
+    if stack.len() >= chip8.stack_limit()
+        && let Some(opcode) = next
+        && opcode & 0xF000 == 0x2000
+    {
+        lines.push((
+            format!(
+                "{:04X}  {}  OVERFLOW",
+                chip8.pc(),
+                disassemble_with(opcode, chip8.symbols())
+            ),
+            Color::RED,
+        ));
+    }
+    for (depth, &ret) in stack.iter().enumerate().rev().take(CALL_ROWS) {
+        let call = ret.wrapping_sub(2);
+        let site = match chip8.mem_range(call as usize, 2) {
+            Some(&[hi, lo]) if hi & 0xF0 == 0x20 => {
+                disassemble_with(u16::from_be_bytes([hi, lo]), chip8.symbols())
+            }
+            // pushed by something else, or the code has changed since
+            _ => String::from("????"),
+        };
+        let returning = depth + 1 == stack.len() && next == Some(0x00EE);
+        let color = if returning {
+            Color::YELLOW
+        } else {
+            Color::WHITE
+        };
+        lines.push((format!("{ret:04X} <- {call:04X}  {site}"), color));
+    }
+    if stack.len() > CALL_ROWS {
+        let older = stack.len() - CALL_ROWS;
+        lines.push((format!("+{older} older"), Color::GRAY));
+    }
+
+    return lines;
+}
+
+// Hex dump of memory starting at row `top_row` in a width x height panel at `y`. The
+// rows holding pc and I get a yellow and a blue background.
+pub fn draw_memory(
+    chip8: &Chip8,
+    d: &mut impl RaylibDraw,
+    y: i32,
+    width: i32,
+    height: i32,
+    top_row: usize,
+) {
+    let sample = format!("0000: {}", "00 ".repeat(MEMORY_ROW_BYTES));
+    let mut font_size = FONT_SIZE;
+    while font_size > MIN_FONT_SIZE && measure_text(&sample, font_size) > width - 2 * PADDING {
+        font_size -= 1;
+    }
+    let line_height = font_size + font_size / 5;
+
+    d.draw_rectangle(0, y, width, height, Color::new(0, 0, 0, 200));
+    let mem = chip8.mem();
+    let pc_row = chip8.pc() as usize / MEMORY_ROW_BYTES;
+    let i_row = chip8.reg_i() as usize / MEMORY_ROW_BYTES;
+    let visible = ((height - 2 * PADDING) / line_height).max(0) as usize;
+    for (line, row) in (top_row..mem.len() / MEMORY_ROW_BYTES)
+        .take(visible)
+        .enumerate()
+    {
+        let line_y = y + PADDING + line as i32 * line_height;
+        if row == pc_row {
+            d.draw_rectangle(0, line_y, width, line_height, Color::new(160, 140, 0, 160));
+        } else if row == i_row {
+            d.draw_rectangle(0, line_y, width, line_height, Color::new(0, 80, 180, 160));
+        }
+
+        let start = row * MEMORY_ROW_BYTES;
+        let bytes: Vec<String> = mem[start..start + MEMORY_ROW_BYTES]
+            .iter()
+            .map(|byte| format!("{byte:02X}"))
+            .collect();
+        let text = format!("{start:04X}: {}", bytes.join(" "));
+        d.draw_text(&text, PADDING, line_y, font_size, Color::WHITE);
+    }
+}
+
+// The cheats in a panel at `y`, numbered for their Ctrl hotkeys, the ones that are on in
+// green. Returns the height of the panel.
+pub fn draw_cheats(chip8: &Chip8, d: &mut impl RaylibDraw, y: i32, width: i32) -> i32 {
+    let cheats = chip8.cheats();
+    let mut lines: Vec<(String, Color)> = cheats
+        .iter()
+        .enumerate()
+        .map(|(i, cheat)| {
+            let key = match i {
+                i if i < CHEAT_KEYS.len() => format!("Ctrl+{}", (i + 1) % 10),
+                _ => String::from("      "),
+            };
+            return match cheat.enabled {
+                true => (format!("{key}  ON   {cheat}"), Color::GREEN),
+                false => (format!("{key}  OFF  {cheat}"), Color::GRAY),
+            };
+        })
+        .collect();
+    if cheats.is_empty() {
+        lines.push((String::from("No cheats, see <rom>.cheats"), Color::WHITE));
+    }
+
+    let height = lines.len() as i32 * LINE_HEIGHT + 2 * PADDING;
+    d.draw_rectangle(0, y, width, height, Color::new(0, 0, 0, 200));
+    for (i, (line, color)) in lines.iter().enumerate() {
+        d.draw_text(
+            line,
+            PADDING,
+            y + PADDING + i as i32 * LINE_HEIGHT,
+            FONT_SIZE,
+            *color,
+        );
+    }
+
+    return height;
+}
+
+// One line at `y`: VF after the last DXYN, red while its collision is still shown, and how
+// many pixels it erased. Returns the height of the panel.
+pub fn draw_collisions(flash: &CollisionFlash, d: &mut impl RaylibDraw, y: i32, width: i32) -> i32 {
+    let (pixels, strength) = flash.pixels();
+    let (text, color) = match flash.vf() {
+        None => (String::from("No DXYN has run yet"), Color::GRAY),
+        Some(vf) if !pixels.is_empty() => (
+            format!(
+                "VF={vf:02X} after the last DXYN, last collision {} pixels",
+                pixels.len()
+            ),
+            Color::RED.fade(0.5 + 0.5 * strength),
+        ),
+        Some(vf) => (format!("VF={vf:02X} after the last DXYN"), Color::WHITE),
+    };
+
+    let height = LINE_HEIGHT + 2 * PADDING;
+    d.draw_rectangle(0, y, width, height, Color::new(0, 0, 0, 200));
+    d.draw_text(&text, PADDING, y + PADDING, FONT_SIZE, color);
+
+    return height;
+}
+
+// The gauges as `name value` lines in a box in the top right corner, starting at `y`.
+// Gauges that can't be read show their error in red.
+pub fn draw_gauges(gauges: &[Gauge], chip8: &Chip8, d: &mut impl RaylibDraw, y: i32, width: i32) {
+    let lines: Vec<(String, Color)> = gauges
+        .iter()
+        .map(|gauge| match gauge.read(chip8) {
+            Ok(value) => (format!("{}  {value}", gauge.name), Color::WHITE),
+            Err(e) => (format!("{}  {e}", gauge.name), Color::RED),
+        })
+        .collect();
+    let text_width = lines
+        .iter()
+        .map(|(line, _)| measure_text(line, FONT_SIZE))
+        .max()
+        .unwrap_or(0);
+
+    let x = width - text_width - 2 * PADDING;
+    let height = lines.len() as i32 * LINE_HEIGHT + 2 * PADDING;
+    d.draw_rectangle(
+        x,
+        y,
+        text_width + 2 * PADDING,
+        height,
+        Color::new(0, 0, 0, 180),
+    );
+    for (i, (line, color)) in lines.iter().enumerate() {
+        d.draw_text(
+            line,
+            x + PADDING,
+            y + PADDING + i as i32 * LINE_HEIGHT,
+            FONT_SIZE,
+            *color,
+        );
+    }
+}
+
+// The most executed opcode patterns and addresses side by side in a panel at `y`, the
+// addresses with their instruction. Returns the height of the panel.
+pub fn draw_stats(chip8: &Chip8, d: &mut impl RaylibDraw, y: i32, width: i32) -> i32 {
+    let Some(stats) = chip8.stats() else {
+        return 0;
+    };
+    let families: Vec<String> = stats
+        .families()
+        .into_iter()
+        .take(STATS_ROWS)
+        .map(|(pattern, count)| format!("{pattern}  {:5.1}%", stats.percent(count)))
+        .collect();
+    let addresses: Vec<String> = stats
+        .addresses()
+        .into_iter()
+        .take(STATS_ROWS)
+        .map(|(pc, count)| {
+            let op = match chip8.mem_range(pc as usize, 2) {
+                Some(&[hi, lo]) => disassemble_with(u16::from_be_bytes([hi, lo]), chip8.symbols()),
+                _ => String::new(),
+            };
+            return format!("{pc:#05X}  {:5.1}%  {op}", stats.percent(count));
+        })
+        .collect();
+
+    let rows = families.len().max(addresses.len()) as i32;
+    let height = (rows + 1) * LINE_HEIGHT + 2 * PADDING;
+    d.draw_rectangle(0, y, width, height, Color::new(0, 0, 0, 200));
+    let title = format!("{} instructions", stats.total());
+    d.draw_text(&title, PADDING, y + PADDING, FONT_SIZE, Color::YELLOW);
+    let columns = [(PADDING, &families), (width / 3, &addresses)];
+    for (x, lines) in columns {
+        for (i, line) in lines.iter().enumerate() {
+            let line_y = y + PADDING + (i as i32 + 1) * LINE_HEIGHT;
+            d.draw_text(line, x, line_y, FONT_SIZE, Color::WHITE);
+        }
+    }
+
+    return height;
+}
+
+// Where the keypad cheatsheet goes in a window this size: a square grid as large as fits,
+// and with `notes` a column for the key descriptions on its right with at least 45% of the
+// width. The two are centered together.
+pub fn keypad_layout(width: i32, height: i32, notes: bool) -> (Viewport, Option<Viewport>) {
+    let (width, height) = (width - 4 * PADDING, height - 4 * PADDING);
+    let notes_width = if notes { width * 9 / 20 } else { 0 };
+    let side = height.min(width - notes_width).max(4 * MIN_FONT_SIZE);
+    let gap = if notes { 2 * PADDING } else { 0 };
+    let notes_width = if notes {
+        (width - side - gap).max(0)
+    } else {
+        0
+    };
+    let x = 2 * PADDING + (width - side - gap - notes_width) / 2;
+    let grid = Viewport {
+        x,
+        y: 2 * PADDING + (height - side) / 2,
+        width: side,
+        height: side,
+    };
+    let notes = notes.then_some(Viewport {
+        x: x + side + gap,
+        y: grid.y,
+        width: notes_width,
+        height: side,
+    });
+
+    return (grid, notes);
+}
+
+// Largest size up to `max` at which every line is at most `width` wide
+fn fitting_font_size(lines: &[String], width: i32, max: i32) -> i32 {
+    let mut font_size = max.max(MIN_FONT_SIZE);
+    while font_size > MIN_FONT_SIZE
+        && lines
+            .iter()
+            .any(|line| measure_text(line, font_size) > width)
+    {
+        font_size -= 1;
+    }
+
+    return font_size;
+}
+
+// The keypad cheatsheet over the whole window: a cell per CHIP-8 key with its hex digit and
+// the host inputs bound to it (the gamepad's only while one is connected), keys being held
+// in yellow, and the ROM's descriptions of the keys beside it
+pub fn draw_keypad(
+    d: &mut impl RaylibDraw,
+    bindings: &Bindings,
+    gamepad: bool,
+    help: &[Option<String>; 16],
+    held: [bool; 16],
+    width: i32,
+    height: i32,
+) {
+    let notes: Vec<String> = (0..16)
+        .filter_map(|key| Some(format!("{key:X}  {}", help[key].as_deref()?)))
+        .collect();
+    let (grid, notes_area) = keypad_layout(width, height, !notes.is_empty());
+    d.draw_rectangle(0, 0, width, height, Color::new(0, 0, 0, 200));
+
+    let cell = grid.width / 4;
+    let pad = (cell / 12).max(2);
+    for (row, keys) in KEYPAD_ROWS.iter().enumerate() {
+        for (col, &key) in keys.iter().enumerate() {
+            let (x, y) = (grid.x + col as i32 * cell, grid.y + row as i32 * cell);
+            let background = if held[key as usize] {
+                Color::new(160, 140, 0, 220)
+            } else {
+                Color::new(40, 40, 40, 220)
+            };
+            d.draw_rectangle(x + 1, y + 1, cell - 2, cell - 2, background);
+            d.draw_rectangle_lines(x + 1, y + 1, cell - 2, cell - 2, Color::GRAY);
+
+            let digit_size = (cell * 2 / 5).max(MIN_FONT_SIZE);
+            d.draw_text(
+                &format!("{key:X}"),
+                x + pad,
+                y + pad,
+                digit_size,
+                Color::WHITE,
+            );
+            let labels: Vec<String> = bindings
+                .for_key(key)
+                .filter(|binding| gamepad || !binding.is_gamepad())
+                .map(|binding| binding.label())
+                .collect();
+            let font_size = fitting_font_size(&labels, cell - 2 * pad, cell / 7);
+            let top = y + pad + digit_size + pad / 2;
+            let line_height = font_size + 2;
+            let fits = ((y + cell - pad - top) / line_height).max(0) as usize;
+            for (i, label) in labels.iter().take(fits).enumerate() {
+                let line_y = top + i as i32 * line_height;
+                d.draw_text(label, x + pad, line_y, font_size, Color::LIGHTGRAY);
+            }
+        }
+    }
+
+    if let Some(area) = notes_area {
+        let font_size = fitting_font_size(&notes, area.width, FONT_SIZE);
+        let line_height = font_size + font_size / 5;
+        for (i, line) in notes.iter().enumerate() {
+            let y = area.y + i as i32 * line_height;
+            d.draw_text(line, area.x, y, font_size, Color::WHITE);
+        }
+    }
+}
+
+// One-line notice (a dropped file that isn't a ROM...) on a red strip along the bottom
+pub fn draw_message(d: &mut impl RaylibDraw, text: &str, width: i32, height: i32) {
+    let strip = LINE_HEIGHT + 2 * PADDING;
+    d.draw_rectangle(0, height - strip, width, strip, Color::new(120, 0, 0, 220));
+    d.draw_text(
+        text,
+        PADDING,
+        height - strip + PADDING,
+        FONT_SIZE,
+        Color::WHITE,
+    );
+}
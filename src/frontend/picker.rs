@@ -0,0 +1,152 @@
+use raylib::prelude::*;
+use std::fs;
+use std::path::PathBuf;
+
+pub const DEFAULT_ROM_DIR: &str = "roms";
+const EXTENSIONS: [&str; 2] = ["ch8", "c8"];
+
+const FONT_SIZE: i32 = 20;
+const LINE_HEIGHT: i32 = FONT_SIZE + 6;
+const PADDING: i32 = 12;
+
+// File list shown when started without a ROM: arrows move, Enter loads
+pub struct Picker {
+    dir: PathBuf,
+    entries: Vec<PathBuf>,
+    selected: usize,
+    // first visible entry
+    top: usize,
+    // why the list is empty, shown instead of it
+    error: Option<String>,
+}
+
+impl Picker {
+    pub fn new(dir: &str) -> Self {
+        let mut picker = Picker {
+            dir: PathBuf::from(dir),
+            entries: Vec::new(),
+            selected: 0,
+            top: 0,
+            error: None,
+        };
+        picker.rescan();
+
+        return picker;
+    }
+
+    // Reads the directory again, keeping the selection when that file is still there
+    pub fn rescan(&mut self) {
+        let previous = self.entries.get(self.selected).cloned();
+        self.entries.clear();
+        self.error = None;
+
+        match fs::read_dir(&self.dir) {
+            Ok(dir) => {
+                self.entries = dir
+                    .filter_map(|entry| Some(entry.ok()?.path()))
+                    .filter(|path| {
+                        let extension = path.extension().map(|ext| ext.to_ascii_lowercase());
+                        return path.is_file()
+                            && extension.is_some_and(|ext| EXTENSIONS.iter().any(|e| ext == *e));
+                    })
+                    .collect();
+                self.entries.sort();
+                if self.entries.is_empty() {
+                    self.error = Some(format!("No .ch8 or .c8 files in {}", self.dir.display()));
+                }
+            }
+            Err(e) => self.error = Some(format!("Can't open {}: {e}", self.dir.display())),
+        }
+
+        self.selected = previous
+            .and_then(|path| self.entries.iter().position(|entry| *entry == path))
+            .unwrap_or(0);
+    }
+
+    // Handles this frame's keys, returns the ROM to load once Enter is pressed
+    pub fn update(&mut self, rl: &RaylibHandle) -> Option<PathBuf> {
+        if self.entries.is_empty() {
+            if rl.is_key_pressed(KeyboardKey::KEY_F5) {
+                self.rescan();
+            }
+            return None;
+        }
+
+        let last = self.entries.len() - 1;
+        let page = 10;
+        if rl.is_key_pressed(KeyboardKey::KEY_DOWN) {
+            self.selected = (self.selected + 1).min(last);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_UP) {
+            self.selected = self.selected.saturating_sub(1);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_PAGE_DOWN) {
+            self.selected = (self.selected + page).min(last);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_PAGE_UP) {
+            self.selected = self.selected.saturating_sub(page);
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_HOME) {
+            self.selected = 0;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_END) {
+            self.selected = last;
+        }
+        if rl.is_key_pressed(KeyboardKey::KEY_F5) {
+            self.rescan();
+        }
+
+        if rl.is_key_pressed(KeyboardKey::KEY_ENTER) || rl.is_key_pressed(KeyboardKey::KEY_KP_ENTER)
+        {
+            return self.entries.get(self.selected).cloned();
+        }
+        return None;
+    }
+
+    pub fn draw(&mut self, d: &mut impl RaylibDraw, width: i32, height: i32) {
+        d.clear_background(Color::BLACK);
+        let title = format!("Pick a ROM from {}", self.dir.display());
+        d.draw_text(&title, PADDING, PADDING, FONT_SIZE, Color::WHITE);
+        let hint = "Up/Down select, Enter load, F5 refresh, Esc quit";
+        d.draw_text(
+            hint,
+            PADDING,
+            height - PADDING - FONT_SIZE,
+            FONT_SIZE,
+            Color::GRAY,
+        );
+
+        let list_y = PADDING + 2 * LINE_HEIGHT;
+        if let Some(error) = &self.error {
+            d.draw_text(error, PADDING, list_y, FONT_SIZE, Color::RED);
+            return;
+        }
+
+        // scroll just enough to keep the selection on screen
+        let visible = ((height - list_y - 2 * LINE_HEIGHT) / LINE_HEIGHT).max(1) as usize;
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + visible {
+            self.top = self.selected + 1 - visible;
+        }
+
+        for (line, (i, path)) in self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(self.top)
+            .take(visible)
+            .enumerate()
+        {
+            let y = list_y + line as i32 * LINE_HEIGHT;
+            let color = if i == self.selected {
+                d.draw_rectangle(0, y - 3, width, LINE_HEIGHT, Color::new(160, 140, 0, 160));
+                Color::WHITE
+            } else {
+                Color::LIGHTGRAY
+            };
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            d.draw_text(&name, PADDING, y, FONT_SIZE, color);
+        }
+    }
+}
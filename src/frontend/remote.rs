@@ -0,0 +1,176 @@
+// --listen: a line protocol over TCP for driving the window from a test script. Every
+// connection gets a thread that parses its lines and hands the commands to the main loop
+// through a channel, so the machine itself is only ever touched by the loop. Each command
+// is answered with one line, `OK [...]` or `ERR message`, before the next one is read.
+//
+//   KEYDOWN K / KEYUP K     press or release CHIP-8 key K (0-F), on top of the keyboard
+//   RESET                   restart the ROM
+//   LOADSTATE BASE64        load a save state (the bytes of an F5 .state file)
+//   SCREENSHOT [PNG]        OK and the display as a base64 PNG
+//   SCREENSHOT ASCII        OK N and then the N rows of the display as in --headless
+//   PAUSE / RESUME
+//   STEP N                  pause and run N instructions, OK pc once they have run
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteCommand {
+    KeyDown(u8),
+    KeyUp(u8),
+    Reset,
+    LoadState(Vec<u8>),
+    Screenshot { ascii: bool },
+    Pause,
+    Resume,
+    Step(u32),
+}
+
+// A command and the connection waiting for its answer
+pub struct Request {
+    pub command: RemoteCommand,
+    reply: Sender<String>,
+}
+
+impl Request {
+    pub fn reply(self, result: Result<String, String>) {
+        let line = match result {
+            Ok(text) if text.is_empty() => String::from("OK"),
+            Ok(text) => format!("OK {text}"),
+            Err(e) => format!("ERR {e}"),
+        };
+        // the client may have hung up in the meantime
+        let _ = self.reply.send(line);
+    }
+}
+
+// Binds `addr` and accepts connections in the background
+pub fn listen(addr: &str) -> Result<Receiver<Request>, String> {
+    let listener =
+        TcpListener::bind(addr).map_err(|e| format!("failed to listen on {addr}: {e}"))?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let sender = sender.clone();
+            thread::spawn(move || serve(stream, sender));
+        }
+    });
+
+    return Ok(receiver);
+}
+
+fn serve(stream: TcpStream, sender: Sender<Request>) {
+    let Ok(mut out) = stream.try_clone() else {
+        return;
+    };
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else { break };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match parse(&line) {
+            Ok(command) => {
+                let (reply, answer) = mpsc::channel();
+                // the window was closed
+                if sender.send(Request { command, reply }).is_err() {
+                    break;
+                }
+                let Ok(answer) = answer.recv() else { break };
+                answer
+            }
+            Err(e) => format!("ERR {e}"),
+        };
+        if writeln!(out, "{reply}").is_err() {
+            break;
+        }
+    }
+}
+
+pub fn parse(line: &str) -> Result<RemoteCommand, String> {
+    let mut words = line.split_whitespace();
+    let name = words.next().unwrap_or("").to_ascii_uppercase();
+    let arg = words.next();
+    if words.next().is_some() {
+        return Err(format!("too many arguments for {name}"));
+    }
+    let key = |arg: Option<&str>| {
+        return match arg.map(|arg| (arg.len(), u8::from_str_radix(arg, 16))) {
+            Some((1, Ok(key))) => Ok(key),
+            _ => Err(format!("{name} expects a key 0-F")),
+        };
+    };
+
+    let command = match (name.as_str(), arg) {
+        ("KEYDOWN", _) => RemoteCommand::KeyDown(key(arg)?),
+        ("KEYUP", _) => RemoteCommand::KeyUp(key(arg)?),
+        ("RESET", None) => RemoteCommand::Reset,
+        ("LOADSTATE", Some(data)) => RemoteCommand::LoadState(decode_base64(data)?),
+        ("LOADSTATE", None) => return Err(String::from("LOADSTATE expects base64 data")),
+        ("SCREENSHOT", None) => RemoteCommand::Screenshot { ascii: false },
+        ("SCREENSHOT", Some(format)) => match format.to_ascii_uppercase().as_str() {
+            "PNG" => RemoteCommand::Screenshot { ascii: false },
+            "ASCII" => RemoteCommand::Screenshot { ascii: true },
+            _ => return Err(String::from("SCREENSHOT expects PNG or ASCII")),
+        },
+        ("PAUSE", None) => RemoteCommand::Pause,
+        ("RESUME", None) => RemoteCommand::Resume,
+        ("STEP", Some(n)) => match n.parse() {
+            Ok(n) if n > 0 => RemoteCommand::Step(n),
+            _ => return Err(format!("STEP expects a positive count, got '{n}'")),
+        },
+        ("STEP", None) => RemoteCommand::Step(1),
+        ("RESET" | "PAUSE" | "RESUME", Some(_)) => {
+            return Err(format!("{name} takes no arguments"));
+        }
+        _ => return Err(format!("unknown command '{name}'")),
+    };
+
+    return Ok(command);
+}
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+            bits | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(bits >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    return out;
+}
+
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let text = text.trim_end_matches('=');
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut bits = 0u32;
+    let mut count = 0;
+    for c in text.bytes() {
+        let Some(value) = BASE64.iter().position(|&b| b == c) else {
+            return Err(format!("invalid base64 character '{}'", c as char));
+        };
+        bits = bits << 6 | value as u32;
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            out.push((bits >> count) as u8);
+        }
+    }
+    // a single character left over can't hold a whole byte
+    if count >= 6 {
+        return Err(String::from("truncated base64 data"));
+    }
+
+    return Ok(out);
+}
@@ -0,0 +1,37 @@
+use rustchip8::Chip8;
+use std::collections::VecDeque;
+
+// 10 seconds at 60 frames per second
+pub const REWIND_FRAMES: usize = 600;
+
+// The last few seconds as save states, one per frame. A 4K machine snapshot is about
+// 6KB, so the whole buffer stays under 4MB.
+pub struct Rewind {
+    states: VecDeque<Vec<u8>>,
+    capacity: usize,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Self {
+        return Rewind {
+            states: VecDeque::with_capacity(capacity),
+            capacity,
+        };
+    }
+
+    pub fn push(&mut self, chip8: &Chip8) {
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(chip8.save_state());
+    }
+
+    // Restores the most recent snapshot, false once there is nothing left to rewind
+    pub fn step_back(&mut self, chip8: &mut Chip8) -> bool {
+        let Some(state) = self.states.pop_back() else {
+            return false;
+        };
+
+        return chip8.load_state(&state).is_ok();
+    }
+}
@@ -0,0 +1,319 @@
+use super::antiflicker::AntiFlicker;
+use super::crt::Crt;
+use super::viewport::Viewport;
+use raylib::consts::TextureFilter;
+use raylib::prelude::*;
+use rustchip8::{Chip8, Framebuffer};
+
+// The framebuffer is converted to RGBA and uploaded to a texture at CHIP-8 resolution
+// whenever it changes, then drawn scaled over the viewport with a single call. Render cost
+// no longer depends on the number of lit pixels.
+pub struct Screen {
+    texture: Option<Texture2D>,
+    linear_filter: bool,
+    picture: Picture,
+}
+
+// What goes into the texture and where it is drawn, apart from the texture so the upload
+// decisions can be tested without a window
+struct Picture {
+    // RGBA bytes in texture layout
+    pixels: Vec<u8>,
+    // columns and rows of the CHIP-8 display the texture was made for
+    size: (usize, usize),
+    // texture width and height, larger than `size` with the CRT filter
+    texels: (usize, usize),
+    view: Option<Viewport>,
+    crt: Crt,
+    crt_enabled: bool,
+    anti_flicker: Option<AntiFlicker>,
+    // the texture doesn't hold the current picture yet
+    stale: bool,
+}
+
+impl Screen {
+    pub fn new(
+        linear_filter: bool,
+        crt: Crt,
+        crt_enabled: bool,
+        anti_flicker: Option<AntiFlicker>,
+    ) -> Self {
+        return Screen {
+            texture: None,
+            linear_filter,
+            picture: Picture::new(crt, crt_enabled, anti_flicker),
+        };
+    }
+
+    pub fn toggle_crt(&mut self) -> bool {
+        self.picture.crt_enabled = !self.picture.crt_enabled;
+        return self.picture.crt_enabled;
+    }
+
+    // The filtered picture as last drawn, only while the CRT filter is on. Screenshots use
+    // this so they look like the window.
+    pub fn crt_pixels(&self) -> Option<(usize, usize, &[u8])> {
+        let picture = &self.picture;
+        if !picture.crt_enabled || picture.stale {
+            return None;
+        }
+        return Some((picture.texels.0, picture.texels.1, &picture.pixels));
+    }
+
+    // Call before begin_drawing(): fits the viewport to `area` of the window (all of it, or
+    // one half in split-screen) and recreates the texture when the resolution changed
+    pub fn update_layout(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        chip8: &Chip8,
+        area: Viewport,
+        integer_scaling: bool,
+    ) {
+        if !self.picture.fit(chip8, area, integer_scaling) && self.texture.is_some() {
+            return;
+        }
+
+        let (width, height) = self.picture.texels;
+        let image = Image::gen_image_color(width as i32, height as i32, Color::BLACK);
+        self.texture = rl.load_texture_from_image(thread, &image).ok();
+        if let Some(texture) = &self.texture {
+            let filter = if self.linear_filter {
+                TextureFilter::TEXTURE_FILTER_BILINEAR
+            } else {
+                TextureFilter::TEXTURE_FILTER_POINT
+            };
+            texture.set_texture_filter(thread, filter);
+        }
+        self.picture.stale = true;
+    }
+
+    // The window is cleared by the caller, split-screen draws two of these. `changed` is
+    // Chip8::take_display_dirty().
+    pub fn draw(
+        &mut self,
+        d: &mut RaylibDrawHandle,
+        chip8: &Chip8,
+        changed: bool,
+        palette: &[Color; 4],
+    ) {
+        let (Some(view), Some(texture)) = (self.picture.view, &mut self.texture) else {
+            return;
+        };
+
+        if self.picture.prepare(chip8, changed, palette) {
+            texture.update_texture(&self.picture.pixels);
+        }
+        // every frame, a static display still has to be drawn after a resize
+        let (cols, rows) = (self.picture.texels.0 as f32, self.picture.texels.1 as f32);
+        d.draw_texture_pro(
+            &*texture,
+            Rectangle::new(0.0, 0.0, cols, rows),
+            Rectangle::new(
+                view.x as f32,
+                view.y as f32,
+                view.width as f32,
+                view.height as f32,
+            ),
+            Vector2::new(0.0, 0.0),
+            0.0,
+            Color::WHITE,
+        );
+    }
+
+    // Red squares over `pixels`, display coordinates, with `strength` from 0.0 (gone) to 1.0.
+    // Call after draw(), it uses the same viewport.
+    pub fn draw_marks(&self, d: &mut RaylibDrawHandle, pixels: &[(usize, usize)], strength: f32) {
+        let Some(view) = self.picture.view else {
+            return;
+        };
+        let (cols, rows) = self.picture.size;
+        if cols == 0 || rows == 0 {
+            return;
+        }
+
+        let color = Color::RED.fade(0.25 + 0.75 * strength.clamp(0.0, 1.0));
+        let cell_width = view.width as f32 / cols as f32;
+        let cell_height = view.height as f32 / rows as f32;
+        for &(x, y) in pixels {
+            if x >= cols || y >= rows {
+                continue;
+            }
+            d.draw_rectangle_rec(
+                Rectangle::new(
+                    view.x as f32 + x as f32 * cell_width,
+                    view.y as f32 + y as f32 * cell_height,
+                    cell_width,
+                    cell_height,
+                ),
+                color,
+            );
+        }
+    }
+}
+
+impl Picture {
+    fn new(crt: Crt, crt_enabled: bool, anti_flicker: Option<AntiFlicker>) -> Self {
+        return Picture {
+            pixels: Vec::new(),
+            size: (0, 0),
+            texels: (0, 0),
+            view: None,
+            crt,
+            crt_enabled,
+            anti_flicker,
+            stale: true,
+        };
+    }
+
+    // The viewport for `area`, a resize only moves the picture. Returns whether the
+    // resolution changed, which takes a new texture.
+    fn fit(&mut self, chip8: &Chip8, area: Viewport, integer_scaling: bool) -> bool {
+        let size = (chip8.resolution().width(), chip8.resolution().height());
+        let (aspect_width, aspect_height) = chip8.resolution().aspect();
+        let view = Viewport::fit(
+            area.width,
+            area.height,
+            aspect_width,
+            aspect_height,
+            integer_scaling,
+        );
+        self.view = Some(Viewport {
+            x: area.x + view.x,
+            y: area.y + view.y,
+            ..view
+        });
+        let texels = if self.crt_enabled {
+            let scale = Crt::scale(size.0);
+            (size.0 * scale, size.1 * scale)
+        } else {
+            size
+        };
+        if self.size == size && self.texels == texels {
+            return false;
+        }
+
+        self.pixels = vec![0; texels.0 * texels.1 * 4];
+        self.size = size;
+        self.texels = texels;
+        self.stale = true;
+        return true;
+    }
+
+    // Renders the picture into `pixels` when the texture doesn't show it anymore. Returns
+    // whether it has to be uploaded, an unchanged display costs nothing.
+    fn prepare(&mut self, chip8: &Chip8, mut changed: bool, palette: &[Color; 4]) -> bool {
+        let display = match &mut self.anti_flicker {
+            Some(anti_flicker) => {
+                // old frames dropping out change the picture even when the display didn't
+                let (blended, blend_changed) = anti_flicker.blend(chip8.display());
+                changed = blend_changed;
+                blended
+            }
+            None => chip8.display(),
+        };
+        // phosphor persistence keeps fading after the display stopped changing
+        let fading = self.crt_enabled && self.crt.animates();
+        if !changed && !self.stale && !fading {
+            return false;
+        }
+
+        if self.crt_enabled {
+            self.crt
+                .render(display, self.size, palette, &mut self.pixels);
+        } else {
+            to_rgba(display, self.size, palette, &mut self.pixels);
+        }
+        self.stale = false;
+        return true;
+    }
+}
+
+// The visible part of the display as RGBA through the palette
+fn to_rgba(
+    display: &Framebuffer,
+    (width, height): (usize, usize),
+    palette: &[Color; 4],
+    out: &mut [u8],
+) {
+    let rows = display.iter().take(height);
+    let pixels = rows.flat_map(|row| row.iter().take(width));
+    for (rgba, &pixel) in out.chunks_exact_mut(4).zip(pixels) {
+        let color = palette[pixel as usize & 0b11];
+        rgba.copy_from_slice(&[color.r, color.g, color.b, 255]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    const PALETTE: [Color; 4] = [Color::BLACK, Color::GREEN, Color::BLUE, Color::WHITE];
+    const FRAMES: usize = 120;
+
+    fn window(width: i32, height: i32) -> Viewport {
+        return Viewport {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+    }
+
+    // The frames of `changed` that upload the picture, and how long they took
+    fn run(picture: &mut Picture, chip8: &Chip8, changed: bool) -> (usize, f64) {
+        let start = Instant::now();
+        let uploads = (0..FRAMES)
+            .filter(|_| picture.prepare(chip8, changed, &PALETTE))
+            .count();
+        return (uploads, start.elapsed().as_secs_f64());
+    }
+
+    #[test]
+    fn a_static_display_is_uploaded_once() {
+        let chip8 = Chip8::new();
+        let mut picture = Picture::new(Crt::new(0.0), false, None);
+        assert!(picture.fit(&chip8, window(640, 320), false));
+        assert!(picture.prepare(&chip8, false, &PALETTE), "a new texture");
+
+        let (static_uploads, static_time) = run(&mut picture, &chip8, false);
+        let (redrawn_uploads, redrawn_time) = run(&mut picture, &chip8, true);
+        assert_eq!((static_uploads, redrawn_uploads), (0, FRAMES));
+        // the redrawn frames convert 2048 pixels each, the static ones nothing
+        assert!(
+            static_time < redrawn_time,
+            "static {static_time}s, redrawn {redrawn_time}s"
+        );
+
+        // CRT persistence keeps fading without the display changing
+        let mut picture = Picture::new(Crt::new(0.5), true, None);
+        picture.fit(&chip8, window(640, 320), false);
+        assert_eq!(run(&mut picture, &chip8, false).0, FRAMES);
+    }
+
+    #[test]
+    fn a_resize_repaints_without_an_upload() {
+        let chip8 = Chip8::new();
+        let mut picture = Picture::new(Crt::new(0.0), false, None);
+        picture.fit(&chip8, window(640, 320), true);
+        picture.prepare(&chip8, true, &PALETTE);
+        assert_eq!(picture.view, Some(window(640, 320)));
+
+        // the same texture drawn over the new viewport every frame
+        assert!(!picture.fit(&chip8, window(800, 500), true));
+        assert!(!picture.prepare(&chip8, false, &PALETTE));
+        let view = Viewport {
+            x: 16,
+            y: 58,
+            width: 768,
+            height: 384,
+        };
+        assert_eq!(picture.view, Some(view));
+
+        // the CRT filter's texture is larger, so it is made again and uploaded
+        picture.crt_enabled = true;
+        assert!(picture.fit(&chip8, window(800, 500), true));
+        assert!(picture.prepare(&chip8, false, &PALETTE));
+    }
+}
@@ -0,0 +1,209 @@
+// Settings kept between runs, in settings.json in the platform's config directory (see
+// the directories crate):
+//
+//   {
+//     "window": { "x": 120, "y": 80, "width": 1024, "height": 512 },
+//     "scale": 16,
+//     "palette": ["000000", "00E430", "0078FF", "FFFFFF"],
+//     "volume": 0.5,
+//     "rom_dir": "roms",
+//     "profile": "cosmac"
+//   }
+//
+// Like a <rom>.json sidecar they are turned into command line flags that go in front of
+// everything else, so the sidecar and the command line win over them. Written on exit from
+// the settings the run started with (without the sidecar's) and where the window was.
+
+use super::cli::Options;
+use directories::ProjectDirs;
+use rustchip8::Profile;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+const FILE_NAME: &str = "settings.json";
+
+// The window's position and size in screen coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Geometry {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+// RRGGBB colors, or the name of a --palette preset
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Palette {
+    Colors(Vec<String>),
+    Preset(String),
+}
+
+// What the file holds, everything optional: a setting that is missing keeps its default
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<Geometry>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scale: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<Palette>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rom_dir: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    // settings this version doesn't know, warned about and not saved again
+    #[serde(flatten, skip_serializing)]
+    unknown: BTreeMap<String, serde_json::Value>,
+}
+
+impl Settings {
+    pub fn from_options(options: &Options) -> Self {
+        let window = match (options.window_pos, options.window_size) {
+            (Some((x, y)), Some((width, height))) => Some(Geometry {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => None,
+        };
+        let colors = options
+            .palette
+            .iter()
+            .map(|[r, g, b]| format!("{r:02X}{g:02X}{b:02X}"))
+            .collect();
+        let profile = match options.profile {
+            Profile::Cosmac => "cosmac",
+            Profile::Chip48 => "chip48",
+            Profile::Schip => "schip",
+        };
+        return Settings {
+            window,
+            scale: Some(options.scale),
+            palette: Some(Palette::Colors(colors)),
+            volume: Some(options.volume),
+            rom_dir: Some(options.rom_dir.clone()),
+            profile: Some(profile.to_string()),
+            unknown: BTreeMap::new(),
+        };
+    }
+
+    pub fn to_json(&self) -> String {
+        // only strings, numbers and objects with string keys, which always serialize
+        let mut json = serde_json::to_string_pretty(self).unwrap();
+        json.push('\n');
+        return json;
+    }
+}
+
+// ~/.config/rustchip8 (or $XDG_CONFIG_HOME) on Linux and the BSDs,
+// ~/Library/Application Support/rustchip8 on macOS and %APPDATA%\rustchip8\config on
+// Windows. None without a home directory.
+pub fn default_path() -> Option<PathBuf> {
+    let dirs = ProjectDirs::from("", "", "rustchip8")?;
+    return Some(dirs.config_dir().join(FILE_NAME));
+}
+
+// The flags the file stands for, and what was wrong with it. A file that doesn't parse,
+// or has a setting of the wrong type, gives no flags. A value the option rejects is left
+// out on its own, so a damaged file never keeps the emulator from starting.
+pub fn to_args(text: &str) -> (Vec<String>, Vec<String>) {
+    let settings: Settings = match serde_json::from_str(text) {
+        Ok(settings) => settings,
+        Err(e) => return (Vec::new(), vec![e.to_string()]),
+    };
+
+    let mut warnings: Vec<String> = settings
+        .unknown
+        .keys()
+        .map(|name| format!("unknown setting \"{name}\""))
+        .collect();
+    let flag = |name: &str, value: String| vec![format!("--{name}"), value];
+    let mut settings_flags = Vec::new();
+    if let Some(scale) = settings.scale {
+        settings_flags.push(("scale", flag("scale", scale.to_string())));
+    }
+    match settings.palette {
+        Some(Palette::Colors(colors)) => {
+            settings_flags.push(("palette", flag("palette", colors.join(","))));
+        }
+        Some(Palette::Preset(name)) => settings_flags.push(("palette", flag("palette", name))),
+        None => {}
+    }
+    if let Some(volume) = settings.volume {
+        settings_flags.push(("volume", flag("volume", volume.to_string())));
+    }
+    if let Some(dir) = settings.rom_dir {
+        settings_flags.push(("rom_dir", flag("rom-dir", dir)));
+    }
+    if let Some(profile) = settings.profile {
+        settings_flags.push(("profile", flag("profile", profile)));
+    }
+    // after "scale", which would drop the size again
+    if let Some(Geometry {
+        x,
+        y,
+        width,
+        height,
+    }) = settings.window
+    {
+        let mut window = flag("window-pos", format!("{x},{y}"));
+        window.extend(flag("window-size", format!("{width}x{height}")));
+        settings_flags.push(("window", window));
+    }
+
+    let mut args = Vec::new();
+    // each setting on its own, so one bad value doesn't throw the others away
+    for (name, flags) in settings_flags {
+        match Options::parse(&flags) {
+            Ok(_) => args.extend(flags),
+            Err(e) => warnings.push(format!("\"{name}\": {e}")),
+        }
+    }
+
+    return (args, warnings);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_settings_read_back() {
+        let flags: Vec<String> = ["--scale", "8", "--palette", "amber", "--profile", "schip"]
+            .map(String::from)
+            .to_vec();
+        let mut settings = Settings::from_options(&Options::parse(&flags).unwrap());
+        settings.window = Some(Geometry {
+            x: 10,
+            y: 20,
+            width: 640,
+            height: 320,
+        });
+        let (args, warnings) = to_args(&settings.to_json());
+        assert!(warnings.is_empty(), "{warnings:?}");
+        let restored = Options::parse(&args).unwrap();
+        assert_eq!(Settings::from_options(&restored), settings);
+    }
+
+    #[test]
+    fn bad_settings_are_skipped() {
+        let (args, warnings) = to_args(r#"{ "scale": 4, "palette": ["red"], "colour": "red" }"#);
+        assert_eq!(args, ["--scale", "4"]);
+        assert_eq!(warnings.len(), 2, "{warnings:?}");
+        assert_eq!(warnings[0], "unknown setting \"colour\"");
+        assert!(warnings[1].starts_with("\"palette\": "), "{warnings:?}");
+
+        // the wrong type throws the file away, the emulator still starts
+        let (args, warnings) = to_args(r#"{ "scale": "big" }"#);
+        assert!(args.is_empty());
+        assert_eq!(warnings.len(), 1);
+        let (args, _) = to_args("not json");
+        assert!(args.is_empty());
+    }
+}
@@ -0,0 +1,34 @@
+// Ctrl+C as a request to stop rather than the end of the process: the first one sets a flag
+// the window, --headless and --bench loops check so they finish their GIF, recording, flags
+// and reports like on a normal exit. A second one, for a loop that doesn't get there, exits
+// right away.
+
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// What a shell reports for a process ended by SIGINT
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+// ctrlc runs the handler on a thread of its own, so the second Ctrl+C can exit normally
+pub fn catch_interrupt() {
+    let result = ctrlc::set_handler(|| {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    });
+    // without it Ctrl+C still ends the process, just without the wrap-up
+    if let Err(e) = result {
+        log::warn!("failed to catch Ctrl+C: {e}");
+    }
+}
+
+pub fn interrupted() -> bool {
+    return INTERRUPTED.load(Ordering::Relaxed);
+}
+
+// For the debugger prompt, where Ctrl+C stops the run and the prompt goes on
+pub fn take_interrupt() -> bool {
+    return INTERRUPTED.swap(false, Ordering::SeqCst);
+}
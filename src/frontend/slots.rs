@@ -0,0 +1,119 @@
+use super::rewind::{REWIND_FRAMES, Rewind};
+use super::viewport::Viewport;
+use log::{error, info};
+use raylib::prelude::*;
+use rustchip8::Chip8;
+
+// A machine with its ROM and the per-game state the window keeps for it. The window runs
+// one slot and parks the other (--rom-b), Ctrl+B swaps them, so each keeps its full state
+// and quirks while the other one is played.
+pub struct EmulatorSlot {
+    // 'A' or 'B', shown in the title when there are two
+    pub label: char,
+    pub chip8: Chip8,
+    // kept for Ctrl+R, dropping a file on the window replaces them
+    pub rom: Vec<u8>,
+    pub rom_path: Option<String>,
+    pub state_path: String,
+    pub rom_name: Option<String>,
+    pub halted: bool,
+    pub rewind: Rewind,
+    // pc of the breakpoint that paused us, it lets that same instruction run on resume
+    pub break_hit: Option<u16>,
+    // shown by the overlay until the next instruction runs
+    pub stop_reason: Option<String>,
+    // from the ROM's sidecar, beside the keypad cheatsheet
+    pub key_help: [Option<String>; 16],
+    // run_parked() cycles waiting for the next timer tick
+    owed_cycles: u32,
+}
+
+impl EmulatorSlot {
+    pub fn new(
+        label: char,
+        chip8: Chip8,
+        rom: Vec<u8>,
+        rom_path: Option<String>,
+        state_path: String,
+        rom_name: Option<String>,
+    ) -> Self {
+        return EmulatorSlot {
+            label,
+            chip8,
+            rom,
+            rom_path,
+            state_path,
+            rom_name,
+            halted: false,
+            rewind: Rewind::new(REWIND_FRAMES),
+            break_hit: None,
+            stop_reason: None,
+            key_help: Default::default(),
+            owed_cycles: 0,
+        };
+    }
+
+    // Keys held when the slot is switched away from would stay down in the parked machine
+    pub fn release_keys(&mut self) {
+        for key in 0..16 {
+            self.chip8.set_key(key, false);
+        }
+    }
+
+    // A frame of the parked slot in split-screen or of a --wall tile: no debugger, recording
+    // or rewind, just a Chip8::run_frame() per timer tick with the frame's cycles shared out
+    // between them. A window frame without a tick keeps its cycles for the next one.
+    pub fn run_parked(&mut self, cycles: u32, ticks: u32, keys: &[bool; 16]) {
+        self.owed_cycles = self.owed_cycles.saturating_add(cycles);
+        for tick in 0..ticks {
+            if self.halted {
+                break;
+            }
+            let cycles = self.owed_cycles / (ticks - tick);
+            self.owed_cycles -= cycles;
+            let result = self.chip8.run_frame(cycles, keys);
+            if let Some((pc, e)) = result.halted {
+                error!("CPU {} halted at {pc:#05X}: {e}", self.label);
+                self.halted = true;
+                self.stop_reason = Some(format!("halted at {pc:#05X}: {e}"));
+            } else if result.exited {
+                info!("Program {} exited", self.label);
+                self.halted = true;
+                self.stop_reason = Some(String::from("exited"));
+            }
+        }
+    }
+}
+
+// Left and right halves of the window for split-screen, A on the left
+pub fn split_areas(width: i32, height: i32) -> [Viewport; 2] {
+    let half = width / 2;
+    return [
+        Viewport {
+            x: 0,
+            y: 0,
+            width: half,
+            height,
+        },
+        Viewport {
+            x: half,
+            y: 0,
+            width: width - half,
+            height,
+        },
+    ];
+}
+
+// A divider between the halves and the slot letters, the active one highlighted
+pub fn draw_split(d: &mut impl RaylibDraw, areas: &[Viewport; 2], active: char) {
+    let divider = areas[1].x;
+    d.draw_line(divider, 0, divider, areas[1].height, Color::DARKGRAY);
+    for (area, label) in areas.iter().zip(['A', 'B']) {
+        let color = if label == active {
+            Color::YELLOW
+        } else {
+            Color::GRAY
+        };
+        d.draw_text(&label.to_string(), area.x + 6, area.y + 4, 20, color);
+    }
+}
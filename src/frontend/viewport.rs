@@ -0,0 +1,36 @@
+// Where the CHIP-8 grid lands inside the window. Anything mapping between window and
+// grid coordinates (drawing now, mouse input later) should go through this.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Viewport {
+    // Centered and letterboxed. With integer scaling every CHIP-8 pixel is the same whole
+    // number of window pixels, otherwise the grid is stretched over the whole window.
+    pub fn fit(
+        window_width: i32,
+        window_height: i32,
+        cols: usize,
+        rows: usize,
+        integer: bool,
+    ) -> Self {
+        let (cols, rows) = (cols as i32, rows as i32);
+        let (width, height) = if integer {
+            let scale = (window_width / cols).min(window_height / rows).max(1);
+            (cols * scale, rows * scale)
+        } else {
+            (window_width, window_height)
+        };
+
+        return Viewport {
+            x: (window_width - width) / 2,
+            y: (window_height - height) / 2,
+            width,
+            height,
+        };
+    }
+}
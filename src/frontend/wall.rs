@@ -0,0 +1,221 @@
+use super::screen::Screen;
+use super::slots::EmulatorSlot;
+use super::viewport::Viewport;
+use raylib::prelude::*;
+
+// window pixels of a tile at scale 1, one per pixel of a hi-res display
+pub const TILE_WIDTH: i32 = 128;
+pub const TILE_HEIGHT: i32 = 64;
+const LABEL_SIZE: i32 = 10;
+const ERROR_SIZE: i32 = 10;
+const GAP: i32 = 2;
+
+// One cell of the wall: a running machine, or why there is none
+pub struct Tile {
+    pub name: String,
+    pub slot: Option<EmulatorSlot>,
+    // the ROM couldn't be loaded, a machine that halts keeps its error in stop_reason
+    pub error: Option<String>,
+    pub screen: Screen,
+}
+
+impl Tile {
+    fn failure(&self) -> Option<&str> {
+        return match &self.slot {
+            Some(slot) if slot.halted => Some(slot.stop_reason.as_deref().unwrap_or("halted")),
+            Some(_) => None,
+            None => Some(self.error.as_deref().unwrap_or("not loaded")),
+        };
+    }
+}
+
+// --wall: every ROM of a directory in its own machine, all running off the same clock and
+// drawn in a grid. Clicking a tile zooms into it, Escape or a right click goes back. The
+// keypad goes to the selected machine only.
+pub struct Wall {
+    pub tiles: Vec<Tile>,
+    pub selected: usize,
+    pub zoomed: bool,
+}
+
+impl Wall {
+    pub fn new(tiles: Vec<Tile>) -> Self {
+        return Wall {
+            tiles,
+            selected: 0,
+            zoomed: false,
+        };
+    }
+
+    // As square as it gets, a column more rather than a row
+    pub fn grid(count: usize) -> (usize, usize) {
+        let cols = (count as f64).sqrt().ceil().max(1.0) as usize;
+        let rows = count.div_ceil(cols).max(1);
+        return (cols, rows);
+    }
+
+    // Where each tile goes in a window of this size, the whole window for a zoomed one
+    pub fn cells(&self, width: i32, height: i32) -> Vec<Viewport> {
+        let (cols, rows) = Wall::grid(self.tiles.len());
+        let (cell_width, cell_height) = (width / cols as i32, height / rows as i32);
+        return (0..self.tiles.len())
+            .map(|i| Viewport {
+                x: (i % cols) as i32 * cell_width,
+                y: (i / cols) as i32 * cell_height,
+                width: cell_width,
+                height: cell_height,
+            })
+            .collect();
+    }
+
+    pub fn selected(&mut self) -> &mut Tile {
+        return &mut self.tiles[self.selected];
+    }
+
+    // Mouse and Escape, before the keypad is read
+    pub fn handle_input(&mut self, rl: &RaylibHandle) {
+        if self.zoomed {
+            if rl.is_key_pressed(KeyboardKey::KEY_ESCAPE)
+                || rl.is_mouse_button_pressed(MouseButton::MOUSE_RIGHT_BUTTON)
+            {
+                self.zoomed = false;
+            }
+            return;
+        }
+        if !rl.is_mouse_button_pressed(MouseButton::MOUSE_LEFT_BUTTON) {
+            return;
+        }
+        let (x, y) = (rl.get_mouse_x(), rl.get_mouse_y());
+        let cells = self.cells(rl.get_screen_width(), rl.get_screen_height());
+        let clicked = cells.iter().position(|cell| {
+            return (cell.x..cell.x + cell.width).contains(&x)
+                && (cell.y..cell.y + cell.height).contains(&y);
+        });
+        if let Some(clicked) = clicked {
+            if clicked != self.selected
+                && let Some(slot) = &mut self.tiles[self.selected].slot
+            {
+                slot.release_keys();
+            }
+            self.selected = clicked;
+            self.zoomed = true;
+        }
+    }
+
+    // A frame of every machine, halted ones stay as they are while the others run
+    pub fn run(&mut self, cycles: u32, ticks: u32, keys: [bool; 16]) {
+        for (i, tile) in self.tiles.iter_mut().enumerate() {
+            let keys = if i == self.selected {
+                keys
+            } else {
+                [false; 16]
+            };
+            if let Some(slot) = &mut tile.slot {
+                slot.run_parked(cycles, ticks, &keys);
+            }
+        }
+    }
+
+    // Call before begin_drawing(), like Screen::update_layout()
+    pub fn update_layout(
+        &mut self,
+        rl: &mut RaylibHandle,
+        thread: &RaylibThread,
+        integer_scaling: bool,
+    ) {
+        let (width, height) = (rl.get_screen_width(), rl.get_screen_height());
+        let cells = self.cells(width, height);
+        for (i, (tile, cell)) in self.tiles.iter_mut().zip(cells).enumerate() {
+            let Some(slot) = &tile.slot else {
+                continue;
+            };
+            let area = match self.zoomed {
+                true if i == self.selected => Viewport {
+                    x: 0,
+                    y: 0,
+                    width,
+                    height,
+                },
+                // hidden behind the zoomed one, but its texture keeps up
+                true => cell,
+                false => Viewport {
+                    x: cell.x + GAP,
+                    y: cell.y + GAP,
+                    width: cell.width - 2 * GAP,
+                    height: cell.height - 2 * GAP,
+                },
+            };
+            tile.screen
+                .update_layout(rl, thread, &slot.chip8, area, integer_scaling);
+        }
+    }
+
+    pub fn draw(&mut self, d: &mut RaylibDrawHandle, palette: &[Color; 4]) {
+        let (width, height) = (d.get_screen_width(), d.get_screen_height());
+        if self.zoomed {
+            let tile = &mut self.tiles[self.selected];
+            if let Some(slot) = &mut tile.slot {
+                let changed = slot.chip8.take_display_dirty();
+                tile.screen.draw(d, &slot.chip8, changed, palette);
+            }
+            let area = Viewport {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            };
+            draw_failure(d, tile, area);
+            return;
+        }
+
+        let cells = self.cells(width, height);
+        for (i, (tile, cell)) in self.tiles.iter_mut().zip(cells).enumerate() {
+            if let Some(slot) = &mut tile.slot {
+                let changed = slot.chip8.take_display_dirty();
+                tile.screen.draw(d, &slot.chip8, changed, palette);
+            }
+            draw_failure(d, tile, cell);
+            d.draw_text(
+                &tile.name,
+                cell.x + GAP + 2,
+                cell.y + GAP + 2,
+                LABEL_SIZE,
+                Color::GRAY,
+            );
+            if i == self.selected {
+                d.draw_rectangle_lines(cell.x, cell.y, cell.width, cell.height, Color::YELLOW);
+            }
+        }
+    }
+}
+
+// Greyed out with the error on top, wrapped to the tile
+fn draw_failure(d: &mut RaylibDrawHandle, tile: &Tile, area: Viewport) {
+    let Some(text) = tile.failure() else {
+        return;
+    };
+    d.draw_rectangle(
+        area.x,
+        area.y,
+        area.width,
+        area.height,
+        Color::new(60, 60, 60, 200),
+    );
+    let mut lines: Vec<String> = Vec::new();
+    for word in text.split_whitespace() {
+        match lines.last_mut() {
+            Some(line) if measure_text(&format!("{line} {word}"), ERROR_SIZE) < area.width - 8 => {
+                line.push(' ');
+                line.push_str(word);
+            }
+            _ => lines.push(word.to_string()),
+        }
+    }
+    let line_height = ERROR_SIZE + 2;
+    let top = area.y + LABEL_SIZE + 8;
+    let fits = ((area.y + area.height - top) / line_height).max(0) as usize;
+    for (i, line) in lines.iter().take(fits).enumerate() {
+        let y = top + i as i32 * line_height;
+        d.draw_text(line, area.x + 4, y, ERROR_SIZE, Color::WHITE);
+    }
+}
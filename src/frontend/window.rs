@@ -0,0 +1,148 @@
+use super::antiflicker::AntiFlicker;
+use super::audio::Beeper;
+use super::cli::Options;
+use super::crt::Crt;
+use super::input::Input;
+use super::keymap::Keymap;
+use super::screen::Screen;
+use super::shutdown;
+use super::viewport::Viewport;
+use raylib::prelude::*;
+use rustchip8::host::{FrameInput, Frontend, Status};
+use rustchip8::{Chip8, disassemble};
+
+pub const TITLE: &str = "CHIP-8";
+
+// A screen for the display with the --crt, --ghosting, --anti-flicker and --linear-filter
+// options
+pub fn new_screen(options: &Options) -> Screen {
+    return Screen::new(
+        options.linear_filter,
+        Crt::new(options.ghosting),
+        options.crt,
+        options.anti_flicker.map(AntiFlicker::new),
+    );
+}
+
+// The raylib window as a host::Frontend: the keyboard and gamepad, the display scaled to the
+// window and the beep. The window loop in main.rs goes through present_with() to draw its
+// overlays and the split-screen over the picture, everything else takes present().
+pub struct Window {
+    // the texture and the audio stream go before the window they were made for
+    pub screen: Screen,
+    beeper: Option<Beeper>,
+    pub input: Input,
+    pub palette: [Color; 4],
+    integer_scaling: bool,
+    // the XO-CHIP pattern, taken from the machine in present_with() for play_beep()
+    pattern: Option<([u8; 16], u8)>,
+    title: String,
+    pub rl: RaylibHandle,
+    pub thread: RaylibThread,
+}
+
+impl Window {
+    pub fn new(rl: RaylibHandle, thread: RaylibThread, options: &Options, keymap: Keymap) -> Self {
+        let beeper = (!options.mute).then(|| {
+            return Beeper::new(
+                &thread,
+                options.wave,
+                options.beep_freq,
+                options.volume,
+                options.audio_buffer,
+            );
+        });
+
+        return Window {
+            screen: new_screen(options),
+            beeper,
+            input: Input::new(keymap, options.gamepad),
+            palette: options.palette.map(|[r, g, b]| Color::new(r, g, b, 255)),
+            integer_scaling: options.integer_scaling,
+            pattern: None,
+            title: String::from(TITLE),
+            rl,
+            thread,
+        };
+    }
+
+    // For when play_beep() won't be called for a while
+    pub fn stop_beep(&mut self) {
+        if let Some(beeper) = &mut self.beeper {
+            beeper.stop();
+        }
+    }
+
+    pub fn set_title(&mut self, title: String) {
+        if title != self.title {
+            self.rl.set_window_title(&self.thread, &title);
+            self.title = title;
+        }
+    }
+
+    // One frame: `chip8` fitted to `area` of the window, then `overlay` drawn over it with
+    // the screen (for its viewport) and the input (for the keypad cheatsheet)
+    pub fn present_with(
+        &mut self,
+        chip8: &Chip8,
+        changed: bool,
+        area: Viewport,
+        overlay: impl FnOnce(&mut RaylibDrawHandle, &Screen, &Input),
+    ) {
+        self.pattern = chip8
+            .uses_audio_pattern()
+            .then(|| (chip8.audio_pattern(), chip8.pitch()));
+        let integer_scaling = self.integer_scaling;
+        self.screen
+            .update_layout(&mut self.rl, &self.thread, chip8, area, integer_scaling);
+        let mut d = self.rl.begin_drawing(&self.thread);
+        d.clear_background(Color::BLACK);
+        self.screen.draw(&mut d, chip8, changed, &self.palette);
+        overlay(&mut d, &self.screen, &self.input);
+    }
+}
+
+impl Frontend for Window {
+    fn poll_input(&mut self) -> FrameInput {
+        let rl = &self.rl;
+        return FrameInput {
+            keys: self.input.poll(rl),
+            toggle_pause: rl.is_key_pressed(KeyboardKey::KEY_SPACE)
+                || rl.is_key_pressed(KeyboardKey::KEY_P),
+            step: rl.is_key_pressed(KeyboardKey::KEY_N) || rl.is_key_pressed(KeyboardKey::KEY_F10),
+        };
+    }
+
+    // The whole window with the status in the title
+    fn present(&mut self, chip8: &Chip8, changed: bool, status: &Status) {
+        let status = match status {
+            Status::Halted { pc, error } => format!("HALTED at {pc:#05X}: {error}"),
+            Status::Paused => format!(
+                "PAUSED at {:#05X}: {}",
+                chip8.pc(),
+                chip8.peek_opcode().map_or(String::from("?"), disassemble)
+            ),
+            Status::Exited => String::from("EXITED"),
+            Status::Running => String::from("RUNNING"),
+        };
+        self.set_title(format!("{TITLE} — {status}"));
+
+        let area = Viewport {
+            x: 0,
+            y: 0,
+            width: self.rl.get_screen_width(),
+            height: self.rl.get_screen_height(),
+        };
+        self.present_with(chip8, changed, area, |_, _, _| {});
+    }
+
+    fn play_beep(&mut self, on: bool) {
+        if let Some(beeper) = &mut self.beeper {
+            beeper.update(on, self.pattern);
+        }
+    }
+
+    fn should_close(&self) -> bool {
+        return self.rl.window_should_close() || shutdown::interrupted();
+    }
+}
@@ -0,0 +1,395 @@
+// Animated GIF of the display without any image crate, streamed one 60Hz frame at a time
+// so a long recording never sits in memory. Identical consecutive frames are merged into
+// one with a longer delay.
+
+use crate::screenshot::Rgb;
+use crate::{Chip8, HIRES_HEIGHT, HIRES_WIDTH, TIMER_HZ};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+// 4 palette entries, so LZW starts from 2-bit codes
+const MIN_CODE_SIZE: u8 = 2;
+const CLEAR: u16 = 1 << MIN_CODE_SIZE;
+const END: u16 = CLEAR + 1;
+const MAX_CODE: u16 = 4095;
+
+// Collects codes into the little-endian bit stream GIF wants, cut into sub-blocks
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+}
+
+impl BitWriter {
+    fn write(&mut self, code: u16, size: u8) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        return self.bytes;
+    }
+}
+
+fn lzw(indices: &[u8]) -> Vec<u8> {
+    let mut out = BitWriter {
+        bytes: Vec::new(),
+        buffer: 0,
+        bits: 0,
+    };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = END + 1;
+    let mut size = MIN_CODE_SIZE + 1;
+    out.write(CLEAR, size);
+
+    let Some((&first, rest)) = indices.split_first() else {
+        out.write(END, size);
+        return out.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+
+        out.write(prefix, size);
+        if next_code > MAX_CODE {
+            // table full: start over instead of growing past 12 bits
+            out.write(CLEAR, size);
+            table.clear();
+            next_code = END + 1;
+            size = MIN_CODE_SIZE + 1;
+        } else {
+            table.insert((prefix, index), next_code);
+            if next_code == 1 << size && size < 12 {
+                size += 1;
+            }
+            next_code += 1;
+        }
+        prefix = index as u16;
+    }
+    out.write(prefix, size);
+    out.write(END, size);
+
+    return out.finish();
+}
+
+pub struct GifRecorder<W: Write> {
+    out: W,
+    // every CHIP-8 pixel of the 128x64 grid becomes scale x scale, low-res ones twice that
+    scale: usize,
+    // palette indices of the frame waiting for its delay to be known
+    pending: Option<Vec<u8>>,
+    // 60Hz frames shown so far, and how much of that time the written frames cover
+    frames: u64,
+    written_cs: u64,
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(mut out: W, scale: u32, palette: &[Rgb; 4]) -> io::Result<Self> {
+        let scale = scale.max(1) as usize;
+        let width = (HIRES_WIDTH * scale) as u16;
+        let height = (HIRES_HEIGHT * scale) as u16;
+
+        out.write_all(b"GIF89a")?;
+        out.write_all(&width.to_le_bytes())?;
+        out.write_all(&height.to_le_bytes())?;
+        // global color table of 4 entries, background index 0, square pixels
+        out.write_all(&[0b1000_0001, 0, 0])?;
+        for color in palette {
+            out.write_all(color)?;
+        }
+        // NETSCAPE2.0 extension: loop forever
+        out.write_all(b"\x21\xFF\x0BNETSCAPE2.0\x03\x01\x00\x00\x00")?;
+
+        return Ok(GifRecorder {
+            out,
+            scale,
+            pending: None,
+            frames: 0,
+            written_cs: 0,
+        });
+    }
+
+    // Adds the display as it looks during one 60Hz frame
+    pub fn push_frame(&mut self, chip8: &Chip8) -> io::Result<()> {
+        let frame = self.indices(chip8);
+        if self.pending.as_ref() != Some(&frame) {
+            self.flush()?;
+            self.pending = Some(frame);
+        }
+        self.frames += 1;
+
+        return Ok(());
+    }
+
+    // Writes the last frame and the trailer, returns the writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        self.out.write_all(&[0x3B])?;
+        self.out.flush()?;
+
+        return Ok(self.out);
+    }
+
+    pub fn frames(&self) -> u64 {
+        return self.frames;
+    }
+
+    fn indices(&self, chip8: &Chip8) -> Vec<u8> {
+        let resolution = chip8.resolution();
+        // VIP hi-res pixels come out half as tall as they are wide
+        let pixel_width = self.scale * HIRES_WIDTH / resolution.width();
+        let pixel_height = self.scale * HIRES_HEIGHT / resolution.height();
+        let out_width = HIRES_WIDTH * self.scale;
+        let out_height = HIRES_HEIGHT * self.scale;
+
+        let mut indices = Vec::with_capacity(out_width * out_height);
+        for y in 0..out_height {
+            let row = &chip8.display()[y / pixel_height];
+            indices.extend((0..out_width).map(|x| row[x / pixel_width] & 0b11));
+        }
+
+        return indices;
+    }
+
+    // The pending frame is shown until now
+    fn flush(&mut self) -> io::Result<()> {
+        let Some(frame) = self.pending.take() else {
+            return Ok(());
+        };
+        // delays are in 1/100 s, rounding the running total keeps 60Hz from drifting
+        let end_cs = (self.frames * 100 + TIMER_HZ as u64 / 2) / TIMER_HZ as u64;
+        let delay = (end_cs - self.written_cs).min(u16::MAX as u64) as u16;
+        self.written_cs = end_cs;
+
+        let width = (HIRES_WIDTH * self.scale) as u16;
+        let height = (HIRES_HEIGHT * self.scale) as u16;
+        // graphic control extension with the delay, no transparency
+        self.out.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        self.out.write_all(&delay.to_le_bytes())?;
+        self.out.write_all(&[0x00, 0x00])?;
+        // image descriptor covering the whole canvas, no local color table
+        self.out.write_all(&[0x2C, 0, 0, 0, 0])?;
+        self.out.write_all(&width.to_le_bytes())?;
+        self.out.write_all(&height.to_le_bytes())?;
+        self.out.write_all(&[0x00, MIN_CODE_SIZE])?;
+        for block in lzw(&frame).chunks(255) {
+            self.out.write_all(&[block.len() as u8])?;
+            self.out.write_all(block)?;
+        }
+        self.out.write_all(&[0x00])?;
+
+        return Ok(());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: [Rgb; 4] = [[0, 0, 0], [255, 255, 255], [255, 0, 0], [0, 0, 255]];
+
+    struct Decoded {
+        width: usize,
+        height: usize,
+        // (delay in 1/100 s, palette indices)
+        frames: Vec<(u16, Vec<u8>)>,
+    }
+
+    // Reads back what GifRecorder writes, panicking on anything that isn't well-formed
+    fn decode(gif: &[u8]) -> Decoded {
+        let mut at = 0;
+        let mut take = |len: usize| {
+            let bytes = &gif[at..at + len];
+            at += len;
+            return bytes;
+        };
+        let word = |bytes: &[u8]| u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+
+        assert_eq!(take(6), b"GIF89a");
+        let screen = take(7);
+        assert_eq!(screen[4], 0b1000_0001, "4 color global table");
+        assert_eq!(take(12), PALETTE.concat());
+        let (width, height) = (word(&screen[0..]), word(&screen[2..]));
+        let mut frames = Vec::new();
+        let mut delay = None;
+        loop {
+            match take(1)[0] {
+                0x21 => {
+                    let label = take(1)[0];
+                    let mut data = Vec::new();
+                    loop {
+                        let len = take(1)[0] as usize;
+                        if len == 0 {
+                            break;
+                        }
+                        data.extend_from_slice(take(len));
+                    }
+                    if label == 0xF9 {
+                        delay = Some(word(&data[1..]) as u16);
+                    }
+                }
+                0x2C => {
+                    let descriptor = take(9);
+                    assert_eq!(word(&descriptor[4..]), width);
+                    assert_eq!(word(&descriptor[6..]), height);
+                    assert_eq!(descriptor[8], 0, "no local color table");
+                    let min_code_size = take(1)[0];
+                    let mut data = Vec::new();
+                    loop {
+                        let len = take(1)[0] as usize;
+                        if len == 0 {
+                            break;
+                        }
+                        data.extend_from_slice(take(len));
+                    }
+                    let indices = unlzw(&data, min_code_size);
+                    assert_eq!(indices.len(), width * height);
+                    frames.push((delay.take().expect("a delay per frame"), indices));
+                }
+                0x3B => break,
+                byte => panic!("unexpected block {byte:#04X} at {}", at - 1),
+            }
+        }
+        assert_eq!(at, gif.len(), "nothing after the trailer");
+
+        return Decoded {
+            width,
+            height,
+            frames,
+        };
+    }
+
+    fn unlzw(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1u16 << min_code_size;
+        let end = clear + 1;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut size = min_code_size + 1;
+        let mut prev: Option<u16> = None;
+        let mut out = Vec::new();
+        let (mut buffer, mut bits, mut bytes) = (0u32, 0u8, data.iter());
+        loop {
+            while bits < size {
+                buffer |= (*bytes.next().expect("an end code") as u32) << bits;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << size) - 1)) as u16;
+            buffer >>= size;
+            bits -= size;
+
+            if code == clear {
+                table = (0..clear).map(|index| vec![index as u8]).collect();
+                table.extend([Vec::new(), Vec::new()]);
+                size = min_code_size + 1;
+                prev = None;
+                continue;
+            }
+            if code == end {
+                break;
+            }
+            let Some(last) = prev else {
+                out.extend_from_slice(&table[code as usize]);
+                prev = Some(code);
+                continue;
+            };
+            let mut entry = table[last as usize].clone();
+            let first = match table.get(code as usize) {
+                Some(known) => known[0],
+                None => {
+                    assert_eq!(code as usize, table.len(), "code past the table");
+                    entry[0]
+                }
+            };
+            entry.push(first);
+            if table.len() < 4096 {
+                table.push(entry);
+                if table.len() == 1 << size && size < 12 {
+                    size += 1;
+                }
+            }
+            out.extend_from_slice(&table[code as usize]);
+            prev = Some(code);
+        }
+
+        return out;
+    }
+
+    // Random sprites all over the screen, different every call
+    fn scribble(chip8: &mut Chip8, seed: &mut u32) {
+        for _ in 0..20 {
+            *seed ^= *seed << 13;
+            *seed ^= *seed >> 17;
+            *seed ^= *seed << 5;
+            chip8.set_reg_i(*seed as u16 & 0x1FF);
+            chip8.set_register(0, (*seed >> 16) as u8);
+            chip8.set_register(1, (*seed >> 24) as u8);
+            chip8.execute(0xD01F).unwrap();
+        }
+    }
+
+    #[test]
+    fn stopped_early_is_still_a_gif() {
+        let mut chip8 = Chip8::with_seed(0);
+        let mut gif = GifRecorder::new(Vec::new(), 4, &PALETTE).unwrap();
+        let mut seed = 1;
+        // 3 frames of one picture, 1 of another, and a last one cut off after 2
+        let mut expected = Vec::new();
+        for held in [3, 1, 2] {
+            scribble(&mut chip8, &mut seed);
+            expected.push(gif.indices(&chip8));
+            for _ in 0..held {
+                gif.push_frame(&chip8).unwrap();
+            }
+        }
+        assert_eq!(gif.frames(), 6);
+        let decoded = decode(&gif.finish().unwrap());
+
+        assert_eq!((decoded.width, decoded.height), (512, 256));
+        let delays: Vec<u16> = decoded.frames.iter().map(|&(delay, _)| delay).collect();
+        // 5/100, 7/100 and 10/100 s into the recording
+        assert_eq!(delays, [5, 2, 3]);
+        for (i, (_, indices)) in decoded.frames.iter().enumerate() {
+            assert!(*indices == expected[i], "frame {i}");
+        }
+        // low-res pixels are 8x8 at scale 4
+        let last = &decoded.frames[2].1;
+        for (y, x) in [(0, 0), (100, 37), (255, 511)] {
+            assert_eq!(last[y * 512 + x], chip8.display()[y / 8][x / 8] & 0b11);
+        }
+    }
+
+    #[test]
+    fn nothing_recorded() {
+        let gif = GifRecorder::new(Vec::new(), 1, &PALETTE).unwrap();
+        let decoded = decode(&gif.finish().unwrap());
+        assert_eq!((decoded.width, decoded.height), (128, 64));
+        assert!(decoded.frames.is_empty());
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let mut seed = 7u32;
+        // long enough to fill the 4096-entry table and start over
+        let noise: Vec<u8> = (0..200_000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                return (seed >> 7) as u8 & 0b11;
+            })
+            .collect();
+        for indices in [&[][..], &[3], &[1; 5000], &noise] {
+            assert!(unlzw(&lzw(indices), MIN_CODE_SIZE) == indices);
+        }
+    }
+}
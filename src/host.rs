@@ -0,0 +1,123 @@
+// What a windowing backend has to provide to run a game, and the frame loop shared by the
+// simple frontends. The loop gets the elapsed time from its caller, so the core never needs
+// a clock (there is none on wasm32) and tests can feed it a simulated one.
+
+use crate::{Chip8, Chip8Error, CpuClock, TimerClock};
+use std::time::Duration;
+
+// Keypad and controls as of one poll
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FrameInput {
+    pub keys: [bool; 16],
+    pub toggle_pause: bool,
+    // one instruction while paused
+    pub step: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Running,
+    Paused,
+    // 00FD
+    Exited,
+    // the CPU stopped on an error, the picture stays up
+    Halted { pc: u16, error: Chip8Error },
+}
+
+pub trait Frontend {
+    fn poll_input(&mut self) -> FrameInput;
+    // `changed` is false when the display is the same as at the last call
+    fn present(&mut self, chip8: &Chip8, changed: bool, status: &Status);
+    // called every frame with whether the sound timer is running
+    fn play_beep(&mut self, on: bool);
+    fn should_close(&self) -> bool;
+}
+
+// CPU and timer pacing between a Frontend and the machine, one Chip8::run_frame() per 60Hz
+// tick that is due
+pub struct FrameLoop {
+    cpu_clock: CpuClock,
+    timer_clock: TimerClock,
+    // cycles due since the last tick, spent by the next one
+    owed: u32,
+    status: Status,
+}
+
+impl FrameLoop {
+    // `hz` instructions per second
+    pub fn new(hz: u32) -> Self {
+        return FrameLoop {
+            cpu_clock: CpuClock::new(hz),
+            timer_clock: TimerClock::new(),
+            owed: 0,
+            status: Status::Running,
+        };
+    }
+
+    pub fn status(&self) -> &Status {
+        return &self.status;
+    }
+
+    // One frame after `dt` of wall time: input, the instructions and timer ticks that are
+    // due (one instruction for a step while paused), then the picture and the beep
+    pub fn frame(&mut self, chip8: &mut Chip8, frontend: &mut impl Frontend, dt: Duration) {
+        let input = frontend.poll_input();
+        // also between ticks, so a press shorter than one still reaches FX0A
+        set_keys(chip8, &input.keys);
+        if input.toggle_pause {
+            self.status = match self.status {
+                Status::Running => Status::Paused,
+                Status::Paused => Status::Running,
+                ref status => status.clone(),
+            };
+        }
+
+        match (&self.status, input.step) {
+            (Status::Running, _) => self.run(chip8, &input.keys, dt),
+            (Status::Paused, true) => {
+                let pc = chip8.pc();
+                if !chip8.is_waiting_for_key() && !chip8.is_waiting_for_vblank() {
+                    match chip8.step() {
+                        Err(error) => self.status = Status::Halted { pc, error },
+                        Ok(_) if chip8.has_exited() => self.status = Status::Exited,
+                        Ok(_) => {}
+                    }
+                } else if chip8.is_waiting_for_vblank() {
+                    // a pending display wait is released by the frame it waits for
+                    chip8.tick_60hz();
+                }
+            }
+            _ => {}
+        }
+
+        let changed = chip8.take_display_dirty();
+        frontend.present(chip8, changed, &self.status);
+        frontend.play_beep(chip8.sound_active());
+    }
+
+    // The cycles that came due are shared out between the ticks, a frame without a tick
+    // (a display faster than 60Hz) saves its cycles for the next one
+    fn run(&mut self, chip8: &mut Chip8, keys: &[bool; 16], dt: Duration) {
+        self.owed = self.owed.saturating_add(self.cpu_clock.advance(dt));
+        let ticks = self.timer_clock.advance(dt);
+        for tick in 0..ticks {
+            let cycles = self.owed / (ticks - tick);
+            self.owed -= cycles;
+            let result = chip8.run_frame(cycles, keys);
+            if let Some((pc, error)) = result.halted {
+                self.status = Status::Halted { pc, error };
+                break;
+            }
+            if result.exited {
+                self.status = Status::Exited;
+                break;
+            }
+        }
+    }
+}
+
+fn set_keys(chip8: &mut Chip8, keys: &[bool; 16]) {
+    for (key, &pressed) in keys.iter().enumerate() {
+        chip8.set_key(key as u8, pressed);
+    }
+}
@@ -0,0 +1,220 @@
+// Busy-wait detection. A ROM waiting on the delay timer spins in something like FX07, 3X00,
+// 1NNN until the next tick changes what FX07 reads, and at a high --hz that is thousands of
+// instructions a frame that change nothing. When an FX07 leaves the machine exactly as the
+// previous one did, with no memory or display writes in between, every further trip around
+// the loop would do the same until a tick, a key change or an edit. A frontend can count
+// whole trips as run without running them and step only the rest of its budget, which
+// leaves the machine exactly where running all of it would have. Only fewer instructions
+// really execute (stats, traces and the measured speed see that), so it is opt-in: see
+// set_idle_skip().
+
+use crate::{Chip8, InputState};
+
+// What the loop could read. Memory isn't in it, a write anywhere forgets the last FX07.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LoopState {
+    pc: u16,
+    reg_i: u16,
+    registers: [u8; 16],
+    // FNV-1a of the return addresses, with the depth
+    stack: u64,
+    delay_timer: u8,
+    sound_timer: u8,
+    rng: u64,
+    input: InputState,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct IdleWatch {
+    // right after the last FX07, None once something was written since
+    last: Option<LoopState>,
+    // (instructions, machine cycles) since then
+    since: (u32, u32),
+    // set when an FX07 left the machine like `last`, with the length of that trip
+    matched: Option<(LoopState, (u32, u32))>,
+}
+
+impl IdleWatch {
+    // The program wrote memory or the display, the loop made progress
+    pub(crate) fn progress(&mut self) {
+        self.last = None;
+        self.matched = None;
+    }
+}
+
+impl Chip8 {
+    // Lets run_frame() (and frontends using idle_trips()) skip the trips around a delay
+    // timer wait. Off by default, it survives reset().
+    pub fn set_idle_skip(&mut self, enabled: bool) {
+        self.idle_skip = enabled;
+        self.idle = IdleWatch::default();
+    }
+
+    pub fn idle_skip(&self) -> bool {
+        return self.idle_skip;
+    }
+
+    // With set_idle_skip(), whether the program is right after an FX07 in a wait that can't
+    // end before something outside it changes. Never with breakpoints or register watches,
+    // which want to see every trip.
+    pub fn is_idle(&self) -> bool {
+        return self.idle_skip
+            && self.breakpoints.is_empty()
+            && self.reg_watches == 0
+            && self
+                .idle
+                .matched
+                .is_some_and(|(state, _)| state == self.loop_state());
+    }
+
+    // While is_idle(): how many (instructions, machine cycles) of whole trips around the
+    // wait fit in `cycles`. Running them would end where the machine is now, so they can be
+    // counted as run instead. (0, 0) when it isn't idle.
+    pub fn idle_trips(&self, cycles: u32) -> (u32, u32) {
+        if !self.is_idle() {
+            return (0, 0);
+        }
+        let Some((_, (instructions, trip_cycles))) = self.idle.matched else {
+            return (0, 0);
+        };
+        let trips = cycles / trip_cycles.max(1);
+
+        return (trips * instructions, trips * trip_cycles);
+    }
+
+    // Called by step() after every instruction that ran, with what it cost
+    pub(crate) fn watch_idle(&mut self, opcode: u16, cycles: u32) {
+        if !self.idle_skip {
+            return;
+        }
+        if self.display_changed {
+            self.idle.progress();
+            return;
+        }
+        let (instructions, spent) = self.idle.since;
+        self.idle.since = (instructions.saturating_add(1), spent.saturating_add(cycles));
+        // FX07
+        if opcode & 0xF0FF != 0xF007 {
+            return;
+        }
+        let state = self.loop_state();
+        self.idle.matched = (self.idle.last == Some(state)).then_some((state, self.idle.since));
+        self.idle.last = Some(state);
+        self.idle.since = (0, 0);
+    }
+
+    fn loop_state(&self) -> LoopState {
+        let mut stack: u64 = 0xCBF2_9CE4_8422_2325 ^ self.stack.len() as u64;
+        for addr in &self.stack {
+            for byte in addr.to_be_bytes() {
+                stack = (stack ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3);
+            }
+        }
+
+        return LoopState {
+            pc: self.pc,
+            reg_i: self.reg_i,
+            registers: self.registers,
+            stack,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            rng: self.rng.state,
+            input: self.input,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Recording;
+
+    // LD V0, 30; LD DT, V0; loop: LD V1, DT; SE V1, 0; JP loop; LD V2, 0x42; JP 0x20C
+    const WAIT: [u8; 14] = [
+        0x60, 0x1E, 0xF0, 0x15, 0xF1, 0x07, 0x31, 0x00, 0x12, 0x04, 0x62, 0x42, 0x12, 0x0C,
+    ];
+
+    fn machine(rom: &[u8], idle_skip: bool) -> Chip8 {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_idle_skip(idle_skip);
+        chip8.load_rom(rom).unwrap();
+        return chip8;
+    }
+
+    #[test]
+    fn wait_loop_ends_the_same_skipped_or_not() {
+        let keys = [false; 16];
+        let mut skipping = machine(&WAIT, true);
+        let mut running = machine(&WAIT, false);
+        let (mut skipped, mut instructions) = (0, 0);
+        for frame in 0..40 {
+            let fast = skipping.run_frame(5000, &keys);
+            let slow = running.run_frame(5000, &keys);
+            assert_eq!(fast.cycles, slow.cycles, "frame {frame}");
+            assert_eq!(fast.instructions + fast.skipped, slow.instructions);
+            assert_eq!(slow.skipped, 0);
+            assert_eq!(skipping.loop_state(), running.loop_state(), "frame {frame}");
+            // the JP 0x20C after the wait isn't one
+            if frame < 29 {
+                skipped += fast.skipped;
+                instructions += fast.instructions;
+            }
+        }
+        assert_eq!(skipping.save_state(), running.save_state());
+        assert_eq!(skipping.registers()[2], 0x42);
+        // nearly the whole wait was skipped
+        assert!(skipped > 29 * 4900, "{skipped} skipped");
+        assert!(instructions < 29 * 100, "{instructions} run");
+    }
+
+    #[test]
+    fn only_after_a_repeated_read() {
+        let mut chip8 = machine(&WAIT, true);
+        // LD V0; LD DT; the first LD V1, DT has nothing to compare with
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        assert!(!chip8.is_idle());
+        // SE, JP and the second read
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        assert!(chip8.is_idle());
+        // one trip is 3 instructions of a cycle each
+        assert_eq!(chip8.idle_trips(100), (99, 99));
+        chip8.tick_60hz();
+        assert!(!chip8.is_idle());
+        assert_eq!(chip8.idle_trips(100), (0, 0));
+    }
+
+    #[test]
+    fn progress_is_not_idle() {
+        // the wait loop with LD [I], V1 in it: memory changes every trip
+        let rom = [
+            0x60, 0x1E, 0xF0, 0x15, 0xA3, 0x00, 0xF1, 0x07, 0xF1, 0x55, 0x31, 0x00, 0x12, 0x06,
+        ];
+        let mut chip8 = machine(&rom, true);
+        let frame = chip8.run_frame(5000, &[false; 16]);
+        assert_eq!(frame.skipped, 0);
+
+        // breakpoints and register watches see every trip
+        let mut chip8 = machine(&WAIT, true);
+        chip8.set_breakpoint(0x300);
+        assert_eq!(chip8.run_frame(5000, &[false; 16]).skipped, 0);
+
+        // and it's off by default
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&WAIT).unwrap();
+        assert!(!chip8.idle_skip());
+        assert_eq!(chip8.run_frame(5000, &[false; 16]).skipped, 0);
+    }
+
+    #[test]
+    fn recordings_say_whether_it_was_on() {
+        for idle_skip in [false, true] {
+            let chip8 = machine(&WAIT, idle_skip);
+            let recording = Recording::from_bytes(&Recording::new(&chip8, 0).to_bytes()).unwrap();
+            assert_eq!(recording.idle_skip, idle_skip);
+        }
+    }
+}
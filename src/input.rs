@@ -0,0 +1,221 @@
+// The keypad as the CPU sees it. The frontend sets the raw state of each key, once a frame
+// (or as keys change), and every timer tick closes the frame so edges can be told apart
+// from held keys. EX9E/EXA1 read a key through its KeyMode, FX0A always waits for a press
+// and then a release of the same key, whatever the mode.
+
+use crate::Chip8;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyMode {
+    // down for as long as it is held, like the VIP keypad
+    #[default]
+    Level,
+    // down only in the frame it went down in, for games that misread held keys
+    PressEdge,
+    // down only in the frame it came back up in
+    ReleaseEdge,
+}
+
+impl FromStr for KeyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "level" => return Ok(KeyMode::Level),
+            "press" => return Ok(KeyMode::PressEdge),
+            "release" => return Ok(KeyMode::ReleaseEdge),
+            _ => {
+                return Err(format!(
+                    "unknown key mode '{s}', expected level, press or release"
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputState {
+    modes: [KeyMode; 16],
+    keys: [bool; 16],
+    // the keys when the last frame ended
+    previous: [bool; 16],
+}
+
+impl InputState {
+    pub fn new() -> Self {
+        return InputState {
+            modes: [KeyMode::Level; 16],
+            keys: [false; 16],
+            previous: [false; 16],
+        };
+    }
+
+    pub fn set_mode(&mut self, key: u8, mode: KeyMode) {
+        self.modes[key as usize & 0xF] = mode;
+    }
+
+    pub fn mode(&self, key: u8) -> KeyMode {
+        return self.modes[key as usize & 0xF];
+    }
+
+    pub fn modes(&self) -> [KeyMode; 16] {
+        return self.modes;
+    }
+
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        self.keys[key as usize & 0xF] = pressed;
+    }
+
+    // A whole frame at once: the keys so far become the previous frame's
+    pub fn update(&mut self, keys: &[bool; 16]) {
+        self.end_frame();
+        self.keys = *keys;
+    }
+
+    pub fn end_frame(&mut self) {
+        self.previous = self.keys;
+    }
+
+    // Raw state, what FX0A and the frontend see
+    pub fn is_held(&self, key: u8) -> bool {
+        return self.keys[key as usize & 0xF];
+    }
+
+    pub fn keys(&self) -> [bool; 16] {
+        return self.keys;
+    }
+
+    // What EX9E/EXA1 see
+    pub fn is_pressed(&self, key: u8) -> bool {
+        let key = key as usize & 0xF;
+        match self.modes[key] {
+            KeyMode::Level => return self.keys[key],
+            KeyMode::PressEdge => return self.keys[key] && !self.previous[key],
+            KeyMode::ReleaseEdge => return !self.keys[key] && self.previous[key],
+        }
+    }
+
+    // After a state load, the keys count as held since before it so no edge shows up
+    pub(crate) fn restore(&mut self, keys: [bool; 16]) {
+        self.keys = keys;
+        self.previous = keys;
+    }
+}
+
+impl Default for InputState {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+impl Chip8 {
+    pub fn set_key_mode(&mut self, key: u8, mode: KeyMode) {
+        self.input.set_mode(key, mode);
+    }
+
+    pub fn input(&self) -> &InputState {
+        return &self.input;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What EX9E sees of key 5 in each frame, holding it in the frames marked true
+    fn frames(mode: KeyMode, held: &[bool]) -> Vec<bool> {
+        let mut input = InputState::new();
+        input.set_mode(5, mode);
+        let mut seen = Vec::new();
+        for &down in held {
+            let mut keys = [false; 16];
+            keys[5] = down;
+            input.update(&keys);
+            seen.push(input.is_pressed(5));
+            // the other keys keep the default
+            assert!(!input.is_pressed(4));
+        }
+        return seen;
+    }
+
+    const HELD: [bool; 8] = [false, true, true, true, false, false, true, false];
+
+    #[test]
+    fn level_follows_the_key() {
+        assert_eq!(frames(KeyMode::Level, &HELD), HELD);
+    }
+
+    #[test]
+    fn press_edge_only_in_the_first_frame() {
+        assert_eq!(
+            frames(KeyMode::PressEdge, &HELD),
+            [false, true, false, false, false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn release_edge_only_in_the_frame_after() {
+        assert_eq!(
+            frames(KeyMode::ReleaseEdge, &HELD),
+            [false, false, false, false, true, false, false, true]
+        );
+    }
+
+    #[test]
+    fn edges_last_until_the_frame_ends() {
+        let mut input = InputState::new();
+        input.set_mode(0xA, KeyMode::PressEdge);
+        input.set_key(0xA, true);
+        // read as often as the ROM likes within the frame
+        assert!(input.is_pressed(0xA) && input.is_pressed(0xA));
+        input.end_frame();
+        assert!(!input.is_pressed(0xA));
+        assert!(input.is_held(0xA));
+        // a tap inside one frame is lost to release edge, which only compares frames
+        input.set_mode(0xB, KeyMode::ReleaseEdge);
+        input.set_key(0xB, true);
+        input.set_key(0xB, false);
+        assert!(!input.is_pressed(0xB));
+    }
+
+    #[test]
+    fn key_wait_ignores_the_mode() {
+        for mode in [KeyMode::Level, KeyMode::PressEdge, KeyMode::ReleaseEdge] {
+            // LD V3, K
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.load_rom(&[0xF3, 0x0A]).unwrap();
+            chip8.set_key_mode(7, mode);
+            chip8.step().unwrap();
+            chip8.set_key(7, true);
+            chip8.tick_60hz();
+            assert!(chip8.is_waiting_for_key(), "{mode:?}");
+            chip8.set_key(7, false);
+            assert!(!chip8.is_waiting_for_key(), "{mode:?}");
+            assert_eq!(chip8.registers()[3], 7);
+        }
+    }
+
+    #[test]
+    fn skip_if_pressed_through_the_machine() {
+        // SKP V0 at 0x200, looped over with JP 0x200 and a LD V1, 1 it can skip
+        let rom = [0xE0, 0x9E, 0x71, 0x01, 0x12, 0x00];
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&rom).unwrap();
+        chip8.set_key_mode(0, KeyMode::PressEdge);
+        chip8.set_key(0, true);
+        // twice around in the frame the key went down, skipping each time
+        for _ in 0..4 {
+            chip8.step().unwrap();
+        }
+        assert_eq!(chip8.registers()[1], 0);
+        chip8.tick_60hz();
+        for _ in 0..6 {
+            chip8.step().unwrap();
+        }
+        assert_eq!(chip8.registers()[1], 2);
+        assert_eq!(chip8.input().mode(0), KeyMode::PressEdge);
+        assert_eq!("Release".parse(), Ok(KeyMode::ReleaseEdge));
+        assert!("hold".parse::<KeyMode>().is_err());
+    }
+}
@@ -0,0 +1,421 @@
+// One decoded opcode with its operands pulled out, shared by the CPU, the disassembler and
+// the tracer so there is exactly one place that knows the encodings.
+
+use crate::split_nibbles;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    // 00E0
+    Cls,
+    // 00EE
+    Ret,
+    // 00CN, SCHIP
+    ScrollDown(u8),
+    // 00FB, SCHIP
+    ScrollRight,
+    // 00FC, SCHIP
+    ScrollLeft,
+    // 00FD, SCHIP
+    Exit,
+    // 00FE, SCHIP
+    LowRes,
+    // 00FF, SCHIP
+    HighRes,
+    // 0NNN, a COSMAC VIP machine code routine
+    Sys(u16),
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipEqImm { x: u8, nn: u8 },
+    // 4XNN
+    SkipNeImm { x: u8, nn: u8 },
+    // 5XY0
+    SkipEqReg { x: u8, y: u8 },
+    // 6XNN
+    LoadImm { x: u8, nn: u8 },
+    // 7XNN
+    AddImm { x: u8, nn: u8 },
+    // 8XY0
+    Move { x: u8, y: u8 },
+    // 8XY1
+    Or { x: u8, y: u8 },
+    // 8XY2
+    And { x: u8, y: u8 },
+    // 8XY3
+    Xor { x: u8, y: u8 },
+    // 8XY4
+    Add { x: u8, y: u8 },
+    // 8XY5
+    Sub { x: u8, y: u8 },
+    // 8XY6
+    ShiftRight { x: u8, y: u8 },
+    // 8XY7
+    SubN { x: u8, y: u8 },
+    // 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    // 9XY0
+    SkipNeReg { x: u8, y: u8 },
+    // ANNN
+    LoadI(u16),
+    // BNNN, x is the register the jump quirk adds instead of V0
+    JumpOffset { x: u8, nnn: u16 },
+    // CXNN
+    Random { x: u8, nn: u8 },
+    // DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    // EX9E
+    SkipKey { x: u8 },
+    // EXA1
+    SkipNotKey { x: u8 },
+    // FX07
+    LoadDelay { x: u8 },
+    // FX0A
+    WaitKey { x: u8 },
+    // FX15
+    SetDelay { x: u8 },
+    // FX18
+    SetSound { x: u8 },
+    // FX1E
+    AddI { x: u8 },
+    // FX29
+    Font { x: u8 },
+    // F000 NNNN, XO-CHIP, the address is the following word
+    LoadILong,
+    // FN01, XO-CHIP
+    Plane(u8),
+    // F002, XO-CHIP
+    Audio,
+    // FX3A, XO-CHIP
+    Pitch { x: u8 },
+    // FX30, SCHIP
+    BigFont { x: u8 },
+    // FX33
+    Bcd { x: u8 },
+    // FX75, SCHIP
+    SaveFlags { x: u8 },
+    // FX85, SCHIP
+    LoadFlags { x: u8 },
+    // FX55
+    Store { x: u8 },
+    // FX65
+    Load { x: u8 },
+}
+
+impl Instruction {
+    // None for words that aren't an instruction of any supported variant, e.g. 5XY1
+    #[inline]
+    pub fn decode(opcode: u16) -> Option<Instruction> {
+        use Instruction::*;
+
+        let nnn = opcode & 0xFFF;
+        let nn = opcode as u8;
+        let instruction = match split_nibbles(opcode) {
+            [0x0, 0x0, 0xE, 0x0] => Cls,
+            [0x0, 0x0, 0xE, 0xE] => Ret,
+            [0x0, 0x0, 0xC, n] => ScrollDown(n),
+            [0x0, 0x0, 0xF, 0xB] => ScrollRight,
+            [0x0, 0x0, 0xF, 0xC] => ScrollLeft,
+            [0x0, 0x0, 0xF, 0xD] => Exit,
+            [0x0, 0x0, 0xF, 0xE] => LowRes,
+            [0x0, 0x0, 0xF, 0xF] => HighRes,
+            [0x0, ..] => Sys(nnn),
+            [0x1, ..] => Jump(nnn),
+            [0x2, ..] => Call(nnn),
+            [0x3, x, ..] => SkipEqImm { x, nn },
+            [0x4, x, ..] => SkipNeImm { x, nn },
+            [0x5, x, y, 0x0] => SkipEqReg { x, y },
+            [0x6, x, ..] => LoadImm { x, nn },
+            [0x7, x, ..] => AddImm { x, nn },
+            [0x8, x, y, 0x0] => Move { x, y },
+            [0x8, x, y, 0x1] => Or { x, y },
+            [0x8, x, y, 0x2] => And { x, y },
+            [0x8, x, y, 0x3] => Xor { x, y },
+            [0x8, x, y, 0x4] => Add { x, y },
+            [0x8, x, y, 0x5] => Sub { x, y },
+            [0x8, x, y, 0x6] => ShiftRight { x, y },
+            [0x8, x, y, 0x7] => SubN { x, y },
+            [0x8, x, y, 0xE] => ShiftLeft { x, y },
+            [0x9, x, y, 0x0] => SkipNeReg { x, y },
+            [0xA, ..] => LoadI(nnn),
+            [0xB, x, ..] => JumpOffset { x, nnn },
+            [0xC, x, ..] => Random { x, nn },
+            [0xD, x, y, n] => Draw { x, y, n },
+            [0xE, x, 0x9, 0xE] => SkipKey { x },
+            [0xE, x, 0xA, 0x1] => SkipNotKey { x },
+            [0xF, 0x0, 0x0, 0x0] => LoadILong,
+            [0xF, 0x0, 0x0, 0x2] => Audio,
+            [0xF, n, 0x0, 0x1] => Plane(n),
+            [0xF, x, 0x0, 0x7] => LoadDelay { x },
+            [0xF, x, 0x0, 0xA] => WaitKey { x },
+            [0xF, x, 0x1, 0x5] => SetDelay { x },
+            [0xF, x, 0x1, 0x8] => SetSound { x },
+            [0xF, x, 0x1, 0xE] => AddI { x },
+            [0xF, x, 0x2, 0x9] => Font { x },
+            [0xF, x, 0x3, 0x0] => BigFont { x },
+            [0xF, x, 0x3, 0x3] => Bcd { x },
+            [0xF, x, 0x3, 0xA] => Pitch { x },
+            [0xF, x, 0x5, 0x5] => Store { x },
+            [0xF, x, 0x6, 0x5] => Load { x },
+            [0xF, x, 0x7, 0x5] => SaveFlags { x },
+            [0xF, x, 0x8, 0x5] => LoadFlags { x },
+            _ => return None,
+        };
+
+        return Some(instruction);
+    }
+
+    // The opcode pattern of the instruction as the comments above write it, e.g. "DXYN"
+    pub fn family(self) -> &'static str {
+        use Instruction::*;
+
+        match self {
+            Cls => return "00E0",
+            Ret => return "00EE",
+            ScrollDown(..) => return "00CN",
+            ScrollRight => return "00FB",
+            ScrollLeft => return "00FC",
+            Exit => return "00FD",
+            LowRes => return "00FE",
+            HighRes => return "00FF",
+            Sys(..) => return "0NNN",
+            Jump(..) => return "1NNN",
+            Call(..) => return "2NNN",
+            SkipEqImm { .. } => return "3XNN",
+            SkipNeImm { .. } => return "4XNN",
+            SkipEqReg { .. } => return "5XY0",
+            LoadImm { .. } => return "6XNN",
+            AddImm { .. } => return "7XNN",
+            Move { .. } => return "8XY0",
+            Or { .. } => return "8XY1",
+            And { .. } => return "8XY2",
+            Xor { .. } => return "8XY3",
+            Add { .. } => return "8XY4",
+            Sub { .. } => return "8XY5",
+            ShiftRight { .. } => return "8XY6",
+            SubN { .. } => return "8XY7",
+            ShiftLeft { .. } => return "8XYE",
+            SkipNeReg { .. } => return "9XY0",
+            LoadI(..) => return "ANNN",
+            JumpOffset { .. } => return "BNNN",
+            Random { .. } => return "CXNN",
+            Draw { .. } => return "DXYN",
+            SkipKey { .. } => return "EX9E",
+            SkipNotKey { .. } => return "EXA1",
+            LoadDelay { .. } => return "FX07",
+            WaitKey { .. } => return "FX0A",
+            SetDelay { .. } => return "FX15",
+            SetSound { .. } => return "FX18",
+            AddI { .. } => return "FX1E",
+            Font { .. } => return "FX29",
+            LoadILong => return "F000",
+            Plane(..) => return "FN01",
+            Audio => return "F002",
+            Pitch { .. } => return "FX3A",
+            BigFont { .. } => return "FX30",
+            Bcd { .. } => return "FX33",
+            SaveFlags { .. } => return "FX75",
+            LoadFlags { .. } => return "FX85",
+            Store { .. } => return "FX55",
+            Load { .. } => return "FX65",
+        }
+    }
+
+    // The opcode decode() turns back into this instruction. LoadILong is only the first word,
+    // the address has to follow it.
+    pub fn encode(self) -> u16 {
+        use Instruction::*;
+
+        let xy = |base: u16, x: u8, y: u8| base | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4;
+        let xnn = |base: u16, x: u8, nn: u8| base | (x as u16 & 0xF) << 8 | nn as u16;
+        let x_only = |base: u16, x: u8| base | (x as u16 & 0xF) << 8;
+        match self {
+            Cls => return 0x00E0,
+            Ret => return 0x00EE,
+            ScrollDown(n) => return 0x00C0 | (n as u16 & 0xF),
+            ScrollRight => return 0x00FB,
+            ScrollLeft => return 0x00FC,
+            Exit => return 0x00FD,
+            LowRes => return 0x00FE,
+            HighRes => return 0x00FF,
+            Sys(addr) => return addr & 0xFFF,
+            Jump(addr) => return 0x1000 | (addr & 0xFFF),
+            Call(addr) => return 0x2000 | (addr & 0xFFF),
+            SkipEqImm { x, nn } => return xnn(0x3000, x, nn),
+            SkipNeImm { x, nn } => return xnn(0x4000, x, nn),
+            SkipEqReg { x, y } => return xy(0x5000, x, y),
+            LoadImm { x, nn } => return xnn(0x6000, x, nn),
+            AddImm { x, nn } => return xnn(0x7000, x, nn),
+            Move { x, y } => return xy(0x8000, x, y),
+            Or { x, y } => return xy(0x8001, x, y),
+            And { x, y } => return xy(0x8002, x, y),
+            Xor { x, y } => return xy(0x8003, x, y),
+            Add { x, y } => return xy(0x8004, x, y),
+            Sub { x, y } => return xy(0x8005, x, y),
+            ShiftRight { x, y } => return xy(0x8006, x, y),
+            SubN { x, y } => return xy(0x8007, x, y),
+            ShiftLeft { x, y } => return xy(0x800E, x, y),
+            SkipNeReg { x, y } => return xy(0x9000, x, y),
+            LoadI(addr) => return 0xA000 | (addr & 0xFFF),
+            // x is the top nibble of nnn, there is nothing else to encode
+            JumpOffset { nnn, .. } => return 0xB000 | (nnn & 0xFFF),
+            Random { x, nn } => return xnn(0xC000, x, nn),
+            Draw { x, y, n } => return xy(0xD000, x, y) | (n as u16 & 0xF),
+            SkipKey { x } => return x_only(0xE09E, x),
+            SkipNotKey { x } => return x_only(0xE0A1, x),
+            LoadDelay { x } => return x_only(0xF007, x),
+            WaitKey { x } => return x_only(0xF00A, x),
+            SetDelay { x } => return x_only(0xF015, x),
+            SetSound { x } => return x_only(0xF018, x),
+            AddI { x } => return x_only(0xF01E, x),
+            Font { x } => return x_only(0xF029, x),
+            LoadILong => return 0xF000,
+            Plane(n) => return x_only(0xF001, n),
+            Audio => return 0xF002,
+            Pitch { x } => return x_only(0xF03A, x),
+            BigFont { x } => return x_only(0xF030, x),
+            Bcd { x } => return x_only(0xF033, x),
+            SaveFlags { x } => return x_only(0xF075, x),
+            LoadFlags { x } => return x_only(0xF085, x),
+            Store { x } => return x_only(0xF055, x),
+            Load { x } => return x_only(0xF065, x),
+        }
+    }
+}
+
+// Mnemonics follow Cowgod's technical reference, e.g. `LD I, 0x22A` or `DRW V0, V1, 5`
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Instruction::*;
+
+        match *self {
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            ScrollDown(n) => write!(f, "SCD {n}"),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LowRes => write!(f, "LOW"),
+            HighRes => write!(f, "HIGH"),
+            Sys(addr) => write!(f, "SYS {addr:#05X}"),
+            Jump(addr) => write!(f, "JP {addr:#05X}"),
+            Call(addr) => write!(f, "CALL {addr:#05X}"),
+            SkipEqImm { x, nn } => write!(f, "SE V{x:X}, {nn:#04X}"),
+            SkipNeImm { x, nn } => write!(f, "SNE V{x:X}, {nn:#04X}"),
+            SkipEqReg { x, y } => write!(f, "SE V{x:X}, V{y:X}"),
+            LoadImm { x, nn } => write!(f, "LD V{x:X}, {nn:#04X}"),
+            AddImm { x, nn } => write!(f, "ADD V{x:X}, {nn:#04X}"),
+            Move { x, y } => write!(f, "LD V{x:X}, V{y:X}"),
+            Or { x, y } => write!(f, "OR V{x:X}, V{y:X}"),
+            And { x, y } => write!(f, "AND V{x:X}, V{y:X}"),
+            Xor { x, y } => write!(f, "XOR V{x:X}, V{y:X}"),
+            Add { x, y } => write!(f, "ADD V{x:X}, V{y:X}"),
+            Sub { x, y } => write!(f, "SUB V{x:X}, V{y:X}"),
+            ShiftRight { x, y } => write!(f, "SHR V{x:X}, V{y:X}"),
+            SubN { x, y } => write!(f, "SUBN V{x:X}, V{y:X}"),
+            ShiftLeft { x, y } => write!(f, "SHL V{x:X}, V{y:X}"),
+            SkipNeReg { x, y } => write!(f, "SNE V{x:X}, V{y:X}"),
+            LoadI(addr) => write!(f, "LD I, {addr:#05X}"),
+            JumpOffset { nnn, .. } => write!(f, "JP V0, {nnn:#05X}"),
+            Random { x, nn } => write!(f, "RND V{x:X}, {nn:#04X}"),
+            Draw { x, y, n } => write!(f, "DRW V{x:X}, V{y:X}, {n}"),
+            SkipKey { x } => write!(f, "SKP V{x:X}"),
+            SkipNotKey { x } => write!(f, "SKNP V{x:X}"),
+            LoadDelay { x } => write!(f, "LD V{x:X}, DT"),
+            WaitKey { x } => write!(f, "LD V{x:X}, K"),
+            SetDelay { x } => write!(f, "LD DT, V{x:X}"),
+            SetSound { x } => write!(f, "LD ST, V{x:X}"),
+            AddI { x } => write!(f, "ADD I, V{x:X}"),
+            Font { x } => write!(f, "LD F, V{x:X}"),
+            LoadILong => write!(f, "LD I, long"),
+            Plane(n) => write!(f, "PLANE {n}"),
+            Audio => write!(f, "AUDIO"),
+            Pitch { x } => write!(f, "PITCH V{x:X}"),
+            BigFont { x } => write!(f, "LD HF, V{x:X}"),
+            Bcd { x } => write!(f, "LD B, V{x:X}"),
+            SaveFlags { x } => write!(f, "LD R, V{x:X}"),
+            LoadFlags { x } => write!(f, "LD V{x:X}, R"),
+            Store { x } => write!(f, "LD [I], V{x:X}"),
+            Load { x } => write!(f, "LD V{x:X}, [I]"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TraceFilter;
+
+    #[test]
+    fn every_word_round_trips() {
+        let mut decoded = 0;
+        for opcode in 0..=u16::MAX {
+            let Some(instruction) = Instruction::decode(opcode) else {
+                continue;
+            };
+            decoded += 1;
+            assert_eq!(instruction.encode(), opcode, "{instruction:?}");
+            // the family pattern names the opcode it came from
+            let family: TraceFilter = instruction.family().parse().unwrap();
+            assert!(
+                family.matches(opcode),
+                "{opcode:04X} {}",
+                instruction.family()
+            );
+        }
+        // 11 families take any operands, 5XY0/9XY0 any X and Y, nine 8XYN, two EXNN, and
+        // F000, F002, FN01 and thirteen FXNN
+        assert_eq!(
+            decoded,
+            11 * 4096 + 2 * 256 + 9 * 256 + 2 * 16 + 2 + 16 + 13 * 16
+        );
+    }
+
+    #[test]
+    fn malformed_words() {
+        // 5XY1, 8XY8-8XYD, 8XYF, 9XY1, EX00, EX9F, FX00 with X != 0, FX02, FXFF
+        let malformed = [
+            0x5121, 0x812F, 0x8128, 0x812D, 0x9121, 0xE100, 0xE19F, 0xF100, 0xF102, 0xF1FF,
+        ];
+        for opcode in malformed {
+            assert_eq!(Instruction::decode(opcode), None, "{opcode:04X}");
+        }
+    }
+
+    #[test]
+    fn operands() {
+        use Instruction::*;
+
+        let cases = [
+            (0x00C7, ScrollDown(7)),
+            (0x0ABC, Sys(0xABC)),
+            (0x1ABC, Jump(0xABC)),
+            (0x2ABC, Call(0xABC)),
+            (0x3A12, SkipEqImm { x: 0xA, nn: 0x12 }),
+            (0x5AB0, SkipEqReg { x: 0xA, y: 0xB }),
+            (0x8AB7, SubN { x: 0xA, y: 0xB }),
+            (0xB345, JumpOffset { x: 3, nnn: 0x345 }),
+            (
+                0xDAB5,
+                Draw {
+                    x: 0xA,
+                    y: 0xB,
+                    n: 5,
+                },
+            ),
+            (0xF201, Plane(2)),
+            (0xF000, LoadILong),
+            (0xF002, Audio),
+            (0xFA33, Bcd { x: 0xA }),
+        ];
+        for (opcode, instruction) in cases {
+            assert_eq!(
+                Instruction::decode(opcode),
+                Some(instruction),
+                "{opcode:04X}"
+            );
+        }
+    }
+}
@@ -0,0 +1,160 @@
+// The keypad layouts and keymap files the windows share. Host keys go by raylib's names
+// without the KEY_ prefix (ONE, UP, RIGHT_CONTROL, ..) and each window turns the names into
+// its own keys: KeyboardKey in the raylib one, scancodes in rustchip8-sdl.
+
+pub type KeyNames = [&'static str; 16];
+
+// Host keys for the CHIP-8 keypad, indexed by CHIP-8 key value:
+//  1 2 3 C      1 2 3 4
+//  4 5 6 D  ->  Q W E R
+//  7 8 9 E      A S D F
+//  A 0 B F      Z X C V
+pub const QWERTY: KeyNames = [
+    "X", "ONE", "TWO", "THREE", "Q", "W", "E", "A", "S", "D", "Z", "C", "FOUR", "R", "F", "V",
+];
+
+// The same physical keys on a French keyboard: 1234 / AZER / QSDF / WXCV
+pub const AZERTY: KeyNames = [
+    "X", "ONE", "TWO", "THREE", "A", "Z", "E", "Q", "S", "D", "W", "C", "FOUR", "R", "F", "V",
+];
+
+// Many games use 2/4/6/8 as a d-pad with 5 as the button, put those on the arrow keys
+// and right Ctrl. The other keys stay where QWERTY has them.
+pub const NATURAL: KeyNames = [
+    "X",
+    "ONE",
+    "UP",
+    "THREE",
+    "LEFT",
+    "RIGHT_CONTROL",
+    "RIGHT",
+    "A",
+    "DOWN",
+    "D",
+    "Z",
+    "C",
+    "FOUR",
+    "R",
+    "F",
+    "V",
+];
+
+pub const LAYOUTS: [(&str, KeyNames); 3] =
+    [("qwerty", QWERTY), ("azerty", AZERTY), ("natural", NATURAL)];
+
+pub fn parse_layout(name: &str) -> Result<KeyNames, String> {
+    return LAYOUTS
+        .iter()
+        .find(|(layout, _)| *layout == name)
+        .map(|(_, names)| *names)
+        .ok_or_else(|| format!("unknown layout '{name}', expected qwerty, azerty or natural"));
+}
+
+const DIGITS: [&str; 10] = [
+    "ZERO", "ONE", "TWO", "THREE", "FOUR", "FIVE", "SIX", "SEVEN", "EIGHT", "NINE",
+];
+
+// A key name as the layouts spell it: the prefix is optional, case doesn't matter and plain
+// digits work too, 1 is ONE. Whether the key exists is up to the window.
+pub fn normalize(name: &str) -> String {
+    let upper = name.to_ascii_uppercase();
+    let bare = upper.strip_prefix("KEY_").unwrap_or(&upper);
+    if let Ok(digit) = bare.parse::<usize>()
+        && bare.len() == 1
+    {
+        return DIGITS[digit].to_string();
+    }
+
+    return bare.to_string();
+}
+
+// The digit of ONE to NINE and ZERO
+pub fn digit(name: &str) -> Option<usize> {
+    return DIGITS.iter().position(|digit| *digit == name);
+}
+
+// Lines of `<CHIP-8 key> = <host key>`, a subset of TOML:
+//
+//   # d-pad on the arrows
+//   2 = "UP"
+//   8 = "KEY_DOWN"
+//
+// Blank lines, # comments and [section] headers are skipped. All 16 keys must be mapped
+// and no host key may be used twice. `parse_key` reads a host key, `key_name` names one in
+// errors.
+pub fn parse<K: Copy + PartialEq>(
+    text: &str,
+    parse_key: impl Fn(&str) -> Result<K, String>,
+    key_name: impl Fn(K) -> String,
+) -> Result<[K; 16], String> {
+    let mut keys: [Option<(K, usize)>; 16] = [None; 16];
+    for (n, line) in text.lines().enumerate() {
+        let n = n + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() || line.starts_with('[') {
+            continue;
+        }
+
+        let (chip8_key, host_key) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {n}: expected '<key> = <host key>'"))?;
+        let chip8_key = chip8_key.trim().trim_matches('"');
+        let index = match u8::from_str_radix(chip8_key, 16) {
+            Ok(index) if chip8_key.len() == 1 => index as usize,
+            _ => return Err(format!("line {n}: '{chip8_key}' is not a CHIP-8 key 0-F")),
+        };
+        let host_key =
+            parse_key(host_key.trim().trim_matches('"')).map_err(|e| format!("line {n}: {e}"))?;
+
+        if let Some((_, first)) = keys[index] {
+            return Err(format!(
+                "line {n}: key {index:X} is already mapped on line {first}"
+            ));
+        }
+        if let Some(other) = keys
+            .iter()
+            .position(|k| k.is_some_and(|(k, _)| k == host_key))
+        {
+            return Err(format!(
+                "line {n}: {} is already used for key {other:X}",
+                key_name(host_key)
+            ));
+        }
+        keys[index] = Some((host_key, n));
+    }
+
+    let missing: Vec<String> = (0..16)
+        .filter(|&i| keys[i].is_none())
+        .map(|i| format!("{i:X}"))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("keys not mapped: {}", missing.join(", ")));
+    }
+
+    return Ok(keys.map(|k| k.expect("every key is mapped").0));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_layouts() {
+        assert_eq!(normalize("key_up"), "UP");
+        assert_eq!(normalize("Right_Control"), "RIGHT_CONTROL");
+        assert_eq!(normalize("7"), "SEVEN");
+        assert_eq!(normalize("12"), "12");
+        assert_eq!(digit("SEVEN"), Some(7));
+        for (name, names) in LAYOUTS {
+            assert_eq!(parse_layout(name), Ok(names));
+            // each name once, the keys are positions in the layout
+            let text: String = (0..16).map(|i| format!("{i:X} = {}\n", names[i])).collect();
+            let position = |key: &str| {
+                let key = normalize(key);
+                return names.iter().position(|name| *name == key).ok_or(key);
+            };
+            let keys = parse(&text, position, |i| names[i].to_string());
+            assert_eq!(keys, Ok(std::array::from_fn(|i| i)), "{name}");
+        }
+    }
+}
@@ -0,0 +1,2571 @@
+#![allow(clippy::needless_return)]
+
+pub mod asm;
+pub mod audio;
+pub mod cheats;
+pub mod disasm;
+pub mod ffi;
+pub mod flags;
+pub mod gif;
+pub mod host;
+pub mod idle;
+pub mod input;
+pub mod instruction;
+pub mod keymap;
+pub mod observer;
+pub mod palette;
+pub mod replay;
+pub mod screenshot;
+#[cfg(feature = "scripting")]
+pub mod script;
+pub mod snapshot;
+pub mod state;
+pub mod stats;
+pub mod symbols;
+pub mod timing;
+pub mod trace;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod watch;
+#[cfg(feature = "zip")]
+pub mod zip;
+
+pub use asm::{AsmError, assemble};
+pub use cheats::{Cheat, CheatError, CheatKind, parse_cheats};
+pub use disasm::{disassemble, disassemble_with};
+pub use flags::{FileFlags, FlagStorage, MemoryFlags};
+pub use input::{InputState, KeyMode};
+pub use instruction::Instruction;
+pub use observer::{AfterStep, Chip8Observer, FrameOptions, FrameResult, FrameStop};
+pub use replay::{InputEvent, Player, Recording, RecordingError};
+pub use state::{LoadedState, StateError};
+pub use stats::Stats;
+pub use symbols::{SymbolError, Symbols, parse_symbols};
+pub use timing::{Timing, VIP_CYCLE_HZ};
+pub use trace::{TraceFilter, Tracer};
+pub use validate::{Finding, Severity, Validation, validate_rom};
+pub use watch::{WatchHit, WatchTarget};
+
+use idle::IdleWatch;
+use log::{debug, trace, warn};
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32;
+// SUPER-CHIP hi-res mode. The framebuffer is always this big, low-res only uses the top-left.
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+// COSMAC VIP hi-res programs: a 1260 at the start jumps over the machine code that switches
+// the VIP to 64x64, 0230 calls its clear screen routine. Both relative to load_addr.
+pub const VIP_HIRES_ENTRY: usize = 0x60;
+pub const VIP_HIRES_CLEAR: u16 = 0x30;
+
+// XO-CHIP bitplanes, each pixel holds one bit per plane
+pub const PLANE_COUNT: usize = 2;
+pub type Pixel = u8;
+pub type Framebuffer = [[Pixel; HIRES_WIDTH]; HIRES_HEIGHT];
+
+pub const PROGRAM_START: usize = 0x200;
+// how deep 2NNN calls nest in most interpreters
+pub const STACK_SIZE: usize = 16;
+pub const MEM_SIZE: usize = 4096;
+// XO-CHIP extended address space reachable through F000 NNNN
+pub const EXTENDED_MEM_SIZE: usize = 65536;
+pub const MAX_ROM_SIZE: usize = MEM_SIZE - PROGRAM_START;
+pub const MAX_EXTENDED_ROM_SIZE: usize = EXTENDED_MEM_SIZE - PROGRAM_START;
+
+pub const FONT_ADDR: usize = 0x050;
+pub const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+// SUPER-CHIP 8x10 digits for FX30, A-F taken from Octo
+pub const BIG_FONT_ADDR: usize = FONT_ADDR + FONT.len();
+pub const BIG_FONT: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Resolution {
+    Low,
+    High,
+    // 64x64, see set_vip_hires()
+    VipHigh,
+}
+
+impl Resolution {
+    pub fn width(self) -> usize {
+        match self {
+            Resolution::Low | Resolution::VipHigh => return SCREEN_WIDTH,
+            Resolution::High => return HIRES_WIDTH,
+        }
+    }
+
+    pub fn height(self) -> usize {
+        match self {
+            Resolution::Low => return SCREEN_HEIGHT,
+            Resolution::High | Resolution::VipHigh => return HIRES_HEIGHT,
+        }
+    }
+
+    // The shape of the picture on screen in square pixels, VIP hi-res pixels were half as
+    // tall so it fills the same 2:1 area as low-res
+    pub fn aspect(self) -> (usize, usize) {
+        match self {
+            Resolution::VipHigh => return (SCREEN_WIDTH, SCREEN_HEIGHT),
+            _ => return (self.width(), self.height()),
+        }
+    }
+}
+
+// The stub VIP hi-res ROMs start with: 1260, and a 0230 clear screen call somewhere past
+// it. Plenty of ordinary ROMs open with a jump to 0x260, but they don't call 0x230.
+pub fn is_vip_hires_rom(rom: &[u8]) -> bool {
+    let clear = (0x0200 + VIP_HIRES_CLEAR).to_be_bytes();
+    return rom.starts_with(&[0x12, 0x60])
+        && rom
+            .get(VIP_HIRES_ENTRY..)
+            .is_some_and(|code| code.chunks_exact(2).any(|word| word == clear));
+}
+
+// Interpreters the quirk presets are modelled after
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Profile {
+    Cosmac,
+    Chip48,
+    Schip,
+}
+
+impl Profile {
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Profile::Cosmac => Quirks {
+                shift_uses_vy: true,
+                memory_increment: true,
+                jump_uses_vx: false,
+                sprite_wrap: false,
+                vf_reset: true,
+                display_wait: true,
+                index_overflow: false,
+            },
+            Profile::Chip48 => Quirks {
+                shift_uses_vy: false,
+                memory_increment: true,
+                jump_uses_vx: true,
+                sprite_wrap: false,
+                vf_reset: false,
+                display_wait: false,
+                index_overflow: false,
+            },
+            Profile::Schip => Quirks {
+                shift_uses_vy: false,
+                memory_increment: false,
+                jump_uses_vx: true,
+                sprite_wrap: false,
+                vf_reset: false,
+                display_wait: false,
+                index_overflow: false,
+            },
+        }
+    }
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cosmac" | "vip" => return Ok(Profile::Cosmac),
+            "chip48" | "chip-48" => return Ok(Profile::Chip48),
+            "schip" | "superchip" => return Ok(Profile::Schip),
+            _ => {
+                return Err(format!(
+                    "unknown profile '{s}', expected cosmac, chip48 or schip"
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirks {
+    // 8XY6/8XYE: load VX from VY before shifting (COSMAC VIP) instead of shifting VX in place
+    pub shift_uses_vy: bool,
+    // FX55/FX65: leave I pointing past the last register touched (COSMAC VIP)
+    pub memory_increment: bool,
+    // BNNN: jump to XNN + VX (CHIP-48/SCHIP) instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // DXYN: wrap sprite pixels around the screen edges instead of clipping them
+    pub sprite_wrap: bool,
+    // 8XY1/8XY2/8XY3: clear VF after the logic operation (COSMAC VIP)
+    pub vf_reset: bool,
+    // DXYN: wait for the next 60Hz vertical blank before running anything else (COSMAC VIP)
+    pub display_wait: bool,
+    // FX1E: set VF when I + VX goes past 0xFFF (Amiga interpreter, Spacefight 2091! needs it)
+    pub index_overflow: bool,
+}
+
+impl Quirks {
+    pub fn new() -> Self {
+        return Profile::Cosmac.quirks();
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        return Quirks::new();
+    }
+}
+
+#[derive(Debug)]
+pub enum RomError {
+    TooLarge { size: usize, max: usize },
+    Empty,
+    BadLoadAddress { addr: u16 },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RomError::TooLarge { size, max } => {
+                write!(
+                    f,
+                    "ROM is {size} bytes, larger than the {max} bytes available"
+                )
+            }
+            RomError::Empty => write!(f, "ROM is empty"),
+            RomError::BadLoadAddress { addr } => {
+                write!(f, "load address {addr:#05X} must be even and inside memory")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Chip8Error {
+    UnknownOpcode { opcode: u16, pc: u16 },
+    // 00EE with nothing to return to, always an error: there is no address to make up
+    StackUnderflow,
+    StackOverflow { pc: u16 },
+    MemoryOutOfBounds { addr: u16 },
+    // 0NNN runs RCA 1802 machine code on a real COSMAC VIP, nothing an interpreter can do
+    MachineCodeRoutine { addr: u16 },
+    // Only with strict control flow, otherwise pc wraps around the end of memory like any
+    // address: running off the end, a BNNN past it, or a 2NNN in the last word whose return
+    // address would be past it. `pc` is where it would have gone.
+    PcOutOfBounds { pc: u32 },
+    // Only with strict control flow, otherwise odd addresses run like on the VIP, which
+    // fetches the two bytes wherever they are: 1NNN, 2NNN or BNNN to an odd `addr`
+    MisalignedJump { addr: u16, pc: u16 },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode { opcode, pc } => {
+                write!(f, "unknown opcode {opcode:04X} at pc {pc:#05X}")
+            }
+            Chip8Error::StackUnderflow => {
+                write!(f, "returned from a subroutine with an empty stack")
+            }
+            Chip8Error::StackOverflow { pc } => {
+                write!(f, "call stack overflowed by the call at pc {pc:#05X}")
+            }
+            Chip8Error::MemoryOutOfBounds { addr } => {
+                write!(f, "memory access out of bounds at {addr:#05X}")
+            }
+            Chip8Error::MachineCodeRoutine { addr } => {
+                write!(
+                    f,
+                    "SYS {addr:#05X} calls COSMAC VIP machine code, the ROM needs a real VIP"
+                )
+            }
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "pc left the end of memory at {pc:#05X}")
+            }
+            Chip8Error::MisalignedJump { addr, pc } => {
+                write!(
+                    f,
+                    "the jump at pc {pc:#05X} goes to the odd address {addr:#05X}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+// XO-CHIP FX3A pitch that plays the audio pattern at 4000 bits per second
+pub const DEFAULT_PITCH: u8 = 64;
+
+pub const TIMER_HZ: u32 = 60;
+
+// Turns elapsed wall time into 60Hz timer ticks, independent of frame and instruction rate.
+// The caller owns the clock, so this works the same with a real or a simulated one.
+pub struct TimerClock {
+    elapsed: Duration,
+}
+
+impl TimerClock {
+    const TICK: Duration = Duration::from_nanos(1_000_000_000 / TIMER_HZ as u64);
+
+    pub fn new() -> Self {
+        TimerClock {
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    // Returns how many timer ticks are due after `dt` more time has passed
+    pub fn advance(&mut self, dt: Duration) -> u32 {
+        self.elapsed += dt;
+        let mut ticks = 0;
+        while self.elapsed >= Self::TICK {
+            self.elapsed -= Self::TICK;
+            ticks += 1;
+        }
+
+        return ticks;
+    }
+}
+
+impl Default for TimerClock {
+    fn default() -> Self {
+        return TimerClock::new();
+    }
+}
+
+// Same idea for the CPU: elapsed wall time becomes an instruction budget at `hz`. The
+// remainder carries over exactly, so the long-run rate matches `hz` whatever the frame
+// rate is.
+pub struct CpuClock {
+    hz: u32,
+    // leftover time in nanoseconds times hz, less than one instruction's worth
+    owed: u128,
+}
+
+impl CpuClock {
+    pub fn new(hz: u32) -> Self {
+        return CpuClock { hz, owed: 0 };
+    }
+
+    pub fn hz(&self) -> u32 {
+        return self.hz;
+    }
+
+    // Returns how many instructions are due after `dt` more time has passed
+    pub fn advance(&mut self, dt: Duration) -> u32 {
+        self.owed += dt.as_nanos() * self.hz as u128;
+        let cycles = self.owed / 1_000_000_000;
+        self.owed %= 1_000_000_000;
+
+        return cycles as u32;
+    }
+}
+
+// xorshift64*, small enough to keep inside the machine state and reproducible from a seed
+struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // xorshift gets stuck on an all-zero state
+        Rng {
+            state: if seed == 0 {
+                0x9E37_79B9_7F4A_7C15
+            } else {
+                seed
+            },
+        }
+    }
+
+    fn next_byte(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        return (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8;
+    }
+}
+
+pub struct Chip8 {
+    // MEM_SIZE bytes, or EXTENDED_MEM_SIZE in XO-CHIP mode. Boxed so 64K doesn't live on the stack.
+    mem: Box<[u8]>,
+    pc: u16,
+    reg_i: u16,
+    stack: Vec<u16>,
+    registers: [u8; 16],
+    display: Framebuffer,
+    resolution: Resolution,
+    // XO-CHIP FN01 plane mask that drawing, clearing and scrolling apply to
+    selected_planes: u8,
+    // see last_collisions()
+    collisions: Vec<(usize, usize)>,
+    delay_timer: u8,
+    sound_timer: u8,
+    quirks: Quirks,
+    rng: Rng,
+    // see input.rs
+    input: InputState,
+    // FX0A parks the destination register here until a key is pressed and released
+    waiting_for_key: Option<u8>,
+    pressed_while_waiting: Option<u8>,
+    // identifies the loaded ROM in save states
+    rom_hash: u64,
+    // where load_rom() puts the program and pc starts, 0x600 for ETI-660 ROMs
+    load_addr: u16,
+    // SUPER-CHIP persistent "RPL user flags" for FX75/FX85
+    rpl_flags: [u8; 8],
+    // where FX75 saves them, see flags.rs
+    flag_storage: Option<Box<dyn FlagStorage>>,
+    flag_error: Option<std::io::Error>,
+    // set by 00FD, the program asked the interpreter to quit
+    exited: bool,
+    // XO-CHIP sound: 128 1-bit samples loaded by F002 and the FX3A playback pitch
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    // whether the ROM touched either, otherwise frontends should play a plain beep
+    uses_audio_pattern: bool,
+    // set by DXYN under the display_wait quirk, cleared by the next timer tick
+    waiting_for_vblank: bool,
+    // whether the sound timer ran after the last tick_60hz(), for its TimerEvents
+    sound_at_tick: bool,
+    tracer: Option<Tracer>,
+    // see observer.rs
+    observer: Option<Box<dyn Chip8Observer>>,
+    // see set_stats()
+    stats: Option<Box<Stats>>,
+    breakpoints: HashSet<u16>,
+    mem_watches: HashSet<u16>,
+    // one bit per V register
+    reg_watches: u16,
+    watch_hit: Option<WatchHit>,
+    // see cheats.rs, kept across reset() and saved in states
+    cheats: Vec<Cheat>,
+    // see symbols.rs, kept across reset()
+    symbols: Symbols,
+    // set by anything that touches the framebuffer, reset at the start of step()
+    display_changed: bool,
+    // same, but only reset by take_display_dirty() so frontends can skip repaints
+    display_dirty: bool,
+    // out-of-range memory accesses are a MemoryOutOfBounds error instead of wrapping around
+    strict_memory: bool,
+    // pc leaving memory or jumping to an odd address is an error, see Chip8Error
+    strict_control_flow: bool,
+    // nested 2NNN calls beyond this are a StackOverflow
+    stack_limit: usize,
+    // 0NNN is skipped instead of being a MachineCodeRoutine error
+    ignore_sys: bool,
+    // what step() reports as each instruction's cost
+    timing: Timing,
+    // set when that happened, reset at the start of step()
+    sys_ignored: bool,
+    // only the first skipped call since the last reset is logged
+    sys_warned: bool,
+    // see set_vip_hires()
+    vip_hires: bool,
+    // see idle.rs
+    idle_skip: bool,
+    idle: IdleWatch,
+}
+
+// What the program is doing right now, for status displays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuState {
+    // also while a DXYN waits for the display
+    Running,
+    // blocked in FX0A
+    WaitingForKey,
+    // parked on a 1NNN that jumps to itself
+    SelfJump,
+    // 00FD
+    Exited,
+}
+
+// What a single Chip8::step() did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    // address the opcode was fetched from
+    pub pc: u16,
+    pub opcode: u16,
+    // DXYN, 00E0, the scroll and resolution instructions
+    pub display_changed: bool,
+    // the sound timer went from 0 to running
+    pub sound_started: bool,
+    // an FX0A is now blocking until a key is pressed and released
+    pub waiting_for_key: bool,
+    // a 0NNN machine code call was skipped because of set_ignore_sys()
+    pub sys_ignored: bool,
+    // machine cycles with Timing::Vip, always 1 with Timing::Fixed
+    pub cycles: u32,
+}
+
+// What a Chip8::tick_60hz() saw happen since the tick before. An FX18 that sets the sound
+// timer to 1 gives sound_started and sound_stopped on the same tick.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimerEvents {
+    // the sound timer was set going since the last tick
+    pub sound_started: bool,
+    // it reached 0, or FX18 set it to 0
+    pub sound_stopped: bool,
+    // the delay timer went from 1 to 0
+    pub delay_expired: bool,
+}
+
+// What Chip8::new() seeds CXNN with. Frontends that need to reproduce a run (input
+// recordings) pick the seed themselves and use Chip8::with_seed().
+#[cfg(not(target_arch = "wasm32"))]
+pub fn time_seed() -> u64 {
+    return SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+}
+
+// There is no clock without an OS (SystemTime::now() panics), web frontends pass a seed
+// from JS to with_seed()
+#[cfg(target_arch = "wasm32")]
+pub fn time_seed() -> u64 {
+    return 0;
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        return Chip8::new();
+    }
+}
+
+impl Chip8 {
+    pub fn new() -> Self {
+        return Chip8::with_seed(time_seed());
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        let mut chip8 = Chip8 {
+            mem: vec![0; MEM_SIZE].into_boxed_slice(),
+            pc: PROGRAM_START as u16,
+            load_addr: PROGRAM_START as u16,
+            reg_i: 0,
+            stack: Vec::new(),
+            registers: [0; 16],
+            display: [[0; HIRES_WIDTH]; HIRES_HEIGHT],
+            resolution: Resolution::Low,
+            selected_planes: 0b01,
+            collisions: Vec::new(),
+            delay_timer: 0,
+            sound_timer: 0,
+            quirks: Quirks::new(),
+            rng: Rng::new(seed),
+            input: InputState::new(),
+            waiting_for_key: None,
+            pressed_while_waiting: None,
+            rom_hash: state::rom_hash(&[]),
+            rpl_flags: [0; 8],
+            flag_storage: None,
+            flag_error: None,
+            exited: false,
+            audio_pattern: [0; 16],
+            pitch: DEFAULT_PITCH,
+            uses_audio_pattern: false,
+            waiting_for_vblank: false,
+            sound_at_tick: false,
+            tracer: None,
+            observer: None,
+            stats: None,
+            breakpoints: HashSet::new(),
+            mem_watches: HashSet::new(),
+            reg_watches: 0,
+            watch_hit: None,
+            cheats: Vec::new(),
+            symbols: Symbols::new(),
+            display_changed: false,
+            display_dirty: true,
+            strict_memory: false,
+            strict_control_flow: false,
+            stack_limit: STACK_SIZE,
+            ignore_sys: false,
+            timing: Timing::Fixed,
+            sys_ignored: false,
+            sys_warned: false,
+            vip_hires: false,
+            idle_skip: false,
+            idle: IdleWatch::default(),
+        };
+        chip8.mem[FONT_ADDR..FONT_ADDR + FONT.len()].copy_from_slice(&FONT);
+        chip8.mem[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+
+        return chip8;
+    }
+
+    // One fetch-execute cycle, the single entry point frontends should drive the CPU with.
+    // Invariant: a malformed ROM never makes this panic, whatever it does wrong comes back
+    // as a Chip8Error. Every memory access goes through read_mem()/write_mem() for this.
+    pub fn step(&mut self) -> Result<StepInfo, Chip8Error> {
+        let pc = self.pc;
+        let result = self.step_instruction();
+        if let Err(error) = &result {
+            self.notify_halt(pc, error);
+        }
+
+        return result;
+    }
+
+    fn step_instruction(&mut self) -> Result<StepInfo, Chip8Error> {
+        let pc = self.pc;
+        let sound_was_active = self.sound_active();
+        self.display_changed = false;
+        self.sys_ignored = false;
+        let opcode = self.fetch()?;
+        // priced before it runs, skips and DXYN depend on the registers going in
+        let cycles = match self.timing {
+            Timing::Fixed => 1,
+            Timing::Vip => Instruction::decode(opcode).map_or(1, |i| self.cycles_for(i)),
+        };
+        self.execute(opcode)?;
+        self.watch_idle(opcode, cycles);
+
+        return Ok(StepInfo {
+            pc,
+            opcode,
+            display_changed: self.display_changed,
+            sound_started: !sound_was_active && self.sound_active(),
+            waiting_for_key: self.is_waiting_for_key(),
+            sys_ignored: self.sys_ignored,
+            cycles,
+        });
+    }
+
+    pub fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        if self.strict_control_flow && self.pc as usize + 1 >= self.mem.len() {
+            return Err(Chip8Error::PcOutOfBounds { pc: self.pc as u32 });
+        }
+        let byte1 = self.read_mem(self.pc as usize)?;
+        let byte2 = self.read_mem(self.pc as usize + 1)?;
+        self.advance_pc(2);
+
+        return Ok((byte1 as u16) << 8 | (byte2 as u16));
+    }
+
+    pub fn execute(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        if let Some(mut tracer) = self.tracer.take() {
+            // fetch() already moved pc past the instruction
+            tracer.record(self, self.instruction_addr(), opcode);
+            self.tracer = Some(tracer);
+        }
+        if let Some(stats) = &mut self.stats {
+            // instruction_addr(), spelled out to borrow only pc and mem
+            let len = self.mem.len();
+            stats.record(((self.pc as usize + len - 2) % len) as u16, opcode);
+        }
+        trace!(
+            target: "rustchip8::cpu",
+            "{}",
+            trace::format_line(self, self.instruction_addr(), opcode)
+        );
+
+        if self.reg_watches == 0 {
+            return self.run_opcode(opcode);
+        }
+        let registers = self.registers;
+        let result = self.run_opcode(opcode);
+        self.check_reg_watches(&registers, self.pc.wrapping_sub(2));
+
+        return result;
+    }
+
+    fn run_opcode(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        let Some(instruction) = Instruction::decode(opcode) else {
+            return Err(Chip8Error::UnknownOpcode {
+                opcode,
+                pc: self.instruction_addr(),
+            });
+        };
+
+        match instruction {
+            Instruction::Cls => self.clear_screen(),
+            Instruction::Ret => {
+                self.pc = self.pop_stack()?;
+            }
+            Instruction::ScrollDown(n) => self.scroll(0, n as isize),
+            Instruction::ScrollRight => self.scroll(4, 0),
+            Instruction::ScrollLeft => self.scroll(-4, 0),
+            Instruction::Exit => {
+                // park on the instruction so nothing else runs afterwards
+                self.exited = true;
+                self.pc = self.instruction_addr();
+            }
+            Instruction::LowRes => {
+                debug!(target: "rustchip8::display", "64x32 at {:#05X}", self.instruction_addr());
+                self.resolution = Resolution::Low;
+                self.clear_screen();
+            }
+            Instruction::HighRes => {
+                debug!(target: "rustchip8::display", "128x64 at {:#05X}", self.instruction_addr());
+                self.resolution = Resolution::High;
+                self.clear_screen();
+            }
+            Instruction::Sys(addr)
+                if self.vip_hires && addr == self.load_addr + VIP_HIRES_CLEAR =>
+            {
+                self.clear_screen();
+            }
+            Instruction::Sys(addr) => {
+                if !self.ignore_sys {
+                    return Err(Chip8Error::MachineCodeRoutine { addr });
+                }
+                if !self.sys_warned {
+                    warn!(
+                        "skipping machine code calls, the first at {:#05X}",
+                        self.instruction_addr()
+                    );
+                    self.sys_warned = true;
+                }
+                self.sys_ignored = true;
+            }
+            Instruction::Jump(addr) => {
+                self.check_jump(addr)?;
+                self.pc = addr;
+            }
+            Instruction::Call(addr) => {
+                self.check_jump(addr)?;
+                // pc is past the end when the call sits in the last word and nothing wrapped it
+                if self.strict_control_flow && self.pc as usize >= self.mem.len() {
+                    return Err(Chip8Error::PcOutOfBounds { pc: self.pc as u32 });
+                }
+                self.push_stack(self.pc)?;
+                self.pc = addr;
+            }
+            Instruction::SkipEqImm { x, nn } => {
+                if self.registers[x as usize] == nn {
+                    self.skip_next();
+                };
+            }
+            Instruction::SkipNeImm { x, nn } => {
+                if self.registers[x as usize] != nn {
+                    self.skip_next();
+                };
+            }
+            Instruction::SkipEqReg { x, y } => {
+                if self.registers[x as usize] == self.registers[y as usize] {
+                    self.skip_next();
+                }
+            }
+            Instruction::LoadImm { x, nn } => {
+                self.registers[x as usize] = nn;
+            }
+            Instruction::AddImm { x, nn } => {
+                let idx = x as usize;
+                // unlike 8XY4 this never touches VF
+                self.registers[idx] = self.registers[idx].wrapping_add(nn);
+            }
+            Instruction::Move { x, y } => {
+                self.registers[x as usize] = self.registers[y as usize];
+            }
+            Instruction::Or { x, y } => {
+                self.registers[x as usize] |= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Instruction::And { x, y } => {
+                self.registers[x as usize] &= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Instruction::Xor { x, y } => {
+                self.registers[x as usize] ^= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
+            }
+            Instruction::Add { x, y } => {
+                let (res, carry) =
+                    self.registers[x as usize].overflowing_add(self.registers[y as usize]);
+                self.registers[x as usize] = res;
+                self.registers[0xF] = carry as u8;
+            }
+            Instruction::Sub { x, y } => {
+                let (res, borrow) =
+                    self.registers[x as usize].overflowing_sub(self.registers[y as usize]);
+                self.registers[x as usize] = res;
+                self.registers[0xF] = !borrow as u8;
+            }
+            Instruction::ShiftRight { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    self.registers[x as usize] = self.registers[y as usize];
+                }
+                let val = self.registers[x as usize];
+                self.registers[x as usize] = val >> 1;
+                // VF is written last so the flag survives when X is F
+                self.registers[0xF] = val & 1;
+            }
+            Instruction::SubN { x, y } => {
+                let (res, borrow) =
+                    self.registers[y as usize].overflowing_sub(self.registers[x as usize]);
+                self.registers[x as usize] = res;
+                self.registers[0xF] = !borrow as u8;
+            }
+            Instruction::ShiftLeft { x, y } => {
+                if self.quirks.shift_uses_vy {
+                    self.registers[x as usize] = self.registers[y as usize];
+                }
+                let val = self.registers[x as usize];
+                self.registers[x as usize] = val << 1;
+                self.registers[0xF] = val >> 7;
+            }
+            Instruction::SkipNeReg { x, y } => {
+                if self.registers[x as usize] != self.registers[y as usize] {
+                    self.skip_next();
+                }
+            }
+            Instruction::LoadI(addr) => {
+                self.reg_i = addr;
+            }
+            Instruction::JumpOffset { x, nnn } => {
+                let offset = if self.quirks.jump_uses_vx {
+                    self.registers[x as usize]
+                } else {
+                    self.registers[0x0]
+                };
+                // NNN + 0xFF can land past the 4K address space, wrap it back in
+                let target = nnn as usize + offset as usize;
+                if self.strict_control_flow && target >= self.mem.len() {
+                    return Err(Chip8Error::PcOutOfBounds { pc: target as u32 });
+                }
+                let target = (target % self.mem.len()) as u16;
+                self.check_jump(target)?;
+                self.pc = target;
+            }
+            Instruction::Random { x, nn } => {
+                self.registers[x as usize] = self.rng.next_byte() & nn;
+            }
+            Instruction::Draw { x, y, n } => {
+                self.draw_sprite(x, y, n)?;
+                self.waiting_for_vblank = self.quirks.display_wait;
+            }
+            Instruction::SkipKey { x } => {
+                let key = self.registers[x as usize] & 0xF;
+                if self.input.is_pressed(key) {
+                    self.skip_next();
+                }
+            }
+            Instruction::SkipNotKey { x } => {
+                let key = self.registers[x as usize] & 0xF;
+                if !self.input.is_pressed(key) {
+                    self.skip_next();
+                }
+            }
+            Instruction::LoadDelay { x } => {
+                self.registers[x as usize] = self.delay_timer;
+            }
+            Instruction::SetDelay { x } => {
+                self.delay_timer = self.registers[x as usize];
+            }
+            Instruction::SetSound { x } => {
+                self.sound_timer = self.registers[x as usize];
+            }
+            Instruction::AddI { x } => {
+                let sum = self.reg_i as u32 + self.registers[x as usize] as u32;
+                self.reg_i = self.wrap_index(sum);
+                if self.quirks.index_overflow {
+                    self.registers[0xF] = (sum > 0xFFF) as u8;
+                }
+            }
+            Instruction::WaitKey { x } => {
+                // Don't block here, the frontend has to keep polling input. Point pc back at
+                // this instruction and let set_key() finish it once a key is released.
+                debug!(target: "rustchip8::input", "V{x:X} waits for a key at {:#05X}", self.instruction_addr());
+                self.waiting_for_key = Some(x);
+                self.pressed_while_waiting = None;
+                self.pc = self.instruction_addr();
+            }
+            Instruction::Font { x } => {
+                let digit = self.registers[x as usize] & 0xF;
+                self.reg_i = (FONT_ADDR + 5 * digit as usize) as u16;
+            }
+            Instruction::LoadILong => {
+                // XO-CHIP: the next word is a full 16-bit address for I, in 4K it wraps
+                let hi = self.read_mem(self.pc as usize)?;
+                let lo = self.read_mem(self.pc as usize + 1)?;
+                self.reg_i = self.wrap_index(u16::from_be_bytes([hi, lo]) as u32);
+                self.advance_pc(2);
+            }
+            Instruction::Audio => {
+                for i in 0..16 {
+                    self.audio_pattern[i] = self.read_mem(self.reg_i as usize + i)?;
+                }
+                self.uses_audio_pattern = true;
+            }
+            Instruction::Pitch { x } => {
+                self.pitch = self.registers[x as usize];
+                self.uses_audio_pattern = true;
+            }
+            Instruction::Plane(n) => {
+                self.selected_planes = n & 0b11;
+            }
+            Instruction::BigFont { x } => {
+                let digit = self.registers[x as usize] & 0xF;
+                self.reg_i = (BIG_FONT_ADDR + 10 * digit as usize) as u16;
+            }
+            Instruction::SaveFlags { x } => {
+                let count = x.min(7) as usize + 1;
+                self.rpl_flags[..count].copy_from_slice(&self.registers[..count]);
+                self.store_flags();
+            }
+            Instruction::LoadFlags { x } => {
+                let count = x.min(7) as usize + 1;
+                self.registers[..count].copy_from_slice(&self.rpl_flags[..count]);
+            }
+            Instruction::Bcd { x } => {
+                let val = self.registers[x as usize];
+                let digits = [val / 100, val / 10 % 10, val % 10];
+                // I near the end wraps like any write, or with strict memory fails before
+                // anything is written rather than after the first digits
+                for i in 0..digits.len() {
+                    self.resolve(self.reg_i as usize + i)?;
+                }
+                for (i, digit) in digits.into_iter().enumerate() {
+                    self.write_mem(self.reg_i as usize + i, digit)?;
+                }
+            }
+            Instruction::Store { x } => {
+                for i in 0..=x as usize {
+                    self.write_mem(self.reg_i as usize + i, self.registers[i])?;
+                }
+                if self.quirks.memory_increment {
+                    self.reg_i = self.wrap_index(self.reg_i as u32 + x as u32 + 1);
+                }
+            }
+            Instruction::Load { x } => {
+                for i in 0..=x as usize {
+                    self.registers[i] = self.read_mem(self.reg_i as usize + i)?;
+                }
+                if self.quirks.memory_increment {
+                    self.reg_i = self.wrap_index(self.reg_i as u32 + x as u32 + 1);
+                }
+            }
+        }
+
+        return Ok(());
+    }
+
+    // I stays a 12-bit address on a 4K machine, XO-CHIP lets it roam all of 64K
+    fn wrap_index(&self, addr: u32) -> u16 {
+        if self.is_extended_memory() {
+            return addr as u16;
+        }
+        return (addr & 0xFFF) as u16;
+    }
+
+    fn push_stack(&mut self, addr: u16) -> Result<(), Chip8Error> {
+        if self.stack.len() >= self.stack_limit {
+            return Err(Chip8Error::StackOverflow {
+                pc: self.instruction_addr(),
+            });
+        }
+        self.stack.push(addr);
+
+        return Ok(());
+    }
+
+    fn pop_stack(&mut self) -> Result<u16, Chip8Error> {
+        return self.stack.pop().ok_or(Chip8Error::StackUnderflow);
+    }
+
+    // Skips the next instruction, which is 4 bytes long if it's XO-CHIP's F000 NNNN. F000
+    // takes its second word in 4K as well, so the skip doesn't depend on the memory size.
+    fn skip_next(&mut self) {
+        let len = if self.peek_opcode() == Some(0xF000) {
+            4
+        } else {
+            2
+        };
+        self.advance_pc(len);
+    }
+
+    // pc wraps around the end of memory like every other access. With strict memory or
+    // control flow it is left past the end so the next fetch fails.
+    fn advance_pc(&mut self, len: u16) {
+        let pc = self.pc as usize + len as usize;
+        self.pc = if self.strict_memory || self.strict_control_flow {
+            pc.min(u16::MAX as usize) as u16
+        } else {
+            (pc % self.mem.len()) as u16
+        };
+    }
+
+    fn check_jump(&self, addr: u16) -> Result<(), Chip8Error> {
+        if self.strict_control_flow && !addr.is_multiple_of(2) {
+            return Err(Chip8Error::MisalignedJump {
+                addr,
+                pc: self.instruction_addr(),
+            });
+        }
+        return Ok(());
+    }
+
+    // Address of the instruction fetch() just returned, pc may have wrapped past it
+    pub(crate) fn instruction_addr(&self) -> u16 {
+        return ((self.pc as usize + self.mem.len() - 2) % self.mem.len()) as u16;
+    }
+
+    pub fn set_key(&mut self, key: u8, pressed: bool) {
+        let key = key & 0xF;
+        let was_pressed = self.input.is_held(key);
+        self.input.set_key(key, pressed);
+
+        let Some(x) = self.waiting_for_key else {
+            return;
+        };
+        if pressed && !was_pressed && self.pressed_while_waiting.is_none() {
+            self.pressed_while_waiting = Some(key);
+        } else if !pressed && was_pressed && self.pressed_while_waiting == Some(key) {
+            let registers = self.registers;
+            self.registers[x as usize] = key;
+            debug!(target: "rustchip8::input", "key {key:X} released into V{x:X}");
+            // pc still points at the FX0A
+            self.check_reg_watches(&registers, self.pc);
+            self.waiting_for_key = None;
+            self.pressed_while_waiting = None;
+            self.advance_pc(2);
+        }
+    }
+
+    pub fn is_waiting_for_key(&self) -> bool {
+        return self.waiting_for_key.is_some();
+    }
+
+    // Frontends should stop executing until the next tick_60hz() while this is set
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        return self.waiting_for_vblank;
+    }
+
+    pub fn sound_active(&self) -> bool {
+        return self.sound_timer > 0;
+    }
+
+    pub fn audio_pattern(&self) -> [u8; 16] {
+        return self.audio_pattern;
+    }
+
+    pub fn pitch(&self) -> u8 {
+        return self.pitch;
+    }
+
+    pub fn uses_audio_pattern(&self) -> bool {
+        return self.uses_audio_pattern;
+    }
+
+    // Only the top-left resolution().width() x resolution().height() pixels are in use,
+    // display_rows() and display_packed() give just those
+    pub fn display(&self) -> &Framebuffer {
+        return &self.display;
+    }
+
+    // Where the last DXYN erased a lit pixel, as (x, y) display coordinates, each once
+    // however many planes it was lit on. Empty when it set VF to 0.
+    pub fn last_collisions(&self) -> &[(usize, usize)] {
+        return &self.collisions;
+    }
+
+    // (width, height) of the current resolution: 64x32, 128x64 or 64x64
+    pub fn display_size(&self) -> (usize, usize) {
+        return (self.resolution.width(), self.resolution.height());
+    }
+
+    /// The visible rows, top to bottom, each display_size().0 pixels long. A pixel is the
+    /// bitplanes it is lit on, 0 when it is off.
+    ///
+    /// ```
+    /// use rustchip8::Chip8;
+    ///
+    /// // LD F, V0; DRW V0, V0, 5: the font's 0 in the top left corner
+    /// let mut chip8 = Chip8::new();
+    /// chip8.load_rom(&[0xF0, 0x29, 0xD0, 0x05]).unwrap();
+    /// chip8.step().unwrap();
+    /// chip8.step().unwrap();
+    /// let lit: Vec<usize> = chip8
+    ///     .display_rows()
+    ///     .map(|row| row.iter().filter(|&&pixel| pixel != 0).count())
+    ///     .take(6)
+    ///     .collect();
+    /// assert_eq!(lit, [4, 2, 2, 2, 4, 0]);
+    /// assert_eq!(chip8.display_rows().count(), 32);
+    /// ```
+    pub fn display_rows(&self) -> impl Iterator<Item = &[Pixel]> {
+        let (width, height) = self.display_size();
+        return self.display[..height].iter().map(move |row| &row[..width]);
+    }
+
+    /// Whether the pixel is lit on any plane, false outside the current resolution.
+    ///
+    /// ```
+    /// use rustchip8::Chip8;
+    ///
+    /// // the font's 0 again: 0xF0, 0x90, 0x90, 0x90, 0xF0
+    /// let mut chip8 = Chip8::new();
+    /// chip8.load_rom(&[0xF0, 0x29, 0xD0, 0x05]).unwrap();
+    /// chip8.step().unwrap();
+    /// chip8.step().unwrap();
+    /// assert!(chip8.pixel(0, 0) && chip8.pixel(3, 1));
+    /// assert!(!chip8.pixel(1, 1) && !chip8.pixel(4, 0));
+    /// assert!(!chip8.pixel(64, 0));
+    /// ```
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        let (width, height) = self.display_size();
+        return x < width && y < height && self.display[y][x] != 0;
+    }
+
+    /// 1 bit per pixel (lit on any plane), rows top to bottom, the leftmost pixel in the
+    /// high bit of each byte. Rows are whole bytes since every width is a multiple of 8.
+    /// Handy for hashing the display or piping it elsewhere.
+    ///
+    /// It is a Vec rather than the [u8; 256] it was before hi-res modes: 64x32 packs into
+    /// 256 bytes, but SUPER-CHIP's 128x64 takes 1024 and the VIP's 64x64 512.
+    ///
+    /// ```
+    /// use rustchip8::Chip8;
+    ///
+    /// let mut chip8 = Chip8::new();
+    /// chip8.load_rom(&[0xF0, 0x29, 0xD0, 0x05]).unwrap();
+    /// chip8.step().unwrap();
+    /// chip8.step().unwrap();
+    /// let packed = chip8.display_packed();
+    /// assert_eq!(packed.len(), 256);
+    /// // each row of the 0 in the first byte of its row
+    /// let glyph: Vec<u8> = (0..5).map(|y| packed[y * 64 / 8]).collect();
+    /// assert_eq!(glyph, [0xF0, 0x90, 0x90, 0x90, 0xF0]);
+    /// let (x, y) = (3, 1);
+    /// assert!(packed[y * 64 / 8 + x / 8] & (0x80 >> (x % 8)) != 0);
+    /// ```
+    pub fn display_packed(&self) -> Vec<u8> {
+        let (width, height) = self.display_size();
+        let mut packed = Vec::with_capacity(width * height / 8);
+        for row in self.display_rows() {
+            for byte in row.chunks(8) {
+                let bits = byte
+                    .iter()
+                    .fold(0u8, |bits, &pixel| bits << 1 | (pixel != 0) as u8);
+                packed.push(bits);
+            }
+        }
+
+        return packed;
+    }
+
+    // Whether the display changed since the last call
+    pub fn take_display_dirty(&mut self) -> bool {
+        return std::mem::take(&mut self.display_dirty);
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        return self.selected_planes;
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        return self.resolution;
+    }
+
+    pub fn has_exited(&self) -> bool {
+        return self.exited;
+    }
+
+    pub fn cpu_state(&self) -> CpuState {
+        if self.exited {
+            return CpuState::Exited;
+        }
+        if self.is_waiting_for_key() {
+            return CpuState::WaitingForKey;
+        }
+        if self.is_self_jump() {
+            return CpuState::SelfJump;
+        }
+        return CpuState::Running;
+    }
+
+    pub fn mem(&self) -> &[u8] {
+        return &self.mem;
+    }
+
+    pub fn registers(&self) -> &[u8; 16] {
+        return &self.registers;
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        return &self.stack;
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        return self.delay_timer;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        return self.sound_timer;
+    }
+
+    // The opcode that the next fetch() will return, without advancing pc
+    pub fn peek_opcode(&self) -> Option<u16> {
+        let hi = self.read_mem(self.pc as usize).ok()?;
+        let lo = self.read_mem(self.pc as usize + 1).ok()?;
+        return Some(u16::from_be_bytes([hi, lo]));
+    }
+
+    // Where `addr` really lands: wrapped around the end of memory, or an error with strict
+    // memory
+    fn resolve(&self, addr: usize) -> Result<usize, Chip8Error> {
+        if addr < self.mem.len() {
+            return Ok(addr);
+        }
+        if self.strict_memory {
+            return Err(Chip8Error::MemoryOutOfBounds { addr: addr as u16 });
+        }
+        return Ok(addr % self.mem.len());
+    }
+
+    pub fn read_mem(&self, addr: usize) -> Result<u8, Chip8Error> {
+        return Ok(self.mem[self.resolve(addr)?]);
+    }
+
+    // `len` bytes from `addr`, None when they run past the end. Unlike read_mem() this
+    // never wraps around.
+    pub fn mem_range(&self, addr: usize, len: usize) -> Option<&[u8]> {
+        return self.mem.get(addr..addr.checked_add(len)?);
+    }
+
+    // Memory write from execute(), also checks the watchpoints
+    pub(crate) fn write_mem(&mut self, addr: usize, val: u8) -> Result<(), Chip8Error> {
+        let addr = self.resolve(addr)?;
+        self.store(addr, val);
+        return Ok(());
+    }
+
+    pub fn set_strict_memory(&mut self, strict: bool) {
+        self.strict_memory = strict;
+    }
+
+    pub fn is_strict_memory(&self) -> bool {
+        return self.strict_memory;
+    }
+
+    // Errors instead of the lenient handling of odd jumps and pc leaving memory, for ROM
+    // developers catching their own bugs. RET with an empty stack is an error either way.
+    pub fn set_strict_control_flow(&mut self, strict: bool) {
+        self.strict_control_flow = strict;
+    }
+
+    pub fn is_strict_control_flow(&self) -> bool {
+        return self.strict_control_flow;
+    }
+
+    pub fn set_stack_limit(&mut self, limit: usize) {
+        self.stack_limit = limit;
+    }
+
+    pub fn stack_limit(&self) -> usize {
+        return self.stack_limit;
+    }
+
+    pub fn set_ignore_sys(&mut self, ignore: bool) {
+        self.ignore_sys = ignore;
+    }
+
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    pub fn timing(&self) -> Timing {
+        return self.timing;
+    }
+
+    // 1NNN jumping to its own address, how most test ROMs park once they are done
+    pub fn is_self_jump(&self) -> bool {
+        return self.pc <= 0xFFF && self.peek_opcode() == Some(0x1000 | self.pc);
+    }
+
+    // FNV-1a of the loaded ROM, identifies it in save states and recordings
+    pub fn rom_hash(&self) -> u64 {
+        return self.rom_hash;
+    }
+
+    pub fn pc(&self) -> u16 {
+        return self.pc;
+    }
+
+    pub fn reg_i(&self) -> u16 {
+        return self.reg_i;
+    }
+
+    // Debugger edits. They go around the watchpoints and leave the machine as if a program
+    // had set the value.
+    pub fn set_register(&mut self, x: u8, val: u8) {
+        self.registers[x as usize & 0xF] = val;
+    }
+
+    // Wraps like the program's own accesses, and is refused past the end in strict mode
+    pub fn poke(&mut self, addr: usize, val: u8) -> Result<(), Chip8Error> {
+        let addr = self.resolve(addr)?;
+        self.mem[addr] = val;
+        self.idle.progress();
+        return Ok(());
+    }
+
+    pub fn set_reg_i(&mut self, val: u16) {
+        self.reg_i = val;
+    }
+
+    // Also stops waiting for a key, the FX0A is left behind
+    pub fn set_pc(&mut self, addr: u16) {
+        self.pc = addr;
+        self.waiting_for_key = None;
+        self.pressed_while_waiting = None;
+    }
+
+    pub fn set_delay_timer(&mut self, val: u8) {
+        self.delay_timer = val;
+    }
+
+    pub fn set_sound_timer(&mut self, val: u8) {
+        self.sound_timer = val;
+    }
+
+    pub fn set_breakpoint(&mut self, addr: u16) {
+        self.breakpoints.insert(addr);
+    }
+
+    // false if there was no breakpoint at addr
+    pub fn clear_breakpoint(&mut self, addr: u16) -> bool {
+        return self.breakpoints.remove(&addr);
+    }
+
+    pub fn breakpoints(&self) -> &HashSet<u16> {
+        return &self.breakpoints;
+    }
+
+    // Whether the next fetch() is from a breakpoint address. Checks the (usually empty)
+    // set first so it costs next to nothing in the run loop.
+    pub fn at_breakpoint(&self) -> bool {
+        return !self.breakpoints.is_empty() && self.breakpoints.contains(&self.pc);
+    }
+
+    // Replacing or clearing the tracer drops the old one, flushing its output
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+        self.tracer = tracer;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        return self.quirks;
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        debug!("quirks {quirks:?}");
+        self.quirks = quirks;
+    }
+
+    pub fn quirks_mut(&mut self) -> &mut Quirks {
+        return &mut self.quirks;
+    }
+
+    // The 60Hz tick: both timers count down, a display_wait DXYN's vertical blank ends and
+    // key edges (see input.rs) start over. Call it exactly TIMER_HZ times per second of
+    // emulated time, however many instructions run in between, so a faster CPU clock
+    // doesn't make delays shorter. run_frame() calls it once per frame itself.
+    pub fn tick_60hz(&mut self) -> TimerEvents {
+        let sound_before = self.sound_active();
+        let delay_before = self.delay_timer;
+        self.waiting_for_vblank = false;
+        self.input.end_frame();
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+
+        let events = TimerEvents {
+            sound_started: sound_before && !self.sound_at_tick,
+            sound_stopped: (sound_before || self.sound_at_tick) && !self.sound_active(),
+            delay_expired: delay_before == 1,
+        };
+        self.sound_at_tick = self.sound_active();
+
+        return events;
+    }
+
+    // Switches between the classic 4K and the XO-CHIP 64K address space, keeping what fits
+    pub fn set_extended_memory(&mut self, enabled: bool) {
+        let size = if enabled { EXTENDED_MEM_SIZE } else { MEM_SIZE };
+        if size == self.mem.len() {
+            return;
+        }
+        debug!("{}K of memory", size / 1024);
+        let mut mem = vec![0; size].into_boxed_slice();
+        let keep = size.min(self.mem.len());
+        mem[..keep].copy_from_slice(&self.mem[..keep]);
+        self.mem = mem;
+    }
+
+    pub fn is_extended_memory(&self) -> bool {
+        return self.mem.len() == EXTENDED_MEM_SIZE;
+    }
+
+    // Moves the program start (pc included) away from PROGRAM_START, call it before
+    // load_rom()
+    pub fn set_load_addr(&mut self, addr: u16) -> Result<(), RomError> {
+        if !addr.is_multiple_of(2) || addr as usize >= self.mem.len() {
+            return Err(RomError::BadLoadAddress { addr });
+        }
+        self.load_addr = addr;
+        self.pc = self.start_addr();
+
+        return Ok(());
+    }
+
+    // COSMAC VIP hi-res (is_vip_hires_rom() tells those ROMs apart): a 64x64 display, pc
+    // starting past the stub at VIP_HIRES_ENTRY and 0230 clearing the screen. Like
+    // set_load_addr(), call it before load_rom(), it survives reset().
+    pub fn set_vip_hires(&mut self, enabled: bool) {
+        if enabled {
+            debug!(target: "rustchip8::display", "64x64 VIP hi-res");
+        }
+        self.vip_hires = enabled;
+        self.resolution = if enabled {
+            Resolution::VipHigh
+        } else {
+            Resolution::Low
+        };
+        self.pc = self.start_addr();
+        self.clear_screen();
+    }
+
+    pub fn is_vip_hires(&self) -> bool {
+        return self.vip_hires;
+    }
+
+    fn start_addr(&self) -> u16 {
+        if self.vip_hires {
+            return self.load_addr + VIP_HIRES_ENTRY as u16;
+        }
+        return self.load_addr;
+    }
+
+    pub fn load_addr(&self) -> u16 {
+        return self.load_addr;
+    }
+
+    pub fn max_rom_size(&self) -> usize {
+        return self.mem.len().saturating_sub(self.load_addr as usize);
+    }
+
+    // Power cycle: back to what a fresh machine starts with, memory of the same size cleared
+    // apart from the fonts. Quirks, the RPL flags (persistent storage on the HP48), the key
+    // modes, tracer, observer, stats, breakpoints, watches, symbols and VIP hi-res mode
+    // survive. Load a ROM again afterwards.
+    pub fn reset(&mut self) {
+        let mut fresh = Chip8::with_seed(self.rng.state);
+        fresh.set_extended_memory(self.is_extended_memory());
+        fresh.quirks = self.quirks;
+        for (key, mode) in self.input.modes().into_iter().enumerate() {
+            fresh.input.set_mode(key as u8, mode);
+        }
+        fresh.rpl_flags = self.rpl_flags;
+        fresh.flag_storage = self.flag_storage.take();
+        fresh.tracer = self.tracer.take();
+        fresh.observer = self.observer.take();
+        fresh.stats = self.stats.take();
+        fresh.breakpoints = std::mem::take(&mut self.breakpoints);
+        fresh.mem_watches = std::mem::take(&mut self.mem_watches);
+        fresh.reg_watches = self.reg_watches;
+        fresh.cheats = std::mem::take(&mut self.cheats);
+        fresh.symbols = std::mem::take(&mut self.symbols);
+        fresh.strict_memory = self.strict_memory;
+        fresh.strict_control_flow = self.strict_control_flow;
+        fresh.stack_limit = self.stack_limit;
+        fresh.ignore_sys = self.ignore_sys;
+        fresh.timing = self.timing;
+        fresh.idle_skip = self.idle_skip;
+        fresh.load_addr = self.load_addr;
+        fresh.pc = self.load_addr;
+        if self.vip_hires {
+            fresh.set_vip_hires(true);
+        }
+
+        *self = fresh;
+    }
+
+    // Instructions are fetched in pairs, so an odd-sized ROM gets a 0x00 after its last byte
+    pub fn load_rom(&mut self, rom_data: &[u8]) -> Result<(), RomError> {
+        if rom_data.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if rom_data.len() > self.max_rom_size() {
+            return Err(RomError::TooLarge {
+                size: rom_data.len(),
+                max: self.max_rom_size(),
+            });
+        }
+        let start = self.load_addr as usize;
+        let fonts = FONT_ADDR..BIG_FONT_ADDR + BIG_FONT.len();
+        if start < fonts.end && start + rom_data.len() > fonts.start {
+            warn!(
+                "the ROM at {start:#05X}-{:#05X} overwrites the fonts at {:#05X}-{:#05X}",
+                start + rom_data.len() - 1,
+                fonts.start,
+                fonts.end - 1
+            );
+        }
+        self.mem[start..start + rom_data.len()].copy_from_slice(rom_data);
+        let end = start + rom_data.len();
+        if !rom_data.len().is_multiple_of(2) {
+            warn!(
+                "the ROM is {} bytes, an odd size, its last instruction is padded with 0x00",
+                rom_data.len()
+            );
+            if let Some(byte) = self.mem.get_mut(end) {
+                *byte = 0;
+            }
+        }
+        self.rom_hash = state::rom_hash(rom_data);
+
+        return Ok(());
+    }
+
+    // Only clears the planes picked with FN01 (just the first one outside XO-CHIP)
+    pub fn clear_screen(&mut self) {
+        self.display_changed = true;
+        self.display_dirty = true;
+        for row in self.display.iter_mut() {
+            for pixel in row.iter_mut() {
+                *pixel &= !self.selected_planes;
+            }
+        }
+    }
+
+    // DXYN draws 8xN sprites, DXY0 a 16x16 one stored as 32 bytes (two per row). With both
+    // XO-CHIP planes selected the second plane's sprite data follows the first one's.
+    fn draw_sprite(&mut self, x: u8, y: u8, n: u8) -> Result<(), Chip8Error> {
+        self.display_changed = true;
+        self.display_dirty = true;
+        let width = self.resolution.width();
+        let height = self.resolution.height();
+        let (rows, cols) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+        let bytes_per_row = cols / 8;
+
+        // the starting coordinate always wraps, the rest of the sprite only with the quirk
+        let x_pos = self.registers[x as usize] as usize % width;
+        let y_pos = self.registers[y as usize] as usize % height;
+        self.registers[0xF] = 0;
+        self.collisions.clear();
+
+        let mut sprite_addr = self.reg_i as usize;
+        for plane in 0..PLANE_COUNT {
+            let plane_bit: Pixel = 1 << plane;
+            if self.selected_planes & plane_bit == 0 {
+                continue;
+            }
+
+            for i in 0..rows {
+                let mut screen_y = y_pos + i;
+                if screen_y >= height {
+                    if !self.quirks.sprite_wrap {
+                        break;
+                    }
+                    screen_y %= height;
+                }
+                let addr = sprite_addr + i * bytes_per_row;
+                let sprite_data = if bytes_per_row == 2 {
+                    (self.read_mem(addr)? as u16) << 8 | self.read_mem(addr + 1)? as u16
+                } else {
+                    (self.read_mem(addr)? as u16) << 8
+                };
+
+                for j in 0..cols {
+                    let mut screen_x = x_pos + j;
+                    if screen_x >= width {
+                        if !self.quirks.sprite_wrap {
+                            break;
+                        }
+                        screen_x %= width;
+                    }
+
+                    let pixel = (sprite_data >> (15 - j)) & 1 != 0;
+                    if pixel {
+                        // like Octo, erasing a pixel on any plane counts as a collision
+                        if self.display[screen_y][screen_x] & plane_bit != 0 {
+                            self.registers[0xF] = 1;
+                            // a sprite covers a pixel once per plane
+                            if plane == 0 || !self.collisions.contains(&(screen_x, screen_y)) {
+                                self.collisions.push((screen_x, screen_y));
+                            }
+                        }
+                        self.display[screen_y][screen_x] ^= plane_bit;
+                    }
+                }
+            }
+            sprite_addr += rows * bytes_per_row;
+        }
+
+        return Ok(());
+    }
+
+    // Shifts the selected planes of the visible display, pixels scrolled in are blank
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        self.display_changed = true;
+        self.display_dirty = true;
+        let width = self.resolution.width() as isize;
+        let height = self.resolution.height() as isize;
+        let planes = self.selected_planes;
+        let old = self.display;
+        for y in 0..height {
+            for x in 0..width {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let inside = (0..width).contains(&src_x) && (0..height).contains(&src_y);
+                let scrolled = if inside {
+                    old[src_y as usize][src_x as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.display[y as usize][x as usize];
+                *pixel = *pixel & !planes | scrolled;
+            }
+        }
+    }
+}
+
+pub(crate) fn split_nibbles(word: u16) -> [u8; 4] {
+    [
+        ((word >> 12) & 0xF) as u8,
+        ((word >> 8) & 0xF) as u8,
+        ((word >> 4) & 0xF) as u8,
+        (word & 0xF) as u8,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(rom: &[u8], extended: bool) -> Chip8 {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_extended_memory(extended);
+        chip8.load_rom(rom).unwrap();
+        return chip8;
+    }
+
+    fn run(chip8: &mut Chip8, steps: usize) {
+        for _ in 0..steps {
+            chip8.step().unwrap();
+        }
+    }
+
+    fn with_quirks(set: impl FnOnce(&mut Quirks)) -> Chip8 {
+        let mut chip8 = Chip8::with_seed(0);
+        set(chip8.quirks_mut());
+        return chip8;
+    }
+
+    #[test]
+    fn shifts_in_both_quirk_modes() {
+        for shift_uses_vy in [false, true] {
+            let mut chip8 = with_quirks(|quirks| quirks.shift_uses_vy = shift_uses_vy);
+            chip8.set_register(1, 0b1000_0001);
+            chip8.set_register(2, 0b0100_0010);
+            // 8126, SHR V1 {, V2}
+            chip8.execute(0x8126).unwrap();
+            let (result, flag) = if shift_uses_vy {
+                (0b0010_0001, 0)
+            } else {
+                (0b0100_0000, 1)
+            };
+            assert_eq!(chip8.registers()[1], result, "vy {shift_uses_vy}");
+            assert_eq!(chip8.registers()[0xF], flag);
+
+            chip8.set_register(1, 0b1000_0001);
+            // 812E, SHL V1 {, V2}
+            chip8.execute(0x812E).unwrap();
+            let (result, flag) = if shift_uses_vy {
+                (0b1000_0100, 0)
+            } else {
+                (0b0000_0010, 1)
+            };
+            assert_eq!(chip8.registers()[1], result, "vy {shift_uses_vy}");
+            assert_eq!(chip8.registers()[0xF], flag);
+        }
+    }
+
+    #[test]
+    fn logic_with_vf_resets_it_after_the_result() {
+        // 8F01 OR VF, V0; 8F12 AND VF, V1; 81F3 XOR V1, VF
+        let cases = [
+            (0x8F01, 0b1100, 0b0011, 0b1111),
+            (0x8F12, 0b1100, 0b0110, 0b0100),
+            (0x81F3, 0b1100, 0b0110, 0b1010),
+        ];
+        for vf_reset in [false, true] {
+            for (opcode, vf, other, result) in cases {
+                let mut chip8 = with_quirks(|quirks| quirks.vf_reset = vf_reset);
+                chip8.set_register(0xF, vf);
+                chip8.set_register(0, other);
+                chip8.set_register(1, other);
+                chip8.execute(opcode).unwrap();
+                // the reset comes after the result is stored, so it wins in VF; without it
+                // VF holds the result when it is X and is only read when it is Y
+                let expected_vf = match (vf_reset, opcode) {
+                    (true, _) => 0,
+                    (false, 0x81F3) => vf,
+                    (false, _) => result,
+                };
+                assert_eq!(
+                    chip8.registers()[0xF],
+                    expected_vf,
+                    "{opcode:04X}, vf_reset {vf_reset}"
+                );
+                if opcode == 0x81F3 {
+                    assert_eq!(chip8.registers()[1], result, "{opcode:04X}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn shift_into_vf_keeps_the_flag() {
+        // the flag is written after the result, so VF ends up holding the shifted-out bit
+        let mut chip8 = with_quirks(|quirks| quirks.shift_uses_vy = false);
+        chip8.set_register(0xF, 0b0000_0011);
+        chip8.execute(0x8F06).unwrap();
+        assert_eq!(chip8.registers()[0xF], 1);
+        chip8.set_register(0xF, 0b0100_0000);
+        chip8.execute(0x8F0E).unwrap();
+        assert_eq!(chip8.registers()[0xF], 0);
+
+        let mut chip8 = with_quirks(|quirks| quirks.shift_uses_vy = true);
+        chip8.set_register(0xF, 0);
+        chip8.set_register(3, 0b1000_0000);
+        chip8.execute(0x8F3E).unwrap();
+        assert_eq!(chip8.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn random_is_masked_and_reproducible() {
+        let rolls = |seed: u64, opcode: u16| {
+            let mut chip8 = Chip8::with_seed(seed);
+            return (0..64)
+                .map(|_| {
+                    chip8.execute(opcode).unwrap();
+                    return chip8.registers()[3];
+                })
+                .collect::<Vec<u8>>();
+        };
+        // C3FF, C30F, C300
+        assert_eq!(rolls(42, 0xC3FF), rolls(42, 0xC3FF));
+        assert_ne!(rolls(42, 0xC3FF), rolls(43, 0xC3FF));
+        assert!(rolls(7, 0xC30F).iter().all(|&roll| roll <= 0x0F));
+        assert!(rolls(7, 0xC300).iter().all(|&roll| roll == 0));
+        // the mask is applied to the same draws
+        let masked: Vec<u8> = rolls(9, 0xC3FF).iter().map(|roll| roll & 0xA5).collect();
+        assert_eq!(rolls(9, 0xC3A5), masked);
+    }
+
+    #[test]
+    fn jump_with_offset_in_both_quirk_modes() {
+        for jump_uses_vx in [false, true] {
+            let mut chip8 = with_quirks(|quirks| quirks.jump_uses_vx = jump_uses_vx);
+            chip8.set_register(0, 0x10);
+            chip8.set_register(3, 0x20);
+            // B345: 0x345 + V0, or 0x345 + V3
+            chip8.execute(0xB345).unwrap();
+            let target = if jump_uses_vx { 0x365 } else { 0x355 };
+            assert_eq!(chip8.pc(), target, "vx {jump_uses_vx}");
+        }
+    }
+
+    #[test]
+    fn jump_with_offset_past_4k() {
+        // BFFF with V0 = 0xFF: 0x10FE wraps to 0x0FE, or fails with strict control flow
+        let mut chip8 = with_quirks(|quirks| quirks.jump_uses_vx = false);
+        chip8.set_register(0, 0xFF);
+        chip8.execute(0xBFFF).unwrap();
+        assert_eq!(chip8.pc(), 0x0FE);
+
+        let mut chip8 = with_quirks(|quirks| quirks.jump_uses_vx = false);
+        chip8.set_strict_control_flow(true);
+        chip8.set_register(0, 0xFF);
+        assert_eq!(
+            chip8.execute(0xBFFF).unwrap_err(),
+            Chip8Error::PcOutOfBounds { pc: 0x10FE }
+        );
+        assert_eq!(chip8.pc(), PROGRAM_START as u16);
+    }
+
+    #[test]
+    fn wait_key_resumes_once_on_release() {
+        // F30A, LD V3, K
+        let mut chip8 = machine(&[0xF3, 0x0A, 0x61, 0x01], false);
+        // held from before the wait, its release has to be ignored
+        chip8.set_key(0x2, true);
+        run(&mut chip8, 1);
+        assert!(chip8.is_waiting_for_key());
+        assert_eq!(chip8.pc(), 0x200);
+        chip8.set_key(0x2, false);
+        assert!(chip8.is_waiting_for_key());
+
+        chip8.set_key(0x5, true);
+        assert!(
+            chip8.is_waiting_for_key(),
+            "a press alone doesn't finish it"
+        );
+        // another key released in between isn't the one that was pressed first
+        chip8.set_key(0x9, true);
+        chip8.set_key(0x9, false);
+        assert!(chip8.is_waiting_for_key());
+        chip8.set_key(0x5, false);
+        assert!(!chip8.is_waiting_for_key());
+        assert_eq!(chip8.registers()[3], 0x5);
+        assert_eq!(chip8.pc(), 0x202);
+
+        // later keys don't land in V3 again
+        chip8.set_key(0x7, true);
+        chip8.set_key(0x7, false);
+        assert_eq!(chip8.registers()[3], 0x5);
+        assert_eq!(chip8.pc(), 0x202);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.registers()[1], 1);
+    }
+
+    #[test]
+    fn font_digits_draw_their_glyph() {
+        for digit in 0..16u8 {
+            let mut chip8 = Chip8::with_seed(0);
+            // the high nibble is ignored
+            chip8.set_register(0, 0xA0 | digit);
+            // F029, LD F, V0 then D125, DRW V1, V2, 5 at (0, 0)
+            chip8.execute(0xF029).unwrap();
+            assert_eq!(chip8.reg_i() as usize, FONT_ADDR + 5 * digit as usize);
+            chip8.execute(0xD125).unwrap();
+            let glyph = &FONT[5 * digit as usize..][..5];
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..8 {
+                    let lit = row & (0x80 >> x) != 0;
+                    assert_eq!(chip8.pixel(x, y), lit, "digit {digit:X} at ({x}, {y})");
+                }
+            }
+            assert!((0..SCREEN_WIDTH).all(|x| !chip8.pixel(x, 5)));
+        }
+    }
+
+    #[test]
+    fn arithmetic_flags_at_the_boundaries() {
+        let values = [0x00u8, 0x01, 0xFF];
+        for vx in values {
+            for vy in values {
+                // 8124 ADD, 8125 SUB, 8127 SUBN: (result, VF)
+                let cases = [
+                    (
+                        0x8124,
+                        vx.wrapping_add(vy),
+                        (vx as u16 + vy as u16 > 0xFF) as u8,
+                    ),
+                    (0x8125, vx.wrapping_sub(vy), (vx >= vy) as u8),
+                    (0x8127, vy.wrapping_sub(vx), (vy >= vx) as u8),
+                ];
+                for (opcode, result, flag) in cases {
+                    let mut chip8 = Chip8::with_seed(0);
+                    chip8.set_register(1, vx);
+                    chip8.set_register(2, vy);
+                    chip8.set_register(0xF, 0x55);
+                    chip8.execute(opcode).unwrap();
+                    let name = format!("{opcode:04X} with {vx:#04X}, {vy:#04X}");
+                    assert_eq!(chip8.registers()[1], result, "{name}");
+                    assert_eq!(chip8.registers()[0xF], flag, "{name}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn arithmetic_into_vf_keeps_the_flag() {
+        // VF is written after the result: 8F14, 8F15, 8F17 with VF = 0xFF, V1 = 0x01
+        for (opcode, flag) in [(0x8F14, 1), (0x8F15, 1), (0x8F17, 0)] {
+            let mut chip8 = Chip8::with_seed(0);
+            chip8.set_register(0xF, 0xFF);
+            chip8.set_register(1, 0x01);
+            chip8.execute(opcode).unwrap();
+            assert_eq!(chip8.registers()[0xF], flag, "{opcode:04X}");
+        }
+    }
+
+    #[test]
+    fn add_immediate_wraps_without_touching_vf() {
+        // 7401, ADD V4, 1, 300 times over
+        let mut chip8 = machine(&[0x74, 0x01].repeat(300), false);
+        chip8.set_register(0xF, 0xAB);
+        run(&mut chip8, 300);
+        assert_eq!(chip8.registers()[4], (300 % 256) as u8);
+        assert_eq!(chip8.registers()[0xF], 0xAB);
+    }
+
+    #[test]
+    fn delay_timer_runs_out_on_a_simulated_clock() {
+        // LD V0, 30; LD DT, V0; then spin on JP 0x204, stepped at 700Hz in 7ms frames
+        let mut chip8 = machine(&[0x60, 0x1E, 0xF0, 0x15, 0x12, 0x04], false);
+        let mut timers = TimerClock::new();
+        let mut cpu = CpuClock::new(700);
+        let frame = Duration::from_millis(7);
+        let mut ticks = 0;
+        let mut elapsed = Duration::ZERO;
+        while chip8.delay_timer() != 0 || ticks == 0 {
+            run(&mut chip8, cpu.advance(frame) as usize);
+            for _ in 0..timers.advance(frame) {
+                chip8.tick_60hz();
+                ticks += 1;
+            }
+            elapsed += frame;
+            assert!(
+                elapsed < Duration::from_secs(1),
+                "the delay timer never ran out"
+            );
+        }
+        assert_eq!(ticks, 30);
+        // 30 ticks is half a second, the first frame that gets there ends at 504ms
+        assert_eq!(elapsed, Duration::from_millis(504));
+    }
+
+    #[test]
+    fn clocks_keep_their_rate_over_uneven_frames() {
+        let mut timers = TimerClock::new();
+        let mut cpu = CpuClock::new(700);
+        let (mut ticks, mut cycles) = (0, 0);
+        // 3ms, 5ms, 17ms, ... adding up to 2 seconds
+        let frames = [3, 5, 17, 25].repeat(40);
+        assert_eq!(frames.iter().sum::<u64>(), 2000);
+        for ms in frames {
+            ticks += timers.advance(Duration::from_millis(ms));
+            cycles += cpu.advance(Duration::from_millis(ms));
+        }
+        assert_eq!(ticks, 120);
+        assert_eq!(cycles, 1400);
+    }
+
+    // An 8x4 block at (62, 30), with (0, 30), where a wrapped sprite lands, already lit
+    fn draw_at_corner(sprite_wrap: bool) -> Chip8 {
+        let mut chip8 = with_quirks(|quirks| quirks.sprite_wrap = sprite_wrap);
+        for addr in 0x300..0x304 {
+            chip8.poke(addr, 0xFF).unwrap();
+        }
+        chip8.poke(0x304, 0x80).unwrap();
+        chip8.set_reg_i(0x304);
+        // DRW V1, V2, 1 at (0, 30)
+        chip8.set_register(1, 0);
+        chip8.set_register(2, 30);
+        chip8.execute(0xD121).unwrap();
+
+        chip8.set_reg_i(0x300);
+        chip8.set_register(1, 62);
+        chip8.execute(0xD124).unwrap();
+        return chip8;
+    }
+
+    #[test]
+    fn sprites_clip_at_the_edges() {
+        let chip8 = draw_at_corner(false);
+        let lit: Vec<(usize, usize)> = (0..SCREEN_HEIGHT)
+            .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| chip8.pixel(x, y))
+            .collect();
+        assert_eq!(lit, [(0, 30), (62, 30), (63, 30), (62, 31), (63, 31)]);
+        // the lit pixel past the edge wasn't drawn over, so no collision
+        assert_eq!(chip8.registers()[0xF], 0);
+    }
+
+    #[test]
+    fn sprites_wrap_with_the_quirk() {
+        let chip8 = draw_at_corner(true);
+        for y in [30, 31, 0, 1] {
+            for x in [62, 63, 0, 1, 2, 3, 4, 5] {
+                // (0, 30) was lit before, the sprite erased it
+                assert_eq!(chip8.pixel(x, y), (x, y) != (0, 30), "({x}, {y})");
+            }
+            assert!(!chip8.pixel(6, y) && !chip8.pixel(61, y));
+        }
+        assert!(!chip8.pixel(62, 2) && !chip8.pixel(62, 29));
+        assert_eq!(chip8.registers()[0xF], 1);
+    }
+
+    #[test]
+    fn add_index_overflow_flag() {
+        // I = 0xFFF, V1 = 1, ADD I, V1
+        for (index_overflow, extended) in [(false, false), (true, false), (true, true)] {
+            let mut chip8 = with_quirks(|quirks| quirks.index_overflow = index_overflow);
+            chip8.set_extended_memory(extended);
+            chip8.set_reg_i(0xFFF);
+            chip8.set_register(1, 1);
+            chip8.set_register(0xF, 0x55);
+            chip8.execute(0xF11E).unwrap();
+            let case = format!("quirk {index_overflow}, 64K {extended}");
+            // masked to 12 bits in 4K, 16 bits in XO-CHIP's 64K
+            assert_eq!(
+                chip8.reg_i(),
+                if extended { 0x1000 } else { 0x000 },
+                "{case}"
+            );
+            let flag = if index_overflow { 1 } else { 0x55 };
+            assert_eq!(chip8.registers()[0xF], flag, "{case}");
+
+            // no overflow clears the flag with the quirk
+            chip8.set_reg_i(0xFFE);
+            chip8.execute(0xF11E).unwrap();
+            assert_eq!(chip8.reg_i(), 0xFFF);
+            let flag = if index_overflow { 0 } else { 0x55 };
+            assert_eq!(chip8.registers()[0xF], flag, "{case}");
+        }
+
+        // past 0xFFFF it wraps in 64K as well
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_extended_memory(true);
+        chip8.set_reg_i(0xFFFF);
+        chip8.set_register(1, 2);
+        chip8.execute(0xF11E).unwrap();
+        assert_eq!(chip8.reg_i(), 0x0001);
+    }
+
+    #[test]
+    fn recursive_call_overflows_after_16_pushes() {
+        // CALL 0x200, calling itself forever
+        let mut chip8 = machine(&[0x22, 0x00], false);
+        run(&mut chip8, STACK_SIZE);
+        assert_eq!(chip8.stack(), &[0x202; STACK_SIZE]);
+        assert_eq!(
+            chip8.step().unwrap_err(),
+            Chip8Error::StackOverflow { pc: 0x200 }
+        );
+        assert_eq!(chip8.stack().len(), STACK_SIZE);
+
+        let mut chip8 = machine(&[0x22, 0x00], false);
+        chip8.set_stack_limit(3);
+        run(&mut chip8, 3);
+        assert!(matches!(
+            chip8.step(),
+            Err(Chip8Error::StackOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn return_with_empty_stack() {
+        let (mut lenient, mut strict) = lenient_and_strict(&[0x00, 0xEE], &[]);
+        assert_eq!(lenient.step().unwrap_err(), Chip8Error::StackUnderflow);
+        assert_eq!(strict.step().unwrap_err(), Chip8Error::StackUnderflow);
+    }
+
+    // The same ROM, with `pokes` written after loading, once lenient and once strict
+    fn lenient_and_strict(rom: &[u8], pokes: &[(usize, u8)]) -> (Chip8, Chip8) {
+        let mut machines = [machine(rom, false), machine(rom, false)];
+        for chip8 in &mut machines {
+            for &(addr, val) in pokes {
+                chip8.poke(addr, val).unwrap();
+            }
+        }
+        machines[1].set_strict_control_flow(true);
+        let [lenient, strict] = machines;
+        return (lenient, strict);
+    }
+
+    #[test]
+    fn call_in_the_last_word() {
+        // JP 0xFFE; CALL 0x300 there; RET at 0x300
+        let pokes = [(0xFFE, 0x23), (0xFFF, 0x00), (0x300, 0x00), (0x301, 0xEE)];
+        let (mut lenient, mut strict) = lenient_and_strict(&[0x1F, 0xFE], &pokes);
+        run(&mut lenient, 2);
+        assert_eq!(lenient.stack(), [0x000]);
+        run(&mut lenient, 1);
+        assert_eq!(lenient.pc(), 0x000);
+
+        run(&mut strict, 1);
+        assert_eq!(
+            strict.step().unwrap_err(),
+            Chip8Error::PcOutOfBounds { pc: 0x1000 }
+        );
+        assert!(strict.stack().is_empty());
+    }
+
+    #[test]
+    fn odd_jump_targets() {
+        // JP 0x203 / CALL 0x203, then LD V0, 0x42 straddling 0x203-0x204
+        for jump in [0x12, 0x22] {
+            let rom = [jump, 0x03, 0x00, 0x60, 0x42];
+            let (mut lenient, mut strict) = lenient_and_strict(&rom, &[]);
+            run(&mut lenient, 2);
+            assert_eq!(lenient.registers()[0], 0x42);
+            assert_eq!(lenient.pc(), 0x205);
+
+            assert_eq!(
+                strict.step().unwrap_err(),
+                Chip8Error::MisalignedJump {
+                    addr: 0x203,
+                    pc: 0x200
+                }
+            );
+        }
+        // BNNN lands on odd addresses too: V0 = 1; JP V0, 0x204; LD V1, 0x42 at 0x205
+        let rom = [0x60, 0x01, 0xB2, 0x04, 0x00, 0x61, 0x42];
+        let (mut lenient, mut strict) = lenient_and_strict(&rom, &[]);
+        run(&mut lenient, 3);
+        assert_eq!(lenient.registers()[1], 0x42);
+        run(&mut strict, 1);
+        assert_eq!(
+            strict.step().unwrap_err(),
+            Chip8Error::MisalignedJump {
+                addr: 0x205,
+                pc: 0x202
+            }
+        );
+    }
+
+    #[test]
+    fn running_off_the_end() {
+        // JP 0xFFE; LD V0, 5 there
+        let pokes = [(0xFFE, 0x60), (0xFFF, 0x05)];
+        let (mut lenient, mut strict) = lenient_and_strict(&[0x1F, 0xFE], &pokes);
+        run(&mut lenient, 2);
+        assert_eq!(lenient.pc(), 0x000);
+
+        run(&mut strict, 2);
+        assert_eq!(strict.registers()[0], 5);
+        assert_eq!(
+            strict.step().unwrap_err(),
+            Chip8Error::PcOutOfBounds { pc: 0x1000 }
+        );
+    }
+
+    #[test]
+    fn machine_code_routines() {
+        // SYS 0x123; LD V1, 1
+        let rom = [0x01, 0x23, 0x61, 0x01];
+        let mut chip8 = machine(&rom, false);
+        let error = chip8.step().unwrap_err();
+        assert_eq!(error, Chip8Error::MachineCodeRoutine { addr: 0x123 });
+        assert!(error.to_string().contains("0x123"), "{error}");
+
+        // --ignore-sys skips it and carries on
+        let mut chip8 = machine(&rom, false);
+        chip8.set_ignore_sys(true);
+        assert!(chip8.step().unwrap().sys_ignored);
+        assert!(!chip8.step().unwrap().sys_ignored);
+        assert_eq!(chip8.registers()[1], 1);
+    }
+
+    #[test]
+    fn eti_660_load_address() {
+        // LD V0, 7; JP 0x602
+        let rom = [0x60, 0x07, 0x16, 0x02];
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_load_addr(0x600).unwrap();
+        assert_eq!(chip8.pc(), 0x600);
+        assert_eq!(chip8.max_rom_size(), MEM_SIZE - 0x600);
+        chip8.load_rom(&rom).unwrap();
+        assert_eq!(chip8.mem()[0x600..0x604], rom);
+        assert_eq!(chip8.mem()[0x200], 0);
+        run(&mut chip8, 2);
+        assert_eq!(chip8.registers()[0], 7);
+        assert_eq!(chip8.pc(), 0x602);
+
+        // reset goes back to 0x600, and so does a saved state
+        let saved = chip8.save_state();
+        chip8.reset();
+        assert_eq!(chip8.pc(), 0x600);
+        let mut other = Chip8::with_seed(0);
+        other.load_state(&saved).unwrap();
+        assert_eq!(other.load_addr(), 0x600);
+
+        let too_big = vec![0; MEM_SIZE - 0x600 + 2];
+        assert!(matches!(
+            chip8.load_rom(&too_big),
+            Err(RomError::TooLarge { .. })
+        ));
+        for addr in [0x601, 0x1000] {
+            assert!(matches!(
+                chip8.set_load_addr(addr),
+                Err(RomError::BadLoadAddress { .. })
+            ));
+        }
+        assert_eq!(chip8.load_addr(), 0x600);
+    }
+
+    #[test]
+    fn skip_covers_long_load() {
+        // SE V0, 0 over F000 NNNN lands on LD V1, 1 in either memory size
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x61, 0x01];
+        for extended in [false, true] {
+            let mut chip8 = machine(&rom, extended);
+            run(&mut chip8, 1);
+            assert_eq!(chip8.pc(), 0x206, "extended {extended}");
+            run(&mut chip8, 1);
+            assert_eq!(chip8.registers()[1], 1);
+            assert_eq!(chip8.reg_i(), 0);
+        }
+    }
+
+    #[test]
+    fn long_load_in_both_memory_sizes() {
+        let rom = [0xF0, 0x00, 0xAB, 0xCD];
+        let mut chip8 = machine(&rom, true);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.mem().len(), EXTENDED_MEM_SIZE);
+        assert_eq!(chip8.reg_i(), 0xABCD);
+        assert_eq!(chip8.pc(), 0x204);
+
+        let mut chip8 = machine(&rom, false);
+        run(&mut chip8, 1);
+        assert_eq!(chip8.mem().len(), MEM_SIZE);
+        assert_eq!(chip8.reg_i(), 0xBCD);
+        assert_eq!(chip8.pc(), 0x204);
+    }
+
+    #[test]
+    fn pc_wraps_only_in_4k() {
+        // JP 0xFFE, LD V0, 5 there
+        let mut chip8 = machine(&[0x1F, 0xFE], false);
+        chip8.poke(0xFFE, 0x60).unwrap();
+        chip8.poke(0xFFF, 0x05).unwrap();
+        run(&mut chip8, 2);
+        assert_eq!(chip8.registers()[0], 5);
+        assert_eq!(chip8.pc(), 0);
+
+        let mut chip8 = machine(&[0x1F, 0xFE], true);
+        chip8.poke(0xFFE, 0x60).unwrap();
+        chip8.poke(0xFFF, 0x05).unwrap();
+        run(&mut chip8, 2);
+        assert_eq!(chip8.pc(), 0x1000);
+    }
+
+    #[test]
+    fn index_wraps_only_in_4k() {
+        // LD I, 0xFFF, LD V0, 2, ADD I, V0
+        let rom = [0xAF, 0xFF, 0x60, 0x02, 0xF0, 0x1E];
+        let mut chip8 = machine(&rom, false);
+        run(&mut chip8, 3);
+        assert_eq!(chip8.reg_i(), 0x001);
+
+        let mut chip8 = machine(&rom, true);
+        run(&mut chip8, 3);
+        assert_eq!(chip8.reg_i(), 0x1001);
+    }
+
+    #[test]
+    fn bcd_digits_rebuild_every_value() {
+        // LD I, 0x300, LD B, V0
+        for val in 0..=255u8 {
+            let mut chip8 = machine(&[0xA3, 0x00, 0xF0, 0x33], false);
+            chip8.set_register(0, val);
+            run(&mut chip8, 2);
+            let digits = chip8.mem_range(0x300, 3).unwrap();
+            assert!(digits.iter().all(|&digit| digit < 10), "{val}: {digits:?}");
+            let rebuilt = digits[0] as u32 * 100 + digits[1] as u32 * 10 + digits[2] as u32;
+            assert_eq!(rebuilt, val as u32);
+        }
+    }
+
+    #[test]
+    fn bcd_at_end_of_memory() {
+        // LD I, 0xFFE, LD B, V0 with V0 = 123: the last digit wraps to 0x000
+        let rom = [0xAF, 0xFE, 0xF0, 0x33];
+        let mut chip8 = machine(&rom, false);
+        chip8.set_register(0, 123);
+        run(&mut chip8, 2);
+        assert_eq!(chip8.mem()[0xFFE..], [1, 2]);
+        assert_eq!(chip8.mem()[0], 3);
+        assert_eq!(chip8.reg_i(), 0xFFE);
+
+        // strict memory fails before writing any digit
+        let mut chip8 = machine(&rom, false);
+        chip8.set_strict_memory(true);
+        chip8.set_register(0, 123);
+        run(&mut chip8, 1);
+        let font = chip8.mem()[0];
+        assert_eq!(
+            chip8.step().unwrap_err(),
+            Chip8Error::MemoryOutOfBounds { addr: 0x1000 }
+        );
+        assert_eq!(chip8.mem()[0xFFE..], [0, 0]);
+        assert_eq!(chip8.mem()[0], font);
+    }
+
+    #[test]
+    fn display_views_agree_at_every_resolution() {
+        for size in [(64, 32), (128, 64), (64, 64)] {
+            let mut chip8 = Chip8::with_seed(0);
+            match size {
+                (128, 64) => chip8.execute(0x00FF).unwrap(),
+                (64, 64) => chip8.set_vip_hires(true),
+                _ => {}
+            }
+            // random sprites at random places, some clipped at the edges
+            let mut seed = 0x2545_F491_4F6C_DD1Du64;
+            let mut random = move || {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                return seed as u8;
+            };
+            for addr in 0x300..0x400 {
+                chip8.poke(addr, random()).unwrap();
+            }
+            for _ in 0..40 {
+                chip8.set_reg_i(0x300 + random() as u16);
+                chip8.set_register(0, random());
+                chip8.set_register(1, random());
+                chip8.execute(0xD01F).unwrap();
+            }
+
+            let (width, height) = size;
+            assert_eq!(chip8.display_size(), size);
+            let packed = chip8.display_packed();
+            assert_eq!(packed.len(), width * height / 8);
+            assert_eq!(chip8.display_rows().count(), height);
+            let mut lit = 0;
+            for (y, row) in chip8.display_rows().enumerate() {
+                assert_eq!(row.len(), width);
+                for x in 0..width {
+                    let bit = packed[y * width / 8 + x / 8] & (0x80 >> (x % 8)) != 0;
+                    assert_eq!(chip8.pixel(x, y), row[x] != 0, "{size:?} {x},{y}");
+                    assert_eq!(bit, row[x] != 0, "{size:?} {x},{y}");
+                    assert_eq!(chip8.display()[y][x], row[x]);
+                    lit += bit as usize;
+                }
+                assert!(!chip8.pixel(width, y));
+            }
+            assert!(lit > 0 && lit < width * height, "{size:?}");
+            assert!(!chip8.pixel(0, height) && !chip8.pixel(usize::MAX, usize::MAX));
+        }
+    }
+
+    // FX18 with V0 = `value`, which the tests run between ticks
+    fn set_sound(chip8: &mut Chip8, value: u8) {
+        chip8.set_register(0, value);
+        chip8.execute(0xF018).unwrap();
+    }
+
+    fn ticks(chip8: &mut Chip8, count: usize) -> Vec<(bool, bool)> {
+        return (0..count)
+            .map(|_| {
+                let events = chip8.tick_60hz();
+                return (events.sound_started, events.sound_stopped);
+            })
+            .collect();
+    }
+
+    #[test]
+    fn sound_starts_and_stops_once() {
+        let mut chip8 = Chip8::with_seed(0);
+        set_sound(&mut chip8, 3);
+        let expected = [(true, false), (false, false), (false, true), (false, false)];
+        assert_eq!(ticks(&mut chip8, 4), expected);
+
+        // a timer of 1 starts and stops on the same tick
+        set_sound(&mut chip8, 1);
+        assert_eq!(ticks(&mut chip8, 2), [(true, true), (false, false)]);
+
+        // setting it again while it sounds is still the same beep
+        set_sound(&mut chip8, 5);
+        assert_eq!(ticks(&mut chip8, 1), [(true, false)]);
+        set_sound(&mut chip8, 5);
+        assert_eq!(ticks(&mut chip8, 2), [(false, false); 2]);
+        // and FX18 with 0 cuts it off
+        set_sound(&mut chip8, 0);
+        assert_eq!(ticks(&mut chip8, 2), [(false, true), (false, false)]);
+        set_sound(&mut chip8, 0);
+        assert_eq!(ticks(&mut chip8, 1), [(false, false)]);
+    }
+
+    #[test]
+    fn delay_expires_once() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_register(0, 2);
+        chip8.execute(0xF015).unwrap();
+        let expired: Vec<bool> = (0..4).map(|_| chip8.tick_60hz().delay_expired).collect();
+        assert_eq!(expired, [false, true, false, false]);
+        assert_eq!(chip8.delay_timer(), 0);
+    }
+
+    #[test]
+    fn run_frame_ticks_once() {
+        // LD V0, 3; LD ST, V0; LD DT, V0; JP 0x206
+        let mut chip8 = machine(&[0x60, 0x03, 0xF0, 0x18, 0xF0, 0x15, 0x12, 0x06], false);
+        let keys = [false; 16];
+        let mut events = Vec::new();
+        for _ in 0..4 {
+            let frame = chip8.run_frame(1000, &keys);
+            events.push(frame.timers);
+        }
+        assert_eq!(chip8.delay_timer(), 0);
+        assert!(events[0].sound_started && !events[0].delay_expired);
+        assert!(events[2].sound_stopped && events[2].delay_expired);
+        let stopped = events.iter().filter(|events| events.sound_stopped).count();
+        assert_eq!(stopped, 1);
+        assert_eq!(events[3], TimerEvents::default());
+    }
+
+    #[test]
+    fn run_frame_with_stops_for_the_debugger() {
+        // LD V0, 10; LD F, V0; DRW V0, V1, 5; DRW V0, V1, 5; JP 0x208
+        let rom = [0x60, 0x0A, 0xF0, 0x29, 0xD0, 0x15, 0xD0, 0x15, 0x12, 0x08];
+        let debug = |resume_at| FrameOptions {
+            debug: true,
+            resume_at,
+            ..FrameOptions::default()
+        };
+        let mut chip8 = machine(&rom, false);
+        chip8.set_breakpoint(0x204);
+        let frame = chip8.run_frame_with(1000, debug(None));
+        assert_eq!(frame.stop, Some(FrameStop::Breakpoint(0x204)));
+        assert_eq!((frame.instructions, frame.draws), (2, 0));
+        assert_eq!(chip8.run_frame_with(1000, debug(None)).instructions, 0);
+        // resumed past it, a draw per frame with the display wait and the second one erases
+        // the first
+        let frame = chip8.run_frame_with(1000, debug(Some(0x204)));
+        assert_eq!(frame.stop, None);
+        assert_eq!((frame.draws, frame.draw_vf), (1, Some(0)));
+        assert!(frame.collisions.is_empty());
+        let frame = chip8.run_frame_with(1000, debug(None));
+        assert_eq!((frame.draws, frame.draw_vf), (1, Some(1)));
+        assert_eq!(frame.collisions, chip8.last_collisions());
+        assert!(!frame.collisions.is_empty());
+        // without FrameOptions::debug breakpoints don't stop it
+        let mut chip8 = machine(&rom, false);
+        chip8.set_breakpoint(0x204);
+        assert_eq!(chip8.run_frame(1000, &[false; 16]).draws, 1);
+
+        // LD I, 0x300; LD V0, 123; LD B, V0; JP 0x206
+        let rom = [0xA3, 0x00, 0x60, 0x7B, 0xF0, 0x33, 0x12, 0x06];
+        let mut chip8 = machine(&rom, false);
+        chip8.watch_mem(0x300);
+        let frame = chip8.run_frame_with(1000, debug(None));
+        assert!(matches!(
+            frame.stop,
+            Some(FrameStop::Watch(WatchHit { pc: 0x204, .. }))
+        ));
+        assert_eq!(frame.instructions, 3);
+
+        let mut chip8 = machine(&rom, false);
+        let mut after_step = |_: &mut Chip8, run: u32| run == 2;
+        let frame = chip8.run_frame_with(
+            1000,
+            FrameOptions {
+                after_step: Some(&mut after_step),
+                ..FrameOptions::default()
+            },
+        );
+        assert_eq!(frame.stop, Some(FrameStop::Requested));
+        assert_eq!(frame.instructions, 2);
+    }
+
+    #[test]
+    fn run_frame_with_pays_back_the_debt() {
+        // LD V0, 3; LD DT, V0; JP 0x204
+        let mut chip8 = machine(&[0x60, 0x03, 0xF0, 0x15, 0x12, 0x04], false);
+        chip8.set_timing(Timing::Vip);
+        let untimed = |debt| FrameOptions {
+            skip_tick: true,
+            debt,
+            ..FrameOptions::default()
+        };
+        let first = chip8.run_frame_with(1, untimed(0));
+        assert_eq!(first.instructions, 1);
+        assert!(first.debt > 0);
+        assert_eq!(first.debt, first.cycles - 1);
+        let second = chip8.run_frame_with(first.debt, untimed(first.debt));
+        assert_eq!((second.instructions, second.debt), (0, 0));
+        chip8.run_frame_with(1000, untimed(0));
+        assert_eq!(chip8.delay_timer(), 3);
+    }
+
+    #[test]
+    fn rom_errors() {
+        let mut chip8 = Chip8::with_seed(0);
+        assert!(matches!(chip8.load_rom(&[]), Err(RomError::Empty)));
+        let err = chip8.load_rom(&[0x12; MAX_ROM_SIZE + 1]).unwrap_err();
+        assert!(matches!(
+            err,
+            RomError::TooLarge {
+                size: 3585,
+                max: 3584
+            }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "ROM is 3585 bytes, larger than the 3584 bytes available"
+        );
+        // a failed load doesn't touch memory
+        assert_eq!(chip8.mem()[0x200], 0);
+    }
+
+    #[test]
+    fn rom_of_exactly_the_maximum_size() {
+        let mut rom = vec![0x11; MAX_ROM_SIZE];
+        rom[MAX_ROM_SIZE - 1] = 0x22;
+        let chip8 = machine(&rom, false);
+        assert_eq!(chip8.mem()[0x200..], rom[..]);
+        assert_eq!(chip8.mem()[0xFFF], 0x22);
+    }
+
+    #[test]
+    fn odd_roms_are_padded() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&[0xFF; 6]).unwrap();
+        chip8.load_rom(&[0x60, 0x42, 0x61]).unwrap();
+        assert_eq!(chip8.mem()[0x200..0x205], [0x60, 0x42, 0x61, 0x00, 0xFF]);
+        run(&mut chip8, 2);
+        assert_eq!(chip8.registers()[..2], [0x42, 0x00]);
+        // at the very end there is nothing to pad
+        assert!(chip8.load_rom(&[0x12; MAX_ROM_SIZE - 1]).is_ok());
+    }
+
+    #[test]
+    fn rom_size_follows_memory_size() {
+        let rom = vec![0; MAX_ROM_SIZE + 2];
+        let mut chip8 = Chip8::with_seed(0);
+        assert!(matches!(
+            chip8.load_rom(&rom),
+            Err(RomError::TooLarge {
+                max: MAX_ROM_SIZE,
+                ..
+            })
+        ));
+        chip8.set_extended_memory(true);
+        assert!(chip8.load_rom(&rom).is_ok());
+    }
+}
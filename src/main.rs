@@ -1,337 +1,1975 @@
-#![allow(dead_code)]
-#![allow(unused_variables)]
+#![allow(clippy::needless_return)]
 
+mod frontend;
+
+use frontend::audio::Beeper;
+use frontend::bench;
+use frontend::cli::{FRAME_RATE, Options, USAGE};
+use frontend::collisions::CollisionFlash;
+use frontend::debugger::{self, Command, STEP_LIMIT, StepTarget};
+use frontend::headless;
+use frontend::input::Input;
+use frontend::keymap::{self, Keymap};
+use frontend::logging;
+use frontend::metadata;
+use frontend::overlay::{
+    CHEAT_KEYS, MEMORY_ROW_BYTES, draw_cheats, draw_collisions, draw_debug, draw_gauges,
+    draw_keypad, draw_memory, draw_message, draw_stats,
+};
+use frontend::picker::Picker;
+use frontend::remote::{self, RemoteCommand, Request};
+use frontend::rewind::{REWIND_FRAMES, Rewind};
+use frontend::settings::{self, Geometry, Settings};
+use frontend::shutdown;
+use frontend::slots::{EmulatorSlot, draw_split, split_areas};
+use frontend::viewport::Viewport;
+use frontend::wall::{TILE_HEIGHT, TILE_WIDTH, Tile, Wall};
+use frontend::window::{TITLE, Window, new_screen};
+use log::{error, info, warn};
 use raylib::ffi::TraceLogLevel::LOG_NONE;
 use raylib::prelude::*;
-use std::io::{Read, stdin};
-
-const SCREEN_WIDTH: i32 = 64;
-const SCREEN_HEIGHT: i32 = 32;
-const SQUARE_SIZE: i32 = 16;
-
-struct Chip8 {
-    mem: [u8; 4096],
-    pc: u16,
-    reg_i: u16,
-    stack: Vec<u16>,
-    registers: [u8; 16],
-    display: [[bool; SCREEN_WIDTH as usize]; SCREEN_HEIGHT as usize],
-    delay_timer: u8,
-    sound_timer: u8,
-}
-
-impl Chip8 {
-    fn new() -> Self {
-        Chip8 {
-            mem: [0; 4096],
-            pc: 0x200,
-            reg_i: 0,
-            stack: Vec::new(),
-            registers: [0; 16],
-            display: [[false; SCREEN_WIDTH as usize]; SCREEN_HEIGHT as usize],
-            delay_timer: 0,
-            sound_timer: 0,
-        }
-    }
-
-    fn fetch(&mut self) -> u16 {
-        let byte1 = self.mem[self.pc as usize];
-        let byte2 = self.mem[(self.pc + 1) as usize];
-        self.pc += 2;
-
-        return (byte1 as u16) << 8 | (byte2 as u16);
-    }
-
-    fn execute(&mut self, instruction: u16) {
-        let instruction = split_nibbles(instruction);
-
-        match instruction {
-            [0x0, 0x0, 0xE, 0x0] => self.clear_screen(),
-            [0x0, 0x0, 0xE, 0xE] => {
-                self.pc = self.stack.pop().expect("Tried to pop at empty stack!");
-            }
-            [0x1, nibb1, nibb2, nibb3] => {
-                let addr = conc_nibbles(&[nibb1, nibb2, nibb3]);
-                self.pc = addr;
-            }
-            [0x2, nibb1, nibb2, nibb3] => {
-                self.stack.push(self.pc);
-                let addr = conc_nibbles(&[nibb1, nibb2, nibb3]);
-                self.pc = addr;
-            }
-            [0x3, x, nibb1, nibb2] => {
-                let val = nibb1 << 4 | nibb2;
-                if self.registers[x as usize] == val {
-                    self.pc += 2
-                };
-            }
-            [0x4, x, nibb1, nibb2] => {
-                let val = nibb1 << 4 | nibb2;
-                if self.registers[x as usize] != val {
-                    self.pc += 2
-                };
-            }
-            [0x5, x, y, 0x0] => {
-                if self.registers[x as usize] == self.registers[y as usize] {
-                    self.pc += 2;
+use rustchip8::gif::GifRecorder;
+use rustchip8::host::Frontend;
+use rustchip8::screenshot::{self, Rgb};
+#[cfg(feature = "scripting")]
+use rustchip8::script::Script;
+use rustchip8::{
+    AfterStep, Chip8, CpuClock, CpuState, EXTENDED_MEM_SIZE, FileFlags, FrameOptions, FrameResult,
+    FrameStop, InputEvent, MAX_EXTENDED_ROM_SIZE, MEM_SIZE, Player, Recording, RomError, Symbols,
+    TimerClock, Tracer, disasm, parse_cheats, parse_symbols, time_seed,
+};
+use std::io::{BufWriter, ErrorKind, IsTerminal, Read, Write, stderr, stdin, stdout};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fs, mem, process};
+
+const SCREEN_WIDTH: i32 = rustchip8::SCREEN_WIDTH as i32;
+const SCREEN_HEIGHT: i32 = rustchip8::SCREEN_HEIGHT as i32;
+// how often the IPS and FPS figures are refreshed
+const STATS_INTERVAL: Duration = Duration::from_millis(500);
+// low-res pixels become 8x8 squares, hi-res ones 4x4
+const SCREENSHOT_SCALE: u32 = 8;
+// GIF pixels per high-res pixel, the same 512x256 as screenshots
+const GIF_SCALE: u32 = SCREENSHOT_SCALE / 2;
+// emulation speed while Shift is held
+const SLOW_MOTION: f64 = 0.25;
+// longest stretch of emulated time a single frame catches up on
+const MAX_FRAME_TIME: Duration = Duration::from_millis(100);
+// how long errors stay at the bottom of the window
+const MESSAGE_TIME: Duration = Duration::from_secs(4);
+// how long the keypad cheatsheet shows when a ROM starts
+const KEYPAD_HELP_TIME: Duration = Duration::from_secs(3);
+// files dropped on the window with any other extension are refused
+const ROM_EXTENSIONS: [&str; 6] = ["ch8", "c8", "sc8", "xo8", "rom", "bin"];
+
+// No path (or "-") keeps the old behavior of piping the ROM through stdin. A zip archive,
+// by its magic, gives the ROM inside, see unzip_rom().
+fn read_rom(path: Option<&str>, zip_member: Option<&str>) -> Result<Vec<u8>, String> {
+    match path {
+        None | Some("-") => {
+            let mut buffer = Vec::new();
+            stdin()
+                .read_to_end(&mut buffer)
+                .map_err(|e| format!("failed to read ROM from stdin: {e}"))?;
+            return unzip_rom(buffer, "stdin", zip_member);
+        }
+        Some(path) => {
+            let data = fs::read(path).map_err(|e| format!("failed to read ROM '{path}': {e}"))?;
+            return unzip_rom(data, path, zip_member);
+        }
+    }
+}
+
+// The member to load is `zip_member` (--zip-member) when given, otherwise the only file with
+// a ROM extension, otherwise the only file. Anything else lists what there is to choose from.
+#[cfg(feature = "zip")]
+fn unzip_rom(data: Vec<u8>, source: &str, zip_member: Option<&str>) -> Result<Vec<u8>, String> {
+    use rustchip8::zip::{self, ZipArchive};
+
+    if !zip::is_zip(&data) {
+        if let Some(name) = zip_member {
+            warn!("--zip-member {name} ignored, {source} is not a zip archive");
+        }
+        return Ok(data);
+    }
+    let mut archive = ZipArchive::parse(&data).map_err(|e| format!("{source}: {e}"))?;
+    let files: Vec<_> = archive
+        .entries()
+        .iter()
+        .filter(|entry| !entry.is_dir())
+        .collect();
+    let entry = match zip_member {
+        Some(name) => *files
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| format!("{source} has no member '{name}'"))?,
+        None => {
+            let roms: Vec<_> = files
+                .iter()
+                .copied()
+                .filter(|entry| {
+                    let extension = Path::new(&entry.name).extension();
+                    extension.is_some_and(|ext| {
+                        let ext = ext.to_string_lossy().to_ascii_lowercase();
+                        ROM_EXTENSIONS.contains(&ext.as_str())
+                    })
+                })
+                .collect();
+            match (roms.as_slice(), files.as_slice()) {
+                ([rom], _) | ([], [rom]) => *rom,
+                ([], []) => return Err(format!("{source} is an empty zip archive")),
+                _ => {
+                    let candidates = if roms.is_empty() { &files } else { &roms };
+                    let names: Vec<&str> =
+                        candidates.iter().map(|entry| entry.name.as_str()).collect();
+                    return Err(format!(
+                        "{source} holds several ROMs, pick one with --zip-member: {}",
+                        names.join(", ")
+                    ));
                 }
             }
-            [0x6, x, nibb1, nibb2] => {
-                let idx = x as usize;
-                let val = nibb1 << 4 | nibb2;
-                self.registers[idx] = val;
-            }
-            [0x7, x, nibb1, nibb2] => {
-                let idx = x as usize;
-                let val = nibb1 << 4 | nibb2;
-                self.registers[idx] += val;
+        }
+    };
+    let entry = entry.clone();
+    // a member that can't be loaded anyway isn't worth inflating
+    if entry.size > MAX_EXTENDED_ROM_SIZE {
+        return Err(format!(
+            "'{}' in {source} is too large ({} bytes, max {MAX_EXTENDED_ROM_SIZE})",
+            entry.name, entry.size
+        ));
+    }
+    let rom = archive.read(&entry).map_err(|e| format!("{source}: {e}"))?;
+    info!("Loading '{}' from {source}", entry.name);
+
+    return Ok(rom);
+}
+
+#[cfg(not(feature = "zip"))]
+fn unzip_rom(data: Vec<u8>, source: &str, _zip_member: Option<&str>) -> Result<Vec<u8>, String> {
+    if data.starts_with(b"PK\x03\x04") {
+        return Err(format!(
+            "{source} is a zip archive, this build has no zip support (the zip feature)"
+        ));
+    }
+    return Ok(data);
+}
+
+// `filtered` is the window's CRT picture when that filter is on, it is saved instead of
+// the plain display
+fn save_screenshot(
+    chip8: &Chip8,
+    palette: &[Rgb; 4],
+    filtered: Option<(usize, usize, &[u8])>,
+    path: &str,
+) {
+    let png = match filtered {
+        Some((width, height, rgba)) => {
+            let scale = SCREENSHOT_SCALE * rustchip8::SCREEN_WIDTH as u32 / width as u32;
+            screenshot::encode_rgba_png(width, height, rgba, scale.max(1))
+        }
+        None => {
+            let width = chip8.resolution().width() as u32;
+            screenshot::encode_png(
+                chip8,
+                SCREENSHOT_SCALE * rustchip8::SCREEN_WIDTH as u32 / width,
+                palette,
+            )
+        }
+    };
+    match fs::write(path, png) {
+        Ok(()) => info!("Saved screenshot to {path}"),
+        Err(e) => error!("failed to save screenshot to {path}: {e}"),
+    }
+}
+
+// rustchip8-000.png, rustchip8-001.png, ... skipping names that are already taken
+fn next_screenshot_path(counter: &mut u32) -> String {
+    loop {
+        let path = format!("rustchip8-{:03}.png", *counter);
+        *counter += 1;
+        if !Path::new(&path).exists() {
+            return path;
+        }
+    }
+}
+
+// YYYYMMDD-HHMMSS in UTC
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let (days, time) = (secs / 86400, secs % 86400);
+    // days since 1970-01-01 to a civil date (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    return format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    );
+}
+
+// <rom>-YYYYMMDD-HHMMSS.gif next to the ROM, in the working directory for stdin
+fn gif_path(rom_path: Option<&str>) -> String {
+    let stem = match rom_path {
+        None | Some("-") => String::from("rustchip8"),
+        Some(path) => {
+            let path = Path::new(path);
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            path.with_file_name(stem.as_ref())
+                .to_string_lossy()
+                .into_owned()
+        }
+    };
+    return format!("{stem}-{}.gif", timestamp());
+}
+
+fn create_gif(path: &str, palette: &[Rgb; 4]) -> Result<GifRecorder<Box<dyn Write>>, String> {
+    let file = fs::File::create(path).map_err(|e| format!("failed to create {path}: {e}"))?;
+    let out: Box<dyn Write> = Box::new(BufWriter::new(file));
+    return GifRecorder::new(out, GIF_SCALE, palette)
+        .map_err(|e| format!("failed to write {path}: {e}"));
+}
+
+fn finish_gif(gif: GifRecorder<Box<dyn Write>>, path: &str) {
+    let frames = gif.frames();
+    match gif.finish() {
+        Ok(_) => info!("Saved {frames} frames to {path}"),
+        Err(e) => error!("failed to write {path}: {e}"),
+    }
+}
+
+// Borderless fullscreen at the monitor's resolution. `windowed` remembers the window
+// position and size so switching back restores them.
+fn toggle_fullscreen(rl: &mut RaylibHandle, windowed: &mut Option<(Vector2, i32, i32)>) {
+    if rl.is_window_fullscreen() {
+        rl.toggle_fullscreen();
+        if let Some((position, width, height)) = windowed.take() {
+            rl.set_window_size(width, height);
+            rl.set_window_position(position.x as i32, position.y as i32);
+        }
+    } else {
+        *windowed = Some((
+            rl.get_window_position(),
+            rl.get_screen_width(),
+            rl.get_screen_height(),
+        ));
+        let monitor = get_current_monitor();
+        rl.set_window_size(get_monitor_width(monitor), get_monitor_height(monitor));
+        rl.toggle_fullscreen();
+    }
+}
+
+fn read_recording(path: &str) -> Result<Recording, String> {
+    let data = fs::read(path).map_err(|e| format!("failed to read recording {path}: {e}"))?;
+    return Recording::from_bytes(&data)
+        .map_err(|e| format!("failed to load recording {path}: {e}"));
+}
+
+fn read_keymap(path: &str) -> Result<Keymap, String> {
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("failed to read keymap '{path}': {e}"))?;
+    return keymap::parse(&text).map_err(|e| format!("keymap '{path}': {e}"));
+}
+
+// The key descriptions of a ROM other than the one the command line is for, a broken
+// sidecar only costs the cheatsheet its notes
+fn read_key_help(rom_path: &str) -> [Option<String>; 16] {
+    return metadata::read_key_help(rom_path).unwrap_or_else(|e| {
+        warn!("{e}");
+        return Default::default();
+    });
+}
+
+// The machine settings from the command line other than the quirks, which the --rom-b
+// machine can have its own of
+fn configure(chip8: &mut Chip8, options: &Options) -> Result<(), RomError> {
+    chip8.set_strict_memory(options.strict_memory);
+    chip8.set_strict_control_flow(options.strict_control_flow);
+    chip8.set_stack_limit(options.stack_size);
+    chip8.set_ignore_sys(options.ignore_sys);
+    chip8.set_timing(options.timing);
+    chip8.set_idle_skip(options.idle_skip);
+    for (key, mode) in options.key_modes.into_iter().enumerate() {
+        chip8.set_key_mode(key as u8, mode);
+    }
+    return chip8.set_load_addr(options.load_addr);
+}
+
+// Power-cycles the machine then loads `rom`, growing to the 64K XO-CHIP memory when it
+// doesn't fit in 4K and switching to VIP hi-res for ROMs made for it
+fn boot(chip8: &mut Chip8, rom: &[u8], options: &Options) -> Result<(), RomError> {
+    chip8.reset();
+    let fits = rom.len() <= MEM_SIZE - chip8.load_addr() as usize;
+    chip8.set_extended_memory(options.extended_memory || !fits);
+    chip8.set_vip_hires(options.vip_hires || rustchip8::is_vip_hires_rom(rom));
+    return chip8.load_rom(rom);
+}
+
+// A file dropped on the window or picked from the list. Anything that doesn't look like a
+// ROM is refused before the running game is touched.
+fn read_rom_file(path: &str) -> Result<Vec<u8>, String> {
+    let extension = Path::new(path)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+    if let Some(extension) = &extension
+        && !ROM_EXTENSIONS.contains(&extension.as_str())
+        && (extension != "zip" || cfg!(not(feature = "zip")))
+    {
+        return Err(format!("'.{extension}' files are not CHIP-8 ROMs"));
+    }
+
+    let data = fs::read(path).map_err(|e| format!("failed to read '{path}': {e}"))?;
+    // dropped archives have no --zip-member, one with several ROMs only gets listed
+    let rom = unzip_rom(data, path, None)?;
+    if rom.is_empty() {
+        return Err(format!("'{path}' is empty"));
+    }
+    if rom.len() > MAX_EXTENDED_ROM_SIZE {
+        return Err(format!(
+            "'{path}' is too large ({} bytes, max {MAX_EXTENDED_ROM_SIZE})",
+            rom.len()
+        ));
+    }
+
+    return Ok(rom);
+}
+
+// Saved next to the ROM, or in the working directory when it came through stdin
+fn state_path_for(rom_path: Option<&str>) -> String {
+    match rom_path {
+        None | Some("-") => return String::from("rustchip8.state"),
+        Some(path) => return format!("{path}.state"),
+    }
+}
+
+// The ROM's file name, for the window title
+fn display_name(rom_path: Option<&str>) -> Option<String> {
+    let path = rom_path.filter(|path| *path != "-")?;
+    return Some(Path::new(path).file_name()?.to_string_lossy().into_owned());
+}
+
+// <rom>.flags next to the ROM, like the save state
+fn flags_path_for(rom_path: Option<&str>) -> String {
+    match rom_path {
+        None | Some("-") => return String::from("rustchip8.flags"),
+        Some(path) => return format!("{path}.flags"),
+    }
+}
+
+fn attach_flags(chip8: &mut Chip8, rom_path: Option<&str>) {
+    let path = flags_path_for(rom_path);
+    if let Err(e) = chip8.set_flag_storage(Some(Box::new(FileFlags::new(&path)))) {
+        warn!("failed to load RPL flags from {path}, they won't be saved: {e}");
+    }
+}
+
+// --stats-out
+fn write_stats(chip8: &Chip8, path: &str) {
+    let Some(stats) = chip8.stats() else {
+        return;
+    };
+    match fs::write(path, stats.to_json()) {
+        Ok(()) => info!("Saved instruction stats to {path}"),
+        Err(e) => error!("failed to write {path}: {e}"),
+    }
+}
+
+// <rom>.cheats next to the ROM, there is none for a ROM from stdin
+fn cheats_path_for(rom_path: Option<&str>) -> Option<String> {
+    let path = rom_path.filter(|path| *path != "-")?;
+    return Some(format!("{path}.cheats"));
+}
+
+// Replaces the machine's cheats with the ones in `path` and writes their pokes. A missing
+// file only counts as an error when it was asked for with --cheats.
+fn load_cheats(chip8: &mut Chip8, path: &str, required: bool) -> Result<(), String> {
+    chip8.set_cheats(Vec::new());
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == ErrorKind::NotFound && !required => return Ok(()),
+        Err(e) => return Err(format!("failed to read cheats {path}: {e}")),
+    };
+    let cheats = parse_cheats(&text, chip8.mem().len()).map_err(|e| format!("{path}: {e}"))?;
+    info!("Loaded {} cheats from {path}, F6 lists them", cheats.len());
+    chip8.set_cheats(cheats);
+    chip8.apply_pokes();
+
+    return Ok(());
+}
+
+// --symbols, none without it
+fn load_symbols(path: Option<&str>) -> Result<Symbols, String> {
+    let Some(path) = path else {
+        return Ok(Symbols::new());
+    };
+    let text =
+        fs::read_to_string(path).map_err(|e| format!("failed to read symbols {path}: {e}"))?;
+    let symbols = parse_symbols(&text).map_err(|e| format!("{path}: {e}"))?;
+    info!("Loaded {} symbols from {path}", symbols.len());
+
+    return Ok(symbols);
+}
+
+// --expect-screen, exits with the differing rows when the display doesn't match the golden
+fn check_screen(chip8: &Chip8, path: &str) {
+    match headless::check_golden(chip8, path) {
+        Ok(true) => info!("Updated {path}"),
+        Ok(false) => {}
+        Err(e) => {
+            error!("{e}");
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "scripting")]
+fn load_script(path: &str, chip8: &mut Chip8, budget: Duration) -> Result<Script, String> {
+    let source =
+        fs::read_to_string(path).map_err(|e| format!("failed to read script {path}: {e}"))?;
+    let mut script = Script::load(&source, chip8).map_err(|e| format!("{path}: {e}"))?;
+    script.set_budget(budget);
+    return Ok(script);
+}
+
+fn load_state(chip8: &mut Chip8, path: &str) -> Result<(), String> {
+    let data = fs::read(path).map_err(|e| format!("failed to read state {path}: {e}"))?;
+    let loaded = chip8
+        .load_state(&data)
+        .map_err(|e| format!("failed to load state {path}: {e}"))?;
+    if loaded.rom_mismatch {
+        warn!("{path} was saved from a different ROM");
+    }
+
+    return Ok(());
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let mut options = Options::parse(&args).unwrap_or_else(|e| {
+        eprintln!("ERROR: {e}");
+        eprintln!("{USAGE}");
+        process::exit(1);
+    });
+    if let Err(e) = logging::init(options.log_level.as_deref(), options.log_file.as_deref()) {
+        eprintln!("ERROR: {e}");
+        process::exit(1);
+    }
+    if let Some((input, output)) = &options.assemble {
+        let source = fs::read_to_string(input).unwrap_or_else(|e| {
+            error!("failed to read {input}: {e}");
+            process::exit(1);
+        });
+        let rom = rustchip8::assemble(&source, options.load_addr).unwrap_or_else(|e| {
+            error!("{input}: {e}");
+            process::exit(1);
+        });
+        if let Err(e) = fs::write(output, &rom) {
+            error!("failed to write {output}: {e}");
+            process::exit(1);
+        }
+        info!("Assembled {} bytes into {output}", rom.len());
+        return;
+    }
+    // The settings file goes in front of the command line too, only for runs with the
+    // window: headless runs and golden tests don't change with what a session left behind
+    let interactive = options.headless.is_none()
+        && !options.bench
+        && !options.disassemble
+        && !options.validate
+        && !options.write_metadata;
+    let config_path = if options.no_config || !interactive {
+        None
+    } else {
+        options
+            .config
+            .clone()
+            .map(PathBuf::from)
+            .or_else(settings::default_path)
+    };
+    let mut args = args;
+    if let Some(path) = &config_path
+        && path.exists()
+    {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                let (mut config_args, warnings) = settings::to_args(&text);
+                for warning in warnings {
+                    warn!("{}: {warning}, using the default", path.display());
+                }
+                config_args.extend(args);
+                args = config_args;
+                options = Options::parse(&args).unwrap_or_else(|e| {
+                    error!("{}: {e}", path.display());
+                    process::exit(1);
+                });
             }
-            [0x8, x, y, 0x0] => {
-                self.registers[x as usize] = self.registers[y as usize];
+            Err(e) => warn!("failed to read {}, using the defaults: {e}", path.display()),
+        }
+    }
+    // what is saved on exit, before the ROM's own settings are added
+    let saved_settings = Settings::from_options(&options);
+    // <rom>.json settings go in front of the command line so explicit flags still win
+    let metadata_path = options
+        .rom_path
+        .as_deref()
+        .filter(|path| *path != "-")
+        .map(|path| format!("{path}.json"));
+    if let Some(path) = &metadata_path
+        && Path::new(path).exists()
+    {
+        let metadata = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| metadata::to_args(&text));
+        let (mut metadata_args, names) = metadata.unwrap_or_else(|e| {
+            error!("{path}: {e}");
+            process::exit(1);
+        });
+        metadata_args.extend(args.iter().cloned());
+        options = Options::parse(&metadata_args).unwrap_or_else(|e| {
+            error!("{path}: {e}");
+            process::exit(1);
+        });
+        info!("Using {} from {path}", names.join(", "));
+    }
+    // --keymap wins, then a per-game <rom>.keymap next to the ROM, then --layout
+    let keymap_path = options.keymap_file.clone().or_else(|| {
+        let path = format!("{}.keymap", options.rom_path.as_deref()?);
+        return Path::new(&path).exists().then_some(path);
+    });
+    let keymap = match &keymap_path {
+        Some(path) => read_keymap(path).unwrap_or_else(|e| {
+            error!("{e}");
+            process::exit(1);
+        }),
+        None => options.keymap,
+    };
+    if options.write_metadata {
+        let Some(path) = &metadata_path else {
+            error!("--write-metadata needs a ROM file");
+            process::exit(1);
+        };
+        if let Err(e) = fs::write(path, metadata::from_options(&options, &keymap)) {
+            error!("failed to write {path}: {e}");
+            process::exit(1);
+        }
+        info!("Saved settings to {path}");
+        return;
+    }
+
+    if let Some(dir) = &options.wall {
+        run_wall(&options, keymap, dir);
+        return;
+    }
+
+    // Started without a ROM and nothing piped in (double-clicked): pick one in the window
+    let picker_mode = options.rom_path.is_none()
+        && stdin().is_terminal()
+        && options.headless.is_none()
+        && !options.bench
+        && !options.disassemble
+        && !options.validate
+        && options.record.is_none()
+        && options.replay.is_none();
+    let rom = if picker_mode {
+        Vec::new()
+    } else if options.bench && options.rom_path.is_none() && stdin().is_terminal() {
+        bench::synthetic_rom(options.load_addr)
+    } else {
+        read_rom(options.rom_path.as_deref(), options.zip_member.as_deref()).unwrap_or_else(|e| {
+            error!("{e}");
+            process::exit(1);
+        })
+    };
+
+    // a name --break doesn't know stops the run before it starts
+    let symbols = load_symbols(options.symbols.as_deref()).unwrap_or_else(|e| {
+        error!("{e}");
+        process::exit(1);
+    });
+    let breakpoints: Vec<u16> = options
+        .breakpoints
+        .iter()
+        .map(|location| debugger::parse_location(location, &symbols))
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|e| {
+            error!("--break: {e}");
+            process::exit(1);
+        });
+
+    if options.disassemble {
+        print!(
+            "{}",
+            disasm::disassemble_rom_with(&rom, options.load_addr, &symbols)
+        );
+        return;
+    }
+    // nonzero only for a ROM that can't be loaded as it is
+    if options.validate {
+        let fits = rom.len() <= MEM_SIZE.saturating_sub(options.load_addr as usize);
+        let mem_size = if options.extended_memory || !fits {
+            EXTENDED_MEM_SIZE
+        } else {
+            MEM_SIZE
+        };
+        let validation = rustchip8::validate_rom(&rom, options.load_addr, mem_size);
+        if options.json {
+            print!("{}", validation.to_json());
+        } else {
+            let name = options.rom_path.as_deref().unwrap_or("-");
+            println!("{name}: {validation}");
+        }
+        if validation.has_errors() {
+            process::exit(1);
+        }
+        return;
+    }
+
+    let replay = options.replay.as_deref().map(|path| {
+        return read_recording(path).unwrap_or_else(|e| {
+            error!("{e}");
+            process::exit(1);
+        });
+    });
+    // a replay only comes out the same with the recorded CXNN seed
+    let seed = replay
+        .as_ref()
+        .map_or_else(time_seed, |recording| recording.seed);
+
+    let mut chip8 = Chip8::with_seed(seed);
+    chip8.set_quirks(options.quirks);
+    chip8.set_stats(options.stats);
+    if let Err(e) = configure(&mut chip8, &options) {
+        error!("{e}");
+        process::exit(1);
+    }
+    // the list has no ROM to boot yet
+    if !picker_mode && let Err(e) = boot(&mut chip8, &rom, &options) {
+        error!("{e}");
+        process::exit(1);
+    }
+
+    // from here on Ctrl+C lets the run wrap up, see frontend::shutdown
+    shutdown::catch_interrupt();
+    if options.bench {
+        let duration = Duration::from_secs_f64(options.bench_time);
+        match bench::run(&mut chip8, &rom, options.cpu_hz, duration) {
+            Ok(report) => print!("{report}"),
+            Err(e) => {
+                error!("{e}");
+                process::exit(1);
             }
-            [0x8, x, y, 0x1] => {
-                self.registers[x as usize] |= self.registers[y as usize];
+        }
+        if shutdown::interrupted() {
+            process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+        }
+        return;
+    }
+
+    if let Some(Err(e)) = replay.as_ref().map(|recording| recording.check(&chip8)) {
+        error!(
+            "cannot replay {}: {e}",
+            options.replay.as_deref().unwrap_or("")
+        );
+        process::exit(1);
+    }
+    let player = replay.map(Player::new);
+    let recording = options
+        .record
+        .as_ref()
+        .map(|_| Recording::new(&chip8, seed));
+
+    chip8.set_symbols(symbols);
+    for addr in breakpoints {
+        chip8.set_breakpoint(addr);
+    }
+    for target in &options.watches {
+        debugger::watch(&mut chip8, *target, true);
+    }
+
+    if options.trace {
+        let out: Box<dyn Write> = match &options.trace_file {
+            Some(path) => match fs::File::create(path) {
+                Ok(file) => Box::new(BufWriter::new(file)),
+                Err(e) => {
+                    error!("failed to create trace file {path}: {e}");
+                    process::exit(1);
+                }
+            },
+            None => Box::new(BufWriter::new(stderr())),
+        };
+        let tracer = Tracer::new(out, options.trace_filter.clone(), options.trace_after);
+        chip8.set_tracer(Some(tracer));
+    }
+
+    if let Some(max_cycles) = options.headless {
+        let mut runner = headless::Runner::new(options.cpu_hz, player);
+        runner.set_frame_limit(options.gif_frames);
+        #[cfg(feature = "scripting")]
+        if let Some(path) = &options.script {
+            match load_script(path, &mut chip8, options.script_budget) {
+                Ok(script) => runner.set_script(script),
+                Err(e) => {
+                    error!("{e}");
+                    process::exit(1);
+                }
             }
-            [0x8, x, y, 0x2] => {
-                self.registers[x as usize] &= self.registers[y as usize];
+        }
+        if let Some(path) = &options.record_gif {
+            match create_gif(path, &options.palette) {
+                Ok(gif) => runner.set_gif(gif),
+                Err(e) => {
+                    error!("{e}");
+                    process::exit(1);
+                }
             }
-            [0x8, x, y, 0x3] => {
-                self.registers[x as usize] ^= self.registers[y as usize];
+        }
+        if options.debug {
+            if let Err(e) = debugger::repl(
+                &mut chip8,
+                &mut runner,
+                max_cycles,
+                stdin().lock(),
+                &mut stdout(),
+            ) {
+                error!("{e}");
+                process::exit(1);
             }
-            [0x8, x, y, 0x4] => {
-                let x_val = self.registers[x as usize] as u16;
-                let y_val = self.registers[y as usize] as u16;
-                let sum = x_val + y_val;
-                if sum > 255 {
-                    self.registers[0xF] = 1
-                };
-                self.registers[x as usize] += self.registers[y as usize];
+            chip8.set_tracer(None);
+            return;
+        }
+        let result = match &options.screenshot_after {
+            Some((after, path)) if *after <= max_cycles => {
+                runner.run_until(&mut chip8, *after).and_then(|()| {
+                    save_screenshot(&chip8, &options.palette, None, path);
+                    return runner.run_until(&mut chip8, max_cycles);
+                })
             }
-            [0x8, x, y, 0x5] => {
-                self.registers[x as usize] -= self.registers[y as usize];
+            _ => runner.run_until(&mut chip8, max_cycles),
+        };
+        let interrupted = shutdown::interrupted();
+        if interrupted {
+            info!("Interrupted after {} instructions", runner.cycles());
+        }
+        print!("{}", headless::dump(&chip8));
+        if let (Some(mut gif), Some(path)) = (runner.take_gif(), &options.record_gif) {
+            // a program that stopped early keeps showing its last picture
+            let mut padding = Ok(());
+            while let Some(frames) = options.gif_frames
+                && gif.frames() < frames
+                && padding.is_ok()
+            {
+                padding = gif.push_frame(&chip8);
             }
-            [0x8, x, y, 0x6] => {
-                //NOTE: ambigious!
-                todo!();
+            finish_gif(gif, path);
+        }
+        // flush the trace before exiting
+        chip8.set_tracer(None);
+        if let Some(path) = &options.stats_out {
+            write_stats(&chip8, path);
+        }
+        if let Err(e) = result {
+            error!("{e}");
+            process::exit(1);
+        }
+        // a run cut short can't be held to the golden
+        if interrupted {
+            process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+        }
+        if let Some(path) = &options.expect_screen {
+            check_screen(&chip8, path);
+        }
+        return;
+    }
+
+    set_trace_log(LOG_NONE);
+
+    let (width, height) = options
+        .window_size
+        .unwrap_or((SCREEN_WIDTH * options.scale, SCREEN_HEIGHT * options.scale));
+    let (mut rl, thread) = raylib::init()
+        .size(width, height)
+        .title(TITLE)
+        .resizable()
+        .build();
+    rl.set_target_fps(FRAME_RATE);
+    if let Some((x, y)) = options.window_pos {
+        rl.set_window_position(x, y);
+    }
+
+    let mut windowed = None;
+    if options.fullscreen {
+        toggle_fullscreen(&mut rl, &mut windowed);
+    }
+
+    let rom_name = options
+        .title
+        .clone()
+        .or_else(|| display_name(options.rom_path.as_deref()));
+    let mut slot = EmulatorSlot::new(
+        'A',
+        chip8,
+        rom,
+        options.rom_path.clone(),
+        state_path_for(options.rom_path.as_deref()),
+        rom_name,
+    );
+    slot.key_help = options.key_help.clone();
+    // recordings and replays start from cleared flags so they come out the same every time
+    let persist_flags = recording.is_none() && player.is_none();
+    if persist_flags && !picker_mode {
+        attach_flags(&mut slot.chip8, options.rom_path.as_deref());
+    }
+    // and without cheats
+    let cheats_path = options
+        .cheats_file
+        .clone()
+        .or_else(|| cheats_path_for(options.rom_path.as_deref()));
+    if persist_flags
+        && let Some(path) = &cheats_path
+        && let Err(e) = load_cheats(&mut slot.chip8, path, options.cheats_file.is_some())
+    {
+        error!("{e}");
+        process::exit(1);
+    }
+    // the other machine, Ctrl+B swaps it with `slot`
+    let parked = options.rom_b.as_deref().map(|path| {
+        let mut slot_b = EmulatorSlot::new(
+            'B',
+            Chip8::with_seed(time_seed()),
+            read_rom(Some(path), None).unwrap_or_else(|e| {
+                error!("{e}");
+                process::exit(1);
+            }),
+            Some(path.to_string()),
+            state_path_for(Some(path)),
+            display_name(Some(path)),
+        );
+        slot_b.key_help = read_key_help(path);
+        slot_b
+            .chip8
+            .set_quirks(options.quirks_b.unwrap_or(options.quirks));
+        if let Err(e) = configure(&mut slot_b.chip8, &options)
+            .and_then(|()| boot(&mut slot_b.chip8, &slot_b.rom, &options))
+        {
+            error!("{path}: {e}");
+            process::exit(1);
+        }
+        attach_flags(&mut slot_b.chip8, Some(path));
+        if let Some(cheats) = cheats_path_for(Some(path))
+            && let Err(e) = load_cheats(&mut slot_b.chip8, &cheats, false)
+        {
+            error!("{e}");
+            process::exit(1);
+        }
+        return slot_b;
+    });
+
+    let gif = options.record_gif.as_ref().and_then(|path| {
+        return match create_gif(path, &options.palette) {
+            Ok(gif) => Some((gif, path.clone())),
+            Err(e) => {
+                error!("{e}");
+                None
             }
-            [0x8, x, y, 0x7] => {
-                let res = self.registers[x as usize] - self.registers[y as usize];
-                self.registers[x as usize] = res;
+        };
+    });
+    let mut session = Session {
+        slot,
+        parked,
+        gif,
+        recording,
+        windowed,
+    };
+    // A panic in a frame still gets the GIF, the recording, the flags and the settings
+    // written before it carries on; so does Ctrl+C, without the panic
+    let mut window = Window::new(rl, thread, &options, keymap);
+    let frames = panic::catch_unwind(AssertUnwindSafe(|| {
+        run_window(&mut window, &options, picker_mode, player, &mut session);
+    }));
+    finish_window(&window.rl, &options, session, config_path, saved_settings);
+    if let Err(panic) = frames {
+        panic::resume_unwind(panic);
+    }
+    if shutdown::interrupted() {
+        process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
+}
+
+// What the window loop leaves behind for finish_window, whether it ends or panics
+struct Session {
+    slot: EmulatorSlot,
+    // the other machine, Ctrl+B swaps it with `slot`
+    parked: Option<EmulatorSlot>,
+    // GIF being recorded and its path, F8 starts and stops it
+    gif: Option<(GifRecorder<Box<dyn Write>>, String)>,
+    recording: Option<Recording>,
+    // the window fullscreen goes back to
+    windowed: Option<(Vector2, i32, i32)>,
+}
+
+// The window until it is closed, Ctrl+C or a panic
+fn run_window(
+    window: &mut Window,
+    options: &Options,
+    picker_mode: bool,
+    mut player: Option<Player>,
+    session: &mut Session,
+) {
+    let Session {
+        slot,
+        parked,
+        gif,
+        recording,
+        windowed,
+    } = session;
+    #[cfg(feature = "scripting")]
+    let mut script = options.script.as_deref().map(|path| {
+        return load_script(path, &mut slot.chip8, options.script_budget).unwrap_or_else(|e| {
+            error!("{e}");
+            process::exit(1);
+        });
+    });
+    let palette = window.palette;
+    // draws the parked slot in split-screen and goes with it when the slots are swapped
+    let mut parked_screen = new_screen(options);
+    let mut paused = false;
+    // paused because the window lost focus, apart from `paused` so focus coming back
+    // doesn't resume a game the user paused
+    let mut auto_paused = false;
+    let mut show_debug = false;
+    let mut show_memory = false;
+    let mut show_cheats = false;
+    let mut show_stats = false;
+    let mut show_gauges = !options.gauges.is_empty();
+    let mut show_collisions = false;
+    // recorded whether shown or not, so turning the view on shows the last draw already
+    let mut collisions = CollisionFlash::new();
+    // when the keypad cheatsheet started showing on its own, F4 shows it too while held
+    let mut keypad_shown = (options.keypad_help && !picker_mode).then(Instant::now);
+    // first row of the memory view
+    let mut memory_top: usize = 0;
+    let mut timer_clock = TimerClock::new();
+    let mut cpu_clock = CpuClock::new(options.cpu_hz);
+    // instructions and frames per second actually run, measured every STATS_INTERVAL
+    let mut ips = 0;
+    let mut fps = 0;
+    let mut frames_drawn: u64 = 0;
+    let mut stats_window = (Instant::now(), 0, 0);
+    // emulated seconds per real second, set by the Tab and Shift hotkeys
+    let mut speed = 1.0;
+    let mut last_frame = Instant::now();
+    let mut screenshot_counter = 0;
+    let mut cycles_run: u64 = 0;
+    let mut cycle_debt: u32 = 0;
+    // frames whose elapsed time is thrown away instead of feeding the timers
+    let mut skip_frame_time = 0;
+    // host keypad state of the previous frame, to record only changes
+    let mut keys_down = [false; 16];
+    let console = debugger::spawn_console();
+    // error shown at the bottom of the window until it times out
+    let mut message: Option<(String, Instant)> = None;
+
+    let mut picker = picker_mode.then(|| Picker::new(&options.rom_dir));
+    let mut show_picker = picker.is_some();
+    if picker.is_some() {
+        // Escape goes back to the list instead of quitting
+        window.rl.set_exit_key(None);
+    }
+
+    // left of a console `s N`
+    let mut console_steps = 0u32;
+    // a step-over or step-out runs at full speed until it gets there, with the
+    // instruction count it started at for STEP_LIMIT
+    let mut step_target: Option<(StepTarget, u64)> = None;
+    let mut quit = false;
+    let remote = options.listen.as_deref().map(|addr| {
+        return remote::listen(addr).unwrap_or_else(|e| {
+            error!("{e}");
+            process::exit(1);
+        });
+    });
+    // keys held by KEYDOWN, pressed on top of the keyboard
+    let mut remote_keys = [false; 16];
+    let mut remote_reset: Option<Request> = None;
+    // a STEP runs its instructions in the next frame and answers once they have run
+    let mut remote_step: Option<(u32, Request)> = None;
+    while !window.should_close() {
+        let mut picked = None;
+        if let Some(picker) = &mut picker {
+            if show_picker {
+                if window.rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) {
+                    break;
+                }
+                picked = picker.update(&window.rl);
+                if picked.is_none() {
+                    let mut d = window.rl.begin_drawing(&window.thread);
+                    let (width, height) = (d.get_screen_width(), d.get_screen_height());
+                    picker.draw(&mut d, width, height);
+                    if let Some((text, shown)) = &message
+                        && shown.elapsed() < MESSAGE_TIME
+                    {
+                        draw_message(&mut d, text, width, height);
+                    }
+                    continue;
+                }
+            } else if window.rl.is_key_pressed(KeyboardKey::KEY_ESCAPE) {
+                picker.rescan();
+                show_picker = true;
+                window.stop_beep();
+                continue;
             }
-            [0x8, x, y, 0xE] => {
-                //NOTE: ambigious!
-                todo!();
+        }
+
+        let mut step_request = None;
+        while let Ok(line) = console.try_recv() {
+            let command = match debugger::parse_command(&line) {
+                Ok(command) => command,
+                Err(e) => {
+                    error!("{e}");
+                    continue;
+                }
+            };
+            match (debugger::apply(&mut slot.chip8, &command), command) {
+                (Some(Ok(output)), _) => print!("{output}"),
+                (Some(Err(e)), _) => eprintln!("{e}"),
+                (None, Command::Continue) => paused = false,
+                (None, Command::Step(n)) => console_steps = n,
+                (None, Command::StepOver) => step_request = Some(Command::StepOver),
+                (None, Command::StepOut) => step_request = Some(Command::StepOut),
+                (None, Command::Quit) => quit = true,
+                (None, _) => {}
             }
-            [0x9, x, y, 0x0] => {
-                if self.registers[x as usize] != self.registers[y as usize] {
-                    self.pc += 2;
+        }
+        if quit {
+            break;
+        }
+        while remote_step.is_none()
+            && remote_reset.is_none()
+            && let Some(request) = remote.as_ref().and_then(|remote| remote.try_recv().ok())
+        {
+            let deterministic = recording.is_some() || player.is_some();
+            match &request.command {
+                RemoteCommand::KeyDown(key) | RemoteCommand::KeyUp(key) => {
+                    let down = matches!(request.command, RemoteCommand::KeyDown(_));
+                    remote_keys[*key as usize] = down;
+                    request.reply(Ok(String::new()));
+                }
+                RemoteCommand::Reset | RemoteCommand::LoadState(_) if deterministic => {
+                    request.reply(Err(String::from(
+                        "can't load or reset while recording or replaying",
+                    )));
+                }
+                RemoteCommand::Reset => remote_reset = Some(request),
+                RemoteCommand::LoadState(data) => match slot.chip8.load_state(data) {
+                    Ok(_) => {
+                        slot.halted = false;
+                        request.reply(Ok(String::new()));
+                    }
+                    Err(e) => request.reply(Err(format!("failed to load state: {e}"))),
+                },
+                RemoteCommand::Screenshot { ascii: true } => {
+                    let screen = headless::screen(&slot.chip8);
+                    let rows = screen.lines().count();
+                    request.reply(Ok(format!("{rows}\n{}", screen.trim_end())));
+                }
+                RemoteCommand::Screenshot { ascii: false } => {
+                    let width = slot.chip8.resolution().width() as u32;
+                    let scale = SCREENSHOT_SCALE * rustchip8::SCREEN_WIDTH as u32 / width;
+                    let png = screenshot::encode_png(&slot.chip8, scale, &options.palette);
+                    request.reply(Ok(remote::encode_base64(&png)));
+                }
+                RemoteCommand::Pause => {
+                    paused = true;
+                    request.reply(Ok(String::new()));
+                }
+                RemoteCommand::Resume => {
+                    paused = false;
+                    request.reply(Ok(String::new()));
+                }
+                RemoteCommand::Step(n) => {
+                    paused = true;
+                    remote_step = Some((*n, request));
                 }
             }
-            [0xA, nibb1, nibb2, nibb3] => {
-                let addr = conc_nibbles(&[nibb1, nibb2, nibb3]);
-                self.reg_i = addr;
-            }
-            [0xB, nibb1, nibb2, nibb3] => {
-                //NOTE: ambigious!
-                todo!()
-            }
-            [0xC, x, nibb1, nibb2] => {
-                //NOTE: ambigious!
-                todo!();
-            }
-            [0xD, x, y, n] => {
-                let x_idx = x as usize;
-                let y_idx = y as usize;
-                let x_pos = self.registers[x_idx] as usize % 64;
-                let y_pos = self.registers[y_idx] as usize % 32;
-                self.registers[0xF] = 0;
-
-                for i in 0..n {
-                    let addr = self.reg_i as usize + i as usize;
-                    let sprite_data = self.mem[addr];
-
-                    for j in 0..8 {
-                        let pixel = (sprite_data >> (7 - j)) & 1 != 0;
-                        let screen_x = (x_pos + j) % 64;
-                        let screen_y = (y_pos + i as usize) % 32;
-
-                        if pixel {
-                            if self.display[screen_y][screen_x] {
-                                self.registers[0xF] = 1;
-                            }
-                            self.display[screen_y][screen_x] ^= true;
-                        }
+        }
+
+        let frame_input = window.poll_input();
+        if frame_input.toggle_pause {
+            paused = !paused;
+        }
+        let focused = options.run_in_background || window.rl.is_window_focused();
+        if auto_paused == focused {
+            auto_paused = !focused;
+            if auto_paused && player.is_none() {
+                // the key-ups may never arrive, so whatever was held is let go now
+                if let Some(recording) = recording {
+                    for key in (0..16).filter(|&key| keys_down[key]) {
+                        let key = key as u8;
+                        recording.push(
+                            cycles_run,
+                            InputEvent::Key {
+                                key,
+                                pressed: false,
+                            },
+                        );
                     }
                 }
+                slot.release_keys();
+                keys_down = [false; 16];
+            } else if !auto_paused {
+                // the time spent in the background isn't caught up
+                timer_clock = TimerClock::new();
+                cpu_clock = CpuClock::new(cpu_clock.hz());
+                cycle_debt = 0;
+                last_frame = Instant::now();
             }
-            [0xE, x, 0x9, 0xE] => {
-                todo!();
-                //Skip if key_pressed() == VX
+        }
+        if paused && window.rl.is_key_pressed(KeyboardKey::KEY_O) {
+            step_request = Some(Command::StepOver);
+        }
+        if paused && window.rl.is_key_pressed(KeyboardKey::KEY_U) {
+            step_request = Some(Command::StepOut);
+        }
+        match step_request {
+            Some(Command::StepOver) => match StepTarget::over(&slot.chip8) {
+                Some(target) => step_target = Some((target, cycles_run)),
+                None => console_steps = console_steps.max(1),
+            },
+            Some(_) => match StepTarget::out(&slot.chip8) {
+                Some(target) => step_target = Some((target, cycles_run)),
+                None => message = Some((String::from("Not in a subroutine"), Instant::now())),
+            },
+            None => {}
+        }
+        if step_request.is_some() && step_target.is_some() {
+            paused = false;
+        }
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F9) {
+            let pc = slot.chip8.pc();
+            if !slot.chip8.clear_breakpoint(pc) {
+                slot.chip8.set_breakpoint(pc);
             }
-            [0xE, x, 0xA, 0x1] => {
-                todo!();
-                //Skip if key_pressed() != VX
+        }
+        let alt = window.rl.is_key_down(KeyboardKey::KEY_LEFT_ALT)
+            || window.rl.is_key_down(KeyboardKey::KEY_RIGHT_ALT);
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F11)
+            || (alt && window.rl.is_key_pressed(KeyboardKey::KEY_ENTER))
+        {
+            toggle_fullscreen(&mut window.rl, windowed);
+            // switching modes stalls this frame and the next buffer swap
+            skip_frame_time = 2;
+        }
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F1) {
+            show_debug = !show_debug;
+        }
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F2) {
+            show_memory = !show_memory;
+        }
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F6) {
+            show_cheats = !show_cheats;
+        }
+        if show_memory {
+            // a page is 256 bytes
+            let rows = slot.chip8.mem().len() / MEMORY_ROW_BYTES;
+            if window.rl.is_key_pressed(KeyboardKey::KEY_PAGE_DOWN) {
+                memory_top = (memory_top + 16).min(rows - 1);
             }
-            [0xF, x, 0x0, 0x7] => {
-                self.registers[x as usize] = self.delay_timer;
+            if window.rl.is_key_pressed(KeyboardKey::KEY_PAGE_UP) {
+                memory_top = memory_top.saturating_sub(16);
             }
-            [0xF, x, 0x1, 0x5] => {
-                self.delay_timer = self.registers[x as usize];
+            if window.rl.is_key_pressed(KeyboardKey::KEY_HOME) {
+                memory_top = 0;
             }
-            [0xF, x, 0x1, 0x8] => {
-                self.sound_timer = self.registers[x as usize];
+            memory_top = memory_top.min(rows - 1);
+        }
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F5) {
+            match fs::write(&slot.state_path, slot.chip8.save_state()) {
+                Ok(()) => info!("Saved state to {}", slot.state_path),
+                Err(e) => error!("failed to save state to {}: {e}", slot.state_path),
             }
-            [0xF, x, 0x1, 0xE] => {
-                //TODO: make overflow, VF = 1, configurable
-                //Overflow would be above 0x1000 (normal addr space)
-                self.reg_i += self.registers[x as usize] as u16;
+        }
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F8) {
+            match gif.take() {
+                Some((recorder, path)) => finish_gif(recorder, &path),
+                None => {
+                    let path = gif_path(slot.rom_path.as_deref());
+                    match create_gif(&path, &options.palette) {
+                        Ok(recorder) => {
+                            info!("Recording GIF to {path}");
+                            *gif = Some((recorder, path));
+                        }
+                        Err(e) => error!("{e}"),
+                    }
+                }
             }
-            [0xF, x, 0x0, 0xA] => {
-                loop {
-                    // if key_pressed() -> store value of keypress in VX, then break
-                    todo!();
+        }
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F12) {
+            save_screenshot(
+                &slot.chip8,
+                &options.palette,
+                window.screen.crt_pixels(),
+                &next_screenshot_path(&mut screenshot_counter),
+            );
+        }
+        // Loading states or rewinding would make a recording or replay diverge
+        let deterministic = recording.is_some() || player.is_some();
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F7) && !deterministic {
+            match load_state(&mut slot.chip8, &slot.state_path) {
+                Ok(()) => {
+                    info!("Loaded state from {}", slot.state_path);
+                    slot.halted = false;
                 }
+                Err(e) => error!("{e}"),
             }
-            [0xF, x, 0x2, 0x9] => {
-                // need to figure out fonts first
-                todo!();
+        }
 
-                // The index register I is set to the address of the hexadecimal character in VX. You
-                // probably stored that font somewhere in the first 512 bytes of memory, so now you
-                // just need to point I to the right character.
+        let ctrl = window.rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL)
+            || window.rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+        for (i, key) in CHEAT_KEYS.into_iter().enumerate() {
+            if ctrl
+                && window.rl.is_key_pressed(key)
+                && let Some(on) = slot.chip8.toggle_cheat(i)
+            {
+                info!("Cheat {} {}", i + 1, if on { "on" } else { "off" });
             }
-            [0xF, x, 0x3, 0x3] => {
-                let val = self.registers[x as usize];
-                let digit1 = val / 100_u8;
-                let digit2 = (val % 100 - val % 10) / 10_u8;
-                let digit3 = val % 10;
-                self.mem[self.reg_i as usize] = digit1;
-                self.mem[self.reg_i as usize + 1] = digit2;
-                self.mem[self.reg_i as usize + 2] = digit3;
+        }
+        if ctrl && window.rl.is_key_pressed(KeyboardKey::KEY_G) {
+            show_gauges = !show_gauges;
+        }
+        // F6 is taken by the cheats, H for hit
+        if ctrl && window.rl.is_key_pressed(KeyboardKey::KEY_H) {
+            show_collisions = !show_collisions;
+        }
+        // F4 is the cheatsheet, B for the --rom-b machine
+        if ctrl
+            && window.rl.is_key_pressed(KeyboardKey::KEY_B)
+            && let Some(other) = parked
+        {
+            slot.release_keys();
+            mem::swap(slot, other);
+            mem::swap(&mut window.screen, &mut parked_screen);
+            collisions.clear();
+            keys_down = [false; 16];
+            cycle_debt = 0;
+            step_target = None;
+        }
+        if ctrl && window.rl.is_key_pressed(KeyboardKey::KEY_T) {
+            window.screen.toggle_crt();
+            parked_screen.toggle_crt();
+        }
+        // counting starts with the first look unless --stats had it on from the start
+        if window.rl.is_key_pressed(KeyboardKey::KEY_F3) {
+            show_stats = !show_stats;
+            if show_stats {
+                slot.chip8.set_stats(true);
+            }
+        }
+        let reset =
+            (ctrl && window.rl.is_key_pressed(KeyboardKey::KEY_R)) || remote_reset.is_some();
+        let dropped = if window.rl.is_file_dropped() {
+            let files = window.rl.get_dropped_files();
+            window.rl.clear_dropped_files();
+            files.into_iter().next()
+        } else {
+            picked.map(|path| path.to_string_lossy().into_owned())
+        };
+        if (reset || dropped.is_some()) && deterministic {
+            message = Some((
+                String::from("Can't load or reset while recording or replaying"),
+                Instant::now(),
+            ));
+        } else if reset || dropped.is_some() {
+            let loaded = match &dropped {
+                Some(path) => read_rom_file(path).map(|new_rom| {
+                    slot.rom = new_rom;
+                    slot.rom_path = Some(path.clone());
+                    slot.state_path = state_path_for(Some(path));
+                    slot.rom_name = display_name(Some(path));
+                }),
+                None => Ok(()),
+            };
+            match loaded.and_then(|()| {
+                return boot(&mut slot.chip8, &slot.rom, options).map_err(|e| e.to_string());
+            }) {
+                Ok(()) => {
+                    message = None;
+                    match &dropped {
+                        Some(path) => {
+                            slot.key_help = read_key_help(path);
+                            if options.keypad_help {
+                                keypad_shown = Some(Instant::now());
+                            }
+                            attach_flags(&mut slot.chip8, Some(path));
+                            if let Some(cheats) = cheats_path_for(Some(path))
+                                && let Err(e) = load_cheats(&mut slot.chip8, &cheats, false)
+                            {
+                                error!("{e}");
+                                message = Some((e, Instant::now()));
+                            }
+                        }
+                        // the ROM was loaded over the pokes
+                        None => slot.chip8.apply_pokes(),
+                    }
+                    slot.halted = false;
+                    slot.break_hit = None;
+                    slot.stop_reason = None;
+                    slot.rewind = Rewind::new(REWIND_FRAMES);
+                    collisions.clear();
+                    show_picker = false;
+                    // time spent on the list must not reach the timers
+                    skip_frame_time = 1;
+                    if let Some(request) = remote_reset.take() {
+                        request.reply(Ok(String::new()));
+                    }
+                }
+                Err(e) => {
+                    error!("{e}");
+                    if let Some(request) = remote_reset.take() {
+                        request.reply(Err(e.clone()));
+                    }
+                    message = Some((e, Instant::now()));
+                }
             }
-            [0xF, x, 0x5, 0x5] => {
-                //NOTE: ambigious instruction
-                todo!()
+        }
+
+        // The CPU and the timers run off the time that really passed, not the frame count,
+        // so the speed doesn't depend on the monitor. After a stall (dragging the window)
+        // only MAX_FRAME_TIME is caught up instead of running seconds of game at once.
+        let now = Instant::now();
+        let dt = if skip_frame_time > 0 {
+            skip_frame_time -= 1;
+            Duration::ZERO
+        } else {
+            (now - last_frame).min(MAX_FRAME_TIME)
+        };
+        last_frame = now;
+        // Fast-forward and slow motion scale emulated time itself, so the CPU and the
+        // timers keep their ratio and games behave the same, just faster or slower
+        let new_speed = if window.rl.is_key_down(KeyboardKey::KEY_TAB) {
+            options.turbo
+        } else if window.rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+            || window.rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT)
+        {
+            SLOW_MOTION
+        } else {
+            1.0
+        };
+        if (new_speed > 1.0) != (speed > 1.0) {
+            // fast-forward also draws as many frames as it can
+            window
+                .rl
+                .set_target_fps(if new_speed > 1.0 { 0 } else { FRAME_RATE });
+        }
+        speed = new_speed;
+        let dt = dt.mul_f64(speed);
+        let ticks = timer_clock.advance(dt);
+        let budget = cpu_clock.advance(dt);
+
+        // `s N` from the console steps one instruction per frame
+        let step = console_steps > 0 || frame_input.step;
+        console_steps = console_steps.saturating_sub(1);
+        let shift = window.rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+            || window.rl.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+        // While paused the timers only move when a whole frame is stepped
+        let (cycles, ticks) = match (paused || auto_paused, step, shift) {
+            (false, _, _) => (budget, ticks),
+            (true, true, true) => (options.cycles_per_frame(), 1),
+            // a pending display wait is released by the frame it waits for
+            (true, true, false) => (1, slot.chip8.is_waiting_for_vblank() as u32),
+            (true, false, _) => (0, 0),
+        };
+        let (cycles, ticks) = match &remote_step {
+            Some((n, _)) => (*n, 0),
+            None => (cycles, ticks),
+        };
+        // Holding Backspace replays the snapshots backwards instead of running
+        let rewinding = window.rl.is_key_down(KeyboardKey::KEY_BACKSPACE) && !deterministic;
+        let (cycles, ticks) = if rewinding {
+            if slot.rewind.step_back(&mut slot.chip8) {
+                slot.halted = false;
             }
-            [0xF, x, 0x6, 0x5] => {
-                //NOTE: ambigious instruction
-                todo!()
+            (0, 0)
+        } else {
+            if cycles > 0 {
+                slot.rewind.push(&slot.chip8);
             }
-            _ => {
-                println!("ERROR: UNKNOWN INSTRUCTION {instruction:#?}");
-                panic!();
+            (cycles, ticks)
+        };
+        // A replay brings its own timer ticks and keys
+        if player.is_none() {
+            for _ in 0..ticks {
+                slot.chip8.tick_60hz();
+                if let Some(recording) = recording {
+                    recording.push(cycles_run, InputEvent::Tick);
+                }
             }
         }
-    }
 
-    fn push_stack(&mut self, addr: u16) {
-        self.stack.push(addr);
-    }
-
-    fn pop_stack(&mut self) -> u16 {
-        return self
-            .stack
-            .pop()
-            .expect("Program tried to pop an empty stack");
-    }
+        if !rewinding && !auto_paused && player.is_none() {
+            for (key, pressed) in frame_input.keys.into_iter().enumerate() {
+                let pressed = pressed || remote_keys[key];
+                #[cfg(feature = "scripting")]
+                let pressed = pressed || script.as_ref().is_some_and(|script| script.keys()[key]);
+                if let Some(recording) = recording
+                    && pressed != keys_down[key]
+                {
+                    let key = key as u8;
+                    recording.push(cycles_run, InputEvent::Key { key, pressed });
+                }
+                keys_down[key] = pressed;
+                slot.chip8.set_key(key as u8, pressed);
+            }
+        }
 
-    fn decrement_timers(&mut self) {
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+        // VIP timing spends cycles per instruction, the last one of a frame may overdraw
+        // the budget and the next frame pays it back
+        let debt = if paused || auto_paused { 0 } else { cycle_debt };
+        if let Some(player) = &mut player
+            && cycles > 0
+        {
+            player.apply_due(&mut slot.chip8, cycles_run);
         }
-        if self.sound_timer > 0 {
-            self.sound_timer -= 1;
+        // A replay's events, --screenshot-after and a step-over or step-out look at every
+        // instruction, which also keeps a replay's events out of skipped idle trips
+        let screenshot_due = options
+            .screenshot_after
+            .as_ref()
+            .is_some_and(|(after, _)| *after > cycles_run);
+        let watched = player.is_some() || step_target.is_some() || screenshot_due;
+        let frame_start = cycles_run;
+        let mut gave_up = false;
+        let mut after_step = |chip8: &mut Chip8, run: u32| {
+            let cycles_run = frame_start + run as u64;
+            if let Some(player) = &mut player {
+                player.apply_due(chip8, cycles_run);
+            }
+            if let Some((after, path)) = &options.screenshot_after
+                && cycles_run == *after
+            {
+                save_screenshot(chip8, &options.palette, window.screen.crt_pixels(), path);
+            }
+            let Some((target, started)) = step_target else {
+                return false;
+            };
+            gave_up = cycles_run - started >= STEP_LIMIT;
+            return target.reached(chip8) || gave_up;
+        };
+        // Keep the window (and the last picture) around after a crash, just stop the CPU
+        let result = if slot.halted {
+            slot.chip8.apply_freezes();
+            FrameResult::default()
+        } else {
+            let after_step: AfterStep = &mut after_step;
+            slot.chip8.run_frame_with(
+                cycles,
+                FrameOptions {
+                    // the ticks were done above
+                    skip_tick: true,
+                    debug: true,
+                    resume_at: slot.break_hit,
+                    debt,
+                    after_step: watched.then_some(after_step),
+                },
+            )
+        };
+        cycles_run += (result.instructions + result.skipped) as u64;
+        cycle_debt = result.debt;
+        collisions.record(&result);
+        if result.instructions > 0 || result.halted.is_some() {
+            slot.break_hit = None;
+            slot.stop_reason = None;
+        }
+        if let Some((pc, e)) = &result.halted {
+            cycles_run += 1;
+            let op = match slot.chip8.mem().get(*pc as usize..*pc as usize + 2) {
+                Some(&[hi, lo]) => {
+                    disasm::disassemble_with(u16::from_be_bytes([hi, lo]), slot.chip8.symbols())
+                }
+                _ => String::from("?"),
+            };
+            error!("CPU halted at {pc:#05X} ({op}): {e}");
+            slot.halted = true;
+        } else if result.exited && !slot.halted {
+            info!("Program exited");
+            slot.halted = true;
+        }
+        match result.stop {
+            Some(FrameStop::Breakpoint(pc)) => {
+                info!("Breakpoint at {pc:#05X}, {}", debugger::HELP);
+                slot.break_hit = Some(pc);
+                slot.stop_reason = Some(format!("BREAK at {pc:#05X}"));
+                paused = true;
+                console_steps = 0;
+                show_debug = true;
+                #[cfg(feature = "scripting")]
+                if let Some(hook) = &mut script
+                    && let Err(e) = hook.on_breakpoint(&mut slot.chip8, pc)
+                {
+                    let e = format!("script stopped: {e}");
+                    error!("{e}");
+                    message = Some((e, Instant::now()));
+                    script = None;
+                }
+            }
+            Some(FrameStop::Watch(hit)) => {
+                info!("Watchpoint: {hit}, {}", debugger::HELP);
+                slot.stop_reason = Some(format!("WATCH {hit}"));
+                paused = true;
+                console_steps = 0;
+                show_debug = true;
+            }
+            // a step-over or step-out got there
+            Some(FrameStop::Requested) => {
+                if gave_up {
+                    let text = format!("Gave up after {STEP_LIMIT} instructions");
+                    info!("{text}");
+                    message = Some((text, Instant::now()));
+                }
+                paused = true;
+                show_debug = true;
+            }
+            None => {}
+        }
+        // reached, or stopped at a breakpoint or by hand first
+        if paused || slot.halted {
+            step_target = None;
+        }
+        // a collision stays up while paused so stepping through draws shows each one
+        if !paused && !auto_paused {
+            collisions.end_frame();
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(hook) = &mut script {
+            let result = if paused || auto_paused {
+                Ok(())
+            } else {
+                hook.on_frame(&mut slot.chip8)
+            };
+            let requests = hook.take_requests();
+            eprint!("{}", requests.output);
+            if requests.screenshot {
+                let path = next_screenshot_path(&mut screenshot_counter);
+                save_screenshot(
+                    &slot.chip8,
+                    &options.palette,
+                    window.screen.crt_pixels(),
+                    &path,
+                );
+            }
+            if let Some(pause) = requests.pause {
+                paused = pause;
+            }
+            if let Err(e) = result {
+                let e = format!("script stopped: {e}");
+                error!("{e}");
+                message = Some((e, Instant::now()));
+                script = None;
+            }
+        }
+        if let Some((_, request)) = remote_step.take() {
+            request.reply(match slot.halted {
+                true => Err(format!("CPU halted at {:#05X}", slot.chip8.pc())),
+                false => Ok(format!("{:#05X}", slot.chip8.pc())),
+            });
+        }
+        if options.split
+            && !rewinding
+            && let Some(other) = parked
+        {
+            other.run_parked(cycles, ticks, &[false; 16]);
+        }
+        if let Some(e) = slot.chip8.take_flag_error() {
+            let e = format!("failed to save RPL flags: {e}");
+            error!("{e}");
+            message = Some((e, Instant::now()));
+        }
+        // one GIF frame per 60Hz tick, so it plays back at game speed
+        if let Some((recorder, path)) = gif {
+            let mut result = Ok(());
+            for _ in 0..ticks {
+                result = result.and_then(|()| recorder.push_frame(&slot.chip8));
+            }
+            if let Err(e) = result {
+                error!("failed to write {path}: {e}, recording stopped");
+                *gif = None;
+            }
+        }
+        frames_drawn += 1;
+        let (window_start, window_cycles, window_frames) = stats_window;
+        if window_start.elapsed() >= STATS_INTERVAL {
+            let seconds = window_start.elapsed().as_secs_f64();
+            ips = ((cycles_run - window_cycles) as f64 / seconds).round() as u64;
+            fps = ((frames_drawn - window_frames) as f64 / seconds).round() as u64;
+            stats_window = (Instant::now(), cycles_run, frames_drawn);
         }
-    }
 
-    fn load_rom(&mut self, rom_data: &[u8]) {
-        self.mem[0x200..0x200 + rom_data.len()].copy_from_slice(rom_data);
-    }
+        // only changes with the stats or the status, so a few times a second at most
+        let mut parts = vec![String::from(TITLE)];
+        if parked.is_some() {
+            parts.push(format!("[{}]", slot.label));
+        }
+        parts.extend(slot.rom_name.clone());
+        if !options.quiet_title {
+            parts.push(format!("{fps}fps"));
+            parts.push(format!("{ips}ips"));
+        }
+        parts.push(if show_picker {
+            String::from("SELECT ROM")
+        } else if slot.halted
+            || matches!(
+                slot.chip8.cpu_state(),
+                CpuState::Exited | CpuState::SelfJump
+            )
+        {
+            String::from("HALTED")
+        } else if paused {
+            format!("PAUSED at {:#05X}", slot.chip8.pc())
+        } else if auto_paused {
+            String::from("PAUSED (unfocused)")
+        } else if slot.chip8.cpu_state() == CpuState::WaitingForKey {
+            String::from("WAITING-KEY")
+        } else {
+            String::from("RUNNING")
+        });
+        if speed != 1.0 {
+            parts.push(format!("{speed}x"));
+        }
+        window.set_title(parts.join(" — "));
 
-    fn clear_screen(&mut self) {
-        self.display = [[false; SCREEN_WIDTH as usize]; SCREEN_HEIGHT as usize];
+        let (width, height) = (window.rl.get_screen_width(), window.rl.get_screen_height());
+        let areas = split_areas(width, height);
+        // A stays on the left whichever slot is active
+        let (area, parked_area) = if slot.label == 'A' {
+            (areas[0], areas[1])
+        } else {
+            (areas[1], areas[0])
+        };
+        let split = options.split && parked.is_some();
+        let area = if split {
+            area
+        } else {
+            Viewport {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }
+        };
+        if split && let Some(other) = &parked {
+            let (rl, thread) = (&mut window.rl, &window.thread);
+            let integer_scaling = options.integer_scaling;
+            parked_screen.update_layout(rl, thread, &other.chip8, parked_area, integer_scaling);
+        }
+        let show_keypad = window.rl.is_key_down(KeyboardKey::KEY_F4)
+            || keypad_shown.is_some_and(|shown| shown.elapsed() < KEYPAD_HELP_TIME);
+        let changed = slot.chip8.take_display_dirty();
+        window.present_with(&slot.chip8, changed, area, |d, screen, input| {
+            if show_collisions {
+                let (pixels, strength) = collisions.pixels();
+                screen.draw_marks(d, pixels, strength);
+            }
+            if split && let Some(other) = parked {
+                let changed = other.chip8.take_display_dirty();
+                parked_screen.draw(d, &other.chip8, changed, &palette);
+                draw_split(d, &areas, slot.label);
+            }
+            let mut overlay_height = 0;
+            if show_debug {
+                let width = d.get_screen_width();
+                overlay_height =
+                    draw_debug(&slot.chip8, d, width, ips, slot.stop_reason.as_deref());
+            }
+            if show_cheats {
+                let width = d.get_screen_width();
+                overlay_height += draw_cheats(&slot.chip8, d, overlay_height, width);
+            }
+            if show_stats {
+                let width = d.get_screen_width();
+                overlay_height += draw_stats(&slot.chip8, d, overlay_height, width);
+            }
+            if show_collisions {
+                let width = d.get_screen_width();
+                overlay_height += draw_collisions(&collisions, d, overlay_height, width);
+            }
+            if show_memory {
+                let (width, height) = (d.get_screen_width(), d.get_screen_height());
+                draw_memory(
+                    &slot.chip8,
+                    d,
+                    overlay_height,
+                    width,
+                    height - overlay_height,
+                    memory_top,
+                );
+            }
+            if show_gauges && !options.gauges.is_empty() {
+                let width = d.get_screen_width();
+                draw_gauges(&options.gauges, &slot.chip8, d, overlay_height, width);
+            }
+            if show_keypad {
+                let (width, height) = (d.get_screen_width(), d.get_screen_height());
+                draw_keypad(
+                    d,
+                    input.bindings(),
+                    input.gamepad_connected(),
+                    &slot.key_help,
+                    slot.chip8.input().keys(),
+                    width,
+                    height,
+                );
+            }
+            if let Some((text, shown)) = &message {
+                if shown.elapsed() < MESSAGE_TIME {
+                    let (width, height) = (d.get_screen_width(), d.get_screen_height());
+                    draw_message(d, text, width, height);
+                } else {
+                    message = None;
+                }
+            }
+        });
+        window.play_beep(slot.chip8.sound_active() && !paused && !auto_paused);
     }
+}
 
-    fn draw_display(&mut self, rl: &mut RaylibHandle, thread: &RaylibThread) {
-        let mut d = rl.begin_drawing(thread);
-        d.clear_background(Color::BLACK);
-        for i in 0..(SCREEN_HEIGHT * SCREEN_WIDTH) {
-            let x = i % SCREEN_WIDTH;
-            let y = i / SCREEN_WIDTH;
-
-            if !self.display[y as usize][x as usize] {
-                continue;
-            }
+// The GIF, the stats, the flags, the recording and the settings of a session, after the
+// window loop, even one that panicked
+fn finish_window(
+    rl: &RaylibHandle,
+    options: &Options,
+    session: Session,
+    config_path: Option<PathBuf>,
+    mut saved_settings: Settings,
+) {
+    let Session {
+        mut slot,
+        mut parked,
+        gif,
+        recording,
+        windowed,
+    } = session;
 
-            d.draw_rectangle(
-                x * SQUARE_SIZE,
-                y * SQUARE_SIZE,
-                SQUARE_SIZE,
-                SQUARE_SIZE,
-                Color::GREEN,
+    if let Some((recorder, path)) = gif {
+        finish_gif(recorder, &path);
+    }
+    // of the machine --stats was given for, whichever slot it is in now
+    if let Some(path) = &options.stats_out
+        && let Some(a) = std::iter::once(&slot)
+            .chain(&parked)
+            .find(|slot| slot.label == 'A')
+    {
+        write_stats(&a.chip8, path);
+    }
+    for slot in std::iter::once(&mut slot).chain(&mut parked) {
+        if let Err(e) = slot.chip8.save_flags() {
+            error!("failed to save RPL flags: {e}");
+        }
+    }
+    if let (Some(recording), Some(path)) = (&recording, &options.record) {
+        match fs::write(path, recording.to_bytes()) {
+            Ok(()) => info!("Saved recording to {path}"),
+            Err(e) => error!("failed to save recording to {path}: {e}"),
+        }
+    }
+    if let Some(path) = &config_path {
+        // in fullscreen the window it goes back to
+        let (position, width, height) = windowed.unwrap_or_else(|| {
+            return (
+                rl.get_window_position(),
+                rl.get_screen_width(),
+                rl.get_screen_height(),
             );
+        });
+        saved_settings.window = Some(Geometry {
+            x: position.x as i32,
+            y: position.y as i32,
+            width,
+            height,
+        });
+        if let Some(dir) = slot
+            .rom_path
+            .as_deref()
+            .filter(|path| *path != "-")
+            .and_then(|path| Path::new(path).parent())
+        {
+            let dir = dir.to_string_lossy();
+            saved_settings.rom_dir = Some(if dir.is_empty() {
+                String::from(".")
+            } else {
+                dir.into_owned()
+            });
         }
+        save_settings(&saved_settings, path);
     }
 }
 
-fn split_nibbles(word: u16) -> [u8; 4] {
-    [
-        ((word >> 12) & 0xF) as u8,
-        ((word >> 8) & 0xF) as u8,
-        ((word >> 4) & 0xF) as u8,
-        (word & 0xF) as u8,
-    ]
-}
-
-fn conc_nibbles(nibbs: &[u8]) -> u16 {
-    let mut addr: u16 = 0;
-    for nibb in nibbs {
-        addr <<= 4;
-        addr |= *nibb as u16;
+// --wall: every ROM in `dir` in its own machine, one window for all of them
+fn run_wall(options: &Options, keymap: Keymap, dir: &str) {
+    let entries = fs::read_dir(dir).unwrap_or_else(|e| {
+        error!("failed to read {dir}: {e}");
+        process::exit(1);
+    });
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| {
+            let extension = path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+            return path.is_file()
+                && extension.is_some_and(|ext| {
+                    ROM_EXTENSIONS.contains(&ext.as_str())
+                        || (ext == "zip" && cfg!(feature = "zip"))
+                });
+        })
+        .collect();
+    paths.sort();
+    if paths.is_empty() {
+        error!("no ROMs in {dir}");
+        process::exit(1);
     }
 
-    return addr;
-}
+    let seed = time_seed();
+    let tiles: Vec<Tile> = paths
+        .iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let path = path.to_string_lossy().into_owned();
+            // every machine gets its own CXNN sequence
+            let seed = seed ^ (i as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            let mut chip8 = Chip8::with_seed(seed);
+            chip8.set_quirks(options.quirks);
+            let loaded = read_rom_file(&path).and_then(|rom| {
+                configure(&mut chip8, options)
+                    .and_then(|()| boot(&mut chip8, &rom, options))
+                    .map_err(|e| e.to_string())?;
+                return Ok(rom);
+            });
+            let name = display_name(Some(&path)).unwrap_or_else(|| path.clone());
+            let screen = new_screen(options);
+            let (slot, error) = match loaded {
+                Ok(rom) => {
+                    let label = (b'A' + (i % 26) as u8) as char;
+                    let mut slot = EmulatorSlot::new(
+                        label,
+                        chip8,
+                        rom,
+                        Some(path.clone()),
+                        state_path_for(Some(&path)),
+                        Some(name.clone()),
+                    );
+                    attach_flags(&mut slot.chip8, Some(&path));
+                    (Some(slot), None)
+                }
+                Err(e) => {
+                    warn!("{path}: {e}");
+                    (None, Some(e))
+                }
+            };
+            return Tile {
+                name,
+                slot,
+                error,
+                screen,
+            };
+        })
+        .collect();
+    let mut wall = Wall::new(tiles);
 
-fn main() {
-    let mut chip8 = Chip8::new();
+    set_trace_log(LOG_NONE);
+    // 2x tiles unless that doesn't fit on the screen
+    let (cols, rows) = Wall::grid(wall.tiles.len());
+    let (cols, rows) = (cols as i32, rows as i32);
+    let (mut rl, thread) = raylib::init()
+        .size(cols * TILE_WIDTH * 2, rows * TILE_HEIGHT * 2)
+        .title(TITLE)
+        .resizable()
+        .build();
+    rl.set_target_fps(FRAME_RATE);
+    rl.set_exit_key(None);
+    let monitor = get_current_monitor();
+    if cols * TILE_WIDTH * 2 > get_monitor_width(monitor)
+        || rows * TILE_HEIGHT * 2 > get_monitor_height(monitor)
+    {
+        rl.set_window_size(cols * TILE_WIDTH, rows * TILE_HEIGHT);
+    }
+    let palette = options.palette.map(|[r, g, b]| Color::new(r, g, b, 255));
+    let mut beeper = (!options.mute).then(|| {
+        return Beeper::new(
+            &thread,
+            options.wave,
+            options.beep_freq,
+            options.volume,
+            options.audio_buffer,
+        );
+    });
+    let mut input = Input::new(keymap, options.gamepad);
+    let mut timer_clock = TimerClock::new();
+    let mut cpu_clock = CpuClock::new(options.cpu_hz);
+    let mut last_frame = Instant::now();
+    let mut title = String::from(TITLE);
 
-    let mut buffer = Vec::new();
-    let lines = stdin()
-        .read_to_end(&mut buffer)
-        .expect("Failed to read ROM file");
+    shutdown::catch_interrupt();
+    while !rl.window_should_close() && !shutdown::interrupted() {
+        wall.handle_input(&rl);
+        let now = Instant::now();
+        let dt = (now - last_frame).min(MAX_FRAME_TIME);
+        last_frame = now;
+        let ticks = timer_clock.advance(dt);
+        let cycles = cpu_clock.advance(dt);
+        wall.run(cycles, ticks, input.poll(&rl));
 
-    chip8.load_rom(&buffer);
+        let selected = wall.selected();
+        if let Some(beeper) = &mut beeper {
+            let sound = selected.slot.as_ref().map(|slot| {
+                let pattern = slot
+                    .chip8
+                    .uses_audio_pattern()
+                    .then(|| (slot.chip8.audio_pattern(), slot.chip8.pitch()));
+                return (slot.chip8.sound_active() && !slot.halted, pattern);
+            });
+            let (active, pattern) = sound.unwrap_or((false, None));
+            beeper.update(active, pattern);
+        }
+        let new_title = format!("{TITLE} — {} — wall of {dir}", selected.name);
+        if new_title != title {
+            rl.set_window_title(&thread, &new_title);
+            title = new_title;
+        }
 
-    set_trace_log(LOG_NONE);
+        wall.update_layout(&mut rl, &thread, options.integer_scaling);
+        let mut d = rl.begin_drawing(&thread);
+        d.clear_background(Color::BLACK);
+        wall.draw(&mut d, &palette);
+    }
 
-    let (mut rl, thread) = raylib::init()
-        .size(SCREEN_WIDTH * SQUARE_SIZE, SCREEN_HEIGHT * SQUARE_SIZE)
-        .title("CHIP-8 Emulator")
-        .build();
+    for slot in wall.tiles.iter_mut().filter_map(|tile| tile.slot.as_mut()) {
+        if let Err(e) = slot.chip8.save_flags() {
+            error!("failed to save RPL flags: {e}");
+        }
+    }
+    if shutdown::interrupted() {
+        process::exit(shutdown::INTERRUPTED_EXIT_CODE);
+    }
+}
 
-    while !rl.window_should_close() {
-        let instruction = chip8.fetch();
-        chip8.execute(instruction);
-        chip8.draw_display(&mut rl, &thread);
-    }
-}
-
-//TODO: put into 050–09F
-
-//0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-//0x20, 0x60, 0x20, 0x20, 0x70, // 1
-//0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-//0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-//0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-//0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-//0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-//0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-//0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-//0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-//0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-//0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-//0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-//0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-//0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-//0xF0, 0x80, 0xF0, 0x80, 0x80  // F
+fn save_settings(settings: &Settings, path: &Path) {
+    if let Some(dir) = path.parent()
+        && let Err(e) = fs::create_dir_all(dir)
+    {
+        error!("failed to create {}: {e}", dir.display());
+        return;
+    }
+    if let Err(e) = fs::write(path, settings.to_json()) {
+        error!("failed to save settings to {}: {e}", path.display());
+    }
+}
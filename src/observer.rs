@@ -0,0 +1,190 @@
+// For embedding the core in another engine's loop: run_frame() is one 60Hz frame (input,
+// instructions, a timer tick) and a Chip8Observer installed with set_observer() hears about
+// what it did, instead of the caller polling the machine afterwards. run_frame_with() is the
+// same frame for a debugger: breakpoints, watchpoints and a check after every instruction.
+
+use crate::{Chip8, Chip8Error, StepInfo, TimerEvents, WatchHit};
+
+// Every method does nothing unless overridden
+pub trait Chip8Observer {
+    // at the end of a run_frame() whose instructions changed the display, read it with
+    // Chip8::display(), display_size() or pixel()
+    fn on_display_update(&mut self, _chip8: &Chip8) {}
+    // every run_frame(), whether the sound timer is running after the tick
+    fn on_sound(&mut self, _on: bool) {}
+    // whenever step() fails, `pc` is where the instruction was fetched from
+    fn on_halt(&mut self, _pc: u16, _error: &Chip8Error) {}
+}
+
+// Why run_frame_with() stopped before its cycles were spent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameStop {
+    // before the instruction at this address
+    Breakpoint(u16),
+    // after the instruction that made the write
+    Watch(WatchHit),
+    // FrameOptions::after_step returned true
+    Requested,
+}
+
+// FrameOptions::after_step
+pub type AfterStep<'a> = &'a mut dyn FnMut(&mut Chip8, u32) -> bool;
+
+// How run_frame_with() differs from run_frame(), which is the default
+#[derive(Default)]
+pub struct FrameOptions<'a> {
+    // no timer tick at the end, for a caller that ticks on its own clock or a replay that
+    // brings its own ticks
+    pub skip_tick: bool,
+    // stop at breakpoints and watchpoints, see FrameResult::stop
+    pub debug: bool,
+    // the breakpoint the frame before stopped at, the first instruction runs past it
+    pub resume_at: Option<u16>,
+    // cycles the frame before ran past its budget (FrameResult::debt), paid back first
+    pub debt: u32,
+    // called after every instruction with the machine and how many instructions the frame
+    // has run, returning true stops the frame. Idle trips aren't skipped with one, it sees
+    // every instruction.
+    pub after_step: Option<AfterStep<'a>>,
+}
+
+// What one run_frame() did
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FrameResult {
+    pub instructions: u32,
+    // machine cycles, more than `instructions` with Timing::Vip. Skipped ones included.
+    pub cycles: u32,
+    // instructions of an idle wait counted as run without running them, see idle.rs
+    pub skipped: u32,
+    pub display_changed: bool,
+    pub sound: bool,
+    // from the frame's tick_60hz()
+    pub timers: TimerEvents,
+    // 00FD ran, later frames do nothing
+    pub exited: bool,
+    // where the instruction that failed was and why, the machine shouldn't be run again
+    pub halted: Option<(u16, Chip8Error)>,
+    // only from run_frame_with()
+    pub stop: Option<FrameStop>,
+    // cycles the last instruction ran past the budget with Timing::Vip, for the next frame
+    // to pay back through FrameOptions::debt
+    pub debt: u32,
+    // DXYNs run, VF after the last one and the pixels the last one that collided erased
+    pub draws: u32,
+    pub draw_vf: Option<u8>,
+    pub collisions: Vec<(usize, usize)>,
+}
+
+impl Chip8 {
+    pub fn set_observer(&mut self, observer: Option<Box<dyn Chip8Observer>>) {
+        self.observer = observer;
+    }
+
+    pub fn take_observer(&mut self) -> Option<Box<dyn Chip8Observer>> {
+        return self.observer.take();
+    }
+
+    // One frame: `input` becomes the keypad, instructions run until `cycles` machine cycles
+    // are spent (or FX0A, a display wait, 00FD or an error stops them early), the timers
+    // tick once and frozen cheats are written again. With set_idle_skip() the trips around
+    // a delay timer wait are counted instead of run. Breakpoints don't stop it, see
+    // run_frame_with().
+    pub fn run_frame(&mut self, cycles: u32, input: &[bool; 16]) -> FrameResult {
+        for (key, &pressed) in input.iter().enumerate() {
+            self.set_key(key as u8, pressed);
+        }
+
+        return self.run_frame_with(cycles, FrameOptions::default());
+    }
+
+    // run_frame() with the keypad left as it is and `options` on top
+    pub fn run_frame_with(&mut self, cycles: u32, mut options: FrameOptions) -> FrameResult {
+        let mut result = FrameResult::default();
+        let mut resume_at = options.resume_at;
+        while options.debt.saturating_add(result.cycles) < cycles {
+            if self.is_waiting_for_key() || self.is_waiting_for_vblank() || self.has_exited() {
+                break;
+            }
+            let left = cycles - options.debt - result.cycles;
+            if options.after_step.is_none() {
+                let (skipped, skipped_cycles) = self.idle_trips(left);
+                if skipped > 0 {
+                    result.skipped += skipped;
+                    result.cycles += skipped_cycles;
+                    continue;
+                }
+            }
+            let pc = self.pc();
+            if options.debug && self.at_breakpoint() && resume_at != Some(pc) {
+                result.stop = Some(FrameStop::Breakpoint(pc));
+                break;
+            }
+            resume_at = None;
+            match self.step() {
+                Ok(StepInfo {
+                    opcode,
+                    cycles,
+                    display_changed,
+                    ..
+                }) => {
+                    result.instructions += 1;
+                    result.cycles += cycles;
+                    result.display_changed |= display_changed;
+                    if opcode & 0xF000 == 0xD000 {
+                        result.draws += 1;
+                        result.draw_vf = Some(self.registers()[0xF]);
+                        if !self.last_collisions().is_empty() {
+                            result.collisions.clear();
+                            result.collisions.extend_from_slice(self.last_collisions());
+                        }
+                    }
+                }
+                Err(error) => {
+                    result.halted = Some((pc, error));
+                    break;
+                }
+            }
+            let requested = options
+                .after_step
+                .as_mut()
+                .is_some_and(|after_step| after_step(self, result.instructions));
+            if options.debug
+                && let Some(hit) = self.take_watch_hit()
+            {
+                result.stop = Some(FrameStop::Watch(hit));
+                break;
+            }
+            if requested {
+                result.stop = Some(FrameStop::Requested);
+                break;
+            }
+        }
+        result.debt = options
+            .debt
+            .saturating_add(result.cycles)
+            .saturating_sub(cycles);
+        if !options.skip_tick {
+            result.timers = self.tick_60hz();
+        }
+        self.apply_freezes();
+        result.sound = self.sound_active();
+        result.exited = self.has_exited();
+
+        if let Some(mut observer) = self.observer.take() {
+            if result.display_changed {
+                observer.on_display_update(self);
+            }
+            observer.on_sound(result.sound);
+            self.observer = Some(observer);
+        }
+
+        return result;
+    }
+
+    // Called by step() on the way out with its error
+    pub(crate) fn notify_halt(&mut self, pc: u16, error: &Chip8Error) {
+        if let Some(observer) = &mut self.observer {
+            observer.on_halt(pc, error);
+        }
+    }
+}
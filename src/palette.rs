@@ -0,0 +1,97 @@
+// The display palettes of the windows: the presets and --palette/--bg/--fg colors.
+
+use crate::screenshot::Rgb;
+
+// Indexed by pixel value: off, plane 1, plane 2, both planes
+pub const PALETTES: [(&str, [Rgb; 4]); 4] = [
+    (
+        "classic",
+        [[0, 0, 0], [0, 228, 48], [0, 120, 255], [255, 255, 255]],
+    ),
+    (
+        "amber",
+        [[16, 8, 0], [255, 176, 0], [160, 80, 0], [255, 232, 160]],
+    ),
+    (
+        "paperwhite",
+        [[240, 236, 224], [32, 32, 32], [128, 128, 128], [0, 0, 0]],
+    ),
+    (
+        "gameboy",
+        [[155, 188, 15], [15, 56, 15], [48, 98, 48], [139, 172, 15]],
+    ),
+];
+
+// RRGGBB, with or without a leading '#'
+pub fn parse_color(value: &str) -> Result<Rgb, String> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid color '{value}', expected RRGGBB hex"));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+
+    return Ok([channel(0), channel(2), channel(4)]);
+}
+
+// A preset name, or up to four comma-separated colors replacing the classic entries in order
+pub fn parse_palette(value: &str) -> Result<[Rgb; 4], String> {
+    if let Some((_, palette)) = PALETTES.iter().find(|(name, _)| *name == value) {
+        return Ok(*palette);
+    }
+
+    let colors: Vec<&str> = value.split(',').collect();
+    if colors.len() > 4 {
+        return Err(format!(
+            "--palette takes at most 4 colors, got {}",
+            colors.len()
+        ));
+    }
+    let mut palette = PALETTES[0].1;
+    for (entry, color) in palette.iter_mut().zip(colors) {
+        *entry = parse_color(color.trim()).map_err(|e| {
+            let names: Vec<&str> = PALETTES.iter().map(|(name, _)| *name).collect();
+            format!("{e} (or a preset: {})", names.join(", "))
+        })?;
+    }
+
+    return Ok(palette);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("ffcc00"), Ok([0xFF, 0xCC, 0x00]));
+        assert_eq!(parse_color("#101020"), Ok([0x10, 0x10, 0x20]));
+        assert_eq!(parse_color("A0b1C2"), Ok([0xA0, 0xB1, 0xC2]));
+        for bad in ["", "fff", "ffcc0", "ffcc000", "ggcc00", "#", "ffcc0é"] {
+            let e = parse_color(bad).unwrap_err();
+            assert!(e.contains("expected RRGGBB"), "{bad}: {e}");
+        }
+    }
+
+    #[test]
+    fn palettes() {
+        for (name, palette) in PALETTES {
+            assert_eq!(parse_palette(name), Ok(palette));
+        }
+        // fewer than four colors keep the rest of the classic palette
+        let classic = PALETTES[0].1;
+        assert_eq!(
+            parse_palette("ffcc00, 101020"),
+            Ok([[0xFF, 0xCC, 0], [0x10, 0x10, 0x20], classic[2], classic[3]])
+        );
+        assert!(
+            parse_palette("1,2,3,4,5")
+                .unwrap_err()
+                .contains("at most 4")
+        );
+        let e = parse_palette("neon").unwrap_err();
+        assert!(
+            e.contains("invalid color 'neon'") && e.contains("gameboy"),
+            "{e}"
+        );
+    }
+}
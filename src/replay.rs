@@ -0,0 +1,203 @@
+// Input recordings for deterministic replays. Layout (all integers big-endian):
+//
+//   magic "RC8R" | version u16 | rom hash u64 | quirk bits u8 | rng seed u64
+//   | idle skip u8 (version 2 on) | event count u32
+//   | events: cycle u64, kind u8 (0 tick, 1 key up, 2 key down), key u8
+//
+// Timer ticks are recorded next to the keys because the window ticks by wall-clock time;
+// replayed against the same ROM, quirks and seed the run is identical. Skipped trips around
+// an idle wait (see idle.rs) count as instructions, so it is identical whether the recording
+// skipped them or not; the header says which.
+
+use crate::state::Reader;
+use crate::{Chip8, Quirks};
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"RC8R";
+const RECORDING_VERSION: u16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputEvent {
+    Key { key: u8, pressed: bool },
+    Tick,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordingError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    Corrupt(&'static str),
+    RomMismatch,
+    QuirksMismatch { recorded: Quirks, current: Quirks },
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordingError::BadMagic => write!(f, "not a RustChip8 input recording"),
+            RecordingError::UnsupportedVersion(v) => write!(f, "unsupported recording version {v}"),
+            RecordingError::Truncated => write!(f, "recording is truncated"),
+            RecordingError::Corrupt(what) => write!(f, "recording is corrupt: {what}"),
+            RecordingError::RomMismatch => write!(f, "recording was made with a different ROM"),
+            RecordingError::QuirksMismatch { recorded, current } => write!(
+                f,
+                "recording was made with quirks {recorded:?}, but the current ones are {current:?}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordingError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recording {
+    pub rom_hash: u64,
+    pub quirks: Quirks,
+    pub seed: u64,
+    // Chip8::idle_skip() while recording, always off before version 2. Only for information,
+    // replays always run every instruction.
+    pub idle_skip: bool,
+    // (cycle, event), the event happens right before that many instructions have run
+    pub events: Vec<(u64, InputEvent)>,
+}
+
+impl Recording {
+    // Start recording a machine that was created with Chip8::with_seed(seed)
+    pub fn new(chip8: &Chip8, seed: u64) -> Self {
+        return Recording {
+            rom_hash: chip8.rom_hash(),
+            quirks: chip8.quirks(),
+            seed,
+            idle_skip: chip8.idle_skip(),
+            events: Vec::new(),
+        };
+    }
+
+    pub fn push(&mut self, cycle: u64, event: InputEvent) {
+        self.events.push((cycle, event));
+    }
+
+    // Refuses to replay against a different ROM or quirk setup
+    pub fn check(&self, chip8: &Chip8) -> Result<(), RecordingError> {
+        if self.rom_hash != chip8.rom_hash() {
+            return Err(RecordingError::RomMismatch);
+        }
+        if self.quirks != chip8.quirks() {
+            return Err(RecordingError::QuirksMismatch {
+                recorded: self.quirks,
+                current: chip8.quirks(),
+            });
+        }
+
+        return Ok(());
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(28 + self.events.len() * 10);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&RECORDING_VERSION.to_be_bytes());
+        out.extend_from_slice(&self.rom_hash.to_be_bytes());
+        out.push(self.quirks.to_bits());
+        out.extend_from_slice(&self.seed.to_be_bytes());
+        out.push(self.idle_skip as u8);
+        out.extend_from_slice(&(self.events.len() as u32).to_be_bytes());
+        for (cycle, event) in &self.events {
+            out.extend_from_slice(&cycle.to_be_bytes());
+            match event {
+                InputEvent::Tick => out.extend_from_slice(&[0, 0]),
+                InputEvent::Key { key, pressed } => {
+                    out.extend_from_slice(&[1 + *pressed as u8, *key])
+                }
+            }
+        }
+
+        return out;
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, RecordingError> {
+        let mut r = Reader { data, pos: 0 };
+        let truncated = |_| RecordingError::Truncated;
+        if r.bytes(4).map_err(|_| RecordingError::BadMagic)? != MAGIC {
+            return Err(RecordingError::BadMagic);
+        }
+        let version = r.u16().map_err(truncated)?;
+        if version == 0 || version > RECORDING_VERSION {
+            return Err(RecordingError::UnsupportedVersion(version));
+        }
+        let rom_hash = r.u64().map_err(truncated)?;
+        let quirks = Quirks::from_bits(r.u8().map_err(truncated)?);
+        let seed = r.u64().map_err(truncated)?;
+        let idle_skip = match version {
+            1 => false,
+            _ => match r.u8().map_err(truncated)? {
+                0 => false,
+                1 => true,
+                _ => return Err(RecordingError::Corrupt("idle skip")),
+            },
+        };
+        let count = r.u32().map_err(truncated)?;
+
+        let mut events = Vec::new();
+        let mut last_cycle = 0;
+        for _ in 0..count {
+            let cycle = r.u64().map_err(truncated)?;
+            if cycle < last_cycle {
+                return Err(RecordingError::Corrupt("events out of order"));
+            }
+            last_cycle = cycle;
+            let event = match (r.u8().map_err(truncated)?, r.u8().map_err(truncated)?) {
+                (0, _) => InputEvent::Tick,
+                (kind @ (1 | 2), key @ 0..=0xF) => InputEvent::Key {
+                    key,
+                    pressed: kind == 2,
+                },
+                _ => return Err(RecordingError::Corrupt("event")),
+            };
+            events.push((cycle, event));
+        }
+
+        return Ok(Recording {
+            rom_hash,
+            quirks,
+            seed,
+            idle_skip,
+            events,
+        });
+    }
+}
+
+// Feeds a recording back into a machine in place of the keyboard and the timer clock
+pub struct Player {
+    events: Vec<(u64, InputEvent)>,
+    next: usize,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        return Player {
+            events: recording.events,
+            next: 0,
+        };
+    }
+
+    // Applies every event due before instruction number `cycle` runs
+    pub fn apply_due(&mut self, chip8: &mut Chip8, cycle: u64) {
+        while let Some(&(at, event)) = self.events.get(self.next) {
+            if at > cycle {
+                break;
+            }
+            match event {
+                InputEvent::Key { key, pressed } => chip8.set_key(key, pressed),
+                InputEvent::Tick => {
+                    chip8.tick_60hz();
+                }
+            }
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        return self.next == self.events.len();
+    }
+}
@@ -0,0 +1,107 @@
+// Framebuffer to PNG without any image crate, so it works headless too. The image data
+// is stored uncompressed (deflate "stored" blocks); CHIP-8 screens are tiny anyway.
+
+use crate::Chip8;
+
+pub type Rgb = [u8; 3];
+
+// Largest payload of a single stored deflate block
+const STORED_BLOCK: usize = 0xFFFF;
+
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                crc >> 1 ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    return !crc;
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+
+    return b << 16 | a;
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = out.len();
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    let crc = crc32(&out[start..]);
+    out.extend_from_slice(&crc.to_be_bytes());
+}
+
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(STORED_BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(blocks.peek().is_none() as u8);
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+
+    return out;
+}
+
+// The visible part of the display (64x32 or 128x64), every pixel drawn as a
+// scale x scale square in palette[pixel value]
+pub fn encode_png(chip8: &Chip8, scale: u32, palette: &[Rgb; 4]) -> Vec<u8> {
+    let width = chip8.resolution().width();
+    let height = chip8.resolution().height();
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in chip8.display().iter().take(height) {
+        for &pixel in row.iter().take(width) {
+            rgba.extend_from_slice(&palette[pixel as usize & 0b11]);
+            rgba.push(255);
+        }
+    }
+
+    return encode_rgba_png(width, height, &rgba, scale);
+}
+
+// Any width x height RGBA picture (alpha is dropped), scaled up by `scale`
+pub fn encode_rgba_png(width: usize, height: usize, rgba: &[u8], scale: u32) -> Vec<u8> {
+    let scale = scale.max(1) as usize;
+    let (out_width, out_height) = (width * scale, height * scale);
+
+    let mut raw = Vec::with_capacity((out_width * 3 + 1) * out_height);
+    for y in 0..out_height {
+        // filter type 0 (none) for every scanline
+        raw.push(0);
+        let row = &rgba[y / scale * width * 4..(y / scale + 1) * width * 4];
+        for x in 0..out_width {
+            raw.extend_from_slice(&row[x / scale * 4..x / scale * 4 + 3]);
+        }
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(out_width as u32).to_be_bytes());
+    header.extend_from_slice(&(out_height as u32).to_be_bytes());
+    // 8-bit RGB, default compression/filter, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut png, b"IEND", &[]);
+
+    return png;
+}
@@ -0,0 +1,477 @@
+// Lua 5.4 bots, cheats and test oracles (--script, behind the "scripting" feature). The
+// script's top level runs once when it is loaded, it sets up globals and may define the
+// callbacks the frontend calls between frames:
+//
+//   last_y = 0                          -- globals keep their value between calls
+//   function on_frame()
+//       if reg(7) < reg(0xB) + 3 then press(1) else release(1) end
+//   end
+//   function on_breakpoint(addr) print("hit", addr); resume() end
+//
+// The machine is reached through the global functions in BUILTINS and builtin(). Scripts
+// get Lua's string, table, math, utf8 and coroutine libraries, not io or os. Every call
+// gets a time budget, a script that runs past it is stopped with an error instead of
+// hanging the window.
+
+use crate::Chip8;
+use mlua::{Function, HookTriggers, Lua, LuaOptions, StdLib, Value, Variadic, VmState};
+use std::cell::RefCell;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_BUDGET: Duration = Duration::from_millis(10);
+// Lua instructions between clock checks
+const CLOCK_INTERVAL: u32 = 1000;
+// what a script may allocate, so a runaway table can't take the machine down
+const MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+// the chunk name, Lua puts it in front of its error messages
+const CHUNK: &str = "script";
+
+// The built-ins besides print(), see builtin()
+const BUILTINS: [&str; 22] = [
+    "pc",
+    "reg_i",
+    "dt",
+    "st",
+    "width",
+    "height",
+    "frame",
+    "screenshot",
+    "pause",
+    "resume",
+    "read_mem",
+    "reg",
+    "set_i",
+    "set_dt",
+    "set_st",
+    "press",
+    "release",
+    "break_at",
+    "clear_break",
+    "write_mem",
+    "set_reg",
+    "pixel",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    // None when the error isn't at a line of the script
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => return write!(f, "line {line}: {}", self.message),
+            None => return write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+// Lua's messages start with "script:LINE: " when they come from a line of the script.
+// Errors raised in a built-in are wrapped in CallbackErrors and carry a traceback.
+impl From<mlua::Error> for ScriptError {
+    fn from(error: mlua::Error) -> Self {
+        let mut error = &error;
+        while let mlua::Error::CallbackError { cause, .. } = error {
+            error = cause;
+        }
+        let message = match error {
+            mlua::Error::SyntaxError { message, .. } | mlua::Error::RuntimeError(message) => {
+                message.clone()
+            }
+            error => error.to_string(),
+        };
+        let message = message.lines().next().unwrap_or_default();
+        let located = message
+            .strip_prefix(CHUNK)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .and_then(|rest| rest.split_once(": "))
+            .and_then(|(line, message)| Some((line.parse().ok()?, message)));
+
+        match located {
+            Some((line, message)) => {
+                return ScriptError {
+                    line: Some(line),
+                    message: message.to_string(),
+                };
+            }
+            None => {
+                return ScriptError {
+                    line: None,
+                    message: message.to_string(),
+                };
+            }
+        }
+    }
+}
+
+// What the script asked the frontend for since the last take_requests()
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptRequests {
+    pub screenshot: bool,
+    // pause() is Some(true), resume() Some(false)
+    pub pause: Option<bool>,
+    // print() output, one line per call
+    pub output: String,
+}
+
+#[derive(Default)]
+struct State {
+    keys: [bool; 16],
+    requests: ScriptRequests,
+    // on_frame calls so far
+    frame: i64,
+}
+
+pub struct Script {
+    lua: Lua,
+    state: State,
+    budget: Duration,
+}
+
+impl Script {
+    // Compiles `source` and runs its top level, which defines the globals and callbacks
+    pub fn load(source: &str, chip8: &mut Chip8) -> Result<Self, ScriptError> {
+        let libs = StdLib::STRING | StdLib::TABLE | StdLib::MATH | StdLib::UTF8 | StdLib::COROUTINE;
+        let lua = Lua::new_with(libs, LuaOptions::default())?;
+        lua.set_memory_limit(MEMORY_LIMIT)?;
+        let mut script = Script {
+            lua,
+            state: State::default(),
+            budget: DEFAULT_BUDGET,
+        };
+        script.run(chip8, |lua| {
+            lua.load(source).set_name(format!("={CHUNK}")).exec()
+        })?;
+        for hook in ["on_frame", "on_breakpoint"] {
+            match script.lua.globals().get::<Value>(hook)? {
+                Value::Nil | Value::Function(_) => {}
+                _ => {
+                    return Err(ScriptError {
+                        line: None,
+                        message: format!("{hook} has to be a function"),
+                    });
+                }
+            }
+        }
+
+        return Ok(script);
+    }
+
+    pub fn set_budget(&mut self, budget: Duration) {
+        self.budget = budget;
+    }
+
+    pub fn on_frame(&mut self, chip8: &mut Chip8) -> Result<(), ScriptError> {
+        self.state.frame += 1;
+        return self.call_hook(chip8, "on_frame", None);
+    }
+
+    pub fn on_breakpoint(&mut self, chip8: &mut Chip8, addr: u16) -> Result<(), ScriptError> {
+        return self.call_hook(chip8, "on_breakpoint", Some(addr));
+    }
+
+    // CHIP-8 keys held with press(), the frontend adds them to the keyboard's
+    pub fn keys(&self) -> [bool; 16] {
+        return self.state.keys;
+    }
+
+    pub fn take_requests(&mut self) -> ScriptRequests {
+        return std::mem::take(&mut self.state.requests);
+    }
+
+    // Scripts don't have to define every hook
+    fn call_hook(
+        &mut self,
+        chip8: &mut Chip8,
+        hook: &str,
+        addr: Option<u16>,
+    ) -> Result<(), ScriptError> {
+        return self.run(chip8, |lua| {
+            match lua.globals().get::<Option<Function>>(hook)? {
+                Some(function) => return function.call::<()>(addr),
+                None => return Ok(()),
+            }
+        });
+    }
+
+    // Runs `f` with the built-ins reaching `chip8` and the clock started
+    fn run<R>(
+        &mut self,
+        chip8: &mut Chip8,
+        f: impl FnOnce(&Lua) -> mlua::Result<R>,
+    ) -> Result<R, ScriptError> {
+        let budget = self.budget;
+        let deadline = Instant::now() + budget;
+        let triggers = HookTriggers::new().every_nth_instruction(CLOCK_INTERVAL);
+        self.lua.set_hook(triggers, move |_, debug| {
+            if Instant::now() > deadline {
+                let message = format!("the script ran longer than {}ms", budget.as_millis());
+                return Err(located(debug.current_line(), message));
+            }
+            return Ok(VmState::Continue);
+        })?;
+
+        let lua = &self.lua;
+        let chip8 = RefCell::new(chip8);
+        let state = RefCell::new(&mut self.state);
+        let (chip8, state) = (&chip8, &state);
+        let result = lua.scope(|scope| {
+            let globals = lua.globals();
+            for name in BUILTINS {
+                let function = scope.create_function(move |lua, args: Variadic<i64>| {
+                    let value = builtin(
+                        &mut chip8.borrow_mut(),
+                        &mut state.borrow_mut(),
+                        name,
+                        &args,
+                    );
+                    // the line of the script that called it
+                    let line = || lua.inspect_stack(1, |debug| debug.current_line()).flatten();
+                    return value.map_err(|message| located(line(), message));
+                })?;
+                globals.set(name, function)?;
+            }
+            let print = scope.create_function(move |_, args: Variadic<Value>| {
+                let mut words = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    words.push(arg.to_string()?);
+                }
+                let output = &mut state.borrow_mut().requests.output;
+                output.push_str(&words.join(" "));
+                output.push('\n');
+                return Ok(());
+            })?;
+            globals.set("print", print)?;
+
+            return f(lua);
+        });
+
+        return Ok(result?);
+    }
+}
+
+// An error raised from Rust, placed like Lua's own
+fn located(line: Option<usize>, message: String) -> mlua::Error {
+    match line {
+        Some(line) => return mlua::Error::runtime(format!("{CHUNK}:{line}: {message}")),
+        None => return mlua::Error::runtime(message),
+    }
+}
+
+// The machine and the frontend as seen from a script
+fn builtin(chip8: &mut Chip8, state: &mut State, name: &str, args: &[i64]) -> Result<i64, String> {
+    let arity = match name {
+        "pc" | "reg_i" | "dt" | "st" | "width" | "height" | "frame" | "screenshot" | "pause"
+        | "resume" => 0,
+        "write_mem" | "set_reg" | "pixel" => 2,
+        _ => 1,
+    };
+    if args.len() != arity {
+        return Err(format!(
+            "{name} takes {arity} argument(s), got {}",
+            args.len()
+        ));
+    }
+    let arg = |index: usize, max: i64| {
+        let value = args[index];
+        if !(0..=max).contains(&value) {
+            return Err(format!("{name} expects 0 to {max:#X}, got {value}"));
+        }
+        return Ok(value);
+    };
+
+    match name {
+        "pc" => return Ok(chip8.pc() as i64),
+        "reg_i" => return Ok(chip8.reg_i() as i64),
+        "dt" => return Ok(chip8.delay_timer() as i64),
+        "st" => return Ok(chip8.sound_timer() as i64),
+        "width" => return Ok(chip8.resolution().width() as i64),
+        "height" => return Ok(chip8.resolution().height() as i64),
+        "frame" => return Ok(state.frame),
+        "screenshot" => state.requests.screenshot = true,
+        "pause" => state.requests.pause = Some(true),
+        "resume" => state.requests.pause = Some(false),
+        "read_mem" => {
+            let addr = arg(0, chip8.mem().len() as i64 - 1)?;
+            return chip8
+                .read_mem(addr as usize)
+                .map(|val| val as i64)
+                .map_err(|e| e.to_string());
+        }
+        "write_mem" => {
+            let addr = arg(0, chip8.mem().len() as i64 - 1)?;
+            let val = arg(1, 0xFF)? as u8;
+            return chip8
+                .poke(addr as usize, val)
+                .map(|()| 0)
+                .map_err(|e| e.to_string());
+        }
+        "reg" => return Ok(chip8.registers()[arg(0, 0xF)? as usize] as i64),
+        "set_reg" => chip8.set_register(arg(0, 0xF)? as u8, arg(1, 0xFF)? as u8),
+        "set_i" => chip8.set_reg_i(arg(0, 0xFFFF)? as u16),
+        "set_dt" => chip8.set_delay_timer(arg(0, 0xFF)? as u8),
+        "set_st" => chip8.set_sound_timer(arg(0, 0xFF)? as u8),
+        "press" => state.keys[arg(0, 0xF)? as usize] = true,
+        "release" => state.keys[arg(0, 0xF)? as usize] = false,
+        "break_at" => chip8.set_breakpoint(arg(0, 0xFFFF)? as u16),
+        "clear_break" => {
+            chip8.clear_breakpoint(arg(0, 0xFFFF)? as u16);
+        }
+        _ => {
+            let (x, y) = (args[0], args[1]);
+            let resolution = chip8.resolution();
+            let inside = (0..resolution.width() as i64).contains(&x)
+                && (0..resolution.height() as i64).contains(&y);
+            if !inside {
+                return Ok(0);
+            }
+            return Ok(chip8.display()[y as usize][x as usize] as i64);
+        }
+    }
+
+    return Ok(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::with_seed(0);
+        // LD V7, 0x20; JP 0x202
+        chip8.load_rom(&[0x67, 0x20, 0x12, 0x02]).unwrap();
+        chip8.step().unwrap();
+        return chip8;
+    }
+
+    // The error from loading `source` or its first on_frame() with `budget`
+    fn error_within(source: &str, budget: Duration) -> ScriptError {
+        let mut chip8 = machine();
+        let mut script = match Script::load(source, &mut chip8) {
+            Ok(script) => script,
+            Err(e) => return e,
+        };
+        script.set_budget(budget);
+        return script.on_frame(&mut chip8).unwrap_err();
+    }
+
+    fn error(source: &str) -> ScriptError {
+        return error_within(source, Duration::from_millis(20));
+    }
+
+    #[test]
+    fn hooks_keep_their_globals() {
+        let source = "
+            seen = 0
+            break_at(0x202)
+            function on_frame()
+                seen = seen + 1
+                if reg(7) == 0x20 then press(0xA) end
+                print('frame', frame(), seen, pc())
+            end
+            function on_breakpoint(addr)
+                set_reg(0, addr & 0xFF)
+                write_mem(0x300, read_mem(0x200))
+                pause()
+            end";
+        let mut chip8 = machine();
+        let mut script = Script::load(source, &mut chip8).unwrap();
+        assert!(chip8.at_breakpoint());
+        for _ in 0..2 {
+            script.on_frame(&mut chip8).unwrap();
+        }
+        assert!(script.keys()[0xA]);
+        script.on_breakpoint(&mut chip8, 0x202).unwrap();
+        assert_eq!(chip8.registers()[0], 0x02);
+        assert_eq!(chip8.read_mem(0x300), Ok(0x67));
+
+        let requests = script.take_requests();
+        assert_eq!(requests.output, "frame 1 1 514\nframe 2 2 514\n");
+        assert_eq!(requests.pause, Some(true));
+        assert_eq!(script.take_requests(), ScriptRequests::default());
+    }
+
+    #[test]
+    fn hooks_are_optional() {
+        let mut chip8 = machine();
+        let mut script = Script::load("x = 1", &mut chip8).unwrap();
+        script.on_frame(&mut chip8).unwrap();
+        script.on_breakpoint(&mut chip8, 0x200).unwrap();
+        assert_eq!(script.keys(), [false; 16]);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let at = |line, message: &str| ScriptError {
+            line: Some(line),
+            message: message.to_string(),
+        };
+        assert_eq!(
+            error("x = 1\nfunction on_frame()\n  x = \nend"),
+            at(4, "unexpected symbol near 'end'")
+        );
+        assert_eq!(
+            error("function on_frame()\n\n  press(16)\nend"),
+            at(3, "press expects 0 to 0xF, got 16")
+        );
+        assert_eq!(
+            error("function on_frame()\n  reg(1, 2)\nend"),
+            at(2, "reg takes 1 argument(s), got 2")
+        );
+        assert_eq!(
+            error("function on_frame()\n  return nothing.here\nend"),
+            at(2, "attempt to index a nil value (global 'nothing')")
+        );
+        // without io and os a script can't close the window
+        assert_eq!(
+            error("os.exit(1)"),
+            at(1, "attempt to index a nil value (global 'os')")
+        );
+        let hook = error("on_frame = 3");
+        assert_eq!(hook.to_string(), "on_frame has to be a function");
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let hang = error("function on_frame()\n  while true do end\nend");
+        assert_eq!(
+            hang,
+            ScriptError {
+                line: Some(2),
+                message: String::from("the script ran longer than 20ms"),
+            }
+        );
+        // Lua's stack limit or the memory limit, whichever comes first, but that takes a
+        // while to reach in a debug build
+        let recursion = error_within(
+            "function f() return f() + 1 end\nfunction on_frame() f() end",
+            Duration::from_secs(30),
+        );
+        assert!(
+            recursion.message.contains("stack overflow") || recursion.message.contains("memory"),
+            "{recursion}"
+        );
+        let memory = error(
+            "function on_frame()\n  t = {}\n  while true do t[#t + 1] = string.rep(\"x\", 1 << 20) end\nend",
+        );
+        assert!(memory.message.contains("memory"), "{memory}");
+    }
+
+    #[test]
+    fn pong_example_loads() {
+        let source = include_str!("../examples/scripts/pong.lua");
+        let mut chip8 = machine();
+        let mut script = Script::load(source, &mut chip8).unwrap();
+        for _ in 0..3 {
+            script.on_frame(&mut chip8).unwrap();
+        }
+        // the ball at y 0x20 is below a paddle at 0
+        assert!(script.keys()[0x4]);
+        assert!(!script.keys()[0x1]);
+    }
+}
@@ -0,0 +1,414 @@
+// The machine state as plain data with public fields, for embedders that want to compare
+// two machines field by field (differential testing against another emulator) or keep the
+// state in their own format. Chip8::restore() puts one back into a machine. With the serde
+// feature it derives Serialize and Deserialize, and Chip8 itself (de)serializes through it,
+// e.g. to JSON or bincode. Without serde, to_json() writes the schema below.
+//
+//   { "pc": 512, "i": 0, "v": [16 numbers], "stack": [...], "delay_timer": 0,
+//     "sound_timer": 0, "resolution": "64x32", "vip_hires": false, "planes": 1,
+//     "quirks": { "shift_uses_vy": true, ... }, "waiting_for_key": null,
+//     "pressed_while_waiting": null, "waiting_for_vblank": false, "keypad": "0000000000000000",
+//     "exited": false, "rpl_flags": [8 numbers], "pitch": 64, "audio_pattern": "00...",
+//     "uses_audio_pattern": false, "rng": 1234, "rom_hash": 5678, "load_addr": 512,
+//     "memory": "hex", "display": ["0110...", ...] }
+//
+// Keypad is key 0 first, display rows hold one digit per pixel (its planes, 0-3).
+
+use crate::{
+    Chip8, EXTENDED_MEM_SIZE, HIRES_HEIGHT, HIRES_WIDTH, MEM_SIZE, Pixel, Quirks, Resolution, Rng,
+    StateError,
+};
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    pub pc: u16,
+    pub i: u16,
+    pub v: [u8; 16],
+    pub stack: Vec<u16>,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub resolution: Resolution,
+    // see Chip8::set_vip_hires()
+    pub vip_hires: bool,
+    pub selected_planes: u8,
+    pub quirks: Quirks,
+    // FX0A's destination register while it waits
+    pub waiting_for_key: Option<u8>,
+    // and the key it saw go down, FX0A ends when that one is released
+    pub pressed_while_waiting: Option<u8>,
+    // DXYN under the display_wait quirk, until the next timer tick
+    pub waiting_for_vblank: bool,
+    pub keypad: [bool; 16],
+    pub exited: bool,
+    pub rpl_flags: [u8; 8],
+    pub pitch: u8,
+    pub audio_pattern: [u8; 16],
+    pub uses_audio_pattern: bool,
+    // the CXNN generator
+    pub rng: u64,
+    // see Chip8::rom_hash()
+    pub rom_hash: u64,
+    pub load_addr: u16,
+    #[cfg_attr(feature = "serde", serde(with = "serde_bytes"))]
+    pub memory: Vec<u8>,
+    // the visible display_size() pixels, row-major
+    #[cfg_attr(feature = "serde", serde(with = "serde_bytes"))]
+    pub display: Vec<Pixel>,
+}
+
+impl Snapshot {
+    // Names of the fields that differ, in declaration order
+    pub fn differences(&self, other: &Snapshot) -> Vec<&'static str> {
+        let fields = [
+            ("pc", self.pc != other.pc),
+            ("i", self.i != other.i),
+            ("v", self.v != other.v),
+            ("stack", self.stack != other.stack),
+            ("delay_timer", self.delay_timer != other.delay_timer),
+            ("sound_timer", self.sound_timer != other.sound_timer),
+            ("resolution", self.resolution != other.resolution),
+            ("vip_hires", self.vip_hires != other.vip_hires),
+            ("planes", self.selected_planes != other.selected_planes),
+            ("quirks", self.quirks != other.quirks),
+            (
+                "waiting_for_key",
+                self.waiting_for_key != other.waiting_for_key,
+            ),
+            (
+                "pressed_while_waiting",
+                self.pressed_while_waiting != other.pressed_while_waiting,
+            ),
+            (
+                "waiting_for_vblank",
+                self.waiting_for_vblank != other.waiting_for_vblank,
+            ),
+            ("keypad", self.keypad != other.keypad),
+            ("exited", self.exited != other.exited),
+            ("rpl_flags", self.rpl_flags != other.rpl_flags),
+            ("pitch", self.pitch != other.pitch),
+            ("audio_pattern", self.audio_pattern != other.audio_pattern),
+            (
+                "uses_audio_pattern",
+                self.uses_audio_pattern != other.uses_audio_pattern,
+            ),
+            ("rng", self.rng != other.rng),
+            ("rom_hash", self.rom_hash != other.rom_hash),
+            ("load_addr", self.load_addr != other.load_addr),
+            ("memory", self.memory != other.memory),
+            ("display", self.display != other.display),
+        ];
+
+        return fields
+            .iter()
+            .filter(|(_, differs)| *differs)
+            .map(|(name, _)| *name)
+            .collect();
+    }
+
+    pub fn to_json(&self) -> String {
+        let quirks = self.quirks;
+
+        let mut out = String::from("{\n");
+        writeln!(out, "  \"pc\": {},", self.pc).unwrap();
+        writeln!(out, "  \"i\": {},", self.i).unwrap();
+        let v = join(&self.v);
+        writeln!(out, "  \"v\": [{v}],").unwrap();
+        let stack = join(&self.stack);
+        writeln!(out, "  \"stack\": [{stack}],").unwrap();
+        writeln!(out, "  \"delay_timer\": {},", self.delay_timer).unwrap();
+        writeln!(out, "  \"sound_timer\": {},", self.sound_timer).unwrap();
+        let (width, height) = (self.resolution.width(), self.resolution.height());
+        writeln!(out, "  \"resolution\": \"{width}x{height}\",").unwrap();
+        writeln!(out, "  \"vip_hires\": {},", self.vip_hires).unwrap();
+        writeln!(out, "  \"planes\": {},", self.selected_planes).unwrap();
+        writeln!(
+            out,
+            "  \"quirks\": {{ \"shift_uses_vy\": {}, \"memory_increment\": {}, \"jump_uses_vx\": {}, \"sprite_wrap\": {}, \"vf_reset\": {}, \"display_wait\": {}, \"index_overflow\": {} }},",
+            quirks.shift_uses_vy,
+            quirks.memory_increment,
+            quirks.jump_uses_vx,
+            quirks.sprite_wrap,
+            quirks.vf_reset,
+            quirks.display_wait,
+            quirks.index_overflow
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  \"waiting_for_key\": {},",
+            self.waiting_for_key
+                .map_or(String::from("null"), |x| x.to_string())
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  \"pressed_while_waiting\": {},",
+            self.pressed_while_waiting
+                .map_or(String::from("null"), |x| x.to_string())
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  \"waiting_for_vblank\": {},",
+            self.waiting_for_vblank
+        )
+        .unwrap();
+        let keypad: String = self
+            .keypad
+            .iter()
+            .map(|&down| (b'0' + down as u8) as char)
+            .collect();
+        writeln!(out, "  \"keypad\": \"{keypad}\",").unwrap();
+        writeln!(out, "  \"exited\": {},", self.exited).unwrap();
+        let rpl_flags = join(&self.rpl_flags);
+        writeln!(out, "  \"rpl_flags\": [{rpl_flags}],").unwrap();
+        writeln!(out, "  \"pitch\": {},", self.pitch).unwrap();
+        writeln!(
+            out,
+            "  \"audio_pattern\": \"{}\",",
+            hex(&self.audio_pattern)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "  \"uses_audio_pattern\": {},",
+            self.uses_audio_pattern
+        )
+        .unwrap();
+        writeln!(out, "  \"rng\": {},", self.rng).unwrap();
+        writeln!(out, "  \"rom_hash\": {},", self.rom_hash).unwrap();
+        writeln!(out, "  \"load_addr\": {},", self.load_addr).unwrap();
+        writeln!(out, "  \"memory\": \"{}\",", hex(&self.memory)).unwrap();
+        out.push_str("  \"display\": [");
+        for (y, row) in self.display.chunks(width).enumerate() {
+            let comma = if y + 1 < height { "," } else { "" };
+            let row: String = row.iter().map(|&pixel| (b'0' + pixel) as char).collect();
+            write!(out, "\n    \"{row}\"{comma}").unwrap();
+        }
+        out.push_str("\n  ]\n}\n");
+
+        return out;
+    }
+}
+
+fn join<T: ToString>(values: &[T]) -> String {
+    return values
+        .iter()
+        .map(T::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+}
+
+fn hex(bytes: &[u8]) -> String {
+    return bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+}
+
+impl Chip8 {
+    pub fn snapshot(&self) -> Snapshot {
+        return Snapshot {
+            pc: self.pc,
+            i: self.reg_i,
+            v: self.registers,
+            stack: self.stack.clone(),
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            resolution: self.resolution,
+            vip_hires: self.vip_hires,
+            selected_planes: self.selected_planes,
+            quirks: self.quirks,
+            waiting_for_key: self.waiting_for_key,
+            pressed_while_waiting: self.pressed_while_waiting,
+            waiting_for_vblank: self.waiting_for_vblank,
+            keypad: self.input.keys(),
+            exited: self.exited,
+            rpl_flags: self.rpl_flags,
+            pitch: self.pitch,
+            audio_pattern: self.audio_pattern,
+            uses_audio_pattern: self.uses_audio_pattern,
+            rng: self.rng.state,
+            rom_hash: self.rom_hash,
+            load_addr: self.load_addr,
+            memory: self.mem.to_vec(),
+            display: self.display_rows().flatten().copied().collect(),
+        };
+    }
+
+    // Like load_state(), nothing changes unless the whole snapshot makes sense. Settings
+    // that aren't in it (breakpoints, cheats, strict modes...) stay as they are.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), StateError> {
+        let size = snapshot.memory.len();
+        if size != MEM_SIZE && size != EXTENDED_MEM_SIZE {
+            return Err(StateError::Corrupt("memory size"));
+        }
+        let load_addr = snapshot.load_addr;
+        if !load_addr.is_multiple_of(2) || load_addr as usize >= size {
+            return Err(StateError::Corrupt("load address"));
+        }
+        let key = |key: Option<u8>| key.is_some_and(|key| key > 0xF);
+        if key(snapshot.waiting_for_key) || key(snapshot.pressed_while_waiting) {
+            return Err(StateError::Corrupt("wait register"));
+        }
+        let (width, height) = (snapshot.resolution.width(), snapshot.resolution.height());
+        if snapshot.display.len() != width * height {
+            return Err(StateError::Corrupt("display size"));
+        }
+
+        let mut display = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+        for (row, pixels) in display.iter_mut().zip(snapshot.display.chunks(width)) {
+            row[..width].copy_from_slice(pixels);
+        }
+        self.mem = snapshot.memory.clone().into_boxed_slice();
+        self.pc = snapshot.pc;
+        self.reg_i = snapshot.i;
+        self.registers = snapshot.v;
+        self.stack = snapshot.stack.clone();
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.display = display;
+        self.display_dirty = true;
+        self.resolution = snapshot.resolution;
+        self.vip_hires = snapshot.vip_hires;
+        self.selected_planes = snapshot.selected_planes & 0b11;
+        self.quirks = snapshot.quirks;
+        self.waiting_for_key = snapshot.waiting_for_key;
+        self.pressed_while_waiting = snapshot.pressed_while_waiting;
+        self.waiting_for_vblank = snapshot.waiting_for_vblank;
+        self.input.restore(snapshot.keypad);
+        self.exited = snapshot.exited;
+        self.rpl_flags = snapshot.rpl_flags;
+        self.pitch = snapshot.pitch;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.uses_audio_pattern = snapshot.uses_audio_pattern;
+        self.rng = Rng::new(snapshot.rng);
+        self.rom_hash = snapshot.rom_hash;
+        self.load_addr = load_addr;
+        self.idle.progress();
+
+        return Ok(());
+    }
+}
+
+// A Chip8 (de)serializes as its Snapshot, so only the machine state travels. Deserializing
+// gives a machine with default settings.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Chip8 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        return serde::Serialize::serialize(&self.snapshot(), serializer);
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Chip8 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let snapshot: Snapshot = serde::Deserialize::deserialize(deserializer)?;
+        let mut chip8 = Chip8::with_seed(snapshot.rng);
+        chip8.restore(&snapshot).map_err(serde::de::Error::custom)?;
+
+        return Ok(chip8);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Profile;
+
+    // A DXYN waiting for the vertical blank under display_wait, with a key held:
+    // LD I, 0x050; RND V1, 0xFF; loop: DRW V0, V1, 5; ADD V1, 1; JP loop
+    fn mid_draw() -> Chip8 {
+        let rom = [0xA0, 0x50, 0xC1, 0xFF, 0xD0, 0x15, 0x71, 0x01, 0x12, 0x04];
+        let mut chip8 = Chip8::with_seed(3);
+        chip8.set_quirks(Profile::Cosmac.quirks());
+        chip8.load_rom(&rom).unwrap();
+        chip8.set_key(0x7, true);
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        assert!(chip8.is_waiting_for_vblank());
+        return chip8;
+    }
+
+    // Both machines keep going the same way, the wait included
+    fn assert_runs_the_same(mut a: Chip8, mut b: Chip8) {
+        assert_eq!(a.snapshot(), b.snapshot());
+        for machine in [&mut a, &mut b] {
+            machine.step().unwrap();
+            assert!(machine.is_waiting_for_vblank());
+            for _ in 0..4 {
+                machine.tick_60hz();
+                for _ in 0..3 {
+                    machine.step().unwrap();
+                }
+            }
+        }
+        assert_eq!(a.snapshot().differences(&b.snapshot()), Vec::<&str>::new());
+        assert_eq!(a.save_state(), b.save_state());
+    }
+
+    #[test]
+    fn restore_round_trips() {
+        let chip8 = mid_draw();
+        let mut copy = Chip8::with_seed(0);
+        copy.restore(&chip8.snapshot()).unwrap();
+        assert!(copy.is_waiting_for_vblank());
+        assert_runs_the_same(chip8, copy);
+    }
+
+    #[test]
+    fn bad_snapshots_leave_the_machine_alone() {
+        let snapshot = mid_draw().snapshot();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        let before = chip8.save_state();
+
+        let mut memory = snapshot.clone();
+        memory.memory.truncate(100);
+        let mut display = snapshot.clone();
+        display.display.pop();
+        let mut register = snapshot.clone();
+        register.waiting_for_key = Some(0x10);
+        for (bad, error) in [
+            (memory, "memory size"),
+            (display, "display size"),
+            (register, "wait register"),
+        ] {
+            assert_eq!(chip8.restore(&bad), Err(StateError::Corrupt(error)));
+        }
+        assert_eq!(chip8.save_state(), before);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trips() {
+        let chip8 = mid_draw();
+        let json = serde_json::to_string(&chip8).unwrap();
+        assert!(json.contains("\"waiting_for_vblank\":true"), "{json}");
+        assert!(json.contains("\"resolution\":\"Low\""), "{json}");
+        let copy: Chip8 = serde_json::from_str(&json).unwrap();
+        assert_runs_the_same(chip8, copy);
+
+        let mut bad = mid_draw().snapshot();
+        bad.load_addr = 0x201;
+        let Err(error) = serde_json::from_str::<Chip8>(&serde_json::to_string(&bad).unwrap())
+        else {
+            panic!("an odd load address deserialized");
+        };
+        assert!(error.to_string().contains("load address"), "{error}");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn bincode_round_trips() {
+        let chip8 = mid_draw();
+        let bytes = bincode::serialize(&chip8).unwrap();
+        // memory goes as bytes, not as a sequence of numbers
+        assert!(bytes.len() < 4096 + 2048 + 512, "{} bytes", bytes.len());
+        let copy: Chip8 = bincode::deserialize(&bytes).unwrap();
+        assert_runs_the_same(chip8, copy);
+
+        let snapshot: Snapshot = bincode::deserialize(&bytes).unwrap();
+        assert_eq!(snapshot, mid_draw().snapshot());
+    }
+}
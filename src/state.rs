@@ -0,0 +1,529 @@
+// Save states. Layout (all integers big-endian):
+//
+//   magic "RC8S" | version u16 | rom hash u64 | fields for that version...
+//
+// Bump STATE_VERSION whenever fields are added and keep reading the older layouts.
+
+use crate::{
+    Cheat, CheatKind, Chip8, DEFAULT_PITCH, EXTENDED_MEM_SIZE, Framebuffer, HIRES_HEIGHT,
+    HIRES_WIDTH, MEM_SIZE, PROGRAM_START, Pixel, Quirks, Resolution, Rng, SCREEN_HEIGHT,
+    SCREEN_WIDTH,
+};
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"RC8S";
+// 1: original CHIP-8 machine
+// 2: 128x64 framebuffer, resolution, RPL flags and exit flag for SUPER-CHIP
+// 3: second XO-CHIP bitplane and the selected plane mask
+// 4: memory is prefixed with its size (4K or the XO-CHIP 64K)
+// 5: XO-CHIP audio pattern and pitch
+// 6: display_wait quirk and its pending vertical blank
+// 7: program load address
+// 8: cheats and whether each is on
+// 9: VIP hi-res mode
+const STATE_VERSION: u16 = 9;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    Truncated,
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a RustChip8 save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {v}"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Corrupt(what) => write!(f, "save state is corrupt: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+pub struct LoadedState {
+    // The state was saved while a different ROM was loaded
+    pub rom_mismatch: bool,
+}
+
+// FNV-1a, only used to tell ROMs apart
+pub(crate) fn rom_hash(rom: &[u8]) -> u64 {
+    let mut hash: u64 = 0xCBF2_9CE4_8422_2325;
+    for byte in rom {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01B3);
+    }
+
+    return hash;
+}
+
+pub(crate) struct Reader<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(StateError::Truncated)?;
+        self.pos += len;
+
+        return Ok(bytes);
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        return Ok(self.bytes(1)?[0]);
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        return Ok(u16::from_be_bytes([bytes[0], bytes[1]]));
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.bytes(4)?);
+        return Ok(u32::from_be_bytes(buf));
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.bytes(8)?);
+        return Ok(u64::from_be_bytes(buf));
+    }
+}
+
+// Option<u8> register/key indices are stored with 0xFF meaning None
+const NONE: u8 = 0xFF;
+
+fn optional(val: u8, what: &'static str) -> Result<Option<u8>, StateError> {
+    match val {
+        NONE => return Ok(None),
+        0..=0xF => return Ok(Some(val)),
+        _ => return Err(StateError::Corrupt(what)),
+    }
+}
+
+// One bit per pixel, rows of HIRES_WIDTH
+fn pack_plane(out: &mut Vec<u8>, display: &Framebuffer, plane: usize) {
+    for row in display {
+        for pixels in row.chunks(8) {
+            out.push(
+                pixels
+                    .iter()
+                    .fold(0, |byte, &p| byte << 1 | (p >> plane) & 1),
+            );
+        }
+    }
+}
+
+fn unpack_plane(
+    r: &mut Reader,
+    display: &mut Framebuffer,
+    plane: usize,
+    width: usize,
+    height: usize,
+) -> Result<(), StateError> {
+    for row in display.iter_mut().take(height) {
+        let packed = r.bytes(width / 8)?;
+        for (x, pixel) in row.iter_mut().take(width).enumerate() {
+            *pixel |= (packed[x / 8] >> (7 - x % 8) & 1) << plane as Pixel;
+        }
+    }
+
+    return Ok(());
+}
+
+impl Quirks {
+    pub(crate) fn to_bits(self) -> u8 {
+        return self.shift_uses_vy as u8
+            | (self.memory_increment as u8) << 1
+            | (self.jump_uses_vx as u8) << 2
+            | (self.sprite_wrap as u8) << 3
+            | (self.vf_reset as u8) << 4
+            | (self.display_wait as u8) << 5
+            | (self.index_overflow as u8) << 6;
+    }
+
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        Quirks {
+            shift_uses_vy: bits & 1 != 0,
+            memory_increment: bits & 1 << 1 != 0,
+            jump_uses_vx: bits & 1 << 2 != 0,
+            sprite_wrap: bits & 1 << 3 != 0,
+            vf_reset: bits & 1 << 4 != 0,
+            display_wait: bits & 1 << 5 != 0,
+            index_overflow: bits & 1 << 6 != 0,
+        }
+    }
+}
+
+impl Chip8 {
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.mem.len() + 2048);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&STATE_VERSION.to_be_bytes());
+        out.extend_from_slice(&self.rom_hash.to_be_bytes());
+
+        out.extend_from_slice(&(self.mem.len() as u32).to_be_bytes());
+        out.extend_from_slice(&self.mem);
+        out.extend_from_slice(&self.pc.to_be_bytes());
+        out.extend_from_slice(&self.reg_i.to_be_bytes());
+        out.push(self.stack.len() as u8);
+        for addr in &self.stack {
+            out.extend_from_slice(&addr.to_be_bytes());
+        }
+        out.extend_from_slice(&self.registers);
+        pack_plane(&mut out, &self.display, 0);
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+        let keypad = (0..16).fold(0u16, |bits, i| {
+            bits | (self.input.is_held(i as u8) as u16) << i
+        });
+        out.extend_from_slice(&keypad.to_be_bytes());
+        out.push(self.quirks.to_bits());
+        out.extend_from_slice(&self.rng.state.to_be_bytes());
+        out.push(self.waiting_for_key.unwrap_or(NONE));
+        out.push(self.pressed_while_waiting.unwrap_or(NONE));
+        out.push(self.resolution as u8);
+        out.extend_from_slice(&self.rpl_flags);
+        out.push(self.exited as u8);
+        pack_plane(&mut out, &self.display, 1);
+        out.push(self.selected_planes);
+        out.extend_from_slice(&self.audio_pattern);
+        out.push(self.pitch);
+        out.push(self.uses_audio_pattern as u8);
+        out.push(self.waiting_for_vblank as u8);
+        out.extend_from_slice(&self.load_addr.to_be_bytes());
+        out.extend_from_slice(&(self.cheats.len() as u16).to_be_bytes());
+        for cheat in &self.cheats {
+            out.push(cheat.kind as u8);
+            out.extend_from_slice(&cheat.addr.to_be_bytes());
+            out.push(cheat.value);
+            out.push(cheat.enabled as u8);
+            // names are cut at 255 bytes, on a char boundary
+            let mut len = cheat.name.len().min(255);
+            while !cheat.name.is_char_boundary(len) {
+                len -= 1;
+            }
+            out.push(len as u8);
+            out.extend_from_slice(&cheat.name.as_bytes()[..len]);
+        }
+        out.push(self.vip_hires as u8);
+
+        return out;
+    }
+
+    // The current state is only replaced once the whole snapshot has been parsed
+    pub fn load_state(&mut self, data: &[u8]) -> Result<LoadedState, StateError> {
+        let mut r = Reader { data, pos: 0 };
+        if r.bytes(4).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version == 0 || version > STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        let rom_hash = r.u64()?;
+
+        let mem_size = if version >= 4 {
+            match r.u32()? as usize {
+                size @ (MEM_SIZE | EXTENDED_MEM_SIZE) => size,
+                _ => return Err(StateError::Corrupt("memory size")),
+            }
+        } else {
+            MEM_SIZE
+        };
+        let mem: Box<[u8]> = r.bytes(mem_size)?.into();
+        let pc = r.u16()?;
+        let reg_i = r.u16()?;
+        let depth = r.u8()?;
+        let mut stack = Vec::with_capacity(depth as usize);
+        for _ in 0..depth {
+            stack.push(r.u16()?);
+        }
+        let mut registers = [0; 16];
+        registers.copy_from_slice(r.bytes(16)?);
+        // version 1 only had the 64x32 screen
+        let (width, height) = if version == 1 {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        } else {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        };
+        let mut display = [[0; HIRES_WIDTH]; HIRES_HEIGHT];
+        unpack_plane(&mut r, &mut display, 0, width, height)?;
+        let delay_timer = r.u8()?;
+        let sound_timer = r.u8()?;
+        let keypad_bits = r.u16()?;
+        let quirks = Quirks::from_bits(r.u8()?);
+        let rng_state = r.u64()?;
+        let waiting_for_key = optional(r.u8()?, "wait register")?;
+        let pressed_while_waiting = optional(r.u8()?, "wait key")?;
+        let mut resolution = Resolution::Low;
+        let mut rpl_flags = [0; 8];
+        let mut exited = false;
+        if version >= 2 {
+            resolution = match r.u8()? {
+                0 => Resolution::Low,
+                1 => Resolution::High,
+                2 => Resolution::VipHigh,
+                _ => return Err(StateError::Corrupt("resolution")),
+            };
+            rpl_flags.copy_from_slice(r.bytes(8)?);
+            exited = r.u8()? != 0;
+        }
+        let mut selected_planes = 0b01;
+        if version >= 3 {
+            unpack_plane(&mut r, &mut display, 1, HIRES_WIDTH, HIRES_HEIGHT)?;
+            selected_planes = r.u8()? & 0b11;
+        }
+        let mut audio_pattern = [0; 16];
+        let mut pitch = DEFAULT_PITCH;
+        let mut uses_audio_pattern = false;
+        if version >= 5 {
+            audio_pattern.copy_from_slice(r.bytes(16)?);
+            pitch = r.u8()?;
+            uses_audio_pattern = r.u8()? != 0;
+        }
+        let waiting_for_vblank = version >= 6 && r.u8()? != 0;
+        let load_addr = if version >= 7 {
+            r.u16()?
+        } else {
+            PROGRAM_START as u16
+        };
+        if !load_addr.is_multiple_of(2) || load_addr as usize >= mem_size {
+            return Err(StateError::Corrupt("load address"));
+        }
+        // older states leave the current cheats alone
+        let mut cheats = None;
+        if version >= 8 {
+            let count = r.u16()?;
+            let mut list = Vec::with_capacity(count as usize);
+            for _ in 0..count {
+                let kind = match r.u8()? {
+                    0 => CheatKind::Freeze,
+                    1 => CheatKind::Poke,
+                    _ => return Err(StateError::Corrupt("cheat kind")),
+                };
+                let addr = r.u16()?;
+                if addr as usize >= mem_size {
+                    return Err(StateError::Corrupt("cheat address"));
+                }
+                let value = r.u8()?;
+                let enabled = r.u8()? != 0;
+                let len = r.u8()? as usize;
+                let name = String::from_utf8_lossy(r.bytes(len)?).into_owned();
+                list.push(Cheat {
+                    kind,
+                    addr,
+                    value,
+                    enabled,
+                    name,
+                });
+            }
+            cheats = Some(list);
+        }
+        // before 9 only a VIP hi-res machine could be in its resolution
+        let vip_hires = if version >= 9 {
+            r.u8()? != 0
+        } else {
+            resolution == Resolution::VipHigh
+        };
+
+        let rom_mismatch = rom_hash != self.rom_hash;
+        self.mem = mem;
+        self.pc = pc;
+        self.reg_i = reg_i;
+        self.stack = stack;
+        self.registers = registers;
+        self.display = display;
+        self.display_dirty = true;
+        self.resolution = resolution;
+        self.rpl_flags = rpl_flags;
+        self.exited = exited;
+        self.selected_planes = selected_planes;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.uses_audio_pattern = uses_audio_pattern;
+        self.waiting_for_vblank = waiting_for_vblank;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.input
+            .restore(std::array::from_fn(|i| keypad_bits >> i & 1 != 0));
+        self.quirks = quirks;
+        self.rng = Rng::new(rng_state);
+        self.waiting_for_key = waiting_for_key;
+        self.pressed_while_waiting = pressed_while_waiting;
+        self.rom_hash = rom_hash;
+        self.load_addr = load_addr;
+        self.vip_hires = vip_hires;
+        self.idle.progress();
+        if let Some(cheats) = cheats {
+            self.cheats = cheats;
+        }
+
+        return Ok(LoadedState { rom_mismatch });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A machine with something in every part of the state: a sprite on screen, a return
+    // address, timers, a held key and a pending FX0A
+    fn busy_machine() -> Chip8 {
+        // LD I, 0x050; DRW V0, V0, 5; CALL 0x20A; JP 0x208; LD DT, V5; LD V9, K; JP 0x20C
+        let rom = [
+            0xA0, 0x50, 0xD0, 0x05, 0x22, 0x0A, 0x12, 0x08, 0xF5, 0x15, 0xF9, 0x0A, 0x12, 0x0C,
+        ];
+        let mut chip8 = Chip8::with_seed(7);
+        chip8.load_rom(&rom).unwrap();
+        chip8.set_register(5, 40);
+        chip8.execute(0xC1FF).unwrap();
+        for _ in 0..5 {
+            chip8.step().unwrap();
+        }
+        chip8.set_sound_timer(9);
+        chip8.set_key(0x3, true);
+        return chip8;
+    }
+
+    #[test]
+    fn save_mutate_load_round_trips() {
+        let mut chip8 = busy_machine();
+        assert!(chip8.is_waiting_for_key());
+        let saved = chip8.save_state();
+
+        chip8.set_key(0x3, false);
+        chip8.set_key(0xA, true);
+        chip8.set_key(0xA, false);
+        for _ in 0..3 {
+            chip8.step().unwrap();
+        }
+        chip8.tick_60hz();
+        chip8.poke(0x300, 0x99).unwrap();
+        chip8.set_register(0xF, 1);
+        chip8.execute(0x00E0).unwrap();
+        chip8.quirks_mut().sprite_wrap = true;
+        assert_ne!(chip8.save_state(), saved);
+
+        let loaded = chip8.load_state(&saved).unwrap();
+        assert!(!loaded.rom_mismatch);
+        assert_eq!(chip8.save_state(), saved);
+        assert!(chip8.is_waiting_for_key());
+        assert!(chip8.pixel(0, 0));
+        assert_eq!(chip8.stack(), &[0x206]);
+    }
+
+    #[test]
+    fn restored_machine_runs_the_same() {
+        let mut chip8 = busy_machine();
+        let saved = chip8.save_state();
+        let mut copy = Chip8::with_seed(1);
+        copy.load_state(&saved).unwrap();
+        for machine in [&mut chip8, &mut copy] {
+            machine.set_key(0x3, false);
+            machine.set_key(0x4, true);
+            machine.set_key(0x4, false);
+            // the random draw continues from the saved generator
+            machine.execute(0xC2FF).unwrap();
+            machine.tick_60hz();
+        }
+        assert_eq!(copy.save_state(), chip8.save_state());
+    }
+
+    #[test]
+    fn other_rom_is_reported() {
+        let saved = busy_machine().save_state();
+        let mut other = Chip8::with_seed(0);
+        other.load_rom(&[0x12, 0x00]).unwrap();
+        assert!(other.load_state(&saved).unwrap().rom_mismatch);
+    }
+
+    #[test]
+    fn bad_states_leave_the_machine_alone() {
+        let saved = busy_machine().save_state();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&[0x12, 0x00]).unwrap();
+        let before = chip8.save_state();
+
+        assert_eq!(chip8.load_state(b"RC8X").err(), Some(StateError::BadMagic));
+        let mut future = saved.clone();
+        future[4..6].copy_from_slice(&(STATE_VERSION + 1).to_be_bytes());
+        assert_eq!(
+            chip8.load_state(&future).err(),
+            Some(StateError::UnsupportedVersion(STATE_VERSION + 1))
+        );
+        for len in [6, 14, 100, saved.len() / 2, saved.len() - 1] {
+            assert_eq!(
+                chip8.load_state(&saved[..len]).err(),
+                Some(StateError::Truncated)
+            );
+        }
+        assert_eq!(chip8.save_state(), before);
+    }
+
+    fn vip_hires_machine() -> Chip8 {
+        let rom = include_bytes!("../test_roms/hires-vip.ch8");
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_vip_hires(true);
+        chip8.load_rom(rom).unwrap();
+        return chip8;
+    }
+
+    // Runs the committed hi-res ROM from `saved` on a machine that was never put in VIP mode
+    fn run_restored(saved: &[u8]) -> Chip8 {
+        let mut copy = Chip8::with_seed(0);
+        copy.load_rom(include_bytes!("../test_roms/hires-vip.ch8"))
+            .unwrap();
+        copy.load_state(saved).unwrap();
+        // the 0230 clear is only a routine in VIP mode
+        for _ in 0..20 {
+            copy.step().unwrap();
+        }
+        return copy;
+    }
+
+    #[test]
+    fn vip_hires_is_saved() {
+        let mut chip8 = vip_hires_machine();
+        let saved = chip8.save_state();
+        for _ in 0..20 {
+            chip8.step().unwrap();
+        }
+        let copy = run_restored(&saved);
+        assert!(copy.is_vip_hires());
+        assert_eq!(copy.display_size(), (64, 64));
+        assert_eq!(copy.save_state(), chip8.save_state());
+        assert!(copy.pixel(63, 63));
+    }
+
+    #[test]
+    fn version_8_states_keep_vip_hires() {
+        let mut chip8 = vip_hires_machine();
+        let mut saved = chip8.save_state();
+        // version 8 ended with the cheats
+        saved.pop();
+        saved[4..6].copy_from_slice(&8u16.to_be_bytes());
+        for _ in 0..20 {
+            chip8.step().unwrap();
+        }
+        let copy = run_restored(&saved);
+        assert!(copy.is_vip_hires());
+        assert_eq!(copy.save_state(), chip8.save_state());
+
+        // a low resolution version 8 state wasn't in VIP mode
+        let mut low = busy_machine().save_state();
+        low.pop();
+        low[4..6].copy_from_slice(&8u16.to_be_bytes());
+        let mut chip8 = vip_hires_machine();
+        chip8.load_state(&low).unwrap();
+        assert!(!chip8.is_vip_hires());
+    }
+}
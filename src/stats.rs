@@ -0,0 +1,127 @@
+// Execution statistics: how often each opcode and each address ran, to find busy-wait
+// loops and the instructions (and so the quirks) a ROM leans on. Off by default, then the
+// only cost is one branch per instruction in execute().
+
+use crate::{Chip8, Instruction};
+use std::fmt::Write;
+
+pub struct Stats {
+    // indexed by opcode and by pc, 64K entries each so the CPU never has to look anything up
+    opcodes: Vec<u64>,
+    addresses: Vec<u64>,
+    total: u64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        return Stats::new();
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        return Stats {
+            opcodes: vec![0; 0x10000],
+            addresses: vec![0; 0x10000],
+            total: 0,
+        };
+    }
+
+    pub(crate) fn record(&mut self, pc: u16, opcode: u16) {
+        self.opcodes[opcode as usize] += 1;
+        self.addresses[pc as usize] += 1;
+        self.total += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        return self.total;
+    }
+
+    // Counts per opcode pattern like "DXYN", the most frequent first, "????" for words that
+    // don't decode
+    pub fn families(&self) -> Vec<(&'static str, u64)> {
+        let mut families: Vec<(&'static str, u64)> = Vec::new();
+        for (opcode, &count) in self.opcodes.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+            let family = Instruction::decode(opcode as u16).map_or("????", Instruction::family);
+            match families.iter_mut().find(|(name, _)| *name == family) {
+                Some((_, total)) => *total += count,
+                None => families.push((family, count)),
+            }
+        }
+        families.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        return families;
+    }
+
+    // Every address that ran, the busiest first
+    pub fn addresses(&self) -> Vec<(u16, u64)> {
+        let mut addresses: Vec<(u16, u64)> = self
+            .addresses
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(pc, &count)| (pc as u16, count))
+            .collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        return addresses;
+    }
+
+    pub fn percent(&self, count: u64) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        return count as f64 * 100.0 / self.total as f64;
+    }
+
+    // The schema stays put for external tools:
+    //
+    //   { "total": N,
+    //     "families": [{ "pattern": "DXYN", "count": N, "percent": 12.5 }, ...],
+    //     "addresses": [{ "pc": "0x0228", "count": N, "percent": 3.25 }, ...] }
+    //
+    // Both lists are sorted by count, highest first, and percentages have two decimals.
+    pub fn to_json(&self) -> String {
+        let mut out = format!("{{\n  \"total\": {},\n  \"families\": [", self.total);
+        let families = self.families();
+        for (i, (pattern, count)) in families.iter().enumerate() {
+            let comma = if i + 1 < families.len() { "," } else { "" };
+            let percent = self.percent(*count);
+            write!(
+                out,
+                "\n    {{ \"pattern\": \"{pattern}\", \"count\": {count}, \"percent\": {percent:.2} }}{comma}"
+            )
+            .unwrap();
+        }
+        out.push_str("\n  ],\n  \"addresses\": [");
+        let addresses = self.addresses();
+        for (i, (pc, count)) in addresses.iter().enumerate() {
+            let comma = if i + 1 < addresses.len() { "," } else { "" };
+            let percent = self.percent(*count);
+            write!(
+                out,
+                "\n    {{ \"pc\": \"{pc:#06X}\", \"count\": {count}, \"percent\": {percent:.2} }}{comma}"
+            )
+            .unwrap();
+        }
+        out.push_str("\n  ]\n}\n");
+
+        return out;
+    }
+}
+
+impl Chip8 {
+    // Turning them off throws the counts away
+    pub fn set_stats(&mut self, enabled: bool) {
+        if enabled != self.stats.is_some() {
+            self.stats = enabled.then(|| Box::new(Stats::new()));
+        }
+    }
+
+    pub fn stats(&self) -> Option<&Stats> {
+        return self.stats.as_deref();
+    }
+}
@@ -0,0 +1,177 @@
+// Labels from a symbol file, one per line:
+//
+//   draw_sprite = 0x2A4
+//   score = 0x3A0       # a comment
+//
+// Addresses are decimal or 0x hex. Names start with a letter or `_` and go on with letters,
+// digits, `_`, `-` and `.`, the way assemblers like Octo write them. An address with several
+// names is shown with the first one.
+
+use crate::Chip8;
+use std::collections::BTreeMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbols {
+    addrs: BTreeMap<String, u16>,
+    labels: BTreeMap<u16, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolError {
+    // 1-based line in the file
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl Symbols {
+    pub fn new() -> Self {
+        return Symbols {
+            addrs: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        };
+    }
+
+    // Returns false when the name is taken
+    pub fn insert(&mut self, name: &str, addr: u16) -> bool {
+        if self.addrs.contains_key(name) {
+            return false;
+        }
+        self.addrs.insert(name.to_string(), addr);
+        self.labels.entry(addr).or_insert_with(|| name.to_string());
+        return true;
+    }
+
+    pub fn addr(&self, name: &str) -> Option<u16> {
+        return self.addrs.get(name).copied();
+    }
+
+    // The name shown for `addr`
+    pub fn label(&self, addr: u16) -> Option<&str> {
+        return self.labels.get(&addr).map(String::as_str);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.addrs.is_empty();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.addrs.len();
+    }
+}
+
+impl Default for Symbols {
+    fn default() -> Self {
+        return Self::new();
+    }
+}
+
+fn is_name(text: &str) -> bool {
+    let mut chars = text.chars();
+    return chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'));
+}
+
+fn number(text: &str) -> Option<u32> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => return u32::from_str_radix(hex, 16).ok(),
+        None => return text.parse().ok(),
+    }
+}
+
+pub fn parse_symbols(text: &str) -> Result<Symbols, SymbolError> {
+    let mut symbols = Symbols::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| SymbolError {
+            line: i + 1,
+            message,
+        };
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let Some((name, addr)) = line.split_once('=') else {
+            return Err(error(String::from("expected name = address")));
+        };
+        let (name, addr) = (name.trim(), addr.trim());
+        if !is_name(name) {
+            return Err(error(format!("invalid name '{name}'")));
+        }
+        let addr = match number(addr) {
+            Some(addr) if addr <= 0xFFFF => addr as u16,
+            _ => return Err(error(format!("invalid address '{addr}'"))),
+        };
+        if !symbols.insert(name, addr) {
+            return Err(error(format!(
+                "'{name}' is already defined at {:#05X}",
+                symbols.addr(name).unwrap_or(0)
+            )));
+        }
+    }
+
+    return Ok(symbols);
+}
+
+impl Chip8 {
+    // Only for display and for looking up names, the machine never reads them
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        return &self.symbols;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> String {
+        return parse_symbols(text).unwrap_err().to_string();
+    }
+
+    #[test]
+    fn names_and_addresses() {
+        let text = "# made by hand\n\
+                    draw_sprite = 0x2A4\n\
+                    \n\
+                    score=928   # decimal\n\
+                    sprite.data = 0X3a0\n\
+                    also-score = 0x3A0\n";
+        let symbols = parse_symbols(text).unwrap();
+        assert_eq!(symbols.len(), 4);
+        assert_eq!(symbols.addr("draw_sprite"), Some(0x2A4));
+        assert_eq!(symbols.addr("score"), Some(0x3A0));
+        assert_eq!(symbols.addr("missing"), None);
+        assert_eq!(symbols.label(0x2A4), Some("draw_sprite"));
+        // the first name of an address is the one shown
+        assert_eq!(symbols.label(0x3A0), Some("score"));
+        assert_eq!(symbols.addr("also-score"), Some(0x3A0));
+        assert_eq!(symbols.label(0x2A6), None);
+        assert!(parse_symbols("  # nothing\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn bad_lines() {
+        assert_eq!(error("a = 1\nb 2"), "line 2: expected name = address");
+        assert_eq!(error("2d = 0x200"), "line 1: invalid name '2d'");
+        assert_eq!(error("a b = 0x200"), "line 1: invalid name 'a b'");
+        assert_eq!(error("a = 0x10000"), "line 1: invalid address '0x10000'");
+        assert_eq!(error("a = zero"), "line 1: invalid address 'zero'");
+        assert_eq!(
+            error("a = 0x200\n\na = 0x300"),
+            "line 3: 'a' is already defined at 0x200"
+        );
+    }
+}
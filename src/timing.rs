@@ -0,0 +1,95 @@
+// COSMAC VIP timing. The original interpreter takes very different times for different
+// instructions (a sprite costs far more than a register load), so with Timing::Vip step()
+// reports what each one cost in 1802 machine cycles and frontends budget those instead of
+// instruction counts. The costs are approximations of the VIP interpreter, including its
+// fetch and dispatch.
+
+use crate::{Chip8, Instruction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Timing {
+    // every instruction costs 1, frontends run a fixed number per second
+    #[default]
+    Fixed,
+    Vip,
+}
+
+// The 1.7609 MHz crystal makes 3668 machine cycles (8 clocks each) per 60Hz frame, the
+// display DMA takes 1024 of them and the timer interrupt about 60
+const VIP_FRAME_CYCLES: u32 = 3668 - 1024 - 60;
+// machine cycles per second left for the interpreter, the budget rate for Timing::Vip
+pub const VIP_CYCLE_HZ: u32 = VIP_FRAME_CYCLES * 60;
+
+// fetching and dispatching, paid by every instruction
+const OVERHEAD: u32 = 40;
+// a taken skip has to step over the next instruction
+const SKIP: u32 = 4;
+
+impl Chip8 {
+    // What `instruction` will cost when run in the current machine state. DXYN depends on
+    // sprite height and whether VX is byte aligned, FX33 on the digits, FX55/FX65 on X.
+    pub fn cycles_for(&self, instruction: Instruction) -> u32 {
+        use Instruction::*;
+
+        let v = |x: u8| self.registers[x as usize];
+        let skip = |taken: bool| if taken { SKIP } else { 0 };
+        let execute = match instruction {
+            // 256 bytes of display memory
+            Cls => 680,
+            Ret => 10,
+            Jump(_) => 12,
+            Call(_) => 26,
+            SkipEqImm { x, nn } => 10 + skip(v(x) == nn),
+            SkipNeImm { x, nn } => 10 + skip(v(x) != nn),
+            SkipEqReg { x, y } => 14 + skip(v(x) == v(y)),
+            SkipNeReg { x, y } => 14 + skip(v(x) != v(y)),
+            LoadImm { .. } => 6,
+            AddImm { .. } => 10,
+            Move { .. }
+            | Or { .. }
+            | And { .. }
+            | Xor { .. }
+            | Add { .. }
+            | Sub { .. }
+            | ShiftRight { .. }
+            | SubN { .. }
+            | ShiftLeft { .. } => 44,
+            LoadI(_) => 12,
+            JumpOffset { .. } => 22,
+            Random { .. } => 36,
+            Draw { x, n, .. } => {
+                // a sprite that isn't byte aligned is shifted across two bytes per row
+                let row = if v(x) % 8 == 0 { 18 } else { 34 };
+                26 + row * n as u32
+            }
+            SkipKey { x } => 14 + skip(self.input.is_pressed(v(x))),
+            SkipNotKey { x } => 14 + skip(!self.input.is_pressed(v(x))),
+            LoadDelay { .. } | WaitKey { .. } | SetDelay { .. } | SetSound { .. } => 10,
+            AddI { .. } => 16,
+            Font { .. } => 20,
+            // BCD by repeated subtraction, one round per unit of each digit
+            Bcd { x } => {
+                let val = v(x) as u32;
+                84 + 16 * (val / 100 + val / 10 % 10 + val % 10)
+            }
+            Store { x } | Load { x } => 14 + 14 * (x as u32 + 1),
+            // not VIP instructions, the SCHIP/XO-CHIP ones get a nominal cost
+            Sys(_)
+            | ScrollDown(_)
+            | ScrollRight
+            | ScrollLeft
+            | Exit
+            | LowRes
+            | HighRes
+            | LoadILong
+            | Plane(_)
+            | Audio
+            | Pitch { .. }
+            | BigFont { .. }
+            | SaveFlags { .. }
+            | LoadFlags { .. } => 10,
+        };
+
+        return OVERHEAD + execute;
+    }
+}
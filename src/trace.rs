@@ -0,0 +1,199 @@
+// Instruction tracing shared by the window and --headless. A Tracer installed with
+// Chip8::set_tracer() sees every instruction right before it executes.
+
+use crate::{Chip8, disassemble_with, split_nibbles};
+use std::io::Write;
+use std::str::FromStr;
+
+// Opcode families like "DXYN" or "FX0A": hex digits must match, X/Y/N/K match anything
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceFilter {
+    patterns: Vec<[Option<u8>; 4]>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, opcode: u16) -> bool {
+        let nibbles = split_nibbles(opcode);
+        return self.patterns.iter().any(|pattern| {
+            pattern
+                .iter()
+                .zip(nibbles)
+                .all(|(want, got)| want.is_none_or(|want| want == got))
+        });
+    }
+}
+
+impl FromStr for TraceFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut patterns = Vec::new();
+        for family in s.split(',').map(str::trim) {
+            let chars: Vec<char> = family.to_ascii_uppercase().chars().collect();
+            if chars.len() != 4 {
+                return Err(format!(
+                    "invalid opcode family '{family}', expected 4 characters like DXYN"
+                ));
+            }
+            let mut pattern = [None; 4];
+            for (slot, c) in pattern.iter_mut().zip(chars) {
+                *slot = match c {
+                    'X' | 'Y' | 'N' | 'K' => None,
+                    _ => match c.to_digit(16) {
+                        Some(digit) => Some(digit as u8),
+                        None => return Err(format!("invalid opcode family '{family}'")),
+                    },
+                };
+            }
+            patterns.push(pattern);
+        }
+
+        return Ok(TraceFilter { patterns });
+    }
+}
+
+pub struct Tracer {
+    out: Box<dyn Write>,
+    filter: Option<TraceFilter>,
+    // instructions to let through before logging starts
+    skip: u64,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write>, filter: Option<TraceFilter>, skip: u64) -> Self {
+        return Tracer { out, filter, skip };
+    }
+
+    // Called by Chip8::execute() with the machine state before `opcode` at `pc` runs
+    pub(crate) fn record(&mut self, chip8: &Chip8, pc: u16, opcode: u16) {
+        if self.skip > 0 {
+            self.skip -= 1;
+            return;
+        }
+        if let Some(filter) = &self.filter
+            && !filter.matches(opcode)
+        {
+            return;
+        }
+
+        // a broken pipe or full disk shouldn't take the emulator down with it
+        let _ = writeln!(self.out, "{}", format_line(chip8, pc, opcode));
+    }
+}
+
+// pc=0x0202 op=6A02 LD VA, 0x02  V=[00 00 ..] I=0x0000 SP=0, with the label of a pc
+// that has one after it: pc=0x02A4 <draw_sprite> op=...
+pub fn format_line(chip8: &Chip8, pc: u16, opcode: u16) -> String {
+    let registers = chip8
+        .registers()
+        .iter()
+        .map(|v| format!("{v:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let label = match chip8.symbols().label(pc) {
+        Some(name) => format!(" <{name}>"),
+        None => String::new(),
+    };
+
+    return format!(
+        "pc={pc:#06X}{label} op={opcode:04X} {}  V=[{registers}] I={:#06X} SP={}",
+        disassemble_with(opcode, chip8.symbols()),
+        chip8.reg_i(),
+        chip8.stack().len()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // A Write the test can read back after handing it to the tracer
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            return self.0.borrow_mut().write(buf);
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            return Ok(());
+        }
+    }
+
+    // LD VA, 0x02; LD I, 0x300; CALL 0x208; JP 0x206; RET
+    fn trace(filter: Option<&str>, skip: u64) -> String {
+        let out = Shared::default();
+        let mut chip8 = Chip8::with_seed(0);
+        chip8
+            .load_rom(&[0x6A, 0x02, 0xA3, 0x00, 0x22, 0x08, 0x12, 0x06, 0x00, 0xEE])
+            .unwrap();
+        let filter = filter.map(|filter| filter.parse().unwrap());
+        chip8.set_tracer(Some(Tracer::new(Box::new(out.clone()), filter, skip)));
+        for _ in 0..6 {
+            chip8.step().unwrap();
+        }
+        chip8.set_tracer(None);
+
+        return String::from_utf8(out.0.take()).unwrap();
+    }
+
+    #[test]
+    fn every_instruction_is_logged() {
+        let zeros = "00 00 00 00 00 00 00 00 00 00";
+        assert_eq!(
+            trace(None, 0),
+            format!(
+                "pc=0x0200 op=6A02 LD VA, 0x02  V=[{zeros} 00 00 00 00 00 00] I=0x0000 SP=0\n\
+                 pc=0x0202 op=A300 LD I, 0x300  V=[{zeros} 02 00 00 00 00 00] I=0x0000 SP=0\n\
+                 pc=0x0204 op=2208 CALL 0x208  V=[{zeros} 02 00 00 00 00 00] I=0x0300 SP=0\n\
+                 pc=0x0208 op=00EE RET  V=[{zeros} 02 00 00 00 00 00] I=0x0300 SP=1\n\
+                 pc=0x0206 op=1206 JP 0x206  V=[{zeros} 02 00 00 00 00 00] I=0x0300 SP=0\n\
+                 pc=0x0206 op=1206 JP 0x206  V=[{zeros} 02 00 00 00 00 00] I=0x0300 SP=0\n"
+            )
+        );
+    }
+
+    #[test]
+    fn filter_and_skip() {
+        let lines = trace(Some("2NNN,00EE"), 0);
+        let ops: Vec<&str> = lines.lines().map(|line| &line[10..17]).collect();
+        assert_eq!(ops, ["op=2208", "op=00EE"]);
+
+        // the first four instructions go by unlogged, filtered or not
+        let lines = trace(None, 4);
+        assert_eq!(lines.lines().count(), 2);
+        assert!(lines.lines().all(|line| line.contains("op=1206")));
+        assert_eq!(trace(Some("2NNN"), 4), "");
+    }
+
+    #[test]
+    fn labelled_pcs() {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.set_symbols(crate::parse_symbols("start = 0x200\nsub = 0x208").unwrap());
+        let line = format_line(&chip8, 0x200, 0x2208);
+        assert!(
+            line.starts_with("pc=0x0200 <start> op=2208 CALL sub  V=["),
+            "{line}"
+        );
+        let line = format_line(&chip8, 0x202, 0x1202);
+        assert!(
+            line.starts_with("pc=0x0202 op=1202 JP 0x202  V=["),
+            "{line}"
+        );
+    }
+
+    #[test]
+    fn filter_families() {
+        let filter: TraceFilter = "dxyn, FX0A".parse().unwrap();
+        assert!(filter.matches(0xD125));
+        assert!(filter.matches(0xF30A));
+        assert!(!filter.matches(0xF30B));
+        assert!(!filter.matches(0x00E0));
+        assert!("DXY".parse::<TraceFilter>().is_err());
+        assert!("DXYZ".parse::<TraceFilter>().is_err());
+    }
+}
@@ -0,0 +1,473 @@
+// Static checks of a ROM before it runs. The code is found by following the control flow
+// from the entry point the way the CPU would, both ways at every skip, so sprites and
+// tables in between aren't mistaken for instructions that don't decode. What is never
+// reached is reported as probable data.
+//
+// Errors are ROMs that can't be loaded as they are. Warnings are reached code that would
+// stop or misbehave at run time, infos are things worth knowing, like the quirk-sensitive
+// instructions that make the choice of profile matter.
+
+use crate::{Instruction, VIP_HIRES_CLEAR, VIP_HIRES_ENTRY, is_vip_hires_rom};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write;
+
+// addresses listed per quirk-sensitive pattern, the rest are counted
+const LISTED_USES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // pad() so the report can line them up
+        match self {
+            Severity::Error => f.pad("error"),
+            Severity::Warning => f.pad("warning"),
+            Severity::Info => f.pad("info"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub severity: Severity,
+    // stable name for tools: empty, too-large, odd-length, unknown-opcode, odd-target,
+    // target-outside-rom, runs-off-end, machine-code, draw-past-end, quirk or data
+    pub kind: &'static str,
+    pub addr: Option<u16>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validation {
+    pub size: usize,
+    pub load_addr: u16,
+    // where the flow analysis started, past the stub for VIP hi-res ROMs
+    pub entry: u16,
+    // bytes of the ROM that are part of a reached instruction
+    pub reached: usize,
+    // errors first, then warnings and infos, each in address order
+    pub findings: Vec<Finding>,
+}
+
+impl Validation {
+    pub fn has_errors(&self) -> bool {
+        return self
+            .findings
+            .iter()
+            .any(|finding| finding.severity == Severity::Error);
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        return self
+            .findings
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .count();
+    }
+
+    // The schema stays put for tools:
+    //
+    //   { "size": N, "load_addr": "0x0200", "entry": "0x0200", "reached": N,
+    //     "errors": N, "warnings": N,
+    //     "findings": [{ "severity": "warning", "kind": "odd-target", "addr": "0x0236",
+    //                    "message": "..." }, ...] }
+    //
+    // `addr` is null for findings about the whole ROM.
+    pub fn to_json(&self) -> String {
+        let mut out = format!(
+            "{{\n  \"size\": {},\n  \"load_addr\": \"{:#06X}\",\n  \"entry\": \"{:#06X}\",\n  \"reached\": {},\n  \"errors\": {},\n  \"warnings\": {},\n  \"findings\": [",
+            self.size,
+            self.load_addr,
+            self.entry,
+            self.reached,
+            self.count(Severity::Error),
+            self.count(Severity::Warning)
+        );
+        for (i, finding) in self.findings.iter().enumerate() {
+            let comma = if i + 1 < self.findings.len() { "," } else { "" };
+            let addr = match finding.addr {
+                Some(addr) => format!("\"{addr:#06X}\""),
+                None => String::from("null"),
+            };
+            let message = finding.message.replace('\\', "\\\\").replace('"', "\\\"");
+            write!(
+                out,
+                "\n    {{ \"severity\": \"{}\", \"kind\": \"{}\", \"addr\": {addr}, \"message\": \"{message}\" }}{comma}",
+                finding.severity, finding.kind
+            )
+            .unwrap();
+        }
+        out.push_str("\n  ]\n}\n");
+
+        return out;
+    }
+}
+
+impl fmt::Display for Validation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} bytes at {:#05X}, {} reached from {:#05X}",
+            self.size, self.load_addr, self.reached, self.entry
+        )?;
+        for finding in &self.findings {
+            match finding.addr {
+                Some(addr) => writeln!(
+                    f,
+                    "{:<7}  {addr:#05X}  {}",
+                    finding.severity, finding.message
+                )?,
+                None => writeln!(f, "{:<7}         {}", finding.severity, finding.message)?,
+            }
+        }
+        let plural = |count: usize, what: &str| match count {
+            1 => format!("1 {what}"),
+            _ => format!("{count} {what}s"),
+        };
+        write!(
+            f,
+            "{}, {}",
+            plural(self.count(Severity::Error), "error"),
+            plural(self.count(Severity::Warning), "warning")
+        )
+    }
+}
+
+// What the flow analysis knows about I when it gets to an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IndexState {
+    Known(u16),
+    Unknown,
+}
+
+struct Analysis<'a> {
+    rom: &'a [u8],
+    base: usize,
+    vip_hires: bool,
+    // per ROM byte, what I was the last time an instruction starting there was reached
+    visited: Vec<Option<IndexState>>,
+    reached: Vec<bool>,
+    pending: Vec<(usize, IndexState)>,
+    findings: Vec<Finding>,
+    quirk_uses: BTreeMap<&'static str, Vec<u16>>,
+}
+
+impl Analysis<'_> {
+    fn end(&self) -> usize {
+        return self.base + self.rom.len();
+    }
+
+    fn in_rom(&self, addr: usize) -> bool {
+        return (self.base..self.end()).contains(&addr);
+    }
+
+    // An odd-sized ROM is padded with 0x00 when it is loaded
+    fn word(&self, addr: usize) -> u16 {
+        let byte = |addr: usize| {
+            return self
+                .rom
+                .get(addr.wrapping_sub(self.base))
+                .copied()
+                .unwrap_or(0);
+        };
+        return u16::from_be_bytes([byte(addr), byte(addr + 1)]);
+    }
+
+    // Once, an instruction looked at again with I unknown would say the same thing twice
+    fn warn(&mut self, kind: &'static str, addr: usize, message: String) {
+        let finding = Finding {
+            severity: Severity::Warning,
+            kind,
+            addr: Some(addr as u16),
+            message,
+        };
+        if !self.findings.contains(&finding) {
+            self.findings.push(finding);
+        }
+    }
+
+    // Reaching an instruction again with a different I makes I unknown there, so every
+    // address is looked at twice at most
+    fn visit(&mut self, addr: usize, index: IndexState) {
+        let offset = addr - self.base;
+        let merged = match self.visited[offset] {
+            None => index,
+            Some(old) if old == index => return,
+            Some(IndexState::Unknown) => return,
+            Some(_) => IndexState::Unknown,
+        };
+        self.visited[offset] = Some(merged);
+        self.pending.push((addr, merged));
+    }
+
+    // The next instruction, which the ROM must still have
+    fn fall_through(&mut self, from: usize, next: usize, index: IndexState) {
+        if self.in_rom(next) {
+            self.visit(next, index);
+        } else {
+            let message = format!("execution runs past the end of the ROM at {next:#05X}");
+            self.warn("runs-off-end", from, message);
+        }
+    }
+
+    // A 1NNN or 2NNN target, followed when it is an even address inside the ROM
+    fn branch(&mut self, from: usize, target: u16, what: &str, index: IndexState) {
+        let target = target as usize;
+        if !target.is_multiple_of(2) {
+            let message = format!("{what} to the odd address {target:#05X}");
+            self.warn("odd-target", from, message);
+        } else if !self.in_rom(target) {
+            let message = format!(
+                "{what} to {target:#05X}, outside the ROM at {:#05X}-{:#05X}",
+                self.base,
+                self.end() - 1
+            );
+            self.warn("target-outside-rom", from, message);
+        } else {
+            self.visit(target, index);
+        }
+    }
+
+    fn step(&mut self, addr: usize, index: IndexState) {
+        let opcode = self.word(addr);
+        for byte in addr..(addr + 2).min(self.end()) {
+            self.reached[byte - self.base] = true;
+        }
+        let Some(instruction) = Instruction::decode(opcode) else {
+            let message = format!("{opcode:04X} doesn't decode but is reached");
+            self.warn("unknown-opcode", addr, message);
+            return;
+        };
+        let next = addr + 2;
+        // what a skip jumps over, F000 NNNN is two words
+        let skipped = if self.in_rom(next) && self.word(next) == 0xF000 {
+            4
+        } else {
+            2
+        };
+        if let Some(pattern) = quirk_pattern(instruction) {
+            self.quirk_uses
+                .entry(pattern)
+                .or_default()
+                .push(addr as u16);
+        }
+
+        use Instruction::*;
+        match instruction {
+            Ret | Exit => {}
+            Jump(target) => self.branch(addr, target, "jump", index),
+            Call(target) => {
+                self.branch(addr, target, "call", index);
+                // whatever the subroutine left in I
+                self.fall_through(addr, next, IndexState::Unknown);
+            }
+            Sys(target)
+                if self.vip_hires && target as usize == self.base + VIP_HIRES_CLEAR as usize =>
+            {
+                self.fall_through(addr, next, index);
+            }
+            Sys(target) => {
+                let message = format!(
+                    "0NNN calls COSMAC VIP machine code at {target:#05X}, which needs --ignore-sys"
+                );
+                self.warn("machine-code", addr, message);
+            }
+            // the target depends on a register, the quirk finding mentions it
+            JumpOffset { .. } => {}
+            SkipEqImm { .. }
+            | SkipNeImm { .. }
+            | SkipEqReg { .. }
+            | SkipNeReg { .. }
+            | SkipKey { .. }
+            | SkipNotKey { .. } => {
+                self.fall_through(addr, next, index);
+                self.fall_through(addr, next + skipped, index);
+            }
+            LoadI(value) => self.fall_through(addr, next, IndexState::Known(value)),
+            LoadILong => {
+                for byte in next..(next + 2).min(self.end()) {
+                    self.reached[byte - self.base] = true;
+                }
+                let value = self.word(next);
+                self.fall_through(addr, next + 2, IndexState::Known(value));
+            }
+            AddI { .. } | Font { .. } | BigFont { .. } | Store { .. } | Load { .. } => {
+                self.fall_through(addr, next, IndexState::Unknown);
+            }
+            Draw { n, .. } => {
+                // a font sprite below the ROM is the interpreter's business
+                if let IndexState::Known(sprite) = index
+                    && sprite as usize >= self.base
+                {
+                    let bytes = if n == 0 { 32 } else { n as usize };
+                    if sprite as usize + bytes > self.end() {
+                        let message = format!(
+                            "draws {bytes} bytes from I = {sprite:#05X}, past the ROM's end at {:#05X}",
+                            self.end() - 1
+                        );
+                        self.warn("draw-past-end", addr, message);
+                    }
+                }
+                self.fall_through(addr, next, index);
+            }
+            _ => self.fall_through(addr, next, index),
+        }
+    }
+
+    // Runs of bytes no reached instruction covers
+    fn data_findings(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            if self.reached[offset] {
+                offset += 1;
+                continue;
+            }
+            let start = offset;
+            while offset < self.rom.len() && !self.reached[offset] {
+                offset += 1;
+            }
+            let undecodable = (start..offset)
+                .step_by(2)
+                .filter(|&word| Instruction::decode(self.word(self.base + word)).is_none())
+                .count();
+            let (first, last) = (self.base + start, self.base + offset - 1);
+            findings.push(Finding {
+                severity: Severity::Info,
+                kind: "data",
+                addr: Some(first as u16),
+                message: format!(
+                    "{first:#05X}-{last:#05X} isn't reached ({} bytes, {undecodable} words that don't decode), probably data",
+                    offset - start
+                ),
+            });
+        }
+
+        return findings;
+    }
+}
+
+// The instructions whose behaviour the quirk presets change
+fn quirk_pattern(instruction: Instruction) -> Option<&'static str> {
+    match instruction {
+        Instruction::ShiftRight { .. } => return Some("8XY6"),
+        Instruction::ShiftLeft { .. } => return Some("8XYE"),
+        Instruction::Store { .. } => return Some("FX55"),
+        Instruction::Load { .. } => return Some("FX65"),
+        Instruction::JumpOffset { .. } => return Some("BNNN"),
+        _ => return None,
+    }
+}
+
+fn quirk_note(pattern: &str) -> &'static str {
+    match pattern {
+        "8XY6" | "8XYE" => return "shifts VY or VX depending on the shift quirk",
+        "FX55" | "FX65" => return "moves I or leaves it depending on the memory quirk",
+        _ => return "adds V0 or VX depending on the jump quirk, its target isn't followed",
+    }
+}
+
+// `rom` as it would be loaded at `base` into `mem_size` bytes of memory
+pub fn validate_rom(rom: &[u8], base: u16, mem_size: usize) -> Validation {
+    let vip_hires = is_vip_hires_rom(rom);
+    let entry = if vip_hires {
+        base + VIP_HIRES_ENTRY as u16
+    } else {
+        base
+    };
+    let mut validation = Validation {
+        size: rom.len(),
+        load_addr: base,
+        entry,
+        reached: 0,
+        findings: Vec::new(),
+    };
+    let error = |kind: &'static str, message: String| Finding {
+        severity: Severity::Error,
+        kind,
+        addr: None,
+        message,
+    };
+    let room = mem_size.saturating_sub(base as usize);
+    if rom.is_empty() {
+        validation
+            .findings
+            .push(error("empty", String::from("the ROM is empty")));
+        return validation;
+    }
+    if rom.len() > room {
+        let message = format!(
+            "{} bytes don't fit in the {room} bytes from {base:#05X} to the end of memory",
+            rom.len()
+        );
+        validation.findings.push(error("too-large", message));
+        return validation;
+    }
+    if !rom.len().is_multiple_of(2) {
+        let message = format!(
+            "{} bytes is an odd size, the last instruction is cut in half",
+            rom.len()
+        );
+        validation.findings.push(error("odd-length", message));
+    }
+
+    let mut analysis = Analysis {
+        rom,
+        base: base as usize,
+        vip_hires,
+        visited: vec![None; rom.len()],
+        reached: vec![false; rom.len()],
+        pending: Vec::new(),
+        findings: Vec::new(),
+        quirk_uses: BTreeMap::new(),
+    };
+    if analysis.in_rom(entry as usize) {
+        analysis.visit(entry as usize, IndexState::Unknown);
+    }
+    while let Some((addr, index)) = analysis.pending.pop() {
+        // reached again with a different I since it was queued
+        if analysis.visited[addr - analysis.base] != Some(index) {
+            continue;
+        }
+        analysis.step(addr, index);
+    }
+
+    let mut findings = std::mem::take(&mut analysis.findings);
+    for (pattern, uses) in &analysis.quirk_uses {
+        let mut uses = uses.clone();
+        uses.sort();
+        uses.dedup();
+        let mut listed: Vec<String> = uses
+            .iter()
+            .take(LISTED_USES)
+            .map(|addr| format!("{addr:#05X}"))
+            .collect();
+        if uses.len() > LISTED_USES {
+            listed.push(format!("{} more", uses.len() - LISTED_USES));
+        }
+        findings.push(Finding {
+            severity: Severity::Info,
+            kind: "quirk",
+            addr: Some(uses[0]),
+            message: format!(
+                "{pattern} at {}: {}",
+                listed.join(", "),
+                quirk_note(pattern)
+            ),
+        });
+    }
+    findings.extend(analysis.data_findings());
+    validation.reached = analysis.reached.iter().filter(|&&reached| reached).count();
+    validation.findings.extend(findings);
+    validation
+        .findings
+        .sort_by_key(|finding| (finding.severity, finding.addr));
+
+    return validation;
+}
@@ -0,0 +1,97 @@
+// The core for a web page, the wasm feature. examples/web/index.html shows how it is built
+// and used. The page owns the clock: it calls step_frame() once per requestAnimationFrame
+// and draws display_rgba().
+
+use crate::Chip8;
+use crate::screenshot::Rgb;
+use wasm_bindgen::prelude::*;
+
+// background, plane 1, plane 2, both planes, like the native frontend's classic palette
+const PALETTE: [Rgb; 4] = [[0, 0, 0], [0, 228, 48], [0, 120, 255], [255, 255, 255]];
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_namespace = Math)]
+    fn random() -> f64;
+}
+
+#[wasm_bindgen]
+pub struct Chip8Wasm {
+    chip8: Chip8,
+}
+
+#[wasm_bindgen]
+impl Chip8Wasm {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Chip8Wasm {
+        let seed = (random() * u32::MAX as f64) as u64;
+        return Chip8Wasm {
+            chip8: Chip8::with_seed(seed),
+        };
+    }
+
+    // Starts the ROM over on a freshly reset machine
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), JsError> {
+        self.chip8.reset();
+        self.chip8.load_rom(rom)?;
+        return Ok(());
+    }
+
+    // One 60Hz frame: up to `cycles` instructions, then the timers tick. Stops early for
+    // FX0A and a display_wait DXYN like the native frontend does.
+    pub fn step_frame(&mut self, cycles: u32) -> Result<(), JsError> {
+        for _ in 0..cycles {
+            if self.chip8.is_waiting_for_key()
+                || self.chip8.is_waiting_for_vblank()
+                || self.chip8.has_exited()
+            {
+                break;
+            }
+            self.chip8.step()?;
+        }
+        self.chip8.tick_60hz();
+
+        return Ok(());
+    }
+
+    // Width and height of the current resolution, 64x32 or 128x64
+    pub fn width(&self) -> usize {
+        return self.chip8.resolution().width();
+    }
+
+    pub fn height(&self) -> usize {
+        return self.chip8.resolution().height();
+    }
+
+    // width() * height() RGBA pixels, for an ImageData
+    pub fn display_rgba(&self) -> Vec<u8> {
+        let (width, height) = (self.width(), self.height());
+        let mut rgba = Vec::with_capacity(width * height * 4);
+        for row in self.chip8.display().iter().take(height) {
+            for &pixel in row.iter().take(width) {
+                let [r, g, b] = PALETTE[pixel as usize & 3];
+                rgba.extend_from_slice(&[r, g, b, 255]);
+            }
+        }
+
+        return rgba;
+    }
+
+    pub fn key_down(&mut self, key: u8) {
+        self.chip8.set_key(key, true);
+    }
+
+    pub fn key_up(&mut self, key: u8) {
+        self.chip8.set_key(key, false);
+    }
+
+    pub fn sound_active(&self) -> bool {
+        return self.chip8.sound_active();
+    }
+}
+
+impl Default for Chip8Wasm {
+    fn default() -> Self {
+        return Chip8Wasm::new();
+    }
+}
@@ -0,0 +1,190 @@
+// Watchpoints: stop when a memory byte or a V register changes value. The run loop
+// polls take_watch_hit() after each instruction.
+
+use crate::Chip8;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTarget {
+    Mem(u16),
+    Reg(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    pub target: WatchTarget,
+    pub old: u8,
+    pub new: u8,
+    // address of the instruction that did the write
+    pub pc: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.target {
+            WatchTarget::Mem(addr) => write!(f, "[{addr:#05X}]")?,
+            WatchTarget::Reg(reg) => write!(f, "V{reg:X}")?,
+        }
+        write!(
+            f,
+            " changed {:02X} -> {:02X} at {:#05X}",
+            self.old, self.new, self.pc
+        )
+    }
+}
+
+impl Chip8 {
+    pub fn watch_mem(&mut self, addr: u16) {
+        self.mem_watches.insert(addr);
+    }
+
+    // false if addr wasn't watched
+    pub fn unwatch_mem(&mut self, addr: u16) -> bool {
+        return self.mem_watches.remove(&addr);
+    }
+
+    pub fn watch_reg(&mut self, reg: u8) {
+        self.reg_watches |= 1 << (reg & 0xF);
+    }
+
+    // false if the register wasn't watched
+    pub fn unwatch_reg(&mut self, reg: u8) -> bool {
+        let bit = 1 << (reg & 0xF);
+        let watched = self.reg_watches & bit != 0;
+        self.reg_watches &= !bit;
+
+        return watched;
+    }
+
+    // The first change since the last call, if any
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        return self.watch_hit.take();
+    }
+
+    fn record_hit(&mut self, hit: WatchHit) {
+        if hit.old != hit.new && self.watch_hit.is_none() {
+            self.watch_hit = Some(hit);
+        }
+    }
+
+    // Compares against the registers from before the instruction at pc ran
+    pub(crate) fn check_reg_watches(&mut self, old: &[u8; 16], pc: u16) {
+        for reg in 0..16 {
+            if self.reg_watches & 1 << reg != 0 {
+                self.record_hit(WatchHit {
+                    target: WatchTarget::Reg(reg),
+                    old: old[reg as usize],
+                    new: self.registers[reg as usize],
+                    pc,
+                });
+            }
+        }
+    }
+
+    // Memory write from execute(), so the instruction sits just before pc. `addr` has to be
+    // in range already, see write_mem().
+    pub(crate) fn store(&mut self, addr: usize, val: u8) {
+        if !self.mem_watches.is_empty() && self.mem_watches.contains(&(addr as u16)) {
+            self.record_hit(WatchHit {
+                target: WatchTarget::Mem(addr as u16),
+                old: self.mem[addr],
+                new: val,
+                pc: self.instruction_addr(),
+            });
+        }
+        self.mem[addr] = val;
+        self.idle.progress();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // LD V0, 0x7B; LD I, 0x3E0; LD B, V0; LD V3, 5; ADD V3, 1; LD [I], V0; LD V0, K;
+    // JP 0x20E
+    const ROM: [u8; 16] = [
+        0x60, 0x7B, 0xA3, 0xE0, 0xF0, 0x33, 0x63, 0x05, 0x73, 0x01, 0xF0, 0x55, 0xF0, 0x0A, 0x12,
+        0x0E,
+    ];
+
+    fn machine() -> Chip8 {
+        let mut chip8 = Chip8::with_seed(0);
+        chip8.load_rom(&ROM).unwrap();
+        return chip8;
+    }
+
+    // Steps until a watchpoint fires
+    fn run_to_hit(chip8: &mut Chip8) -> WatchHit {
+        for _ in 0..16 {
+            chip8.step().unwrap();
+            if let Some(hit) = chip8.take_watch_hit() {
+                return hit;
+            }
+        }
+        panic!("no watchpoint fired");
+    }
+
+    #[test]
+    fn memory_writes() {
+        let mut chip8 = machine();
+        chip8.watch_mem(0x3E0);
+        // FX33 writes the hundreds digit, 1
+        let hit = run_to_hit(&mut chip8);
+        assert_eq!(
+            hit,
+            WatchHit {
+                target: WatchTarget::Mem(0x3E0),
+                old: 0x00,
+                new: 0x01,
+                pc: 0x204
+            }
+        );
+        assert_eq!(hit.to_string(), "[0x3E0] changed 00 -> 01 at 0x204");
+        // then FX55 stores V0 over it
+        let hit = run_to_hit(&mut chip8);
+        assert_eq!((hit.old, hit.new, hit.pc), (0x01, 0x7B, 0x20A));
+        assert!(chip8.unwatch_mem(0x3E0));
+        assert!(!chip8.unwatch_mem(0x3E0));
+    }
+
+    #[test]
+    fn register_writes() {
+        let mut chip8 = machine();
+        chip8.watch_reg(3);
+        let hit = run_to_hit(&mut chip8);
+        assert_eq!(hit.target, WatchTarget::Reg(3));
+        assert_eq!((hit.old, hit.new, hit.pc), (0x00, 0x05, 0x206));
+        let hit = run_to_hit(&mut chip8);
+        assert_eq!((hit.old, hit.new, hit.pc), (0x05, 0x06, 0x208));
+        assert_eq!(hit.to_string(), "V3 changed 05 -> 06 at 0x208");
+    }
+
+    #[test]
+    fn key_wait_reports_the_fx0a() {
+        let mut chip8 = machine();
+        chip8.watch_reg(0);
+        // LD V0, 0x7B first
+        assert_eq!(run_to_hit(&mut chip8).pc, 0x200);
+        while !chip8.is_waiting_for_key() {
+            chip8.step().unwrap();
+        }
+        assert_eq!(chip8.take_watch_hit(), None);
+        chip8.set_key(0xC, true);
+        chip8.set_key(0xC, false);
+        let hit = chip8.take_watch_hit().unwrap();
+        assert_eq!((hit.old, hit.new, hit.pc), (0x7B, 0x0C, 0x20C));
+    }
+
+    #[test]
+    fn writes_of_the_same_value_dont_fire() {
+        let mut chip8 = machine();
+        chip8.set_register(3, 5);
+        chip8.watch_reg(3);
+        // LD V3, 5 over a 5 changes nothing, the ADD does
+        let hit = run_to_hit(&mut chip8);
+        assert_eq!((hit.old, hit.new, hit.pc), (0x05, 0x06, 0x208));
+        assert!(chip8.unwatch_reg(3));
+        assert!(!chip8.unwatch_reg(3));
+    }
+}
@@ -0,0 +1,251 @@
+// Taking a ROM out of a zip archive, through the zip crate: the list of members and the
+// contents of one, stored or deflated. Encrypted members and other compression methods are
+// reported, not read.
+
+use crate::screenshot::crc32;
+use std::fmt;
+use std::io::{Cursor, Read};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ZipError {
+    Corrupt(String),
+    Unsupported(String),
+    Encrypted(String),
+    UnsupportedMethod { name: String, method: u16 },
+    ChecksumMismatch(String),
+}
+
+impl fmt::Display for ZipError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ZipError::Corrupt(what) => write!(f, "zip archive is corrupt: {what}"),
+            ZipError::Unsupported(what) => write!(f, "zip archive is not supported: {what}"),
+            ZipError::Encrypted(name) => {
+                write!(f, "'{name}' in the zip archive is password-protected")
+            }
+            ZipError::UnsupportedMethod { name, method } => {
+                write!(
+                    f,
+                    "'{name}' uses zip compression method {method}, only stored and deflate are supported"
+                )
+            }
+            ZipError::ChecksumMismatch(name) => {
+                write!(f, "'{name}' in the zip archive fails its CRC check")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ZipError {}
+
+impl From<zip::result::ZipError> for ZipError {
+    fn from(error: zip::result::ZipError) -> Self {
+        match error {
+            zip::result::ZipError::InvalidArchive(what) => {
+                return ZipError::Corrupt(what.into_owned());
+            }
+            zip::result::ZipError::UnsupportedArchive(what) => {
+                return ZipError::Unsupported(what.to_string());
+            }
+            error => return ZipError::Corrupt(error.to_string()),
+        }
+    }
+}
+
+// Local file or end of directory (an empty archive) header
+pub fn is_zip(data: &[u8]) -> bool {
+    return data.starts_with(b"PK\x03\x04") || data.starts_with(b"PK\x05\x06");
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipEntry {
+    pub name: String,
+    // uncompressed, as the archive states it
+    pub size: usize,
+    crc: u32,
+    dir: bool,
+    // in the central directory
+    index: usize,
+}
+
+impl ZipEntry {
+    pub fn is_dir(&self) -> bool {
+        return self.dir;
+    }
+}
+
+pub struct ZipArchive<'a> {
+    archive: zip::ZipArchive<Cursor<&'a [u8]>>,
+    entries: Vec<ZipEntry>,
+}
+
+impl<'a> ZipArchive<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ZipError> {
+        let archive = zip::ZipArchive::new(Cursor::new(data))?;
+        let mut entries = Vec::with_capacity(archive.len());
+        for index in 0..archive.len() {
+            let file = archive.by_index_data(index)?;
+            entries.push(ZipEntry {
+                name: file.name()?.into_owned(),
+                size: usize::try_from(file.size()).unwrap_or(usize::MAX),
+                crc: file.crc32(),
+                dir: file.is_dir(),
+                index,
+            });
+        }
+
+        return Ok(ZipArchive { archive, entries });
+    }
+
+    pub fn entries(&self) -> &[ZipEntry] {
+        return &self.entries;
+    }
+
+    pub fn read(&mut self, entry: &ZipEntry) -> Result<Vec<u8>, ZipError> {
+        let file = match self.archive.by_index(entry.index) {
+            Ok(file) => file,
+            Err(zip::result::ZipError::UnsupportedArchive(
+                zip::result::ZipError::PASSWORD_REQUIRED,
+            )) => return Err(ZipError::Encrypted(entry.name.clone())),
+            Err(zip::result::ZipError::CompressionMethodNotSupported(method)) => {
+                return Err(ZipError::UnsupportedMethod {
+                    name: entry.name.clone(),
+                    method,
+                });
+            }
+            Err(e) => return Err(e.into()),
+        };
+        // a byte past the stated size is enough to tell, a zip bomb shouldn't get to fill
+        // the memory
+        let mut data = Vec::with_capacity(entry.size.min(crate::MAX_EXTENDED_ROM_SIZE));
+        let result = file.take(entry.size as u64 + 1).read_to_end(&mut data);
+        if data.len() > entry.size {
+            return Err(ZipError::Corrupt(format!(
+                "'{}' is larger than its stated size",
+                entry.name
+            )));
+        }
+        if let Err(e) = result {
+            // the crate checks the CRC at the end of the data too, but only says it's invalid
+            if data.len() == entry.size && crc32(&data) != entry.crc {
+                return Err(ZipError::ChecksumMismatch(entry.name.clone()));
+            }
+            return Err(ZipError::Corrupt(format!("'{}': {e}", entry.name)));
+        }
+
+        return Ok(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::CompressionMethod;
+    use zip::write::SimpleFileOptions;
+
+    const ROM: &[u8] = include_bytes!("../test_roms/ibm.ch8");
+
+    fn archive(method: CompressionMethod) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = SimpleFileOptions::default().compression_method(method);
+        zip.add_directory("roms/", options).unwrap();
+        zip.start_file("roms/ibm.ch8", options).unwrap();
+        zip.write_all(ROM).unwrap();
+        return zip.finish().unwrap().into_inner();
+    }
+
+    // The header of `name` with `signature`, its name follows at `name_at`
+    fn header(zip: &[u8], signature: &[u8], name_at: usize, name: &str) -> usize {
+        let mut pos = 0;
+        loop {
+            pos += zip[pos..].windows(4).position(|w| w == signature).unwrap();
+            if zip[pos + name_at..].starts_with(name.as_bytes()) {
+                return pos;
+            }
+            pos += 4;
+        }
+    }
+
+    // Writes `bytes` to a field both the local header and the central directory entry of
+    // `name` have, at `local` and `central` in them
+    fn patch(zip: &mut [u8], name: &str, (local, central): (usize, usize), bytes: &[u8]) {
+        let local = header(zip, b"PK\x03\x04", 30, name) + local;
+        zip[local..local + bytes.len()].copy_from_slice(bytes);
+        let central = header(zip, b"PK\x01\x02", 46, name) + central;
+        zip[central..central + bytes.len()].copy_from_slice(bytes);
+    }
+
+    fn read(zip: &[u8]) -> Result<Vec<u8>, ZipError> {
+        let mut archive = ZipArchive::parse(zip)?;
+        let entry = archive.entries()[1].clone();
+        return archive.read(&entry);
+    }
+
+    #[test]
+    fn stored_and_deflated_members() {
+        for method in [CompressionMethod::Stored, CompressionMethod::Deflated] {
+            let zip = archive(method);
+            assert!(is_zip(&zip));
+            let archive = ZipArchive::parse(&zip).unwrap();
+            let names: Vec<_> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+            assert_eq!(names, ["roms/", "roms/ibm.ch8"]);
+            assert!(archive.entries()[0].is_dir());
+            assert_eq!(archive.entries()[1].size, ROM.len());
+            assert_eq!(read(&zip).unwrap(), ROM, "{method:?}");
+        }
+        assert!(!is_zip(ROM));
+    }
+
+    #[test]
+    fn truncated_archives_are_errors() {
+        for method in [CompressionMethod::Stored, CompressionMethod::Deflated] {
+            let zip = archive(method);
+            for len in 0..zip.len() {
+                assert!(
+                    ZipArchive::parse(&zip[..len]).is_err(),
+                    "{method:?}, {len} bytes"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn damaged_members_are_errors() {
+        // more data than the archive says there is, as a zip bomb would have
+        let mut zip = archive(CompressionMethod::Deflated);
+        patch(&mut zip, "roms/ibm.ch8", (22, 24), &16u32.to_le_bytes());
+        assert_eq!(
+            read(&zip),
+            Err(ZipError::Corrupt(String::from(
+                "'roms/ibm.ch8' is larger than its stated size"
+            )))
+        );
+
+        let mut zip = archive(CompressionMethod::Stored);
+        let pos = zip.windows(ROM.len()).position(|w| w == ROM).unwrap();
+        zip[pos + 10] ^= 0xFF;
+        assert_eq!(
+            read(&zip),
+            Err(ZipError::ChecksumMismatch(String::from("roms/ibm.ch8")))
+        );
+
+        // bzip2
+        let mut zip = archive(CompressionMethod::Stored);
+        patch(&mut zip, "roms/ibm.ch8", (8, 10), &12u16.to_le_bytes());
+        assert_eq!(
+            read(&zip),
+            Err(ZipError::UnsupportedMethod {
+                name: String::from("roms/ibm.ch8"),
+                method: 12
+            })
+        );
+
+        let mut zip = archive(CompressionMethod::Stored);
+        patch(&mut zip, "roms/ibm.ch8", (6, 8), &1u16.to_le_bytes());
+        assert_eq!(
+            read(&zip),
+            Err(ZipError::Encrypted(String::from("roms/ibm.ch8")))
+        );
+    }
+}
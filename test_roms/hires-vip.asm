@@ -0,0 +1,27 @@
+; COSMAC VIP hi-res check for --hires-vip: the 1260 stub, a 0230 clear and sprites below
+; row 32 that only a 64x64 display shows. It stands in for the hi-res games of the period,
+; none of which has a license that lets it be vendored here. Build with
+;   RustChip8 --assemble test_roms/hires-vip.asm test_roms/hires-vip.ch8
+
+        JP 0x260          ; jumps over the VIP machine code, zeros here
+        db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+        db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+        db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+        db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+        db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+        db 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
+        SYS 0x230         ; the hi-res clear screen routine
+        LD I, box
+        LD V0, 0
+        LD V1, 0
+        DRW V0, V1, 8     ; top left
+        LD V0, 56
+        LD V1, 56
+        DRW V0, V1, 8     ; bottom right, past row 32
+        LD V0, 28
+        LD V1, 40
+        LD I, arrow
+        DRW V0, V1, 8
+end:    JP end
+box:    db 0xFF, 0x81, 0x81, 0x81, 0x81, 0x81, 0x81, 0xFF
+arrow:  db 0x18, 0x3C, 0x7E, 0xFF, 0x18, 0x18, 0x18, 0x18
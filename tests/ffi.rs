@@ -0,0 +1,137 @@
+#![allow(clippy::needless_return)]
+
+// The C interface from C: include/rustchip8.h has to be what cbindgen makes of src/ffi.rs,
+// and examples/ffi/main.c has to compile against it, link with the cdylib and print what
+// the same run gives in Rust.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use rustchip8::Chip8;
+
+fn root() -> &'static Path {
+    return Path::new(env!("CARGO_MANIFEST_DIR"));
+}
+
+fn header() -> String {
+    let mut out = Vec::new();
+    cbindgen::generate(root())
+        .expect("cbindgen failed")
+        .write(&mut out);
+    return String::from_utf8(out).unwrap();
+}
+
+// target/debug (or release), where cargo put librustchip8 next to the test's deps/
+fn lib_dir() -> PathBuf {
+    let exe = env::current_exe().unwrap();
+    return exe.parent().unwrap().parent().unwrap().to_path_buf();
+}
+
+// The Rust triple cc wants, the tests run on the host
+fn host() -> String {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| String::from("rustc"));
+    let out = Command::new(rustc).arg("-vV").output().unwrap();
+    let info = String::from_utf8(out.stdout).unwrap();
+    let host = info.lines().find_map(|line| line.strip_prefix("host: "));
+    return host.expect("no host in rustc -vV").to_string();
+}
+
+// What main.c prints, the same calls made through the Rust API
+fn expected(rom: &[u8]) -> String {
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.load_rom(rom).unwrap();
+    for _ in 0..60 {
+        for _ in 0..12 {
+            if chip8.is_waiting_for_key() || chip8.is_waiting_for_vblank() || chip8.has_exited() {
+                break;
+            }
+            chip8.step().unwrap();
+        }
+        chip8.tick_60hz();
+    }
+    let mut out = String::new();
+    for row in chip8.display_rows() {
+        let row: String = row
+            .iter()
+            .map(|&pixel| if pixel != 0 { '#' } else { '.' })
+            .collect();
+        out.push_str(&row);
+        out.push('\n');
+    }
+    out.push_str(&format!(
+        "PC {:#05x}, V0 {}\n",
+        chip8.pc(),
+        chip8.registers()[0]
+    ));
+    out.push_str("null machine: null Chip8 pointer\n");
+
+    return out;
+}
+
+#[test]
+fn header_is_generated() {
+    let committed = fs::read_to_string(root().join("include/rustchip8.h")).unwrap();
+    assert!(
+        committed == header(),
+        "include/rustchip8.h is out of date, run `cbindgen --config cbindgen.toml --output include/rustchip8.h`"
+    );
+}
+
+#[test]
+fn c_example_links_and_runs() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("ffi");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("rustchip8.h"), header()).unwrap();
+    let exe = dir.join("chip8-ffi");
+
+    let host = host();
+    let compiler = cc::Build::new()
+        .target(&host)
+        .host(&host)
+        .opt_level(0)
+        .cargo_metadata(false)
+        .get_compiler();
+    let mut cmd = compiler.to_command();
+    cmd.arg(root().join("examples/ffi/main.c"))
+        .arg("-I")
+        .arg(&dir);
+    if compiler.is_like_msvc() {
+        cmd.arg(format!("/Fe{}", exe.display()))
+            .arg("/link")
+            .arg(format!("/LIBPATH:{}", lib_dir().display()))
+            .arg("rustchip8.dll.lib");
+    } else {
+        cmd.arg("-o")
+            .arg(&exe)
+            .arg("-L")
+            .arg(lib_dir())
+            .arg("-lrustchip8");
+    }
+    let status = cmd.status().unwrap();
+    assert!(status.success(), "{cmd:?} failed");
+
+    let rom = root().join("test_roms/ibm.ch8");
+    // the loader finds librustchip8 in target/debug
+    let path = env::join_paths(
+        [lib_dir()]
+            .into_iter()
+            .chain(env::var_os("PATH").iter().flat_map(env::split_paths)),
+    )
+    .unwrap();
+    let out = Command::new(&exe)
+        .arg(&rom)
+        .env("LD_LIBRARY_PATH", lib_dir())
+        .env("DYLD_LIBRARY_PATH", lib_dir())
+        .env("PATH", path)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert_eq!(stdout, expected(&fs::read(rom).unwrap()));
+}
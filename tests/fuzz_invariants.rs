@@ -0,0 +1,135 @@
+#![allow(clippy::needless_return)]
+
+// The invariants of fuzz/fuzz_targets/rom.rs without cargo-fuzz: every input that found a
+// bug (fuzz/regressions) replayed, then random ROMs from a fixed seed so a failure always
+// reproduces, and a million cycles of plain random bytes. Keep run() in step with the fuzz
+// target.
+
+use std::fs;
+use std::path::Path;
+
+use rustchip8::{Chip8, MAX_ROM_SIZE, Quirks};
+
+const STEPS: u32 = 100_000;
+const RANDOM_ROMS: u32 = 1_000;
+const RANDOM_STEPS: u32 = 5_000;
+const HAMMER_CYCLES: u32 = 1_000_000;
+
+// xorshift64, fixed so a failure is the same failure on every run
+fn random() -> impl FnMut() -> u64 {
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    return move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        return state;
+    };
+}
+
+// The first two bytes pick the quirks and machine options, the rest is the program.
+// Returns how many instructions ran.
+fn run(data: &[u8], steps: u32) -> u32 {
+    let [config, keys, rom @ ..] = data else {
+        return 0;
+    };
+    if rom.is_empty() {
+        return 0;
+    }
+    let rom = &rom[..rom.len().min(MAX_ROM_SIZE)];
+    let flag = |bit: u8| config & (1 << bit) != 0;
+
+    let mut chip8 = Chip8::with_seed(*keys as u64);
+    chip8.set_quirks(Quirks {
+        shift_uses_vy: flag(0),
+        memory_increment: flag(1),
+        jump_uses_vx: flag(2),
+        sprite_wrap: flag(3),
+        vf_reset: flag(4),
+        display_wait: flag(5),
+        index_overflow: flag(6),
+    });
+    chip8.set_strict_memory(flag(7));
+    chip8.set_extended_memory(keys & 1 != 0);
+    chip8.set_stack_limit(1 + (*keys as usize >> 4));
+    chip8.set_ignore_sys(keys & 2 != 0);
+    chip8.load_rom(rom).unwrap();
+
+    let mut key_state = *keys as u32;
+    for step in 0..steps {
+        if step % 16 == 0 {
+            chip8.tick_60hz();
+        }
+        if step % 64 == 0 {
+            key_state = key_state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            chip8.set_key((key_state >> 16) as u8 & 0xF, key_state & 0x100 != 0);
+        }
+        if chip8.step().is_err() || chip8.has_exited() {
+            return step;
+        }
+
+        let mem_len = chip8.mem().len();
+        let pc_limit = if chip8.is_strict_memory() {
+            mem_len + 2
+        } else {
+            mem_len
+        };
+        assert!(
+            (chip8.pc() as usize) < pc_limit,
+            "pc {:#X} outside memory",
+            chip8.pc()
+        );
+        assert!(
+            (chip8.reg_i() as usize) < mem_len,
+            "I {:#X} outside memory",
+            chip8.reg_i()
+        );
+        assert!(chip8.stack().len() <= chip8.stack_limit());
+        assert!(chip8.stack().iter().all(|&addr| (addr as usize) < pc_limit));
+    }
+
+    return steps;
+}
+
+#[test]
+fn regressions() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/regressions");
+    let mut replayed = 0;
+    for entry in fs::read_dir(&dir).unwrap() {
+        let path = entry.unwrap().path();
+        println!("{}", path.display());
+        run(&fs::read(&path).unwrap(), STEPS);
+        replayed += 1;
+    }
+    assert!(replayed > 0, "nothing in {}", dir.display());
+}
+
+#[test]
+fn random_roms() {
+    let mut next = random();
+    for _ in 0..RANDOM_ROMS {
+        let len = 2 + (next() % 512) as usize;
+        let mut data: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+        // mostly jumps and calls into the ROM, so runs don't all end in the zeros after it
+        for i in (2..len - 1).step_by(2) {
+            if next().is_multiple_of(4) {
+                let addr = (0x200 + next() as usize % len) & !1;
+                let opcode = if next().is_multiple_of(2) { 0x10 } else { 0x20 };
+                data[i] = opcode | (addr >> 8) as u8;
+                data[i + 1] = addr as u8;
+            }
+        }
+        run(&data, RANDOM_STEPS);
+    }
+}
+
+#[test]
+fn million_cycles_of_random_bytes() {
+    // plain random bytes filling all of 4K, a fresh machine whenever one stops
+    let mut next = random();
+    let mut cycles = 0;
+    while cycles < HAMMER_CYCLES {
+        let data: Vec<u8> = (0..2 + MAX_ROM_SIZE).map(|_| next() as u8).collect();
+        // at least one instruction runs, an error is counted as a cycle too
+        cycles += run(&data, HAMMER_CYCLES - cycles).max(1);
+    }
+}
@@ -0,0 +1,50 @@
+#![allow(clippy::needless_return)]
+
+// The core on its own, the way a project embedding it would use it: no window, no
+// frontend, only the library.
+
+use std::fs;
+use std::path::Path;
+
+use rustchip8::{Chip8, PROGRAM_START};
+
+fn rom(name: &str) -> Vec<u8> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("test_roms")
+        .join(name);
+    return fs::read(path).unwrap();
+}
+
+#[test]
+fn ibm_logo_runs_headless() {
+    let rom = rom("ibm.ch8");
+    let mut chip8 = Chip8::with_seed(0);
+    chip8.load_rom(&rom).unwrap();
+    assert_eq!(&chip8.mem()[PROGRAM_START..][..rom.len()], &rom[..]);
+
+    for _ in 0..60 {
+        let frame = chip8.run_frame(20, &[false; 16]);
+        assert!(frame.halted.is_none());
+    }
+    // the logo is drawn and the ROM parks on its final 1NNN
+    assert!(chip8.is_self_jump());
+    assert!(chip8.display_rows().flatten().any(|&pixel| pixel != 0));
+    assert_eq!(chip8.stack(), &[] as &[u16]);
+    // memory the ROM doesn't touch stays as loaded
+    assert_eq!(&chip8.mem()[PROGRAM_START..][..rom.len()], &rom[..]);
+}
+
+#[test]
+fn registers_and_memory_after_a_tiny_rom() {
+    // LD V0, 0x2A; LD I, 0x300; LD [I], V0; JP 0x206
+    let mut chip8 = Chip8::with_seed(0);
+    chip8
+        .load_rom(&[0x60, 0x2A, 0xA3, 0x00, 0xF0, 0x55, 0x12, 0x06])
+        .unwrap();
+    for _ in 0..4 {
+        chip8.step().unwrap();
+    }
+    assert_eq!(chip8.registers()[0], 0x2A);
+    assert_eq!(chip8.read_mem(0x300).unwrap(), 0x2A);
+    assert_eq!(chip8.pc(), 0x206);
+}